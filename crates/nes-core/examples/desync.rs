@@ -0,0 +1,57 @@
+//! Runs a ROM under two accuracy profiles at once and reports the first cycle where they stop
+//! agreeing, to track down which frame and component an accuracy setting affects.
+//!
+//! Input comes from an FM2 movie if one is given, or else the console runs for 10 seconds with
+//! no buttons pressed.
+//!
+//! ```sh
+//! cargo run -p nes-core --release --example desync -- rom.nes fast accurate [movie.fm2]
+//! ```
+
+use nes_core::{AccuracyProfile, Cartridge, Controller, Replay, System};
+
+/// How many frames to run for when there's no movie to take input from.
+const DEFAULT_FRAMES: usize = 600;
+
+fn main() -> Result<(), String> {
+    let mut args = std::env::args().skip(1);
+    let usage = "usage: desync <rom> <profile a> <profile b> [movie.fm2]";
+    let rom_path = args.next().ok_or(usage)?;
+    let profile_a: AccuracyProfile = args.next().ok_or(usage)?.parse()?;
+    let profile_b: AccuracyProfile = args.next().ok_or(usage)?.parse()?;
+
+    let rom = std::fs::read(&rom_path).map_err(|err| format!("failed to read ROM: {err}"))?;
+    let inputs: Vec<_> = match args.next() {
+        Some(movie_path) => {
+            let movie = std::fs::read_to_string(&movie_path)
+                .map_err(|err| format!("failed to read movie: {err}"))?;
+            Replay::new(movie.lines())?
+                .map(|(_, controller_1, controller_2)| (controller_1, controller_2))
+                .collect()
+        }
+        None => vec![(Controller::new(), Controller::new()); DEFAULT_FRAMES],
+    };
+
+    let setup = |profile: AccuracyProfile| {
+        let rom = &rom;
+        move || {
+            let cartridge = Cartridge::new(rom)?;
+            let region = cartridge.detect_region(None);
+            let system = System::with_accuracy_profile(cartridge, profile);
+            system.bus.borrow_mut().set_region(region);
+            Ok(system)
+        }
+    };
+
+    match nes_core::find_divergence(setup(profile_a), setup(profile_b), &inputs)? {
+        Some(divergence) => println!(
+            "diverged on frame {} at cycle {}: {}",
+            divergence.frame,
+            divergence.cycle,
+            divergence.components.join(", ")
+        ),
+        None => println!("no divergence in {} frames", inputs.len()),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,1536 @@
+use crate::{
+    audio::{BufferWatermark, BufferWatermarks, SAMPLE_RATE},
+    resampler::{Resampler, SincFilter},
+    savestate::{ApuEnvelopeState, ApuState, ApuSweepState, StateSerialize},
+    FrameTiming,
+};
+
+const BUFFER_SIZE: usize = 1024;
+const VOLUME: i16 = 2000;
+const LENGTH_COUNTER_MAP: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+/// How much the fast-forward fade changes the volume per output sample, so that muting or
+/// unmuting takes about 10ms.
+const FADE_STEP: f32 = 1.0 / 441.0;
+/// How much of a sudden jump in the output is left after each output sample, so that jumps from
+/// toggling channels or loading savestates are smoothed out over about 5ms instead of clicking.
+const DECLICK_DECAY: f32 = 0.995;
+/// How many samples the output is faded out for after a reset, enough to cover a game's startup
+/// code silencing the channels that were playing.
+const RESET_SILENCE_SAMPLES: u32 = 2048;
+/// Master clock rates are all whole multiples of 1/22 Hz, so scaling them by this lets output
+/// samples be timed with integers, which keeps the number of samples per frame from drifting.
+const CLOCK_RATE_SCALE: f64 = 22.0;
+const NOISE_TIMER_MAP: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+
+type WatermarkCallback = Box<dyn FnMut(BufferWatermark)>;
+
+/// Volume multipliers applied to each audio source when mixing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelGains {
+    pub pulse_1: f32,
+    pub pulse_2: f32,
+    pub triangle: f32,
+    pub noise: f32,
+    /// Audio generated by the cartridge, such as from the VRC6 or Sunsoft 5B.
+    pub expansion: f32,
+}
+
+impl Default for ChannelGains {
+    fn default() -> Self {
+        Self {
+            pulse_1: 1.0,
+            pulse_2: 1.0,
+            triangle: 1.0,
+            noise: 1.0,
+            expansion: 1.0,
+        }
+    }
+}
+
+/// What the APU does with its output while the emulator is running faster than real time.
+///
+/// In both modes, every `speed` consecutive output samples are averaged into one so
+/// that audio is produced at the real-time rate instead of piling up in the frontend's queue.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FastForwardAudio {
+    /// Plays the time-compressed audio.
+    #[default]
+    Decimate,
+    /// Fades the audio out, and back in once the emulator returns to normal speed.
+    Mute,
+}
+
+#[derive(Default)]
+pub struct Apu {
+    audio_buffer: Vec<f32>,
+    /// Called when the audio buffer's fill level reaches one of its watermarks, if set.
+    buffer_watermarks: Option<(BufferWatermarks, WatermarkCallback)>,
+
+    channel_data: Box<[u8; 16]>,
+
+    pulse_1: PulseChannel,
+    pulse_2: PulseChannel,
+    triangle: TriangleChannel,
+    noise: NoiseChannel,
+
+    pub is_pulse_1_enabled: bool,
+    pub is_pulse_2_enabled: bool,
+    pub is_triangle_enabled: bool,
+    pub is_noise_enabled: bool,
+    pub gains: ChannelGains,
+    /// The current output of the cartridge's expansion audio, if it has any. This is updated by
+    /// the bus before each clock.
+    pub expansion_output: Option<i16>,
+
+    use_five_frame_sequence: bool,
+    disable_frame_interrupt: bool,
+    clock_timer: usize,
+    /// CPU cycles left until a write to $4017 resets the frame counter, or 0 if none is pending.
+    frame_counter_reset_delay: u8,
+
+    /// Counts up by `sample_step` each CPU cycle, producing a sample each time it passes
+    /// `sample_threshold`.
+    sample_accumulator: u64,
+    sample_step: u64,
+    sample_threshold: u64,
+
+    speed: u32,
+    fast_forward_audio: FastForwardAudio,
+    /// The sum and number of samples waiting to be averaged into the next output sample.
+    pending_sample: (f32, u32),
+    fade_gain: f32,
+    /// The most recent mixed sample, before fading.
+    last_output: f32,
+    /// Added to the output to bridge a sudden jump in it, decaying to 0 over time.
+    declick_offset: f32,
+    /// How many of the upcoming samples should have jumps in the output smoothed out.
+    declick_samples: u32,
+    /// How many more samples the output stays faded out for after a reset.
+    reset_samples: u32,
+    /// The output when the console was reset, which is faded out in place of the game's output.
+    reset_level: f32,
+    /// How far the output is faded in, ramping down after a reset and back up once it's over.
+    reset_gain: f32,
+    /// The channel enables and gains used to mix the last sample, to notice when they change.
+    last_mix_settings: ([bool; 4], ChannelGains),
+    resampler: Resampler,
+    /// The mix from the previous CPU cycle, for [Resampler::Linear].
+    previous_output: f32,
+    /// Only allocated once [Resampler::Sinc] is selected.
+    sinc_filter: Option<Box<SincFilter>>,
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        let mut apu = Self {
+            audio_buffer: Vec::with_capacity(BUFFER_SIZE),
+            pulse_1: PulseChannel::new(1),
+            pulse_2: PulseChannel::new(2),
+
+            is_pulse_1_enabled: true,
+            is_pulse_2_enabled: true,
+            is_triangle_enabled: true,
+            is_noise_enabled: true,
+            speed: 1,
+            fade_gain: 1.0,
+            reset_gain: 1.0,
+            last_mix_settings: ([true; 4], ChannelGains::default()),
+            ..Default::default()
+        };
+        apu.set_timing(FrameTiming::default());
+        apu
+    }
+
+    /// Sets the clock rate samples are produced relative to. This should match the PPU's timing.
+    pub fn set_timing(&mut self, timing: FrameTiming) {
+        self.sample_step = SAMPLE_RATE as u64
+            * timing.master_clocks_per_cpu_cycle as u64
+            * CLOCK_RATE_SCALE as u64;
+        self.sample_threshold = (timing.master_clock_rate * CLOCK_RATE_SCALE).round() as u64;
+        self.sample_accumulator = 0;
+    }
+
+    pub fn clock(&mut self) {
+        let mut is_quarter_frame = false;
+        let mut is_half_frame = false;
+        if self.clock_timer == 3728 * 2 + 1 {
+            is_quarter_frame = true;
+        } else if self.clock_timer == 7456 * 2 + 1 {
+            is_quarter_frame = true;
+            is_half_frame = true;
+        } else if self.clock_timer == 11185 * 2 + 1 {
+            is_quarter_frame = true;
+        } else if self.clock_timer == 14914 * 2 {
+            // Quarter frame.
+        } else if self.clock_timer == 14914 * 2 + 1 && !self.use_five_frame_sequence {
+            is_quarter_frame = true;
+            is_half_frame = true;
+        } else if self.clock_timer == 14915 * 2 {
+            // Quarter frame.
+        } else if self.clock_timer == 18640 * 2 + 1 && self.use_five_frame_sequence {
+            is_quarter_frame = true;
+            is_half_frame = true;
+        }
+
+        if is_quarter_frame {
+            self.clock_quarter_frame();
+        }
+        if is_half_frame {
+            self.clock_half_frame();
+        }
+        self.commit_length_counters();
+
+        if self.clock_timer.is_multiple_of(2) {
+            self.pulse_1.clock();
+            self.pulse_2.clock();
+        }
+        self.triangle.clock();
+        self.noise.clock();
+
+        // How far this cycle is from the next sample, in samples.
+        let time_to_sample =
+            (self.sample_threshold - self.sample_accumulator) as f32 / self.sample_threshold as f32;
+        self.sample_accumulator += self.sample_step;
+        let is_sample_due = self.sample_accumulator >= self.sample_threshold;
+        if is_sample_due {
+            self.sample_accumulator -= self.sample_threshold;
+        }
+        let sample = match self.resampler {
+            Resampler::Nearest => is_sample_due.then(|| self.mix()),
+            Resampler::Linear => {
+                let output = self.mix();
+                let previous_output = std::mem::replace(&mut self.previous_output, output);
+                is_sample_due.then(|| {
+                    // How far the sample falls between the previous cycle and this one.
+                    let overshoot = self.sample_accumulator as f32 / self.sample_step as f32;
+                    output - (output - previous_output) * overshoot
+                })
+            }
+            Resampler::Sinc => {
+                let output = self.mix();
+                let filter = self
+                    .sinc_filter
+                    .get_or_insert_with(|| Box::new(SincFilter::new(output)));
+                filter.set_level(output, time_to_sample);
+                is_sample_due.then(|| filter.next_sample())
+            }
+        };
+        if let Some(sample) = sample {
+            let output = self.declick(sample);
+            let output = self.ramp_reset(output);
+            self.push_sample(output);
+        }
+        self.clock_timer += 1;
+        if (self.clock_timer == 14915 * 2 && !self.use_five_frame_sequence)
+            || (self.clock_timer == 18641 * 2 && self.use_five_frame_sequence)
+        {
+            self.clock_timer = 0;
+        }
+        if self.frame_counter_reset_delay > 0 {
+            self.frame_counter_reset_delay -= 1;
+            if self.frame_counter_reset_delay == 0 {
+                self.clock_timer = 0;
+            }
+        }
+    }
+
+    /// Mixes the channels' current outputs, scaled to -1 to 1.
+    fn mix(&self) -> f32 {
+        let mut output = 0.0;
+        if self.is_pulse_1_enabled {
+            output += self.pulse_1.output() as f32 * self.gains.pulse_1;
+        }
+        if self.is_pulse_2_enabled {
+            output += self.pulse_2.output() as f32 * self.gains.pulse_2;
+        }
+        if self.is_triangle_enabled {
+            output += self.triangle.output as f32 * self.gains.triangle;
+        }
+        if self.is_noise_enabled {
+            output += self.noise.output() as f32 * self.gains.noise;
+        }
+        if let Some(expansion_output) = self.expansion_output {
+            output += expansion_output as f32 * self.gains.expansion;
+        }
+        output / i16::MAX as f32
+    }
+
+    /// Applies writes to the length counters made this cycle, which happens after the frame
+    /// counter has had a chance to clock them.
+    fn commit_length_counters(&mut self) {
+        self.pulse_1.length_counter.commit();
+        self.pulse_2.length_counter.commit();
+        self.triangle.length_counter.commit();
+        self.noise.length_counter.commit();
+    }
+
+    /// Clocks the envelopes and the triangle's linear counter.
+    fn clock_quarter_frame(&mut self) {
+        self.pulse_1.clock_envelope();
+        self.pulse_2.clock_envelope();
+        self.triangle.clock_linear_counter();
+        self.noise.clock_envelope();
+    }
+
+    /// Clocks the length counters and sweep units.
+    fn clock_half_frame(&mut self) {
+        self.pulse_1.length_counter.clock();
+        self.pulse_2.length_counter.clock();
+        self.triangle.length_counter.clock();
+        self.noise.length_counter.clock();
+
+        self.pulse_1.clock_sweep();
+        self.pulse_2.clock_sweep();
+    }
+
+    /// Checks that the APU's counters and indices are within the ranges the hardware allows, for
+    /// tests that drive it with random register writes. Returns a description of the first one
+    /// that isn't.
+    pub fn validate_invariants(&self) -> Result<(), String> {
+        let check = |is_valid: bool, description: &str| {
+            if is_valid {
+                Ok(())
+            } else {
+                Err(description.to_string())
+            }
+        };
+        for (name, pulse) in [("pulse 1", &self.pulse_1), ("pulse 2", &self.pulse_2)] {
+            let check = |is_valid, description| check(is_valid, &format!("{name} {description}"));
+            check(pulse.timer_reload <= 0x07FF, "period is over 11 bits")?;
+            check(pulse.sequence_counter < 8, "duty step is over 7")?;
+            check(pulse.sweep.shift_count < 8, "sweep shift is over 7")?;
+            check(pulse.sweep.divider_reload < 8, "sweep period is over 7")?;
+            pulse.envelope.validate_invariants(name)?;
+        }
+        check(
+            self.triangle.timer_reload <= 0x07FF,
+            "triangle period is over 11 bits",
+        )?;
+        check(
+            self.triangle.sequence_counter < 32,
+            "triangle step is over 31",
+        )?;
+        check(
+            self.triangle.linear_counter <= 0x7F,
+            "triangle linear counter is over 127",
+        )?;
+        check(
+            self.noise.shift_register != 0 && self.noise.shift_register < 0x8000,
+            "noise shift register is 0 or over 15 bits",
+        )?;
+        self.noise.envelope.validate_invariants("noise")?;
+        check(
+            self.clock_timer < 18641 * 2,
+            "frame counter is past the end of its sequence",
+        )
+    }
+
+    /// Returns how many times faster than real time the emulator is being run.
+    pub fn speed(&self) -> u32 {
+        self.speed
+    }
+
+    /// Sets how many times faster than real time the emulator is being run, so that the output can
+    /// be compressed to match. 1 is normal speed, and 0 is treated as 1.
+    pub fn set_speed(&mut self, speed: u32) {
+        self.speed = speed.max(1);
+    }
+
+    pub fn fast_forward_audio(&self) -> FastForwardAudio {
+        self.fast_forward_audio
+    }
+
+    pub fn set_fast_forward_audio(&mut self, mode: FastForwardAudio) {
+        self.fast_forward_audio = mode;
+    }
+
+    pub fn resampler(&self) -> Resampler {
+        self.resampler
+    }
+
+    /// Sets how the output is resampled to the audio sample rate. This doesn't affect emulation,
+    /// so it can be changed at any time, even during a replay.
+    pub fn set_resampler(&mut self, resampler: Resampler) {
+        if resampler == self.resampler {
+            return;
+        }
+        self.resampler = resampler;
+        let output = self.mix();
+        self.previous_output = output;
+        if resampler == Resampler::Sinc {
+            self.sinc_filter = Some(Box::new(SincFilter::new(output)));
+        }
+        self.declick_samples = self.declick_samples.max(1);
+    }
+
+    /// Fades out whatever was playing when the console is reset, keeps the output silent while the
+    /// game's startup code silences its channels, then fades the game's output back in. Toggling
+    /// channels, changing their gains, and applying savestates are smoothed out automatically.
+    pub fn smooth_reset(&mut self) {
+        let output = if self.reset_samples > 0 {
+            self.reset_level
+        } else {
+            self.last_output
+        };
+        self.reset_level = output * self.reset_gain;
+        self.reset_gain = 1.0;
+        self.reset_samples = RESET_SILENCE_SAMPLES;
+    }
+
+    /// Ramps the output down and back up around a reset, as started by [Apu::smooth_reset].
+    fn ramp_reset(&mut self, sample: f32) -> f32 {
+        if self.reset_samples > 0 {
+            self.reset_samples -= 1;
+            self.reset_gain = (self.reset_gain - FADE_STEP).max(0.0);
+            return self.reset_level * self.reset_gain;
+        }
+        self.reset_gain = (self.reset_gain + FADE_STEP).min(1.0);
+        sample * self.reset_gain
+    }
+
+    /// Bridges jumps in the output caused by the emulator rather than the game, which would
+    /// otherwise be heard as clicks.
+    fn declick(&mut self, sample: f32) -> f32 {
+        let mix_settings = (
+            [
+                self.is_pulse_1_enabled,
+                self.is_pulse_2_enabled,
+                self.is_triangle_enabled,
+                self.is_noise_enabled,
+            ],
+            self.gains,
+        );
+        if mix_settings != self.last_mix_settings {
+            self.last_mix_settings = mix_settings;
+            self.declick_samples = self.declick_samples.max(1);
+        }
+        if self.declick_samples > 0 {
+            self.declick_samples -= 1;
+            self.declick_offset = self.last_output - sample;
+        }
+
+        let output = sample + self.declick_offset;
+        self.declick_offset *= DECLICK_DECAY;
+        self.last_output = output;
+        output
+    }
+
+    fn push_sample(&mut self, sample: f32) {
+        let (sum, count) = &mut self.pending_sample;
+        *sum += sample;
+        *count += 1;
+        if *count < self.speed {
+            return;
+        }
+        let sample = *sum / *count as f32;
+        self.pending_sample = (0.0, 0);
+
+        let is_muted = self.speed > 1 && self.fast_forward_audio == FastForwardAudio::Mute;
+        self.fade_gain = if is_muted {
+            (self.fade_gain - FADE_STEP).max(0.0)
+        } else {
+            (self.fade_gain + FADE_STEP).min(1.0)
+        };
+        self.audio_buffer.push(sample * self.fade_gain);
+        self.check_buffer_watermarks();
+    }
+
+    pub fn drain_audio_buffer(&mut self) -> Vec<f32> {
+        let samples = std::mem::replace(&mut self.audio_buffer, Vec::with_capacity(BUFFER_SIZE));
+        self.check_buffer_watermarks();
+        samples
+    }
+
+    /// Calls `callback` whenever the number of samples in the audio buffer reaches `low` or
+    /// `high`, as tracked by [BufferWatermarks], for frontends that play audio straight out of the
+    /// buffer. The callback runs partway through clocking the system, so it can't use the
+    /// emulator, but it can signal the frontend to run more or less of it.
+    pub fn set_buffer_watermarks(
+        &mut self,
+        low: usize,
+        high: usize,
+        callback: impl FnMut(BufferWatermark) + 'static,
+    ) {
+        self.buffer_watermarks = Some((BufferWatermarks::new(low, high), Box::new(callback)));
+    }
+
+    pub fn clear_buffer_watermarks(&mut self) {
+        self.buffer_watermarks = None;
+    }
+
+    fn check_buffer_watermarks(&mut self) {
+        if let Some((watermarks, callback)) = &mut self.buffer_watermarks {
+            if let Some(watermark) = watermarks.update(self.audio_buffer.len()) {
+                callback(watermark);
+            }
+        }
+    }
+
+    pub fn audio_buffer(&self) -> &[f32] {
+        &self.audio_buffer
+    }
+
+    pub fn audio_buffer_length(&self) -> usize {
+        self.audio_buffer.len()
+    }
+
+    pub fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            // Whether each channel's length counter is still running.
+            0x4015 => {
+                self.pulse_1.length_counter.is_active() as u8
+                    | (self.pulse_2.length_counter.is_active() as u8) << 1
+                    | (self.triangle.length_counter.is_active() as u8) << 2
+                    | (self.noise.length_counter.is_active() as u8) << 3
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn cpu_write(&mut self, addr: u16, data: u8) {
+        if let 0x4000..=0x400F = addr {
+            self.channel_data[addr as usize & 0xF] = data;
+        }
+
+        match addr {
+            0x4000 => {
+                self.pulse_1.duty_cycle = match (data >> 6) & 0x03 {
+                    0 => 0b00000001,
+                    1 => 0b00000011,
+                    2 => 0b00001111,
+                    3 => 0b11111100,
+                    _ => unreachable!(),
+                };
+                self.pulse_1
+                    .length_counter
+                    .write_halt((data >> 5) & 0x01 != 0);
+                self.pulse_1.envelope.divider_reload = data & 0x0F;
+                self.pulse_1.envelope.divider = self.pulse_1.envelope.divider_reload;
+                self.pulse_1.envelope.constant_volume_flag = (data >> 4) & 0x01 != 0;
+            }
+            0x4001 => {
+                self.pulse_1.sweep.shift_count = data & 0x07;
+                self.pulse_1.sweep.negate_flag = (data >> 3) & 0x01 != 0;
+                self.pulse_1.sweep.divider_reload = (data >> 4) & 0x07;
+                self.pulse_1.sweep.divider = self.pulse_1.sweep.divider_reload;
+                self.pulse_1.sweep.is_enabled = (data >> 7) & 0x01 != 0;
+                self.pulse_1.sweep.reload_flag = true;
+                self.pulse_1.sweep.target_period = self.pulse_1.timer_reload;
+            }
+            0x4002 => {
+                self.pulse_1.timer_reload = (self.pulse_1.timer_reload & 0xFF00) | data as u16
+            }
+            0x4003 => {
+                self.pulse_1.timer_reload =
+                    (self.pulse_1.timer_reload & 0x00FF) | ((data as u16 & 0x07) << 8);
+                self.pulse_1.timer = self.pulse_1.timer_reload;
+                let length = LENGTH_COUNTER_MAP[((data >> 3) & 0x1F) as usize];
+                self.pulse_1
+                    .length_counter
+                    .reload(length, self.pulse_1.is_enabled);
+                self.pulse_1.envelope.start_flag = true;
+                self.pulse_1.sweep.target_period = self.pulse_1.timer_reload;
+            }
+            0x4004 => {
+                self.pulse_2.duty_cycle = match (data >> 6) & 0x03 {
+                    0 => 0b00000001,
+                    1 => 0b00000011,
+                    2 => 0b00001111,
+                    3 => 0b11111100,
+                    _ => unreachable!(),
+                };
+                self.pulse_2
+                    .length_counter
+                    .write_halt((data >> 5) & 0x01 != 0);
+                self.pulse_2.envelope.divider_reload = data & 0x0F;
+                self.pulse_2.envelope.divider = self.pulse_2.envelope.divider_reload;
+                self.pulse_2.envelope.constant_volume_flag = (data >> 4) & 0x01 != 0;
+            }
+            0x4005 => {
+                self.pulse_2.sweep.shift_count = data & 0x07;
+                self.pulse_2.sweep.negate_flag = (data >> 3) & 0x01 != 0;
+                self.pulse_2.sweep.divider_reload = (data >> 4) & 0x07;
+                self.pulse_2.sweep.divider = self.pulse_2.sweep.divider_reload;
+                self.pulse_2.sweep.is_enabled = (data >> 7) & 0x01 != 0;
+                self.pulse_2.sweep.reload_flag = true;
+                self.pulse_2.sweep.target_period = self.pulse_2.timer_reload;
+            }
+            0x4006 => {
+                self.pulse_2.timer_reload = (self.pulse_2.timer_reload & 0xFF00) | data as u16
+            }
+            0x4007 => {
+                self.pulse_2.timer_reload =
+                    (self.pulse_2.timer_reload & 0x00FF) | ((data as u16 & 0x07) << 8);
+                self.pulse_2.timer = self.pulse_2.timer_reload;
+                let length = LENGTH_COUNTER_MAP[((data >> 3) & 0x1F) as usize];
+                self.pulse_2
+                    .length_counter
+                    .reload(length, self.pulse_2.is_enabled);
+                self.pulse_2.envelope.start_flag = true;
+                self.pulse_2.sweep.target_period = self.pulse_2.timer_reload;
+            }
+            0x4008 => {
+                self.triangle
+                    .length_counter
+                    .write_halt((data >> 7) & 0x01 != 0);
+                self.triangle.linear_counter_reload = data & 0x7F;
+            }
+            0x400A => {
+                self.triangle.timer_reload = (self.triangle.timer_reload & 0xFF00) | data as u16
+            }
+            0x400B => {
+                self.triangle.timer_reload =
+                    (self.triangle.timer_reload & 0x00FF) | ((data as u16 & 0x07) << 8);
+                self.triangle.timer = self.triangle.timer_reload;
+                let length = LENGTH_COUNTER_MAP[((data >> 3) & 0x1F) as usize] + 1;
+                self.triangle
+                    .length_counter
+                    .reload(length, self.triangle.is_enabled);
+                self.triangle.linear_counter_reload_flag = true;
+            }
+            0x400C => {
+                self.noise
+                    .length_counter
+                    .write_halt((data >> 5) & 0x01 != 0);
+                self.noise.envelope.divider_reload = data & 0x0F;
+                self.noise.envelope.divider = self.noise.envelope.divider_reload;
+                self.noise.envelope.constant_volume_flag = (data >> 4) & 0x01 != 0;
+            }
+            0x400E => {
+                self.noise.timer_reload = NOISE_TIMER_MAP[(data & 0x0F) as usize];
+                self.noise.timer = self.noise.timer_reload;
+                self.noise.mode_flag = (data >> 7) & 0x01 != 0;
+            }
+            0x400F => {
+                let length = LENGTH_COUNTER_MAP[((data >> 3) & 0x1F) as usize];
+                self.noise
+                    .length_counter
+                    .reload(length, self.noise.is_enabled);
+                self.noise.envelope.start_flag = true;
+            }
+            // Enabling a channel doesn't reload its length counter; that only happens on the next
+            // write to the channel's length register.
+            0x4015 => {
+                self.pulse_1.is_enabled = data & 0x01 != 0;
+                self.pulse_2.is_enabled = data & 0x02 != 0;
+                self.triangle.is_enabled = data & 0x04 != 0;
+                self.noise.is_enabled = data & 0x08 != 0;
+                self.pulse_1
+                    .length_counter
+                    .set_enabled(self.pulse_1.is_enabled);
+                self.pulse_2
+                    .length_counter
+                    .set_enabled(self.pulse_2.is_enabled);
+                self.triangle
+                    .length_counter
+                    .set_enabled(self.triangle.is_enabled);
+                self.noise.length_counter.set_enabled(self.noise.is_enabled);
+            }
+            0x4017 => {
+                self.use_five_frame_sequence = data & 0x80 != 0;
+                self.disable_frame_interrupt = data & 0x40 != 0;
+                if self.use_five_frame_sequence {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+                // The frame counter is reset 3 CPU cycles after a write made during an APU cycle
+                // (every other CPU cycle), or 4 cycles after one made between APU cycles.
+                //
+                // See <https://www.nesdev.org/wiki/APU_Frame_Counter>.
+                self.frame_counter_reset_delay = if self.clock_timer.is_multiple_of(2) {
+                    3
+                } else {
+                    4
+                };
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Apu {
+    /// Returns the same state as [StateSerialize::save_state], without serializing it.
+    pub fn state(&self) -> ApuState {
+        let envelope = |envelope: &Envelope, length_counter: &LengthCounter| ApuEnvelopeState {
+            divider_reload: envelope.divider_reload,
+            divider: envelope.divider,
+            mode: envelope.constant_volume_flag as u8 | (length_counter.is_halted as u8) << 1,
+            decay_level: envelope.decay_level,
+        };
+        let sweep = |sweep: &Sweep| ApuSweepState {
+            is_enabled: sweep.is_enabled,
+            target_period: sweep.target_period,
+            divider: sweep.divider,
+        };
+
+        ApuState {
+            channel_data: *self.channel_data,
+            channel_enables: self.pulse_1.is_enabled as u8
+                | (self.pulse_2.is_enabled as u8) << 1
+                | (self.triangle.is_enabled as u8) << 2
+                | (self.noise.is_enabled as u8) << 3,
+            frame_mode: self.disable_frame_interrupt as u8
+                | (self.use_five_frame_sequence as u8) << 1,
+            noise_shift_register: self.noise.shift_register,
+            triangle_linear_counter_reload_flag: self.triangle.linear_counter_reload_flag,
+            triangle_linear_counter: self.triangle.linear_counter,
+
+            pulse_1_envelope: envelope(&self.pulse_1.envelope, &self.pulse_1.length_counter),
+            pulse_2_envelope: envelope(&self.pulse_2.envelope, &self.pulse_2.length_counter),
+            noise_envelope: envelope(&self.noise.envelope, &self.noise.length_counter),
+
+            pulse_1_sweep: sweep(&self.pulse_1.sweep),
+            pulse_2_sweep: sweep(&self.pulse_2.sweep),
+
+            pulse_1_length_counter: self.pulse_1.length_counter.counter,
+            pulse_2_length_counter: self.pulse_2.length_counter.counter,
+            triangle_length_counter: self.triangle.length_counter.counter,
+            noise_length_counter: self.noise.length_counter.counter,
+        }
+    }
+}
+
+/// The audio buffer, sample rate, speed, channel toggles and gains, resampler, and the fading and
+/// declicking applied to the output only affect how the audio is presented, so they aren't saved.
+impl StateSerialize for Apu {
+    type State<'a> = ApuState;
+
+    fn apply_state(&mut self, state: ApuState) {
+        let channel_data = state.channel_data;
+        // Expansion audio is restored with the mapper, which reports its output on the next clock.
+        self.expansion_output = None;
+        self.declick_samples = self.declick_samples.max(1);
+        // The registers are restored by writing them, but the timers and the envelope and sweep
+        // restart flags those writes reset aren't saved, so they carry over from before the load.
+        let timers = (
+            self.pulse_1.timer,
+            self.pulse_2.timer,
+            self.triangle.timer,
+            self.noise.timer,
+        );
+        let start_flags = (
+            self.pulse_1.envelope.start_flag,
+            self.pulse_2.envelope.start_flag,
+            self.noise.envelope.start_flag,
+        );
+        let sweep_reload_flags = (
+            self.pulse_1.sweep.reload_flag,
+            self.pulse_2.sweep.reload_flag,
+        );
+
+        self.cpu_write(0x4000, channel_data[0x0]);
+        self.cpu_write(0x4001, channel_data[0x1]);
+        self.cpu_write(0x4002, channel_data[0x2]);
+        self.cpu_write(0x4003, channel_data[0x3]);
+
+        self.cpu_write(0x4004, channel_data[0x4]);
+        self.cpu_write(0x4005, channel_data[0x5]);
+        self.cpu_write(0x4006, channel_data[0x6]);
+        self.cpu_write(0x4007, channel_data[0x7]);
+
+        self.cpu_write(0x4008, channel_data[0x8]);
+        self.cpu_write(0x400A, channel_data[0xA]);
+        self.cpu_write(0x400B, channel_data[0xB]);
+
+        self.cpu_write(0x400C, channel_data[0xC]);
+        self.cpu_write(0x400E, channel_data[0xE]);
+        self.cpu_write(0x400F, channel_data[0xF]);
+
+        self.cpu_write(0x4015, state.channel_enables);
+        // Writing $4017 would clock the frame units and restart the sequencer, so set the mode
+        // directly.
+        self.disable_frame_interrupt = state.frame_mode & 0x01 != 0;
+        self.use_five_frame_sequence = state.frame_mode & 0x02 != 0;
+        self.frame_counter_reset_delay = 0;
+
+        (
+            self.pulse_1.timer,
+            self.pulse_2.timer,
+            self.triangle.timer,
+            self.noise.timer,
+        ) = timers;
+        (
+            self.pulse_1.envelope.start_flag,
+            self.pulse_2.envelope.start_flag,
+            self.noise.envelope.start_flag,
+        ) = start_flags;
+        (
+            self.pulse_1.sweep.reload_flag,
+            self.pulse_2.sweep.reload_flag,
+        ) = sweep_reload_flags;
+
+        self.noise.shift_register = state.noise_shift_register;
+        self.triangle.linear_counter_reload_flag = state.triangle_linear_counter_reload_flag;
+        self.triangle.linear_counter = state.triangle_linear_counter;
+
+        self.commit_length_counters();
+        self.pulse_1.length_counter.is_halted = state.pulse_1_envelope.mode & 0x02 != 0;
+        self.pulse_2.length_counter.is_halted = state.pulse_2_envelope.mode & 0x02 != 0;
+        self.noise.length_counter.is_halted = state.noise_envelope.mode & 0x02 != 0;
+
+        apply_envelope_state(&mut self.pulse_1.envelope, state.pulse_1_envelope);
+        apply_envelope_state(&mut self.pulse_2.envelope, state.pulse_2_envelope);
+        apply_envelope_state(&mut self.noise.envelope, state.noise_envelope);
+
+        apply_sweep_state(&mut self.pulse_1.sweep, state.pulse_1_sweep);
+        apply_sweep_state(&mut self.pulse_2.sweep, state.pulse_2_sweep);
+
+        self.pulse_1.length_counter.counter = state.pulse_1_length_counter;
+        self.pulse_2.length_counter.counter = state.pulse_2_length_counter;
+        self.triangle.length_counter.counter = state.triangle_length_counter;
+        self.noise.length_counter.counter = state.noise_length_counter;
+
+        fn apply_envelope_state(target: &mut Envelope, source: ApuEnvelopeState) {
+            // These are 4-bit counters, so keep a corrupt savestate from pushing them past 15.
+            target.divider_reload = source.divider_reload & 0x0F;
+            target.divider = source.divider.min(16);
+            target.constant_volume_flag = source.mode & 0x01 != 0;
+            target.decay_level = source.decay_level & 0x0F;
+        }
+
+        fn apply_sweep_state(target: &mut Sweep, source: ApuSweepState) {
+            target.is_enabled = source.is_enabled;
+            target.target_period = source.target_period;
+            target.divider = source.divider;
+        }
+    }
+
+    fn save_state_into(&self, buffer: &mut Vec<u8>) {
+        use crate::savestate::serialize_into;
+
+        let state = self.state();
+        let (pulse_1, pulse_2, noise) = (
+            &state.pulse_1_envelope,
+            &state.pulse_2_envelope,
+            &state.noise_envelope,
+        );
+
+        serialize_into(buffer, &state.channel_data, "PSG");
+        serialize_into(buffer, &state.channel_enables, "ENCH");
+        serialize_into(buffer, &state.frame_mode, "IQFM");
+        serialize_into(buffer, &state.noise_shift_register, "NREG");
+        serialize_into(buffer, &state.triangle_linear_counter_reload_flag, "TRIM");
+        serialize_into(buffer, &state.triangle_linear_counter, "TRIC");
+
+        serialize_into(buffer, &pulse_1.divider_reload, "E0SP");
+        serialize_into(buffer, &pulse_2.divider_reload, "E1SP");
+        serialize_into(buffer, &noise.divider_reload, "E2SP");
+
+        serialize_into(buffer, &pulse_1.mode, "E0MO");
+        serialize_into(buffer, &pulse_2.mode, "E1MO");
+        serialize_into(buffer, &noise.mode, "E2MO");
+
+        serialize_into(buffer, &pulse_1.divider, "E0D1");
+        serialize_into(buffer, &pulse_2.divider, "E1D1");
+        serialize_into(buffer, &noise.divider, "E2D1");
+
+        serialize_into(buffer, &pulse_1.decay_level, "E0DV");
+        serialize_into(buffer, &pulse_2.decay_level, "E1DV");
+        serialize_into(buffer, &noise.decay_level, "E2DV");
+
+        serialize_into(buffer, &(state.pulse_1_length_counter as u32), "LEN0");
+        serialize_into(buffer, &(state.pulse_2_length_counter as u32), "LEN1");
+        serialize_into(buffer, &(state.triangle_length_counter as u32), "LEN2");
+        serialize_into(buffer, &(state.noise_length_counter as u32), "LEN3");
+
+        serialize_into(
+            buffer,
+            &[
+                state.pulse_1_sweep.is_enabled,
+                state.pulse_2_sweep.is_enabled,
+            ],
+            "SWEE",
+        );
+
+        serialize_into(buffer, &(state.pulse_1_sweep.target_period as u32), "CRF1");
+        serialize_into(buffer, &(state.pulse_2_sweep.target_period as u32), "CRF2");
+
+        serialize_into(
+            buffer,
+            &[state.pulse_1_sweep.divider, state.pulse_2_sweep.divider],
+            "SWCT",
+        );
+    }
+}
+
+struct PulseChannel {
+    envelope: Envelope,
+    sweep: Sweep,
+
+    is_enabled: bool,
+    duty_cycle: u8,
+    timer: u16,
+    timer_reload: u16,
+    sequence_counter: u8,
+    length_counter: LengthCounter,
+    output: i16,
+}
+
+impl PulseChannel {
+    pub fn new(pulse_unit: u8) -> Self {
+        Self {
+            envelope: Envelope::new(),
+            sweep: Sweep::new(pulse_unit),
+
+            is_enabled: false,
+            duty_cycle: 0b00000001,
+            timer: 0,
+            timer_reload: 0,
+            sequence_counter: 0,
+            length_counter: LengthCounter::default(),
+            output: 0,
+        }
+    }
+
+    pub fn clock(&mut self) {
+        self.timer = self.timer.wrapping_sub(1) & 0x07FF;
+        if self.timer == 0x07FF {
+            let bit_mux = 0x80 >> self.sequence_counter;
+            let sample = if (self.duty_cycle & bit_mux) != 0 {
+                VOLUME
+            } else {
+                -VOLUME
+            };
+            let sample = if self.timer_reload > 8 { sample } else { 0 };
+            self.output = sample;
+            if self.sequence_counter > 0 {
+                self.sequence_counter -= 1;
+            } else {
+                self.sequence_counter = 7;
+            }
+            self.timer = self.timer_reload + 1;
+        }
+        if !self.length_counter.is_active() {
+            self.output = 0;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock(self.length_counter.is_halted);
+    }
+
+    pub fn clock_sweep(&mut self) {
+        self.sweep.clock(self.timer_reload);
+        // A period past 11 bits would mute the channel on hardware, rather than being used.
+        if self.sweep.target_period <= 0x07FF {
+            self.timer_reload = self.sweep.target_period;
+        }
+    }
+
+    pub fn output(&self) -> i16 {
+        (self.output as f32 * (self.envelope.output_volume as f32 / 15.0)) as i16
+    }
+}
+
+impl Default for PulseChannel {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+#[derive(Default)]
+struct TriangleChannel {
+    is_enabled: bool,
+    timer: u16,
+    timer_reload: u16,
+    sequence_counter: u8,
+    length_counter: LengthCounter,
+    linear_counter: u8,
+    linear_counter_reload: u8,
+    linear_counter_reload_flag: bool,
+    output: i16,
+}
+
+impl TriangleChannel {
+    pub fn clock(&mut self) {
+        self.timer = self.timer.wrapping_sub(1) & 0x07FF;
+        if self.timer == 0x07FF {
+            let sample = if self.sequence_counter > 15 {
+                let value = (self.sequence_counter - 16) as i16 - 8;
+                (value as f32 / 15.0) * (VOLUME * 2) as f32
+            } else {
+                let value = (15 - self.sequence_counter) as i16 - 8;
+                (value as f32 / 15.0) * (VOLUME * 2) as f32
+            } as i16;
+            // Prevent ultrasonic frequencies from being played.
+            let sample = if self.timer_reload > 2 { sample } else { 0 };
+            self.output = sample;
+            if self.linear_counter > 0 && self.length_counter.is_active() {
+                if self.sequence_counter < 31 {
+                    self.sequence_counter += 1;
+                } else {
+                    self.sequence_counter = 0;
+                }
+            }
+            self.timer = self.timer_reload + 1;
+        }
+        if !self.length_counter.is_active() {
+            self.output = 0;
+        }
+    }
+
+    pub fn clock_linear_counter(&mut self) {
+        if self.linear_counter_reload_flag {
+            self.linear_counter = self.linear_counter_reload;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.length_counter.is_halted {
+            self.linear_counter_reload_flag = false;
+        }
+    }
+}
+
+struct NoiseChannel {
+    envelope: Envelope,
+
+    is_enabled: bool,
+    timer: u16,
+    timer_reload: u16,
+    length_counter: LengthCounter,
+    mode_flag: bool,
+    shift_register: u16,
+    output: i16,
+}
+
+impl NoiseChannel {
+    pub fn new() -> Self {
+        Self {
+            envelope: Envelope::new(),
+            is_enabled: false,
+            timer: 0,
+            timer_reload: 0,
+            length_counter: LengthCounter::default(),
+            mode_flag: false,
+            shift_register: 0b000000000000001,
+            output: 0,
+        }
+    }
+
+    pub fn clock(&mut self) {
+        self.timer = self.timer.wrapping_sub(1);
+        if self.timer == 0xFFFF {
+            let sample = if self.shift_register & 0x01 != 0 {
+                0
+            } else {
+                VOLUME
+            };
+            self.output = sample;
+            let feedback = (self.shift_register & 0x01)
+                ^ if self.mode_flag {
+                    (self.shift_register >> 6) & 0x01
+                } else {
+                    (self.shift_register >> 1) & 0x01
+                };
+            self.shift_register >>= 1;
+            self.shift_register |= feedback << 14;
+            self.timer = self.timer_reload + 1;
+        }
+        if !self.length_counter.is_active() {
+            self.output = 0;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock(self.length_counter.is_halted);
+    }
+
+    pub fn output(&self) -> i16 {
+        (self.output as f32 * (self.envelope.output_volume as f32 / 15.0)) as i16
+    }
+}
+
+impl Default for NoiseChannel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Silences a channel once a set amount of time has passed, unless halted.
+///
+/// Writes to the halt flag and length take effect at the end of the CPU cycle, after the frame
+/// counter has clocked the counter. A length written on the same cycle that the counter is clocked
+/// is ignored, unless the counter was already 0.
+///
+/// See <https://www.nesdev.org/wiki/APU_Length_Counter>.
+#[derive(Default)]
+struct LengthCounter {
+    counter: u8,
+    is_halted: bool,
+    pending_halt: Option<bool>,
+    /// A length written this cycle, along with the counter's value at the time.
+    pending_reload: Option<(u8, u8)>,
+}
+
+impl LengthCounter {
+    fn clock(&mut self) {
+        if self.counter > 0 && !self.is_halted {
+            self.counter -= 1;
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.counter > 0
+    }
+
+    fn write_halt(&mut self, is_halted: bool) {
+        self.pending_halt = Some(is_halted);
+    }
+
+    /// Loads a new length, which is ignored while the channel is disabled.
+    fn reload(&mut self, length: u8, is_enabled: bool) {
+        if is_enabled {
+            self.pending_reload = Some((length, self.counter));
+        }
+    }
+
+    /// Disabling a channel through $4015 clears its length counter immediately.
+    fn set_enabled(&mut self, is_enabled: bool) {
+        if !is_enabled {
+            self.counter = 0;
+            self.pending_reload = None;
+        }
+    }
+
+    fn commit(&mut self) {
+        if let Some((length, previous)) = self.pending_reload.take() {
+            if self.counter == previous {
+                self.counter = length;
+            }
+        }
+        if let Some(is_halted) = self.pending_halt.take() {
+            self.is_halted = is_halted;
+        }
+    }
+}
+
+#[derive(Default)]
+struct Envelope {
+    divider: u8,
+    divider_reload: u8,
+    decay_level: u8,
+    start_flag: bool,
+    constant_volume_flag: bool,
+    output_volume: u8,
+}
+
+impl Envelope {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn clock(&mut self, loop_flag: bool) {
+        if !self.start_flag {
+            if self.divider > 0 {
+                self.divider -= 1;
+            }
+        } else {
+            self.start_flag = false;
+            self.decay_level = 15;
+            self.divider = self.divider_reload + 1;
+        }
+        if self.divider == 0 {
+            self.divider = self.divider_reload + 1;
+            if self.decay_level > 0 {
+                self.decay_level -= 1;
+            } else if loop_flag {
+                self.decay_level = 15;
+            }
+        }
+        self.output_volume = if self.constant_volume_flag {
+            self.divider_reload
+        } else {
+            self.decay_level
+        };
+    }
+
+    /// Checks the envelope for [Apu::validate_invariants], naming it after its channel.
+    fn validate_invariants(&self, channel: &str) -> Result<(), String> {
+        if self.divider_reload > 15 || self.divider > 16 {
+            Err(format!("{channel} envelope period is over 15"))
+        } else if self.decay_level > 15 || self.output_volume > 15 {
+            Err(format!("{channel} envelope volume is over 15"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+struct Sweep {
+    pulse_unit: u8,
+    is_enabled: bool,
+    divider: u8,
+    divider_reload: u8,
+    shift_count: u8,
+    negate_flag: bool,
+    reload_flag: bool,
+    target_period: u16,
+}
+
+impl Sweep {
+    pub fn new(pulse_unit: u8) -> Self {
+        Self {
+            pulse_unit,
+            is_enabled: false,
+            divider: 0,
+            divider_reload: 0,
+            shift_count: 0,
+            negate_flag: false,
+            reload_flag: false,
+            target_period: 0,
+        }
+    }
+
+    pub fn clock(&mut self, period: u16) {
+        self.divider = self.divider.wrapping_sub(1);
+        if self.divider == 0xFF {
+            let change_amount = (period >> self.shift_count) as i16;
+            let change_amount = if self.negate_flag {
+                if self.pulse_unit == 1 {
+                    -change_amount - 1
+                } else {
+                    -change_amount
+                }
+            } else {
+                change_amount
+            };
+            self.target_period = period.saturating_add_signed(change_amount);
+        }
+        if !self.is_enabled {
+            self.target_period = period;
+        }
+        if self.divider == 0xFF || self.reload_flag {
+            self.divider = self.divider_reload + 1;
+            self.reload_flag = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resamplers() {
+        let mut buffers = Vec::new();
+        for resampler in [Resampler::Nearest, Resampler::Linear, Resampler::Sinc] {
+            let mut apu = Apu::new();
+            apu.set_resampler(resampler);
+            apu.expansion_output = Some(0);
+            for _ in 0..10_000 {
+                apu.clock();
+            }
+            // A step in the output, which the sinc filter should settle at the same level after.
+            apu.expansion_output = Some(i16::MAX / 2);
+            for _ in 0..20_000 {
+                apu.clock();
+            }
+            buffers.push(apu.drain_audio_buffer());
+        }
+
+        let (nearest, linear, sinc) = (&buffers[0], &buffers[1], &buffers[2]);
+        assert_eq!(nearest.len(), linear.len());
+        assert_eq!(nearest.len(), sinc.len());
+        let last = nearest.last().unwrap();
+        assert!(*last > 0.0);
+        assert!((linear.last().unwrap() - last).abs() < 1e-4);
+        assert!((sinc.last().unwrap() - last).abs() < 1e-4);
+    }
+
+    #[test]
+    fn sweep_overflow() {
+        // A sweep with no shift would double the period every half frame, past 11 bits.
+        let mut apu = Apu::new();
+        apu.cpu_write(0x4015, 0x01);
+        apu.cpu_write(0x4001, 0x80);
+        apu.cpu_write(0x4002, 0xFF);
+        apu.cpu_write(0x4003, 0x03);
+        for _ in 0..100_000 {
+            apu.clock();
+        }
+        assert_eq!(apu.validate_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn length_counter_writes() {
+        /// The value of the frame counter's timer on the cycle it clocks the length counters.
+        const HALF_FRAME: usize = 7456 * 2 + 1;
+
+        let mut apu = Apu::new();
+        apu.cpu_write(0x4000, 0x0F);
+
+        // Lengths written while a channel is disabled are ignored, and enabling it doesn't reload.
+        apu.cpu_write(0x4003, 0x08);
+        apu.cpu_write(0x4015, 0x01);
+        apu.clock();
+        assert_eq!(apu.cpu_read(0x4015), 0x00);
+        apu.cpu_write(0x4003, 0x08);
+        apu.clock();
+        assert_eq!(apu.cpu_read(0x4015), 0x01);
+        assert_eq!(apu.pulse_1.length_counter.counter, 254);
+
+        // A length written as the counter is clocked is ignored...
+        apu.clock_timer = HALF_FRAME;
+        apu.cpu_write(0x4003, 0x18);
+        apu.clock();
+        assert_eq!(apu.pulse_1.length_counter.counter, 253);
+
+        // ...unless the counter was already 0.
+        apu.cpu_write(0x4015, 0x00);
+        assert_eq!(apu.cpu_read(0x4015), 0x00);
+        apu.cpu_write(0x4015, 0x01);
+        apu.clock_timer = HALF_FRAME;
+        apu.cpu_write(0x4003, 0x18);
+        apu.clock();
+        assert_eq!(apu.pulse_1.length_counter.counter, 2);
+
+        // Halting takes effect after the counter is clocked on the same cycle.
+        apu.clock_timer = HALF_FRAME;
+        apu.cpu_write(0x4000, 0x2F);
+        apu.clock();
+        assert_eq!(apu.pulse_1.length_counter.counter, 1);
+        apu.clock_timer = HALF_FRAME;
+        apu.clock();
+        assert_eq!(apu.pulse_1.length_counter.counter, 1);
+
+        // And so does unhalting.
+        apu.clock_timer = HALF_FRAME;
+        apu.cpu_write(0x4000, 0x0F);
+        apu.clock();
+        assert_eq!(apu.pulse_1.length_counter.counter, 1);
+    }
+
+    #[test]
+    fn frame_counter_write() {
+        let mut apu = Apu::new();
+        apu.cpu_write(0x4015, 0x01);
+        apu.cpu_write(0x4000, 0x0F);
+        apu.cpu_write(0x4003, 0x08);
+        apu.clock();
+        assert_eq!(apu.pulse_1.length_counter.counter, 254);
+
+        // Selecting the 5-step sequence clocks the quarter and half frame units immediately.
+        apu.cpu_write(0x4017, 0x80);
+        assert_eq!(apu.pulse_1.length_counter.counter, 253);
+        apu.cpu_write(0x4017, 0x00);
+        assert_eq!(apu.pulse_1.length_counter.counter, 253);
+
+        for (clock_timer, delay) in [(100, 3), (101, 4)] {
+            apu.clock_timer = clock_timer;
+            apu.cpu_write(0x4017, 0x00);
+            for _ in 1..delay {
+                apu.clock();
+                assert_ne!(apu.clock_timer, 0);
+            }
+            apu.clock();
+            assert_eq!(apu.clock_timer, 0);
+        }
+    }
+
+    #[test]
+    fn samples_per_frame() {
+        let mut apu = Apu::new();
+        let mut counts = Vec::new();
+        // 10 NTSC frames of 29780.5 cycles each.
+        for frame in 0..10 {
+            for _ in 0..29780 + frame % 2 {
+                apu.clock();
+            }
+            counts.push(apu.drain_audio_buffer().len());
+        }
+
+        assert_eq!(counts.iter().sum::<usize>(), 7337);
+        assert!(counts.iter().all(|&count| count == 733 || count == 734));
+    }
+
+    #[test]
+    fn fast_forward_audio() {
+        let mut apu = Apu::new();
+        apu.set_speed(4);
+        for _ in 0..8 {
+            apu.push_sample(0.5);
+        }
+        assert_eq!(apu.drain_audio_buffer(), [0.5, 0.5]);
+
+        apu.set_fast_forward_audio(FastForwardAudio::Mute);
+        for _ in 0..441 * 4 {
+            apu.push_sample(0.5);
+        }
+        let samples = apu.drain_audio_buffer();
+        assert_eq!(samples.len(), 441);
+        assert!(samples.windows(2).all(|pair| pair[1] < pair[0]));
+        assert_eq!(samples[440], 0.0);
+
+        apu.set_speed(1);
+        apu.push_sample(0.5);
+        assert!(apu.drain_audio_buffer()[0] > 0.0);
+    }
+
+    #[test]
+    fn buffer_watermarks() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let mut apu = Apu::new();
+        let reached = Rc::new(RefCell::new(Vec::new()));
+        let callback_reached = reached.clone();
+        apu.set_buffer_watermarks(2, 4, move |watermark| {
+            callback_reached.borrow_mut().push(watermark)
+        });
+
+        for _ in 0..6 {
+            apu.push_sample(0.0);
+        }
+        // The first sample leaves the buffer at the low watermark, and the rest fill it past the
+        // high one, which is only reported once.
+        assert_eq!(
+            *reached.borrow(),
+            [BufferWatermark::Low, BufferWatermark::High]
+        );
+        apu.drain_audio_buffer();
+        apu.push_sample(0.0);
+        assert_eq!(reached.borrow().len(), 3);
+        assert_eq!(reached.borrow()[2], BufferWatermark::Low);
+
+        apu.clear_buffer_watermarks();
+        for _ in 0..6 {
+            apu.push_sample(0.0);
+        }
+        assert_eq!(reached.borrow().len(), 3);
+    }
+
+    #[test]
+    fn mixing() {
+        let mut apu = Apu::new();
+        for pulse in [&mut apu.pulse_1, &mut apu.pulse_2] {
+            pulse.output = 1000;
+            pulse.envelope.output_volume = 15;
+        }
+        apu.triangle.output = 2000;
+        apu.noise.output = 3000;
+        apu.noise.envelope.output_volume = 15;
+        let scale = i16::MAX as f32;
+        assert_eq!(apu.mix(), 7000.0 / scale);
+        // The expansion gain doesn't matter until the cartridge produces audio.
+        apu.gains.expansion = 0.5;
+        assert_eq!(apu.mix(), 7000.0 / scale);
+        apu.expansion_output = Some(-4000);
+        assert_eq!(apu.mix(), 5000.0 / scale);
+
+        apu.gains = ChannelGains {
+            pulse_1: 0.5,
+            pulse_2: 0.0,
+            triangle: 2.0,
+            noise: 1.0,
+            expansion: 1.0,
+        };
+        assert_eq!(apu.mix(), (500.0 + 4000.0 + 3000.0 - 4000.0) / scale);
+        // Disabling a channel drops it from the mix whatever its gain.
+        apu.is_triangle_enabled = false;
+        assert_eq!(apu.mix(), -500.0 / scale);
+
+        // Gains are presentation settings, so savestates leave them alone.
+        let mut other = Apu::new();
+        other.gains.noise = 0.25;
+        other.apply_state(apu.state());
+        assert_eq!(other.gains.noise, 0.25);
+        assert_eq!(other.expansion_output, None);
+    }
+
+    #[test]
+    fn declicking() {
+        let mut apu = Apu::new();
+        apu.expansion_output = Some(i16::MAX);
+        let run = |apu: &mut Apu, samples: usize| {
+            while apu.audio_buffer_length() < samples {
+                apu.clock();
+            }
+            apu.drain_audio_buffer()
+        };
+        // Jumping from silence when the game starts producing sound isn't smoothed.
+        assert_eq!(run(&mut apu, 10)[9], 1.0);
+
+        // Muting a channel ramps it down rather than cutting it off.
+        apu.gains.expansion = 0.0;
+        let samples = run(&mut apu, 2000);
+        assert!(samples[0] > 0.99);
+        assert!(samples.windows(2).all(|pair| pair[1] < pair[0]));
+        assert!(samples[1999] < 0.01);
+    }
+
+    #[test]
+    fn reset_ramp() {
+        let run = |apu: &mut Apu, samples: usize| {
+            while apu.audio_buffer_length() < samples {
+                apu.clock();
+            }
+        };
+        // After a reset the game's startup code silences the channel and starts playing again,
+        // while powering off takes the cartridge's audio away straight after.
+        for is_power_off in [false, true] {
+            let mut apu = Apu::new();
+            apu.expansion_output = Some(i16::MAX);
+            run(&mut apu, 100);
+            apu.drain_audio_buffer();
+
+            apu.smooth_reset();
+            if is_power_off {
+                apu.expansion_output = None;
+            } else {
+                run(&mut apu, 10);
+                apu.expansion_output = Some(0);
+                run(&mut apu, 1000);
+                apu.expansion_output = Some(i16::MAX);
+            }
+            run(&mut apu, 4000);
+            let samples = apu.drain_audio_buffer();
+
+            assert!(samples[0] > 0.99);
+            assert_eq!(samples[1000], 0.0);
+            let max_step = samples
+                .windows(2)
+                .map(|pair| (pair[1] - pair[0]).abs())
+                .fold(0.0, f32::max);
+            assert!(max_step <= FADE_STEP * 1.01, "{max_step}");
+            let last = samples[samples.len() - 1];
+            assert_eq!(last, if is_power_off { 0.0 } else { 1.0 });
+        }
+    }
+
+    #[test]
+    fn reload_state() {
+        let run = |frame_mode: u8, reload: bool| {
+            let mut apu = Apu::new();
+            apu.cpu_write(0x4015, 0x0F);
+            apu.cpu_write(0x4017, frame_mode);
+            apu.cpu_write(0x4000, 0x05);
+            apu.cpu_write(0x4001, 0x9A);
+            apu.cpu_write(0x4002, 0x40);
+            apu.cpu_write(0x4003, 0x18);
+            apu.cpu_write(0x4008, 0x20);
+            apu.cpu_write(0x400B, 0x28);
+            apu.cpu_write(0x400C, 0x03);
+            apu.cpu_write(0x400E, 0x02);
+            apu.cpu_write(0x400F, 0x30);
+            for _ in 0..20_000 {
+                apu.clock();
+            }
+            if reload {
+                apu.apply_state(apu.state());
+            }
+            for _ in 0..50_000 {
+                apu.clock();
+            }
+            apu.save_state()
+        };
+
+        // Reloading the APU's own state mid-run shouldn't change what happens next.
+        for frame_mode in [0x00, 0x80] {
+            assert_eq!(run(frame_mode, true), run(frame_mode, false));
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use crate::FrameTiming;
+
+/// The rate VGM files count time in, in samples per second, whatever rate they're played at.
+const VGM_SAMPLE_RATE: f64 = 44100.0;
+/// The size of a VGM 1.71 header, after which the commands start.
+const VGM_HEADER_SIZE: usize = 0x100;
+
+/// A write to one of the APU's registers, as recorded by [crate::Bus::set_apu_logging], for
+/// ripping a game's music without having to trace the CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApuWrite {
+    /// The frame the write happened during, as returned by [crate::Ppu::frame_count].
+    pub frame: u64,
+    /// The CPU cycle the write happened on, as returned by [crate::Bus::cycle].
+    pub cycle: usize,
+    pub addr: u16,
+    pub value: u8,
+}
+
+impl ApuWrite {
+    /// Parses a write from the line format written by its [std::fmt::Display] implementation.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let invalid = || format!("`{line}` is not a valid APU write");
+        let mut fields = line.split_whitespace();
+        let mut next = || fields.next().ok_or_else(invalid);
+        let frame = next()?.parse().map_err(|_| invalid())?;
+        let cycle = next()?.parse().map_err(|_| invalid())?;
+        let addr = next()?.strip_prefix('$').ok_or_else(invalid)?;
+        let addr = u16::from_str_radix(addr, 16).map_err(|_| invalid())?;
+        let value = next()?.strip_prefix('$').ok_or_else(invalid)?;
+        let value = u8::from_str_radix(value, 16).map_err(|_| invalid())?;
+
+        Ok(Self {
+            frame,
+            cycle,
+            addr,
+            value,
+        })
+    }
+}
+
+/// Formats the write as a line of `frame cycle $addr $value`, with the address and value in
+/// hexadecimal, such as `12 340510 $4000 $BF`.
+impl std::fmt::Display for ApuWrite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} ${:04X} ${:02X}",
+            self.frame, self.cycle, self.addr, self.value
+        )
+    }
+}
+
+/// Encodes APU register writes as a VGM file, so a game's music can be played back in VGM
+/// players without the game.
+///
+/// Writes are timed by their CPU cycles, counting from the first write, at the CPU clock rate of
+/// `timing`. Only writes to $4000-$401F are included, as VGM's NES APU commands don't cover
+/// expansion audio. DMC samples live in CPU memory, which isn't captured, so the DMC channel
+/// won't play back.
+pub fn to_vgm(writes: &[ApuWrite], timing: FrameTiming) -> Vec<u8> {
+    let mut vgm = vec![0; VGM_HEADER_SIZE];
+    let start = writes.first().map_or(0, |write| write.cycle);
+    let samples_per_cycle = VGM_SAMPLE_RATE / timing.cpu_clock_rate();
+    let mut total_samples = 0;
+    for write in writes
+        .iter()
+        .filter(|write| (0x4000..=0x401F).contains(&write.addr))
+    {
+        let sample = (write.cycle.saturating_sub(start) as f64 * samples_per_cycle) as u32;
+        push_vgm_wait(&mut vgm, sample.saturating_sub(total_samples));
+        total_samples = total_samples.max(sample);
+        vgm.extend_from_slice(&[0xB4, (write.addr - 0x4000) as u8, write.value]);
+    }
+    // End of sound data.
+    vgm.push(0x66);
+
+    let eof_offset = (vgm.len() - 0x04) as u32;
+    let mut header = |offset: usize, value: u32| {
+        vgm[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    };
+    header(0x04, eof_offset);
+    header(0x08, 0x171);
+    header(0x18, total_samples);
+    header(0x24, timing.frame_rate().round() as u32);
+    // The data offset is relative to its own position.
+    header(0x34, (VGM_HEADER_SIZE - 0x34) as u32);
+    header(0x84, timing.cpu_clock_rate().round() as u32);
+    vgm[0x00..0x04].copy_from_slice(b"Vgm ");
+    vgm
+}
+
+/// Appends commands that wait for the given number of samples, using the shortest ones possible.
+fn push_vgm_wait(vgm: &mut Vec<u8>, mut samples: u32) {
+    while samples > 0 {
+        let wait = samples.min(0xFFFF);
+        match wait {
+            // One NTSC or PAL frame.
+            735 => vgm.push(0x62),
+            882 => vgm.push(0x63),
+            1..=16 => vgm.push(0x70 + wait as u8 - 1),
+            _ => {
+                vgm.push(0x61);
+                vgm.extend_from_slice(&(wait as u16).to_le_bytes());
+            }
+        }
+        samples -= wait;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let write = ApuWrite {
+            frame: 12,
+            cycle: 340510,
+            addr: 0x4000,
+            value: 0xBF,
+        };
+        assert_eq!(write.to_string(), "12 340510 $4000 $BF");
+        assert_eq!(ApuWrite::parse(&write.to_string()), Ok(write));
+        assert!(ApuWrite::parse("12 340510 4000 $BF").is_err());
+        assert!(ApuWrite::parse("12 340510 $4000").is_err());
+    }
+
+    #[test]
+    fn vgm() {
+        let write = |cycle, addr, value| ApuWrite {
+            frame: 0,
+            cycle,
+            addr,
+            value,
+        };
+        let timing = FrameTiming::NTSC;
+        let second = timing.cpu_clock_rate().round() as usize;
+        let writes = [
+            write(100, 0x4000, 0xBF),
+            // Expansion audio has no VGM command, so it's left out.
+            write(200, 0x4080, 0x80),
+            write(100 + second, 0x4015, 0x0F),
+            write(100 + second + 300, 0x4017, 0x40),
+        ];
+
+        let vgm = to_vgm(&writes, timing);
+        assert_eq!(&vgm[0x00..0x04], b"Vgm ");
+        assert_eq!(&vgm[0x04..0x08], &(vgm.len() as u32 - 4).to_le_bytes());
+        assert_eq!(&vgm[0x84..0x88], &1789773u32.to_le_bytes());
+        assert_eq!(
+            &vgm[VGM_HEADER_SIZE..],
+            [
+                0xB4, 0x00, 0xBF, // $4000
+                0x61, 0x44, 0xAC, // 44100 samples
+                0xB4, 0x15, 0x0F, // $4015
+                0x76, // 7 samples
+                0xB4, 0x17, 0x40, // $4017
+                0x66,
+            ]
+        );
+        assert_eq!(&vgm[0x18..0x1C], &44107u32.to_le_bytes());
+    }
+}
@@ -0,0 +1,246 @@
+use std::{io::Write, time::Duration};
+
+/// The rate at which the APU produces samples.
+pub const SAMPLE_RATE: u32 = 44100;
+
+/// A destination for the mono samples produced by the APU.
+pub trait AudioSink {
+    /// Queues samples for playback.
+    fn push_samples(&mut self, samples: &[f32]);
+
+    /// Returns the number of samples that have been queued but not yet played.
+    fn queued_samples(&self) -> usize;
+
+    /// Returns how long it will take to play all currently queued samples.
+    fn latency(&self) -> Duration {
+        Duration::from_secs_f64(self.queued_samples() as f64 / SAMPLE_RATE as f64)
+    }
+}
+
+/// A threshold crossed by the number of samples waiting in an audio buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferWatermark {
+    /// The buffer has drained down to the low watermark, so emulation should run ahead to keep
+    /// audio from cutting out.
+    Low,
+    /// The buffer has filled up to the high watermark, so emulation can wait for it to play.
+    High,
+}
+
+/// Tracks an audio buffer's fill level against a low and a high watermark, so that a frontend can
+/// pace emulation by how much audio is waiting instead of by a fixed threshold.
+///
+/// Each watermark is only reported when it's first reached, and not again until the other one has
+/// been, so the level can move around between them without reporting anything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferWatermarks {
+    low: usize,
+    high: usize,
+    last: Option<BufferWatermark>,
+}
+
+impl BufferWatermarks {
+    /// Creates a tracker for the given watermarks, in samples. `high` is raised to `low` if it's
+    /// below it.
+    pub fn new(low: usize, high: usize) -> Self {
+        Self {
+            low,
+            high: high.max(low),
+            last: None,
+        }
+    }
+
+    pub fn low(&self) -> usize {
+        self.low
+    }
+
+    pub fn high(&self) -> usize {
+        self.high
+    }
+
+    /// Returns the watermark most recently reached, or `None` if neither has been yet.
+    pub fn last(&self) -> Option<BufferWatermark> {
+        self.last
+    }
+
+    /// Checks the buffer's current fill level, returning the watermark it's just reached, if any.
+    pub fn update(&mut self, level: usize) -> Option<BufferWatermark> {
+        let reached = if level >= self.high {
+            BufferWatermark::High
+        } else if level <= self.low {
+            BufferWatermark::Low
+        } else {
+            return None;
+        };
+        if self.last == Some(reached) {
+            return None;
+        }
+        self.last = Some(reached);
+        Some(reached)
+    }
+}
+
+/// Converts samples from [SAMPLE_RATE] to the rate an output device plays at, for devices that
+/// can't be opened at [SAMPLE_RATE] itself, by interpolating linearly between neighboring samples.
+#[derive(Debug, Clone)]
+pub struct RateConverter {
+    output_rate: u32,
+    /// How far the next output sample is past `previous`, in units of `1 / output_rate` input
+    /// samples, so that it never drifts.
+    position: u64,
+    /// The last input sample from the previous call, which the next output sample may fall after.
+    previous: f32,
+}
+
+impl RateConverter {
+    pub fn new(output_rate: u32) -> Self {
+        Self {
+            output_rate,
+            position: 0,
+            previous: 0.0,
+        }
+    }
+
+    pub fn output_rate(&self) -> u32 {
+        self.output_rate
+    }
+
+    /// Converts `samples`, which follow on from the ones passed to the previous call, appending
+    /// the result to `output`.
+    pub fn convert(&mut self, samples: &[f32], output: &mut Vec<f32>) {
+        let Some(&last) = samples.last() else {
+            return;
+        };
+        let rate = self.output_rate as u64;
+        let end = samples.len() as u64 * rate;
+        while self.position < end {
+            let index = (self.position / rate) as usize;
+            let before = match index {
+                0 => self.previous,
+                index => samples[index - 1],
+            };
+            let fraction = (self.position % rate) as f32 / rate as f32;
+            output.push(before + (samples[index] - before) * fraction);
+            self.position += SAMPLE_RATE as u64;
+        }
+        self.position -= end;
+        self.previous = last;
+    }
+}
+
+/// Collects samples in memory so that they can be written out as a WAV file.
+#[derive(Debug, Default)]
+pub struct WavRecorder {
+    samples: Vec<f32>,
+}
+
+impl WavRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    /// Writes the recorded samples as a 16-bit mono PCM WAV file.
+    pub fn write(&self, mut writer: impl Write) -> Result<(), String> {
+        let data_size = u32::try_from(self.samples.len() * 2)
+            .map_err(|_| "recording too long for a WAV file")?;
+
+        let mut bytes = Vec::with_capacity(44 + data_size as usize);
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_size).to_le_bytes());
+        bytes.extend_from_slice(b"WAVE");
+        bytes.extend_from_slice(b"fmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        // PCM format, 1 channel.
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        // Byte rate, block alignment, and bits per sample.
+        bytes.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_size.to_le_bytes());
+        for &sample in &self.samples {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+
+        writer.write_all(&bytes).map_err(|err| err.to_string())
+    }
+}
+
+impl AudioSink for WavRecorder {
+    fn push_samples(&mut self, samples: &[f32]) {
+        self.samples.extend_from_slice(samples);
+    }
+
+    /// Samples are consumed as soon as they're pushed, so there's never any queue.
+    fn queued_samples(&self) -> usize {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wav_recorder() {
+        let mut recorder = WavRecorder::new();
+        recorder.push_samples(&[0.0, 1.0]);
+        recorder.push_samples(&[-1.0, 2.0]);
+        assert_eq!(recorder.samples(), [0.0, 1.0, -1.0, 2.0]);
+        assert_eq!(recorder.queued_samples(), 0);
+
+        let mut wav = Vec::new();
+        recorder.write(&mut wav).unwrap();
+        assert_eq!(wav.len(), 44 + 8);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(wav[4..8], (36u32 + 8).to_le_bytes());
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(wav[24..28], SAMPLE_RATE.to_le_bytes());
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(wav[40..44], 8u32.to_le_bytes());
+        // Samples outside the valid range are clipped.
+        let samples: Vec<_> = wav[44..]
+            .chunks(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect();
+        assert_eq!(samples, [0, i16::MAX, -i16::MAX, i16::MAX]);
+    }
+
+    #[test]
+    fn rate_conversion() {
+        let ramp: Vec<_> = (0..SAMPLE_RATE)
+            .map(|i| i as f32 / SAMPLE_RATE as f32)
+            .collect();
+        let mut converter = RateConverter::new(48000);
+        let mut output = Vec::new();
+        for chunk in ramp.chunks(735) {
+            converter.convert(chunk, &mut output);
+        }
+        // A second of input is a second of output, regardless of how it's split up.
+        assert_eq!(output.len(), 48000);
+        let mut whole = Vec::new();
+        RateConverter::new(48000).convert(&ramp, &mut whole);
+        assert_eq!(output, whole);
+        // Interpolating a straight line gives back points on it, one input sample behind, once
+        // past the silence the converter starts from.
+        for (i, &sample) in output.iter().enumerate().skip(2) {
+            let expected = (i as f32 * 44100.0 / 48000.0 - 1.0) / SAMPLE_RATE as f32;
+            assert!(
+                (sample - expected).abs() < 1e-6,
+                "{i}: {sample} != {expected}"
+            );
+        }
+
+        // Converting to the same rate only delays the samples.
+        let mut output = Vec::new();
+        RateConverter::new(SAMPLE_RATE).convert(&[0.25, 0.5, 0.75], &mut output);
+        assert_eq!(output, [0.0, 0.25, 0.5]);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::Controller;
+
+/// Sticky buttons for players who can't hold a button down, applied to a controller's input
+/// before it reaches the console.
+///
+/// While the toggle key is held, pressing a button latches it on, or releases it if it's already
+/// latched. Latched buttons stay pressed until they're released the same way. [AutoHold::apply]
+/// should be called before recording a replay, so that movies contain the input the game
+/// actually saw.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AutoHold {
+    buttons: u8,
+    held: u8,
+    previous: u8,
+}
+
+impl AutoHold {
+    /// Creates a transform that only latches the pressed buttons in `buttons`.
+    pub fn new(buttons: Controller) -> Self {
+        Self {
+            buttons: buttons.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the buttons that can be latched.
+    pub fn buttons(&self) -> Controller {
+        self.buttons.into()
+    }
+
+    /// Changes the buttons that can be latched, releasing any that no longer can be.
+    pub fn set_buttons(&mut self, buttons: Controller) {
+        self.buttons = buttons.into();
+        self.held &= self.buttons;
+    }
+
+    /// Returns the buttons currently latched on.
+    pub fn held(&self) -> Controller {
+        self.held.into()
+    }
+
+    pub fn release_all(&mut self) {
+        self.held = 0;
+    }
+
+    /// Transforms a frame of input, latching or releasing newly pressed buttons while
+    /// `is_toggle_held`. Buttons pressed to toggle a latch aren't passed through, so the game
+    /// doesn't see a tap when a button is released.
+    pub fn apply(&mut self, input: Controller, is_toggle_held: bool) -> Controller {
+        let input = u8::from(input);
+        let newly_pressed = input & !self.previous;
+        self.previous = input;
+
+        if is_toggle_held {
+            self.held ^= newly_pressed & self.buttons;
+            Controller::from(self.held | (input & !self.buttons))
+        } else {
+            Controller::from(self.held | input)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toggle() {
+        let mut auto_hold = AutoHold::new(Controller::new().with_a(true).with_b(true));
+        let a = Controller::new().with_a(true);
+        let start = Controller::new().with_start(true);
+
+        // Pressing A with the toggle held latches it.
+        assert_eq!(auto_hold.apply(a, true), a);
+        assert_eq!(auto_hold.apply(Controller::new(), false), a);
+        assert_eq!(auto_hold.held(), a);
+
+        // Pressing it again releases it, and keeping it held down doesn't flip it back.
+        assert_eq!(auto_hold.apply(a, true), Controller::new());
+        assert_eq!(auto_hold.apply(a, true), Controller::new());
+        assert_eq!(auto_hold.apply(Controller::new(), false), Controller::new());
+
+        // Buttons that can't be latched pass through as usual.
+        assert_eq!(auto_hold.apply(start, true), start);
+        assert_eq!(auto_hold.apply(Controller::new(), false), Controller::new());
+    }
+}
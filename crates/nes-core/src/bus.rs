@@ -0,0 +1,1610 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    apu_log::ApuWrite,
+    dma::{DmaAccess, DmaStats, OamDma},
+    ppu::PpuWarningKind,
+    savestate::{serialize, serialize_into, StateSerialize},
+    serial_device::{ControllerPort, SerialDevice},
+    AccuracyProfile, Apu, Cartridge, Controller, Cpu, FrameTiming, MemoryDevice, Ppu, Region,
+    Savestate, SavestateError, SavestateMetadata, SystemState, TestEvent, TestHook,
+};
+
+/// The maximum number of CPU cycles [Bus::run_until] will run before giving up, equal to roughly
+/// 10 NTSC frames.
+const MAX_RUN_CYCLES: usize = 29781 * 10;
+
+/// A point in emulation to stop at, used by [Bus::run_until].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopCondition {
+    /// The end of the current frame.
+    Frame,
+    /// The PPU reaching the given scanline and dot.
+    ///
+    /// The PPU is clocked 3 dots at a time (sometimes 4 on PAL consoles), so the PPU may end up a
+    /// few dots past the target.
+    Dot { scanline: u16, dot: u16 },
+    /// The CPU servicing the next NMI.
+    Nmi,
+    /// The CPU servicing the next IRQ.
+    Irq,
+    /// The [TestHook] reporting an event, or having one that hasn't been drained yet.
+    TestEvent,
+    /// The PPU setting the sprite 0 hit flag. [Ppu::sprite_zero_hit_position] has the exact
+    /// scanline and dot it was set on, as the PPU may have moved a few dots past it.
+    SpriteZeroHit,
+    /// The CPU reading either controller port.
+    InputRead,
+}
+
+/// How long [Bus::fast_boot] runs for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FastBoot {
+    /// Until the end of the first frame the game reads the controllers in, giving up after the
+    /// given number of frames for games that read them from the very start.
+    UntilInputRead { max_frames: u32 },
+    /// A fixed number of frames.
+    Frames(u32),
+}
+
+/// What handles accesses to a region of the CPU address space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryHandler {
+    /// The console's 2 KiB of internal RAM.
+    Ram,
+    /// The PPU's registers.
+    Ppu,
+    /// The APU's registers. These are write-only, apart from $4015.
+    Apu,
+    /// The OAM DMA register. This is write-only.
+    OamDma,
+    /// The controller ports. Writes to $4017 go to the APU's frame counter instead.
+    Controllers,
+    /// The cartridge's mapper.
+    Cartridge,
+    /// Nothing responds, so reads return the last value on the data bus.
+    OpenBus,
+    /// A device mapped with [Bus::map_device], with the name it was mapped under.
+    Device(&'static str),
+}
+
+/// A region of the CPU address space, as returned by [Bus::memory_map].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub start: u16,
+    pub end: u16,
+    pub handler: MemoryHandler,
+    /// The size of the memory or registers backing this region, if it's mirrored throughout the
+    /// region.
+    pub mirror_size: Option<u16>,
+}
+
+/// A device mapped into the CPU address space with [Bus::map_device].
+struct MappedDevice {
+    start: u16,
+    end: u16,
+    name: &'static str,
+    device: Box<dyn MemoryDevice>,
+}
+
+/// An interrupt serviced by the CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    Nmi,
+    Irq,
+}
+
+pub struct Bus {
+    cpu: Rc<RefCell<Cpu>>,
+    ram: Box<[u8; 2048]>,
+    ppu: Rc<RefCell<Ppu>>,
+    apu: Rc<RefCell<Apu>>,
+    cartridge: Rc<RefCell<Cartridge>>,
+    controller_1: Controller,
+    controller_1_state: Controller,
+    controller_2: Controller,
+    controller_2_state: Controller,
+    controller_strobe: bool,
+    is_microphone_active: bool,
+    /// The number of times either controller port has been read since power-on.
+    input_reads: u64,
+    /// Devices connected in place of the standard controllers on each port.
+    devices: [Option<Box<dyn SerialDevice>>; 2],
+    /// Devices mapped into the CPU address space, which take priority over the cartridge.
+    memory_devices: Vec<MappedDevice>,
+    /// Input from the frontend that will be latched at the start of the next frame.
+    next_controller_1: Controller,
+    next_controller_2: Controller,
+    next_microphone: bool,
+    is_input_latch_due: bool,
+
+    cycle: usize,
+    /// Master clocks left over after clocking the PPU, for regions where the PPU isn't clocked a
+    /// whole number of times per CPU cycle.
+    master_clock_remainder: u32,
+    oam_dma: OamDma,
+    /// DMA activity during the last complete frame, and during the current one.
+    dma_stats: DmaStats,
+    frame_dma_stats: DmaStats,
+    emit_irq: bool,
+    serviced_interrupt: Option<Interrupt>,
+    /// The last value driven onto the CPU data bus, returned by reads from unmapped addresses.
+    open_bus: u8,
+    /// Whether undriven bits read as [Bus::open_bus] rather than 0.
+    has_open_bus: bool,
+    accuracy_profile: AccuracyProfile,
+    /// Whether savestates made with a different ROM are turned away.
+    is_checking_savestate_rom: bool,
+    test_hook: Option<TestHook>,
+    test_events: Vec<TestEvent>,
+    /// Non-fatal problems found while running or loading savestates, oldest first.
+    warnings: Vec<String>,
+    /// Writes to APU registers since the log was last drained, if logging is enabled.
+    apu_log: Option<Vec<ApuWrite>>,
+    /// Plain RAM covering the whole CPU address space, replacing everything else on the bus, for
+    /// CPU tests written for a bare 6502.
+    #[cfg(test)]
+    pub(crate) flat_memory: Option<Box<[u8; 0x10000]>>,
+}
+
+impl Bus {
+    pub fn new(
+        cpu: Rc<RefCell<Cpu>>,
+        ram: Box<[u8; 2048]>,
+        ppu: Rc<RefCell<Ppu>>,
+        apu: Rc<RefCell<Apu>>,
+        cartridge: Rc<RefCell<Cartridge>>,
+    ) -> Rc<RefCell<Self>> {
+        let bus = Self {
+            cpu,
+            ram,
+            ppu,
+            apu,
+            cartridge,
+            controller_1: Controller::default(),
+            controller_1_state: Controller::default(),
+            controller_2: Controller::default(),
+            controller_2_state: Controller::default(),
+            controller_strobe: false,
+            is_microphone_active: false,
+            input_reads: 0,
+            devices: [None, None],
+            memory_devices: Vec::new(),
+            next_controller_1: Controller::default(),
+            next_controller_2: Controller::default(),
+            next_microphone: false,
+            is_input_latch_due: true,
+
+            cycle: 0,
+            master_clock_remainder: 0,
+            oam_dma: OamDma::default(),
+            dma_stats: DmaStats::default(),
+            frame_dma_stats: DmaStats::default(),
+            emit_irq: false,
+            serviced_interrupt: None,
+            open_bus: 0,
+            has_open_bus: true,
+            accuracy_profile: AccuracyProfile::default(),
+            is_checking_savestate_rom: true,
+            test_hook: None,
+            test_events: Vec::new(),
+            warnings: Vec::new(),
+            apu_log: None,
+            #[cfg(test)]
+            flat_memory: None,
+        };
+
+        Rc::new_cyclic(|rc| {
+            bus.cpu.borrow_mut().connect_bus(rc.clone());
+            bus.ppu.borrow_mut().connect_bus(rc.clone());
+            bus.cartridge.borrow_mut().connect_bus(rc.clone());
+            RefCell::new(bus)
+        })
+    }
+
+    /// Sets the region to emulate, which determines the PPU's frame layout and the rate the APU
+    /// produces samples at. Like [Ppu::set_timing], this should be done before running any frames.
+    pub fn set_region(&mut self, region: Region) {
+        let timing = FrameTiming::new(region);
+        self.ppu.borrow_mut().set_timing(timing);
+        self.apu.borrow_mut().set_timing(timing);
+    }
+
+    pub fn accuracy_profile(&self) -> AccuracyProfile {
+        self.accuracy_profile
+    }
+
+    pub fn has_open_bus(&self) -> bool {
+        self.has_open_bus
+    }
+
+    /// Sets whether reads from unmapped addresses and the undriven bits of registers return the
+    /// last value on the data bus, as on hardware, or 0. A few games, such as Paperboy, rely on
+    /// open bus when reading the controllers.
+    pub fn set_open_bus(&mut self, has_open_bus: bool) {
+        self.has_open_bus = has_open_bus;
+    }
+
+    /// Returns what the bits nothing drives during a read come back as.
+    fn undriven_bits(&self) -> u8 {
+        if self.has_open_bus {
+            self.open_bus
+        } else {
+            0
+        }
+    }
+
+    /// Applies the accuracy settings of a profile to the CPU and PPU. Like [Bus::set_region],
+    /// this should be done before running any frames, as the PPU's warm-up only happens after
+    /// power-on.
+    pub fn set_accuracy_profile(&mut self, profile: AccuracyProfile) {
+        self.accuracy_profile = profile;
+        self.has_open_bus = profile.has_open_bus();
+        self.cpu
+            .borrow_mut()
+            .set_dummy_accesses(profile.has_dummy_accesses());
+        let mut ppu = self.ppu.borrow_mut();
+        ppu.set_mask_delay(profile.has_mask_delay());
+        ppu.set_warm_up(profile.has_ppu_warm_up());
+        ppu.set_a12_filter(profile.has_a12_filter());
+        ppu.set_sprite_evaluation_timing(profile.has_sprite_evaluation_timing());
+    }
+
+    /// Returns the clock rates and frame layout of the region being emulated.
+    pub fn timing(&self) -> FrameTiming {
+        self.ppu.borrow().timing()
+    }
+
+    /// Returns the DMA activity during the last complete frame. Frames end as vblank does, like
+    /// [Bus::run_frame].
+    pub fn dma_stats(&self) -> DmaStats {
+        self.dma_stats
+    }
+
+    /// Sets the hook that watches for results reported by test ROMs, or removes it.
+    pub fn set_test_hook(&mut self, test_hook: Option<TestHook>) {
+        self.test_hook = test_hook;
+        self.test_events.clear();
+    }
+
+    /// Takes the events reported through the test hook since the last call.
+    pub fn drain_test_events(&mut self) -> Vec<TestEvent> {
+        std::mem::take(&mut self.test_events)
+    }
+
+    /// Takes the warnings about problems that were worked around since the last call, oldest
+    /// first, such as junk in the ROM's header, savestate sections that weren't recognized and
+    /// were skipped, or the CPU halting. Frontends should show these to the user, as they can
+    /// explain a game that misbehaves.
+    pub fn drain_warnings(&mut self) -> Vec<String> {
+        let warnings = self.cpu.borrow_mut().drain_warnings();
+        self.warnings.extend(warnings);
+        self.take_cartridge_warnings();
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Moves the cartridge's warnings to the bus's, to keep them in order.
+    fn take_cartridge_warnings(&mut self) {
+        let warnings = self.cartridge.borrow_mut().drain_warnings();
+        self.warnings.extend(warnings);
+    }
+
+    /// Starts or stops recording writes to APU registers, retrieved with [Bus::drain_apu_log].
+    /// Stopping discards anything not yet retrieved.
+    ///
+    /// Nothing limits how many writes are kept, so the log should be drained regularly, such as
+    /// after every frame.
+    pub fn set_apu_logging(&mut self, is_enabled: bool) {
+        self.apu_log = is_enabled.then(Vec::new);
+    }
+
+    pub fn is_apu_logging(&self) -> bool {
+        self.apu_log.is_some()
+    }
+
+    /// Takes the APU register writes recorded since the last call, oldest first.
+    pub fn drain_apu_log(&mut self) -> Vec<ApuWrite> {
+        self.apu_log
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    pub fn request_irq(&mut self) {
+        self.emit_irq = true;
+    }
+
+    /// Sets the buttons held on each controller.
+    ///
+    /// Input is latched at the start of each frame, so games see the same input for a whole frame
+    /// regardless of when the frontend calls this. Input set between frames applies to the next
+    /// frame, while input set mid-frame is deferred to the frame after, which keeps replays
+    /// deterministic.
+    pub fn set_controller_state(
+        &mut self,
+        controller_1_state: Controller,
+        controller_2_state: Controller,
+    ) {
+        self.next_controller_1 = controller_1_state;
+        self.next_controller_2 = controller_2_state;
+    }
+
+    /// Sets whether the microphone on the Famicom's second controller is picking up sound. Like
+    /// controller input, this is latched at the start of each frame.
+    pub fn set_microphone(&mut self, is_active: bool) {
+        self.next_microphone = is_active;
+    }
+
+    /// Connects a device to a controller port in place of the standard controller, or goes back
+    /// to the standard controller if `device` is `None`.
+    pub fn connect_device(&mut self, port: ControllerPort, device: Option<Box<dyn SerialDevice>>) {
+        self.devices[port as usize] = device;
+    }
+
+    fn latch_input(&mut self) {
+        self.controller_1 = self.next_controller_1;
+        self.controller_2 = self.next_controller_2;
+        self.is_microphone_active = self.next_microphone;
+        self.is_input_latch_due = false;
+    }
+
+    pub fn cpu_read(&mut self, addr: u16) -> u8 {
+        #[cfg(test)]
+        if let Some(memory) = &self.flat_memory {
+            return memory[addr as usize];
+        }
+
+        let data = match self.memory_handler(addr) {
+            MemoryHandler::Ram => self.ram[addr as usize & 0x07FF],
+            MemoryHandler::Ppu => self.ppu.borrow_mut().cpu_read(addr & 0x07),
+            // Bit 5 of the APU status isn't driven.
+            MemoryHandler::Apu if addr == 0x4015 => {
+                (self.apu.borrow().cpu_read(addr) & !0x20) | (self.undriven_bits() & 0x20)
+            }
+            MemoryHandler::Controllers => self.read_controller_port(addr),
+            MemoryHandler::Cartridge => self.cartridge.borrow().cpu_read(addr),
+            MemoryHandler::Device(_) => self
+                .mapped_device_mut(addr)
+                .and_then(|device| device.read(addr))
+                .unwrap_or(self.undriven_bits()),
+            // Write-only registers and unmapped addresses.
+            MemoryHandler::Apu | MemoryHandler::OamDma | MemoryHandler::OpenBus => {
+                self.undriven_bits()
+            }
+        };
+        self.open_bus = data;
+        data
+    }
+
+    /// Reads the next bit from a controller port, or from the device connected in its place.
+    fn read_controller_port(&mut self, addr: u16) -> u8 {
+        self.input_reads += 1;
+        match addr {
+            0x4016 | 0x4017 if self.devices[addr as usize & 0x01].is_some() => {
+                let device = self.devices[addr as usize & 0x01].as_mut().unwrap();
+                let microphone = (addr == 0x4016 && self.is_microphone_active) as u8;
+                (device.read() & 0x1F) | microphone << 2 | (self.undriven_bits() & 0xE0)
+            }
+            0x4016 => {
+                if self.controller_strobe {
+                    self.controller_1_state = self.controller_1;
+                }
+                let data = self.controller_1_state.0 & 0x01;
+                self.controller_1_state.0 >>= 1;
+                // The microphone is read from $4016 despite being on the second controller. Only
+                // the low bits are driven by the controller port.
+                data | ((self.is_microphone_active as u8) << 2) | (self.undriven_bits() & 0xE0)
+            }
+            0x4017 => {
+                if self.controller_strobe {
+                    self.controller_2_state = self.controller_2;
+                }
+                let data = self.controller_2_state.0 & 0x01;
+                self.controller_2_state.0 >>= 1;
+                data | (self.undriven_bits() & 0xE0)
+            }
+            _ => unreachable!("${addr:04X} isn't a controller port"),
+        }
+    }
+
+    /// Reads from the CPU address space without any side effects, for debuggers. Registers can't
+    /// be read without affecting them, so they return the last value on the data bus instead.
+    pub fn peek(&self, addr: u16) -> u8 {
+        match self.memory_handler(addr) {
+            MemoryHandler::Ram => self.ram[addr as usize & 0x07FF],
+            MemoryHandler::Cartridge => self.cartridge.borrow().cpu_read(addr),
+            MemoryHandler::Device(_) => self
+                .mapped_device(addr)
+                .and_then(|mapped| mapped.device.peek(addr))
+                .unwrap_or(self.undriven_bits()),
+            _ => self.undriven_bits(),
+        }
+    }
+
+    pub fn cpu_write(&mut self, addr: u16, data: u8) {
+        #[cfg(test)]
+        if let Some(memory) = &mut self.flat_memory {
+            memory[addr as usize] = data;
+            return;
+        }
+
+        self.open_bus = data;
+        if let Some(event) = self
+            .test_hook
+            .as_mut()
+            .and_then(|hook| hook.observe_write(addr, data))
+        {
+            self.test_events.push(event);
+        }
+        match self.memory_handler(addr) {
+            MemoryHandler::Ram => self.ram[addr as usize & 0x07FF] = data,
+            MemoryHandler::Ppu => self.ppu.borrow_mut().cpu_write(addr & 0x07, data),
+            MemoryHandler::Controllers if addr == 0x4016 => {
+                self.controller_strobe = (data & 0x01) != 0;
+                for device in self.devices.iter_mut().flatten() {
+                    device.write(data);
+                }
+                self.controller_1_state = self.controller_1;
+                self.controller_2_state = self.controller_2;
+            }
+            // $4017 is shared between the second controller port and the APU's frame counter.
+            MemoryHandler::Apu | MemoryHandler::Controllers => {
+                if let Some(apu_log) = &mut self.apu_log {
+                    apu_log.push(ApuWrite {
+                        frame: self.ppu.borrow().frame_count(),
+                        cycle: self.cycle,
+                        addr,
+                        value: data,
+                    });
+                }
+                self.apu.borrow_mut().cpu_write(addr, data);
+            }
+            MemoryHandler::OamDma => {
+                self.ppu.borrow_mut().cpu_write(addr, data);
+                self.oam_dma.start(data);
+                self.frame_dma_stats.oam_transfers += 1;
+            }
+            MemoryHandler::Device(_) => {
+                if let Some(device) = self.mapped_device_mut(addr) {
+                    device.write(addr, data);
+                }
+            }
+            MemoryHandler::Cartridge => self.cartridge.borrow_mut().cpu_write(addr, data),
+            // Mappers also see writes to addresses they don't respond to reads from, as some of
+            // them only have write-only registers there.
+            MemoryHandler::OpenBus if addr >= 0x4020 => {
+                self.cartridge.borrow_mut().cpu_write(addr, data)
+            }
+            MemoryHandler::OpenBus => (),
+        }
+    }
+
+    /// Returns what handles accesses to `addr`, which is how every access through the CPU
+    /// address space is decoded.
+    pub fn memory_handler(&self, addr: u16) -> MemoryHandler {
+        if let Some(mapped) = self.mapped_device(addr) {
+            return MemoryHandler::Device(mapped.name);
+        }
+        match addr {
+            0x0000..=0x1FFF => MemoryHandler::Ram,
+            0x2000..=0x3FFF => MemoryHandler::Ppu,
+            0x4000..=0x4013 | 0x4015 => MemoryHandler::Apu,
+            0x4014 => MemoryHandler::OamDma,
+            0x4016 | 0x4017 => MemoryHandler::Controllers,
+            // Normally disabled APU and I/O test functionality.
+            0x4018..=0x401F => MemoryHandler::OpenBus,
+            _ if self.cartridge.borrow().maps_cpu_addr(addr) => MemoryHandler::Cartridge,
+            _ => MemoryHandler::OpenBus,
+        }
+    }
+
+    fn mapped_device(&self, addr: u16) -> Option<&MappedDevice> {
+        self.memory_devices
+            .iter()
+            .find(|mapped| (mapped.start..=mapped.end).contains(&addr))
+    }
+
+    fn mapped_device_mut(&mut self, addr: u16) -> Option<&mut Box<dyn MemoryDevice>> {
+        self.memory_devices
+            .iter_mut()
+            .find(|mapped| (mapped.start..=mapped.end).contains(&addr))
+            .map(|mapped| &mut mapped.device)
+    }
+
+    /// Maps a device into the CPU address space from `start` to `end` inclusive, such as the
+    /// registers of hardware the cartridge doesn't provide. The device handles every access there
+    /// in place of the cartridge, and shows up under `name` in [Bus::memory_map].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the region is empty, overlaps the console's own hardware at
+    /// $0000-$4017, or overlaps another mapped device.
+    pub fn map_device(
+        &mut self,
+        start: u16,
+        end: u16,
+        name: &'static str,
+        device: Box<dyn MemoryDevice>,
+    ) -> Result<(), String> {
+        if start > end {
+            return Err(format!("${start:04X}-${end:04X} is an empty region"));
+        }
+        if start < 0x4018 {
+            return Err(format!(
+                "${start:04X}-${end:04X} overlaps the console's hardware at $0000-$4017"
+            ));
+        }
+        if let Some(mapped) = self
+            .memory_devices
+            .iter()
+            .find(|mapped| start <= mapped.end && mapped.start <= end)
+        {
+            return Err(format!(
+                "${start:04X}-${end:04X} overlaps {} at ${:04X}-${:04X}",
+                mapped.name, mapped.start, mapped.end
+            ));
+        }
+
+        self.memory_devices.push(MappedDevice {
+            start,
+            end,
+            name,
+            device,
+        });
+        Ok(())
+    }
+
+    /// Removes the device mapped under `name`, returning it, or `None` if there isn't one.
+    pub fn unmap_device(&mut self, name: &str) -> Option<Box<dyn MemoryDevice>> {
+        let index = self
+            .memory_devices
+            .iter()
+            .position(|mapped| mapped.name == name)?;
+        Some(self.memory_devices.remove(index).device)
+    }
+
+    /// Returns what handles each region of the CPU address space, in ascending order.
+    ///
+    /// The cartridge regions below $8000 depend on the mapper, so this may differ between games.
+    /// It's built from [Bus::memory_handler], so it always matches how accesses are decoded.
+    pub fn memory_map(&self) -> Vec<MemoryRegion> {
+        let mut regions: Vec<MemoryRegion> = Vec::new();
+        for addr in 0..=0xFFFF {
+            let handler = self.memory_handler(addr);
+            match regions.last_mut() {
+                Some(region) if region.handler == handler => region.end = addr,
+                _ => regions.push(MemoryRegion {
+                    start: addr,
+                    end: addr,
+                    handler,
+                    mirror_size: match handler {
+                        MemoryHandler::Ram => Some(0x0800),
+                        MemoryHandler::Ppu => Some(0x0008),
+                        _ => None,
+                    },
+                }),
+            }
+        }
+        regions
+    }
+
+    pub fn ppu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.cartridge.borrow().ppu_read(addr),
+            _ => 0,
+        }
+    }
+
+    pub fn ppu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.cartridge.borrow_mut().ppu_write(addr, data),
+            _ => todo!(),
+        }
+    }
+
+    /// Clocks the system relative to the CPU clock, meaning the PPU is clocked 3 times per call (or
+    /// 3.2 times on average for PAL consoles).
+    ///
+    /// This is an associated function instead of a method due to how the CPU and PPU need mutable
+    /// access to the bus, which means borrowing the bus RefCell to call this function would always
+    /// be invalid.
+    pub fn clock(
+        bus: Rc<RefCell<Bus>>,
+        cpu: Rc<RefCell<Cpu>>,
+        ppu: Rc<RefCell<Ppu>>,
+        apu: Rc<RefCell<Apu>>,
+    ) {
+        Self::clock_cycle(&bus, &mut cpu.borrow_mut(), &ppu, &apu);
+    }
+
+    /// Clocks the system for the given number of CPU cycles, like calling [Bus::clock] in a loop
+    /// but without the overhead of cloning every component and borrowing the CPU for each cycle.
+    pub fn run_cycles(
+        bus: Rc<RefCell<Bus>>,
+        cpu: Rc<RefCell<Cpu>>,
+        ppu: Rc<RefCell<Ppu>>,
+        apu: Rc<RefCell<Apu>>,
+        cycles: usize,
+    ) {
+        let mut cpu = cpu.borrow_mut();
+        for _ in 0..cycles {
+            Self::clock_cycle(&bus, &mut cpu, &ppu, &apu);
+        }
+    }
+
+    /// Clocks the system until the current frame is finished, then clears
+    /// [Ppu::is_frame_ready]. This is the fastest way to run a frame when nothing needs to happen
+    /// partway through it.
+    pub fn run_frame(
+        bus: Rc<RefCell<Bus>>,
+        cpu: Rc<RefCell<Cpu>>,
+        ppu: Rc<RefCell<Ppu>>,
+        apu: Rc<RefCell<Apu>>,
+    ) {
+        if !ppu.borrow().is_frame_ready {
+            let mut cpu = cpu.borrow_mut();
+            while !Self::clock_cycle(&bus, &mut cpu, &ppu, &apu) {}
+        }
+        ppu.borrow_mut().is_frame_ready = false;
+    }
+
+    /// Runs frames as fast as possible without drawing them or keeping their audio, to get past a
+    /// game's intro in automated tests and tools. Returns the number of frames run.
+    ///
+    /// Nothing the game can observe is skipped, so the console ends up in the same state it
+    /// would be in after running the frames normally with the same input.
+    pub fn fast_boot(
+        bus: Rc<RefCell<Bus>>,
+        cpu: Rc<RefCell<Cpu>>,
+        ppu: Rc<RefCell<Ppu>>,
+        apu: Rc<RefCell<Apu>>,
+        boot: FastBoot,
+    ) -> u32 {
+        let max_frames = match boot {
+            FastBoot::UntilInputRead { max_frames } => max_frames,
+            FastBoot::Frames(frames) => frames,
+        };
+        let was_output_skipped = ppu.borrow().is_output_skipped();
+        ppu.borrow_mut().set_output_skipped(true);
+
+        let mut frames = 0;
+        while frames < max_frames {
+            let input_reads = bus.borrow().input_reads;
+            Self::run_frame(bus.clone(), cpu.clone(), ppu.clone(), apu.clone());
+            apu.borrow_mut().drain_audio_buffer();
+            frames += 1;
+            if matches!(boot, FastBoot::UntilInputRead { .. })
+                && bus.borrow().input_reads != input_reads
+            {
+                break;
+            }
+        }
+
+        ppu.borrow_mut().set_output_skipped(was_output_skipped);
+        frames
+    }
+
+    /// Clocks the system for one CPU cycle, returning whether vblank ended and the frame became
+    /// ready. The caller borrows the CPU, so that loops can hold it across cycles. The bus, PPU,
+    /// and APU are only borrowed between the CPU's accesses, as it reaches them through the bus.
+    fn clock_cycle(
+        bus: &RefCell<Bus>,
+        cpu: &mut Cpu,
+        ppu: &RefCell<Ppu>,
+        apu: &RefCell<Apu>,
+    ) -> bool {
+        let dma_access = {
+            let mut bus = bus.borrow_mut();
+            if bus.is_input_latch_due {
+                bus.latch_input();
+            }
+            let cycle = bus.cycle;
+            let dma_access = bus.oam_dma.clock(cycle);
+            if dma_access.is_some() {
+                bus.frame_dma_stats.stall_cycles += 1;
+            }
+            dma_access
+        };
+        match dma_access {
+            None => cpu.clock(),
+            Some(DmaAccess::Wait) => (),
+            Some(DmaAccess::Read(addr)) => {
+                let mut bus = bus.borrow_mut();
+                let data = bus.cpu_read(addr);
+                bus.oam_dma.latch(data);
+                bus.frame_dma_stats.bytes_read += 1;
+                bus.frame_dma_stats.last_read_addr = Some(addr);
+            }
+            // Write to the OAMDATA register.
+            Some(DmaAccess::Write(data)) => ppu.borrow_mut().cpu_write(0x04, data),
+        }
+        let (master_clock_remainder, is_dma_active) = {
+            let bus = bus.borrow();
+            (bus.master_clock_remainder, bus.oam_dma.is_active())
+        };
+        let (master_clocks, has_vblank_ended, is_nmi_due) = {
+            let mut ppu = ppu.borrow_mut();
+            if !ppu.is_overclocking() {
+                let expansion_output = bus.borrow().cartridge.borrow_mut().clock_audio();
+                let mut apu = apu.borrow_mut();
+                apu.expansion_output = expansion_output;
+                apu.clock();
+            }
+            let timing = ppu.timing();
+            let was_frame_ready = ppu.is_frame_ready;
+            let mut master_clocks = master_clock_remainder + timing.master_clocks_per_cpu_cycle;
+            while master_clocks >= timing.master_clocks_per_ppu_dot {
+                ppu.clock();
+                master_clocks -= timing.master_clocks_per_ppu_dot;
+            }
+            // The frame becomes ready as vblank ends.
+            let has_vblank_ended = !was_frame_ready && ppu.is_frame_ready;
+            if has_vblank_ended && ppu.is_validating() && cpu.is_in_nmi_handler() {
+                ppu.warn(PpuWarningKind::NmiOverran);
+            }
+            let is_nmi_due = !is_dma_active && std::mem::take(&mut ppu.emit_nmi);
+            (master_clocks, has_vblank_ended, is_nmi_due)
+        };
+        let is_irq_due = {
+            let mut bus = bus.borrow_mut();
+            bus.master_clock_remainder = master_clocks;
+            if has_vblank_ended {
+                bus.is_input_latch_due = true;
+                bus.dma_stats = std::mem::take(&mut bus.frame_dma_stats);
+            }
+            !is_dma_active && std::mem::take(&mut bus.emit_irq)
+        };
+        // Servicing an interrupt pushes to the stack through the bus.
+        if is_nmi_due {
+            cpu.nmi();
+        }
+        let is_irq_serviced = is_irq_due && cpu.irq();
+        let mut bus = bus.borrow_mut();
+        bus.serviced_interrupt = if is_irq_serviced {
+            Some(Interrupt::Irq)
+        } else if is_nmi_due {
+            Some(Interrupt::Nmi)
+        } else {
+            None
+        };
+        bus.cycle += 1;
+        has_vblank_ended
+    }
+
+    /// Clocks the system until the given condition is met, allowing for finer control than
+    /// running whole frames.
+    ///
+    /// Returns whether the condition was met. To avoid hanging on conditions that never occur,
+    /// such as waiting for an NMI while NMIs are disabled, this gives up after roughly 10 frames.
+    pub fn run_until(
+        bus: Rc<RefCell<Bus>>,
+        cpu: Rc<RefCell<Cpu>>,
+        ppu: Rc<RefCell<Ppu>>,
+        apu: Rc<RefCell<Apu>>,
+        condition: StopCondition,
+    ) -> bool {
+        const DOTS_PER_SCANLINE: usize = 341;
+
+        let position = |ppu: &Ppu| ppu.scanline() as usize * DOTS_PER_SCANLINE + ppu.dot() as usize;
+
+        let mut cpu = cpu.borrow_mut();
+        for _ in 0..MAX_RUN_CYCLES {
+            let previous = position(&ppu.borrow());
+            let was_sprite_zero_hit = ppu.borrow().sprite_zero_hit_position().is_some();
+            let input_reads = bus.borrow().input_reads;
+            Self::clock_cycle(&bus, &mut cpu, &ppu, &apu);
+
+            let is_met = match condition {
+                StopCondition::Frame => {
+                    let is_frame_ready = ppu.borrow().is_frame_ready;
+                    ppu.borrow_mut().is_frame_ready = false;
+                    is_frame_ready
+                }
+                StopCondition::Dot { scanline, dot } => {
+                    let target = scanline as usize * DOTS_PER_SCANLINE + dot as usize;
+                    let current = position(&ppu.borrow());
+                    // Account for the position wrapping around at the end of a frame.
+                    if previous <= current {
+                        previous < target && target <= current
+                    } else if ppu.borrow().is_overclocking() {
+                        // An overclocking scanline is being repeated, so nothing new was reached.
+                        false
+                    } else {
+                        previous < target || target <= current
+                    }
+                }
+                StopCondition::Nmi => bus.borrow().serviced_interrupt == Some(Interrupt::Nmi),
+                StopCondition::Irq => bus.borrow().serviced_interrupt == Some(Interrupt::Irq),
+                StopCondition::TestEvent => !bus.borrow().test_events.is_empty(),
+                StopCondition::SpriteZeroHit => {
+                    !was_sprite_zero_hit && ppu.borrow().sprite_zero_hit_position().is_some()
+                }
+                StopCondition::InputRead => bus.borrow().input_reads != input_reads,
+            };
+            if is_met {
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn reset(cpu: Rc<RefCell<Cpu>>, ppu: Rc<RefCell<Ppu>>) {
+        cpu.borrow_mut().reset();
+        ppu.borrow_mut().reset();
+    }
+
+    /// Swaps in a different cartridge while running, such as a rebuilt homebrew ROM, then resets
+    /// the console. Internal RAM is cleared unless `keep_ram` is set, in which case the new
+    /// program starts with whatever the old one left behind. Returns the old cartridge.
+    pub fn swap_cartridge(
+        bus: Rc<RefCell<Self>>,
+        cartridge: Cartridge,
+        keep_ram: bool,
+    ) -> Cartridge {
+        let (cpu, ppu, apu, shared) = {
+            let bus = bus.borrow();
+            (
+                bus.cpu.clone(),
+                bus.ppu.clone(),
+                bus.apu.clone(),
+                bus.cartridge.clone(),
+            )
+        };
+        let old = std::mem::replace(&mut *shared.borrow_mut(), cartridge);
+        shared.borrow_mut().connect_bus(Rc::downgrade(&bus));
+        if !keep_ram {
+            bus.borrow_mut().ram.fill(0);
+        }
+        Self::reset(cpu, ppu);
+        apu.borrow_mut().smooth_reset();
+        old
+    }
+
+    /// Sets whether savestates made with a different ROM than the current one are turned away,
+    /// which is the default. Disabling this lets advanced users carry a state over to a hack or
+    /// revision of the same game, at the risk of loading garbage. Savestates from FCEUX don't say
+    /// which ROM they're for, so they're never checked.
+    pub fn set_savestate_rom_check(&mut self, is_enabled: bool) {
+        self.is_checking_savestate_rom = is_enabled;
+    }
+
+    /// Applies a parsed savestate to the whole system, along with the accuracy profile it was
+    /// made with.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the savestate was made with a different ROM, or the mapper's state
+    /// doesn't fit the current cartridge, in which case the system is left untouched.
+    pub fn apply_state(&mut self, state: Savestate) -> Result<(), SavestateError> {
+        // Everything else was validated while parsing, so once the mapper's state checks out,
+        // nothing can fail partway through.
+        let cartridge = self.cartridge.borrow();
+        if self.is_checking_savestate_rom {
+            cartridge.check_identity(&state.metadata)?;
+        }
+        cartridge.validate_state(&state.mapper_state)?;
+        drop(cartridge);
+
+        let cpu_state = state.cpu_state;
+        let cpuc_state = state.cpuc_state;
+        let ppu_state = state.ppu_state;
+        let apu_state = state.apu_state;
+        let mapper_state = state.mapper_state;
+
+        self.cpu.borrow_mut().apply_state(&cpu_state);
+        self.set_ram(cpu_state.ram);
+        // States from FCEUX don't include the count, so keep the current parity.
+        if let Some(cycle) = cpuc_state.bus_cycle {
+            self.cycle = cycle as usize;
+        }
+        self.ppu.borrow_mut().apply_state(ppu_state);
+        self.apu.borrow_mut().apply_state(apu_state);
+        self.take_cartridge_warnings();
+        self.warnings.extend(state.warnings);
+        self.cartridge.borrow_mut().apply_state(mapper_state);
+        self.take_cartridge_warnings();
+        // Savestates from FCEUX and older versions don't say, so keep the current profile.
+        if let Some(profile) = state.metadata.accuracy_profile {
+            self.set_accuracy_profile(profile);
+        }
+
+        Ok(())
+    }
+
+    /// Decompresses, parses, and applies a savestate in one step.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the savestate is malformed, in an unsupported format, or can't be
+    /// applied, in which case the system is left untouched.
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), SavestateError> {
+        let decompressed = Savestate::decompress(bytes)?;
+        let savestate = Savestate::new(&decompressed)?;
+
+        self.apply_state(savestate)
+    }
+
+    /// Saves the current system state to a compressed FCEUX FCS savestate.
+    ///
+    /// This only needs a shared borrow of the system components, so it can be called on demand
+    /// at any point between clock cycles.
+    pub fn save_state(&self) -> Vec<u8> {
+        self.save_state_with_metadata(None, None)
+    }
+
+    /// Saves the current system state like [Bus::save_state], also recording when it was saved,
+    /// in milliseconds since the Unix epoch, and the name of the ROM, for listing the savestate
+    /// in a save manager. The core doesn't read the clock itself, as it can't on every platform.
+    pub fn save_state_with_metadata(
+        &self,
+        timestamp: Option<u64>,
+        rom_name: Option<&str>,
+    ) -> Vec<u8> {
+        let mut cpu_state = self.cpu.borrow().save_state();
+        serialize_into(&mut cpu_state, &self.ram, "RAM");
+        let cpuc_state = serialize(&(self.cycle as u64), "CYCL");
+        let ppu_state = self.ppu.borrow().save_state();
+        let apu_state = self.apu.borrow().save_state();
+        let mapper_state = self.cartridge.borrow().save_state();
+        let thumbnail = self.ppu.borrow().thumbnail();
+        let metadata = SavestateMetadata {
+            timestamp,
+            rom_name: rom_name.map(str::to_string),
+            rom_crc32: Some(self.cartridge.borrow().rom_crc32()),
+            mapper_id: Some(self.cartridge.borrow().mapper_id()),
+            frame_count: Some(self.ppu.borrow().frame_count()),
+            emulator_version: Some(crate::capabilities::VERSION.to_string()),
+            accuracy_profile: Some(self.accuracy_profile),
+        };
+
+        Savestate::save(
+            &cpu_state,
+            &cpuc_state,
+            &ppu_state,
+            &apu_state,
+            &mapper_state,
+            &metadata.save(),
+            &thumbnail,
+        )
+    }
+
+    /// Saves the current system state into `buffer`, replacing its contents, like
+    /// [Bus::save_state] but uncompressed and without metadata or a thumbnail. Once the buffer has
+    /// grown to fit a state, this doesn't allocate, so it's suited to taking a state every frame,
+    /// as [crate::RewindBuffer] does. [Bus::load_state] loads these like any other savestate.
+    pub fn save_state_into(&self, buffer: &mut Vec<u8>) {
+        Savestate::save_uncompressed_into(
+            buffer,
+            |buffer| {
+                self.cpu.borrow().save_state_into(buffer);
+                serialize_into(buffer, &self.ram, "RAM");
+            },
+            |buffer| serialize_into(buffer, &(self.cycle as u64), "CYCL"),
+            |buffer| self.ppu.borrow().save_state_into(buffer),
+            |buffer| self.apu.borrow().save_state_into(buffer),
+            |buffer| self.cartridge.borrow().save_state_into(buffer),
+        );
+    }
+
+    /// Captures the state of the whole system, for comparing it with another's without saving
+    /// and decompressing savestates. See [SystemState].
+    pub fn system_state(&self) -> SystemState {
+        SystemState {
+            cpu: self.cpu.borrow().state(self.ram.clone()),
+            cycle: self.cycle as u64,
+            ppu: self.ppu.borrow().state(),
+            apu: self.apu.borrow().state(),
+            cartridge: self.cartridge.borrow().state(),
+        }
+    }
+
+    /// Returns the number of CPU cycles the bus has run for, including cycles where the CPU was
+    /// halted by DMA.
+    pub fn cycle(&self) -> usize {
+        self.cycle
+    }
+
+    pub fn set_ram(&mut self, ram: Box<[u8; 2048]>) {
+        self.ram = ram;
+    }
+
+    /// Returns a checksum of internal RAM, which is cheap enough to compare every frame when
+    /// checking that two runs of the same input haven't diverged.
+    pub fn ram_crc32(&self) -> u32 {
+        crate::crc32(&self.ram[..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FourScore;
+
+    type Components = (
+        Rc<RefCell<Bus>>,
+        Rc<RefCell<Cpu>>,
+        Rc<RefCell<Ppu>>,
+        Rc<RefCell<Apu>>,
+    );
+
+    #[test]
+    fn input_latched_per_frame() {
+        let (bus, cpu, ppu, apu) = setup();
+        let run_frame = || {
+            Bus::run_until(
+                bus.clone(),
+                cpu.clone(),
+                ppu.clone(),
+                apu.clone(),
+                StopCondition::Frame,
+            )
+        };
+        let read_a_button = || {
+            let mut bus = bus.borrow_mut();
+            bus.cpu_write(0x4016, 1);
+            bus.cpu_write(0x4016, 0);
+            bus.cpu_read(0x4016) & 0x01 != 0
+        };
+
+        bus.borrow_mut()
+            .set_controller_state(Controller::new().with_a(true), Controller::new());
+        assert!(run_frame());
+        assert!(read_a_button());
+
+        // Input set mid-frame isn't seen until the frame after.
+        Bus::clock(bus.clone(), cpu.clone(), ppu.clone(), apu.clone());
+        bus.borrow_mut()
+            .set_controller_state(Controller::new(), Controller::new());
+        assert!(read_a_button());
+        assert!(run_frame());
+        assert!(read_a_button());
+        Bus::clock(bus.clone(), cpu.clone(), ppu.clone(), apu.clone());
+        assert!(!read_a_button());
+    }
+
+    #[test]
+    fn memory_devices() {
+        /// Four registers, with the last one write-only.
+        struct Registers([u8; 4]);
+        impl MemoryDevice for Registers {
+            fn read(&mut self, addr: u16) -> Option<u8> {
+                (addr != 0x5003).then_some(self.0[addr as usize - 0x5000])
+            }
+            fn write(&mut self, addr: u16, data: u8) {
+                self.0[addr as usize - 0x5000] = data;
+            }
+        }
+
+        let (bus, ..) = setup();
+        let mut bus = bus.borrow_mut();
+        bus.map_device(0x5000, 0x5003, "registers", Box::new(Registers([0; 4])))
+            .unwrap();
+        assert!(bus
+            .map_device(0x4000, 0x4000, "apu", Box::new(Registers([0; 4])))
+            .is_err());
+        assert!(bus
+            .map_device(0x5003, 0x5004, "overlap", Box::new(Registers([0; 4])))
+            .is_err());
+
+        bus.cpu_write(0x5001, 0x12);
+        assert_eq!(bus.cpu_read(0x5001), 0x12);
+        // Write-only registers leave the last value on the data bus.
+        bus.cpu_write(0x5003, 0x34);
+        assert_eq!(bus.cpu_read(0x5003), 0x34);
+        // Devices can't be peeked at unless they support it.
+        assert_eq!(bus.peek(0x5001), 0x34);
+
+        let region = bus
+            .memory_map()
+            .into_iter()
+            .find(|region| region.handler == MemoryHandler::Device("registers"))
+            .unwrap();
+        assert_eq!((region.start, region.end), (0x5000, 0x5003));
+
+        assert!(bus.unmap_device("registers").is_some());
+        assert_ne!(
+            bus.memory_handler(0x5000),
+            MemoryHandler::Device("registers")
+        );
+    }
+
+    #[test]
+    fn connected_device() {
+        let (bus, ..) = setup();
+        let mut four_score = FourScore::new(ControllerPort::Two);
+        four_score.set_controllers(Controller::new(), Controller::new().with_a(true));
+        let mut bus = bus.borrow_mut();
+        bus.connect_device(ControllerPort::Two, Some(Box::new(four_score)));
+
+        bus.cpu_write(0x4016, 1);
+        bus.cpu_write(0x4016, 0);
+        let bits: Vec<u8> = (0..9).map(|_| bus.cpu_read(0x4017) & 0x01).collect();
+        assert_eq!(bits, [0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn microphone() {
+        let (bus, ..) = setup();
+        let mut bus = bus.borrow_mut();
+        let read_microphone = |bus: &mut Bus| {
+            (
+                bus.cpu_read(0x4016) & 0x04 != 0,
+                bus.cpu_read(0x4017) & 0x04 != 0,
+            )
+        };
+
+        // The microphone is latched with the controllers at the start of a frame.
+        bus.set_microphone(true);
+        assert_eq!(read_microphone(&mut bus), (false, false));
+        bus.latch_input();
+        // It's only read from $4016, and doesn't depend on the controller strobe.
+        assert_eq!(read_microphone(&mut bus), (true, false));
+        bus.cpu_write(0x4016, 1);
+        assert_eq!(read_microphone(&mut bus), (true, false));
+        bus.cpu_write(0x4016, 0);
+
+        // It's still read with another device connected.
+        bus.connect_device(
+            ControllerPort::One,
+            Some(Box::new(FourScore::new(ControllerPort::One))),
+        );
+        assert_eq!(read_microphone(&mut bus), (true, false));
+
+        bus.set_microphone(false);
+        bus.latch_input();
+        assert_eq!(read_microphone(&mut bus), (false, false));
+    }
+
+    #[test]
+    fn swap_cartridge() {
+        let (bus, cpu, ..) = setup();
+        let cartridge = Cartridge::from_raw_prg(&[0xEA], 0x8000, [0, 0x8000, 0]).unwrap();
+        bus.borrow_mut().cpu_write(0x0010, 0x42);
+
+        Bus::swap_cartridge(bus.clone(), cartridge, true);
+        assert_eq!(cpu.borrow().program_counter(), 0x8000);
+        assert_eq!(bus.borrow_mut().cpu_read(0x8000), 0xEA);
+        assert_eq!(bus.borrow_mut().cpu_read(0x0010), 0x42);
+
+        let cartridge = Cartridge::from_raw_prg(&[], 0x8000, [0; 3]).unwrap();
+        Bus::swap_cartridge(bus.clone(), cartridge, false);
+        assert_eq!(bus.borrow_mut().cpu_read(0x8000), 0x00);
+        assert_eq!(bus.borrow_mut().cpu_read(0x0010), 0x00);
+    }
+
+    #[test]
+    fn test_hook() {
+        let source = "
+            lda #$80
+            sta $6000
+            ldx #$00
+        signature:
+            lda $8100,x
+            sta $6001,x
+            inx
+            cpx #$06
+            bne signature
+            lda #$00
+            sta $6000
+        loop:
+            jmp loop
+        ";
+        let mut program = crate::cpu::assemble(0x8000, source).unwrap();
+        program.resize(0x100, 0xEA);
+        program.extend_from_slice(&[0xDE, 0xB0, 0x61, b'o', b'k', 0]);
+        let cartridge = Cartridge::from_raw_prg(&program, 0x8000, [0, 0x8000, 0]).unwrap();
+        let (bus, cpu, ppu, apu) = setup();
+        Bus::swap_cartridge(bus.clone(), cartridge, false);
+        bus.borrow_mut().set_test_hook(Some(TestHook::default()));
+
+        let condition = StopCondition::TestEvent;
+        assert!(Bus::run_until(bus.clone(), cpu, ppu, apu, condition));
+        assert_eq!(
+            bus.borrow_mut().drain_test_events(),
+            [TestEvent::Finished {
+                code: 0,
+                text: "ok".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn accuracy_profile() {
+        let (bus, cpu, ppu, apu) = setup();
+        bus.borrow_mut().set_accuracy_profile(AccuracyProfile::Fast);
+        assert!(!cpu.borrow().has_dummy_accesses());
+        assert!(!ppu.borrow().has_mask_delay());
+
+        // The PPU only warms up after power-on, so it's done once the first frame has finished.
+        bus.borrow_mut()
+            .set_accuracy_profile(AccuracyProfile::Accurate);
+        assert!(cpu.borrow().has_dummy_accesses());
+        assert!(ppu.borrow().is_warming_up());
+        let condition = StopCondition::Frame;
+        assert!(Bus::run_until(
+            bus.clone(),
+            cpu,
+            ppu.clone(),
+            apu,
+            condition
+        ));
+        assert!(!ppu.borrow().is_warming_up());
+        bus.borrow_mut()
+            .set_accuracy_profile(AccuracyProfile::Accurate);
+        assert!(!ppu.borrow().is_warming_up());
+        assert!(ppu.borrow().has_a12_filter() && ppu.borrow().has_sprite_evaluation_timing());
+
+        // Savestates carry the profile they were made with.
+        bus.borrow_mut().set_accuracy_profile(AccuracyProfile::Fast);
+        assert!(!bus.borrow().has_open_bus());
+        let state = bus.borrow().save_state();
+        let (other, other_cpu, ..) = setup();
+        other.borrow_mut().load_state(&state).unwrap();
+        assert_eq!(other.borrow().accuracy_profile(), AccuracyProfile::Fast);
+        assert!(!other_cpu.borrow().has_dummy_accesses());
+    }
+
+    #[test]
+    fn open_bus() {
+        let (bus, ..) = setup();
+        let mut bus = bus.borrow_mut();
+        // The controller ports only drive the low bits, and nothing is mapped at $5000.
+        bus.cpu_write(0x5000, 0xA5);
+        assert_eq!(bus.cpu_read(0x4017) & 0xE0, 0xA0);
+        assert_eq!(bus.cpu_read(0x5000), 0xA0);
+        bus.set_open_bus(false);
+        assert_eq!(bus.cpu_read(0x4017) & 0xE0, 0);
+        assert_eq!(bus.cpu_read(0x5000), 0);
+    }
+
+    #[test]
+    fn save_state_into() {
+        let (bus, cpu, ppu, apu) = setup();
+        for _ in 0..5 {
+            Bus::clock(bus.clone(), cpu.clone(), ppu.clone(), apu.clone());
+        }
+        let mut buffer = Vec::new();
+        bus.borrow().save_state_into(&mut buffer);
+        let (capacity, pointer) = (buffer.capacity(), buffer.as_ptr());
+        bus.borrow().save_state_into(&mut buffer);
+        assert_eq!((buffer.capacity(), buffer.as_ptr()), (capacity, pointer));
+
+        let (other, ..) = setup();
+        other.borrow_mut().load_state(&buffer).unwrap();
+        assert_eq!(other.borrow().system_state(), bus.borrow().system_state());
+    }
+
+    #[test]
+    fn system_state_matches_savestate() {
+        let (bus, cpu, ppu, apu) = setup();
+        for _ in 0..5 {
+            Bus::clock(bus.clone(), cpu.clone(), ppu.clone(), apu.clone());
+        }
+        let mut bus = bus.borrow_mut();
+        bus.cpu_write(0x0010, 0x42);
+        bus.cpu_write(0x2000, 0x80);
+        bus.cpu_write(0x4000, 0x3F);
+        bus.cpu_write(0x4015, 0x0F);
+
+        let bytes = bus.save_state();
+        let decompressed = Savestate::decompress(&bytes).unwrap();
+        let savestate = Savestate::new(&decompressed).unwrap();
+        let state = bus.system_state();
+        assert_eq!(state.cpu, savestate.cpu_state);
+        assert_eq!(state.ppu, savestate.ppu_state);
+        assert_eq!(state.apu, savestate.apu_state);
+    }
+
+    #[test]
+    fn cycle_saved() {
+        let (bus, cpu, ppu, apu) = setup();
+        for _ in 0..5 {
+            Bus::clock(bus.clone(), cpu.clone(), ppu.clone(), apu.clone());
+        }
+        let state = bus.borrow().save_state();
+
+        let (other, ..) = setup();
+        assert_eq!(other.borrow().cycle(), 0);
+        other.borrow_mut().load_state(&state).unwrap();
+        assert_eq!(other.borrow().cycle(), 5);
+    }
+
+    #[test]
+    fn mismatched_state_rejected() {
+        let (bus, cpu, ppu, apu) = setup();
+        let mut cpu_state = cpu.borrow().save_state();
+        serialize_into(&mut cpu_state, &bus.borrow().ram, "RAM");
+        // This cartridge has 8KiB of CHR RAM, not 4KiB.
+        let state = Savestate::save(
+            &cpu_state,
+            &serialize(&0u64, "CYCL"),
+            &ppu.borrow().save_state(),
+            &apu.borrow().save_state(),
+            &serialize(&vec![0xFFu8; 4 * 1024], "CHRR"),
+            &[],
+            &[],
+        );
+
+        for _ in 0..5 {
+            Bus::clock(bus.clone(), cpu.clone(), ppu.clone(), apu.clone());
+        }
+        bus.borrow_mut().cpu_write(0x0010, 0x42);
+        let before = bus.borrow().save_state();
+        assert_eq!(
+            bus.borrow_mut().load_state(&state),
+            Err(SavestateError::Corrupt(
+                "mapper chunk `CHRR` is 4096 bytes, but this cartridge's is 8192".to_string()
+            ))
+        );
+        assert_eq!(bus.borrow().save_state(), before);
+    }
+
+    #[test]
+    fn state_from_other_rom() {
+        let (bus, ..) = setup();
+        let state = bus.borrow().save_state();
+        let cartridge = Cartridge::from_raw_prg(&[0xEA], 0x8000, [0, 0x8000, 0]).unwrap();
+        let rom_crc32 = cartridge.rom_crc32();
+        let old_rom_crc32 = Bus::swap_cartridge(bus.clone(), cartridge, false).rom_crc32();
+
+        let result = bus.borrow_mut().load_state(&state);
+        assert_eq!(
+            result,
+            Err(SavestateError::WrongRom {
+                savestate_crc32: old_rom_crc32,
+                rom_crc32
+            })
+        );
+        bus.borrow_mut().set_savestate_rom_check(false);
+        assert_eq!(bus.borrow_mut().load_state(&state), Ok(()));
+    }
+
+    #[test]
+    fn ram_crc32() {
+        let (bus, ..) = setup();
+        bus.borrow_mut().set_ram(crate::new_boxed_array());
+        assert_eq!(bus.borrow().ram_crc32(), 0xF1E8BA9E);
+        bus.borrow_mut().cpu_write(0x0800, 0x01);
+        assert_ne!(bus.borrow().ram_crc32(), 0xF1E8BA9E);
+    }
+
+    #[test]
+    fn oam_dma() {
+        let (bus, cpu, ppu, apu) = setup();
+        for i in 0..=0xFF {
+            bus.borrow_mut().cpu_write(0x0200 + i, i as u8);
+        }
+
+        // The copy always covers the whole page, starting from the current OAM address.
+        bus.borrow_mut().cpu_write(0x2003, 0x10);
+        bus.borrow_mut().cpu_write(0x4014, 0x02);
+        let mut cycles = 0;
+        while bus.borrow().oam_dma.is_active() {
+            Bus::clock(bus.clone(), cpu.clone(), ppu.clone(), apu.clone());
+            cycles += 1;
+        }
+        assert!(cycles == 513 || cycles == 514);
+        assert_eq!(ppu.borrow().oam_addr, 0x10);
+
+        // The transfer is reported once the frame it happened in is over.
+        assert_eq!(bus.borrow().dma_stats(), DmaStats::default());
+        Bus::run_frame(bus.clone(), cpu.clone(), ppu.clone(), apu.clone());
+        let stats = bus.borrow().dma_stats();
+        assert_eq!(stats.stall_cycles, cycles);
+        assert_eq!(stats.oam_transfers, 1);
+        assert_eq!(stats.bytes_read, 256);
+        assert_eq!(stats.last_read_addr, Some(0x02FF));
+        for i in [0x00, 0x10, 0xEF, 0xFF] {
+            bus.borrow_mut().cpu_write(0x2003, i);
+            assert_eq!(bus.borrow_mut().cpu_read(0x2004), i.wrapping_sub(0x10));
+        }
+    }
+
+    #[test]
+    fn batched_clocking() {
+        let (bus, cpu, ppu, apu) = setup();
+        let mut cycles = 0;
+        while !ppu.borrow().is_frame_ready {
+            Bus::clock(bus.clone(), cpu.clone(), ppu.clone(), apu.clone());
+            cycles += 1;
+        }
+        ppu.borrow_mut().is_frame_ready = false;
+        Bus::clock(bus.clone(), cpu.clone(), ppu.clone(), apu.clone());
+
+        let (other_bus, other_cpu, other_ppu, other_apu) = setup();
+        let components = || {
+            (
+                other_bus.clone(),
+                other_cpu.clone(),
+                other_ppu.clone(),
+                other_apu.clone(),
+            )
+        };
+        let (bus_ref, cpu_ref, ppu_ref, apu_ref) = components();
+        Bus::run_frame(bus_ref, cpu_ref, ppu_ref, apu_ref);
+        assert!(!other_ppu.borrow().is_frame_ready);
+        assert_eq!(other_bus.borrow().cycle(), cycles);
+        let (bus_ref, cpu_ref, ppu_ref, apu_ref) = components();
+        Bus::run_cycles(bus_ref, cpu_ref, ppu_ref, apu_ref, 1);
+
+        assert_eq!(other_bus.borrow().save_state(), bus.borrow().save_state());
+    }
+
+    #[test]
+    fn stop_conditions() {
+        let (bus, cpu, ppu, apu) = setup();
+        let run = |condition| {
+            Bus::run_until(
+                bus.clone(),
+                cpu.clone(),
+                ppu.clone(),
+                apu.clone(),
+                condition,
+            )
+        };
+
+        assert!(run(StopCondition::Dot {
+            scanline: 31,
+            dot: 80
+        }));
+        let (scanline, dot) = (ppu.borrow().scanline(), ppu.borrow().dot());
+        assert_eq!(scanline, 31);
+        assert!((80..84).contains(&dot), "stopped at dot {dot}");
+
+        // No NMI happens until it's enabled in PPUCTRL, so this gives up.
+        assert!(!run(StopCondition::Nmi));
+        bus.borrow_mut().cpu_write(0x2000, 0x80);
+        assert!(run(StopCondition::Nmi));
+        assert_eq!(ppu.borrow().scanline(), 241);
+        // The zeroed NMI vector points back to the loop.
+        assert_eq!(cpu.borrow().snapshot().program_counter, 0x0000);
+        bus.borrow_mut().cpu_write(0x2000, 0x00);
+
+        // IRQs are ignored while the interrupt disable flag, set by reset, is on.
+        assert!(!cpu.borrow_mut().irq());
+        // CLI, then loop on the JMP.
+        for (addr, byte) in [0x58, 0x4C, 0x01, 0x00].into_iter().enumerate() {
+            bus.borrow_mut().cpu_write(addr as u16, byte);
+        }
+        cpu.borrow_mut().set_program_counter(0x0000);
+        assert!(run(StopCondition::Dot {
+            scanline: 100,
+            dot: 0
+        }));
+        let stack_pointer = cpu.borrow().snapshot().stack_pointer;
+        bus.borrow_mut().request_irq();
+        assert!(run(StopCondition::Irq));
+        let snapshot = cpu.borrow().snapshot();
+        assert_eq!(snapshot.program_counter, 0x0000);
+        assert_eq!(snapshot.stack_pointer, stack_pointer.wrapping_sub(3));
+        assert!(cpu.borrow_mut().irq());
+    }
+
+    #[test]
+    fn sprite_zero_hit() {
+        let (bus, cpu, ppu, apu) = setup();
+        {
+            let mut ppu = ppu.borrow_mut();
+            // Make tile 0 solid, so the background is opaque everywhere and so is sprite 0.
+            for addr in 0..8 {
+                ppu.ppu_write(addr, 0xFF);
+            }
+            ppu.cpu_write(0x03, 0x00);
+            for data in [0x20, 0x00, 0x00, 0x40] {
+                ppu.cpu_write(0x04, data);
+            }
+            ppu.cpu_write(0x01, 0x1E);
+        }
+
+        let run = || {
+            Bus::run_until(
+                bus.clone(),
+                cpu.clone(),
+                ppu.clone(),
+                apu.clone(),
+                StopCondition::SpriteZeroHit,
+            )
+        };
+        // Sprites are drawn a scanline below their Y position, and dots are 1 past the X position.
+        assert!(run());
+        assert_eq!(ppu.borrow().sprite_zero_hit_position(), Some((0x21, 0x41)));
+        let frame_count = ppu.borrow().frame_count();
+        assert!(run());
+        assert_eq!(ppu.borrow().sprite_zero_hit_position(), Some((0x21, 0x41)));
+        assert_eq!(ppu.borrow().frame_count(), frame_count + 1);
+    }
+
+    #[test]
+    fn fast_boot() {
+        let (bus, cpu, ppu, apu) = setup();
+        let boot = FastBoot::UntilInputRead { max_frames: 5 };
+
+        // The program never reads the controllers, so this gives up.
+        let frames = Bus::fast_boot(bus.clone(), cpu.clone(), ppu.clone(), apu.clone(), boot);
+        assert_eq!(frames, 5);
+        assert!(!ppu.borrow().is_output_skipped());
+        assert!(apu.borrow_mut().drain_audio_buffer().is_empty());
+
+        // LDA $4016, then loop back.
+        for (addr, data) in [0xAD, 0x16, 0x40, 0x4C, 0x00, 0x00].into_iter().enumerate() {
+            bus.borrow_mut().cpu_write(addr as u16, data);
+        }
+        let frames = Bus::fast_boot(bus.clone(), cpu.clone(), ppu.clone(), apu.clone(), boot);
+        assert_eq!(frames, 1);
+        assert!(Bus::run_until(bus, cpu, ppu, apu, StopCondition::InputRead));
+    }
+
+    #[test]
+    fn apu_log() {
+        let (bus, cpu, ppu, apu) = setup();
+        // STA $4015, then loop back.
+        for (addr, data) in [0x8D, 0x15, 0x40, 0x4C, 0x00, 0x00].into_iter().enumerate() {
+            bus.borrow_mut().cpu_write(addr as u16, data);
+        }
+        Bus::run_frame(bus.clone(), cpu.clone(), ppu.clone(), apu.clone());
+        assert!(bus.borrow_mut().drain_apu_log().is_empty());
+
+        bus.borrow_mut().set_apu_logging(true);
+        Bus::run_frame(bus.clone(), cpu, ppu, apu);
+        let log = bus.borrow_mut().drain_apu_log();
+        assert!(!log.is_empty());
+        assert!(log
+            .iter()
+            .all(|write| write.addr == 0x4015 && write.frame == 1));
+        assert!(log
+            .windows(2)
+            .all(|writes| writes[0].cycle < writes[1].cycle));
+        assert!(bus.borrow_mut().drain_apu_log().is_empty());
+    }
+
+    /// Picks a PPU or APU register to write to. OAM DMA and the controller ports are left out, as
+    /// they reach beyond the two chips.
+    fn register() -> impl proptest::strategy::Strategy<Value = u16> {
+        use proptest::prelude::*;
+
+        prop_oneof![
+            0x2000..=0x2007u16,
+            0x4000..=0x4013u16,
+            Just(0x4015u16),
+            Just(0x4017u16),
+        ]
+    }
+
+    proptest::proptest! {
+        #![proptest_config(proptest::test_runner::Config::with_cases(32))]
+
+        /// Random register writes, spread out over random stretches of time, never push the PPU or
+        /// APU's counters and indices out of range.
+        #[test]
+        fn register_write_invariants(
+            writes in proptest::collection::vec((register(), 0..=0xFFu8, 0..4000usize), 1..48),
+        ) {
+            use proptest::test_runner::TestCaseError;
+
+            let (bus, cpu, ppu, apu) = setup();
+            for (addr, data, cycles) in writes {
+                bus.borrow_mut().cpu_write(addr, data);
+                Bus::run_cycles(bus.clone(), cpu.clone(), ppu.clone(), apu.clone(), cycles);
+                ppu.borrow().validate_invariants().map_err(TestCaseError::fail)?;
+                apu.borrow().validate_invariants().map_err(TestCaseError::fail)?;
+            }
+        }
+    }
+
+    fn setup() -> Components {
+        // An infinite loop at $0000, which the zeroed reset vector points to.
+        let mut ram = crate::new_boxed_array();
+        ram[0..3].copy_from_slice(&[0x4C, 0x00, 0x00]);
+
+        // An empty cartridge with CHR RAM.
+        let cartridge = Cartridge::from_raw_prg(&[], 0x8000, [0; 3]).unwrap();
+        let cartridge = Rc::new(RefCell::new(cartridge));
+        let cpu = Rc::new(RefCell::new(Cpu::new()));
+        let ppu = Rc::new(RefCell::new(Ppu::new(cartridge.clone())));
+        let apu = Rc::new(RefCell::new(Apu::new()));
+        let bus = Bus::new(cpu.clone(), ram, ppu.clone(), apu.clone(), cartridge);
+        cpu.borrow_mut().reset();
+
+        (bus, cpu, ppu, apu)
+    }
+}
@@ -0,0 +1,191 @@
+use crate::{mapper::SUPPORTED_MAPPERS, savestate::FCEUX_VERSION, Region};
+
+/// The version of the emulator core.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// What this build of the emulator supports, so that frontends and scripts can adapt their UI
+/// and turn away files it can't handle with a precise message, instead of failing to load them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The version of the emulator core.
+    pub version: &'static str,
+    /// The iNES mapper numbers that cartridges can use.
+    pub mappers: &'static [u8],
+    pub regions: &'static [Region],
+    pub accuracy_options: &'static [AccuracyOption],
+    pub accuracy_profiles: &'static [AccuracyProfile],
+    /// The format savestates are read and written in.
+    pub savestate_format: &'static str,
+    /// The FCEUX version written to savestate headers. Savestates written by any version of
+    /// FCEUX are accepted, with chunks this emulator doesn't know about ignored.
+    pub savestate_version: u32,
+    /// The optional Cargo features this build was compiled with.
+    pub features: &'static [&'static str],
+}
+
+/// A setting that changes how accurately the console is emulated, or helps track down accuracy
+/// problems in games.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccuracyOption {
+    /// Warnings for PPU accesses that misbehave on hardware, set with [crate::Ppu::set_validation].
+    PpuValidation,
+    /// Extra scanlines after vblank to reduce slowdown, which real consoles don't have, set with
+    /// [crate::Ppu::set_overclock_scanlines].
+    Overclocking,
+    /// The contents of palette RAM at power-on, which differ between consoles, set with
+    /// [crate::Ppu::set_power_up_palette].
+    PowerUpPalette,
+    /// Rotating which sprites are dropped on scanlines with more than 8, so that they flicker
+    /// instead of disappearing, set with [crate::Ppu::set_sprite_flicker_seed].
+    SpriteFlicker,
+}
+
+impl AccuracyOption {
+    pub const ALL: [Self; 4] = [
+        Self::PpuValidation,
+        Self::Overclocking,
+        Self::PowerUpPalette,
+        Self::SpriteFlicker,
+    ];
+}
+
+impl std::fmt::Display for AccuracyOption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PpuValidation => write!(f, "PPU validation"),
+            Self::Overclocking => write!(f, "overclocking"),
+            Self::PowerUpPalette => write!(f, "power-up palette"),
+            Self::SpriteFlicker => write!(f, "sprite flicker"),
+        }
+    }
+}
+
+/// A preset for the accuracy settings that trade speed for behavior few games rely on, set with
+/// [crate::Bus::set_accuracy_profile].
+///
+/// The profile affects emulation, so replays record the one they were made with.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AccuracyProfile {
+    /// Leaves out CPU dummy accesses and open bus, and applies PPUMASK writes immediately.
+    Fast,
+    /// Emulates everything games are known to rely on.
+    #[default]
+    Balanced,
+    /// Also emulates the PPU ignoring writes while it warms up after power-on, the MMC3's filter
+    /// on PPU A12, and sprite evaluation spread across the scanline, which test ROMs check for
+    /// but games avoid.
+    Accurate,
+}
+
+impl AccuracyProfile {
+    pub const ALL: [Self; 3] = [Self::Fast, Self::Balanced, Self::Accurate];
+
+    /// See [crate::Cpu::set_dummy_accesses].
+    pub const fn has_dummy_accesses(self) -> bool {
+        !matches!(self, Self::Fast)
+    }
+
+    /// See [crate::Ppu::set_mask_delay].
+    pub const fn has_mask_delay(self) -> bool {
+        !matches!(self, Self::Fast)
+    }
+
+    /// See [crate::Ppu::set_warm_up].
+    pub const fn has_ppu_warm_up(self) -> bool {
+        matches!(self, Self::Accurate)
+    }
+
+    /// See [crate::Bus::set_open_bus].
+    pub const fn has_open_bus(self) -> bool {
+        !matches!(self, Self::Fast)
+    }
+
+    /// See [crate::Ppu::set_a12_filter].
+    pub const fn has_a12_filter(self) -> bool {
+        matches!(self, Self::Accurate)
+    }
+
+    /// See [crate::Ppu::set_sprite_evaluation_timing].
+    pub const fn has_sprite_evaluation_timing(self) -> bool {
+        matches!(self, Self::Accurate)
+    }
+}
+
+impl std::str::FromStr for AccuracyProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fast" => Ok(Self::Fast),
+            "balanced" => Ok(Self::Balanced),
+            "accurate" => Ok(Self::Accurate),
+            _ => Err(format!("unknown accuracy profile `{s}`")),
+        }
+    }
+}
+
+impl std::fmt::Display for AccuracyProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fast => write!(f, "fast"),
+            Self::Balanced => write!(f, "balanced"),
+            Self::Accurate => write!(f, "accurate"),
+        }
+    }
+}
+
+impl Capabilities {
+    /// Returns the capabilities of this build.
+    pub fn current() -> Self {
+        Self {
+            version: VERSION,
+            mappers: &SUPPORTED_MAPPERS,
+            regions: &[Region::Ntsc, Region::Pal],
+            accuracy_options: &AccuracyOption::ALL,
+            accuracy_profiles: &AccuracyProfile::ALL,
+            savestate_format: "FCEUX FCS",
+            savestate_version: FCEUX_VERSION,
+            features: &[
+                #[cfg(feature = "logging")]
+                "logging",
+                #[cfg(feature = "memview")]
+                "memview",
+                #[cfg(feature = "gdb")]
+                "gdb",
+            ],
+        }
+    }
+
+    pub fn supports_mapper(&self, id: u8) -> bool {
+        self.mappers.contains(&id)
+    }
+
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.contains(&feature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cartridge;
+
+    #[test]
+    fn mappers() {
+        let capabilities = Capabilities::current();
+        assert!(capabilities.supports_mapper(4));
+        assert!(!capabilities.supports_mapper(5));
+
+        // Every mapper reported as supported can be loaded, and no others.
+        for id in 0..=255u8 {
+            let mut rom = vec![0; 16 + 32 * 1024 + 8 * 1024];
+            rom[0..8].copy_from_slice(&[0x4E, 0x45, 0x53, 0x1A, 2, 1, id << 4, id & 0xF0]);
+            let result = Cartridge::new(&rom);
+            assert_eq!(
+                result.is_ok(),
+                capabilities.supports_mapper(id),
+                "mapper {id}"
+            );
+        }
+    }
+}
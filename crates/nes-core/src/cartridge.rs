@@ -0,0 +1,687 @@
+use std::{cell::RefCell, rc::Weak};
+
+use crate::{
+    is_bit_set,
+    mapper::{Mapper, Mapper0, Mapper1, Mapper2, Mapper228, Mapper4, Mirroring, SUPPORTED_MAPPERS},
+    savestate::{
+        CartridgeState, MapperState, SavestateError, SavestateMetadata, StateSerialize, Subchunk,
+    },
+    Bus, GameGenie, Region,
+};
+
+/// The size of the CHR banks returned by [Cartridge::chr_bank], the smallest any supported mapper
+/// switches.
+pub const CHR_BANK_SIZE: usize = 0x0400;
+/// The size of the trainer some ROMs carry between the header and PRG ROM.
+const TRAINER_SIZE: usize = 512;
+
+pub struct Cartridge {
+    mapper: Box<dyn Mapper>,
+    bus: Weak<RefCell<Bus>>,
+    game_genie: Option<GameGenie>,
+    has_battery: bool,
+    region: Option<Region>,
+    /// Whether the region came from an NES 2.0 header, rather than the unreliable iNES flags.
+    has_nes_20_region: bool,
+    mapper_id: u8,
+    rom_info: RomInfo,
+    /// The CRC32 of PRG and CHR ROM, which identifies the game in savestates.
+    rom_crc32: u32,
+    /// Problems with the ROM or a savestate that were worked around, for the frontend to show.
+    warnings: Vec<String>,
+}
+
+impl Cartridge {
+    pub fn new(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 16 {
+            return Err("header ended unexpectedly".into());
+        }
+        let (header, rest) = bytes.split_at(16);
+        if &header[0..4] != b"NES\x1a" {
+            return Err("not a nes file".into());
+        }
+
+        let mut warnings = Vec::new();
+        let mut header: [u8; 16] = header.try_into().unwrap();
+        repair_header(&mut header, &mut warnings);
+        let rom_info = RomInfo::new(&header);
+
+        let prg_rom_blocks = rom_info.prg_rom_blocks;
+        let chr_rom_blocks = rom_info.chr_rom_blocks;
+        let mapper_id = rom_info.mapper_id;
+        let mirror_flag = rom_info.mirror_flag;
+
+        if rom_info.uses_exponent_size {
+            return Err("exponent-multiplier rom sizes not supported".into());
+        }
+
+        let prg_rom_bytes = prg_rom_blocks as usize * 16 * 1024;
+        let chr_rom_bytes = chr_rom_blocks as usize * 8 * 1024;
+        if prg_rom_bytes == 0 {
+            return Err("header specifies no prg rom".into());
+        }
+
+        // The trainer was loaded into PRG RAM by copiers. Nothing uses it, but it has to be
+        // skipped to find PRG ROM.
+        let trainer_bytes = if rom_info.contains_trainer {
+            TRAINER_SIZE
+        } else {
+            0
+        };
+        let expected_len = 16 + trainer_bytes + prg_rom_bytes + chr_rom_bytes;
+        if bytes.len() != expected_len {
+            let trainer = if trainer_bytes > 0 {
+                format!(", a {trainer_bytes} byte trainer")
+            } else {
+                String::new()
+            };
+            let sizes = format!(
+                "{expected_len} bytes (a 16 byte header{trainer}, {prg_rom_bytes} bytes of prg \
+                 rom, and {chr_rom_bytes} bytes of chr rom)"
+            );
+            if bytes.len() < expected_len {
+                // Say which part was cut short, to tell a truncated file from a wrong header.
+                let missing = if bytes.len() < 16 + trainer_bytes {
+                    "trainer"
+                } else if bytes.len() < 16 + trainer_bytes + prg_rom_bytes {
+                    "prg rom"
+                } else {
+                    "chr rom"
+                };
+                return Err(format!(
+                    "file is {} bytes but header specifies {sizes}, so the {missing} is cut short",
+                    bytes.len()
+                ));
+            }
+            warnings.push(format!(
+                "file is {} bytes but header specifies {sizes}; ignoring the extra {} bytes",
+                bytes.len(),
+                bytes.len() - expected_len
+            ));
+        }
+        let rest = &rest[trainer_bytes..];
+        let (prg_rom, rest) = rest.split_at(prg_rom_bytes);
+        let (chr_rom, _) = rest.split_at(chr_rom_bytes);
+        let mut crc = flate2::Crc::new();
+        crc.update(prg_rom);
+        crc.update(chr_rom);
+
+        let mapper: Box<dyn Mapper> = match mapper_id {
+            0 => Box::new(Mapper0::new(prg_rom, chr_rom, mirror_flag)?),
+            1 => Box::new(Mapper1::new(
+                prg_rom,
+                chr_rom,
+                rom_info.mmc1_prg_ram_size(),
+            )?),
+            2 => Box::new(Mapper2::new(prg_rom, chr_rom, mirror_flag)?),
+            4 => Box::new(Mapper4::new(prg_rom, chr_rom)?),
+            228 => Box::new(Mapper228::new(prg_rom, chr_rom)?),
+            id => {
+                let supported: Vec<_> = SUPPORTED_MAPPERS.iter().map(u8::to_string).collect();
+                return Err(format!(
+                    "mapper {id} not implemented (supported mappers: {})",
+                    supported.join(", ")
+                ));
+            }
+        };
+
+        Ok(Self {
+            mapper,
+            bus: Weak::new(),
+            game_genie: None,
+            has_battery: rom_info.has_persistent_prg_ram,
+            region: rom_info.region,
+            has_nes_20_region: rom_info.uses_nes_20,
+            mapper_id,
+            rom_info,
+            rom_crc32: crc.sum(),
+            warnings,
+        })
+    }
+
+    /// Loads a ROM after applying an IPS or BPS patch to it.
+    pub fn with_patch(bytes: &[u8], patch: &[u8]) -> Result<Self, String> {
+        Self::new(&crate::patch::apply(bytes, patch)?)
+    }
+
+    /// Loads a raw 6502 program with no iNES header into an NROM cartridge, for using the crate
+    /// as a plain 6502 emulator or writing CPU tests.
+    ///
+    /// The program is placed at `origin`, which must be in cartridge space ($8000-$FFFF), and
+    /// `vectors` holds the NMI, reset, and IRQ vectors in that order. Unused space is zeroed.
+    pub fn from_raw_prg(program: &[u8], origin: u16, vectors: [u16; 3]) -> Result<Self, String> {
+        if origin < 0x8000 {
+            return Err(format!(
+                "raw programs must be loaded at $8000 or above, not ${origin:04X}"
+            ));
+        }
+        let start = origin as usize - 0x8000;
+        if start + program.len() > 0x7FFA {
+            return Err(format!(
+                "{} byte program at ${origin:04X} overlaps the interrupt vectors",
+                program.len()
+            ));
+        }
+
+        let mut prg_rom = vec![0; 32 * 1024];
+        prg_rom[start..start + program.len()].copy_from_slice(program);
+        for (i, vector) in vectors.iter().enumerate() {
+            let index = 0x7FFA + i * 2;
+            prg_rom[index..index + 2].copy_from_slice(&vector.to_le_bytes());
+        }
+
+        Ok(Self {
+            mapper: Box::new(Mapper0::new(&prg_rom, &[], 0)?),
+            bus: Weak::new(),
+            game_genie: None,
+            has_battery: false,
+            region: None,
+            has_nes_20_region: false,
+            mapper_id: 0,
+            // NROM with 32k of PRG ROM and CHR RAM, as the mapper is set up.
+            rom_info: RomInfo::new(&[b'N', b'E', b'S', 0x1A, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+            rom_crc32: crate::crc32(&prg_rom),
+            warnings: Vec::new(),
+        })
+    }
+
+    pub fn mapper_id(&self) -> u8 {
+        self.mapper_id
+    }
+
+    /// Returns what the ROM's header says about the cartridge, for frontends to show.
+    pub fn rom_info(&self) -> &RomInfo {
+        &self.rom_info
+    }
+
+    /// Returns the CRC32 of the ROM's PRG and CHR ROM, leaving out the header so that fixing a
+    /// header doesn't change it.
+    pub fn rom_crc32(&self) -> u32 {
+        self.rom_crc32
+    }
+
+    /// Takes the warnings about problems with the ROM, or with mapper state from savestates, that
+    /// were worked around since the last call. See [crate::Bus::drain_warnings].
+    pub fn drain_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Returns the region the ROM's header says it was made for, if it says.
+    pub fn header_region(&self) -> Option<Region> {
+        self.region
+    }
+
+    /// Picks the region to emulate for this ROM. An NES 2.0 header is trusted first, then tags in
+    /// the ROM's file name if given, then the old iNES region flags, which are often garbage in
+    /// dumps from the era. Anything undetermined defaults to NTSC.
+    pub fn detect_region(&self, filename: Option<&str>) -> Region {
+        let from_filename = || filename.and_then(Region::from_filename);
+        match (self.region, self.has_nes_20_region) {
+            (Some(region), true) => region,
+            (region, _) => from_filename().or(region).unwrap_or_default(),
+        }
+    }
+
+    pub fn connect_bus(&mut self, bus: Weak<RefCell<Bus>>) {
+        self.bus = bus;
+    }
+
+    pub fn set_game_genie_codes<T: AsRef<str>>(&mut self, codes: &[T]) -> Result<(), String> {
+        self.game_genie = Some(GameGenie::new(codes)?);
+        Ok(())
+    }
+
+    pub fn cpu_read(&self, addr: u16) -> u8 {
+        let value = self.mapper.cpu_read(addr);
+        if let Some(game_genie) = self.game_genie.as_ref() {
+            for code in game_genie.codes() {
+                if code.address == addr && (code.compare == Some(value) || code.compare.is_none()) {
+                    return code.value;
+                }
+            }
+        }
+        value
+    }
+
+    pub fn cpu_write(&mut self, addr: u16, data: u8) {
+        self.mapper.cpu_write(addr, data)
+    }
+
+    pub fn ppu_read(&self, addr: u16) -> u8 {
+        self.mapper.ppu_read(addr)
+    }
+
+    pub fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.mapper.ppu_write(addr, data)
+    }
+
+    /// Returns a copy of the 8 KiB of CHR currently banked into the PPU's pattern tables.
+    pub fn banked_chr(&self) -> Vec<u8> {
+        (0..0x2000).map(|addr| self.mapper.ppu_read(addr)).collect()
+    }
+
+    /// Returns all of the cartridge's CHR ROM, or its CHR RAM, for tools that browse more than
+    /// what's banked in.
+    pub fn chr(&self) -> &[u8] {
+        self.mapper.chr()
+    }
+
+    /// Returns the number of [CHR_BANK_SIZE] banks of CHR.
+    pub fn chr_bank_count(&self) -> usize {
+        self.mapper.chr().len() / CHR_BANK_SIZE
+    }
+
+    /// Returns a [CHR_BANK_SIZE] bank of CHR, or `None` if there's no such bank.
+    pub fn chr_bank(&self, bank: usize) -> Option<&[u8]> {
+        self.mapper.chr().chunks_exact(CHR_BANK_SIZE).nth(bank)
+    }
+
+    /// Returns which CHR bank is mapped into each [CHR_BANK_SIZE] window of the pattern tables.
+    pub fn banked_chr_banks(&self) -> [usize; 8] {
+        std::array::from_fn(|window| {
+            self.mapper.map_chr_addr((window * CHR_BANK_SIZE) as u16) / CHR_BANK_SIZE
+        })
+    }
+
+    pub fn mirroring(&self) -> Mirroring {
+        self.mapper.mirroring()
+    }
+
+    pub fn maps_cpu_addr(&self, addr: u16) -> bool {
+        self.mapper.maps_cpu_addr(addr)
+    }
+
+    pub fn count_scanline(&mut self) {
+        self.mapper.count_scanline();
+        if self.mapper.check_irq() {
+            self.bus.upgrade().unwrap().borrow_mut().request_irq();
+        }
+    }
+
+    pub fn clock_audio(&mut self) -> Option<i16> {
+        self.mapper.clock_audio()
+    }
+
+    /// Returns the contents of battery-backed PRG RAM, or `None` if the cartridge has no battery.
+    pub fn battery_ram(&self) -> Option<&[u8]> {
+        self.mapper.prg_ram().filter(|_| self.has_battery)
+    }
+
+    /// Restores battery-backed PRG RAM from a previous session's save.
+    pub fn load_battery_ram(&mut self, data: &[u8]) -> Result<(), String> {
+        if !self.has_battery {
+            return Err("cartridge has no battery".into());
+        }
+        let Some(prg_ram) = self.mapper.prg_ram_mut() else {
+            return Err("cartridge has no prg ram".into());
+        };
+        if data.len() != prg_ram.len() {
+            return Err(format!(
+                "save is {} bytes but prg ram is {}",
+                data.len(),
+                prg_ram.len()
+            ));
+        }
+        prg_ram.copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Returns whether battery-backed PRG RAM has changed since the last call, so frontends only
+    /// need to write a save out when there's something new in it.
+    pub fn take_dirty(&mut self) -> bool {
+        self.mapper.take_dirty() && self.has_battery
+    }
+}
+
+impl Cartridge {
+    /// Checks that a savestate was made with this cartridge's ROM, if it says which ROM it was
+    /// made with.
+    pub(crate) fn check_identity(
+        &self,
+        metadata: &SavestateMetadata,
+    ) -> Result<(), SavestateError> {
+        match metadata.rom_crc32 {
+            Some(savestate_crc32) if savestate_crc32 != self.rom_crc32 => {
+                Err(SavestateError::WrongRom {
+                    savestate_crc32,
+                    rom_crc32: self.rom_crc32,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that a mapper state can be applied to this cartridge without leaving it half
+    /// updated. Every chunk the mapper saves itself has to be the same size in the state, as any
+    /// other size means the state is malformed or from a cartridge with a different mapper or
+    /// amount of RAM. Chunks the mapper doesn't save, like FCEUX-only ones, are left to it.
+    pub(crate) fn validate_state(&self, state: &MapperState) -> Result<(), String> {
+        let own_state = self.mapper.save_state();
+        for (description, own_section) in Subchunk::new(&own_state)? {
+            for (_, section) in state.chunks().filter(|&(other, _)| other == description) {
+                if section.len() != own_section.len() {
+                    return Err(format!(
+                        "mapper chunk `{description}` is {} bytes, but this cartridge's is {}",
+                        section.len(),
+                        own_section.len()
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the mapper's state, for comparing it with another cartridge's. See
+    /// [crate::SystemState].
+    pub fn state(&self) -> CartridgeState {
+        self.mapper.state()
+    }
+}
+
+/// Only the mapper's state is saved, as the ROM itself is loaded separately.
+impl StateSerialize for Cartridge {
+    type State<'a> = MapperState<'a>;
+
+    fn apply_state(&mut self, state: MapperState) {
+        self.mapper.apply_state(state, &mut self.warnings);
+    }
+
+    fn save_state_into(&self, buffer: &mut Vec<u8>) {
+        self.mapper.save_state_into(buffer)
+    }
+}
+
+/// Clears junk written over the end of an iNES header, such as the `DiskDude!` signature left by
+/// an old ROM tool, which would otherwise be read as the upper bits of the mapper number and
+/// other flags.
+///
+/// iNES leaves bytes 12-15 as zero, so junk there means bytes 7-15 can't be trusted either.
+/// NES 2.0 headers use all of them, so they're left alone.
+fn repair_header(header: &mut [u8; 16], warnings: &mut Vec<String>) {
+    let uses_nes_20 = !is_bit_set(header[7], 2) && is_bit_set(header[7], 3);
+    if uses_nes_20 || header[12..16].iter().all(|&byte| byte == 0) {
+        return;
+    }
+    warnings.push(format!(
+        "ignoring junk at the end of the header ({:?})",
+        String::from_utf8_lossy(&header[7..16])
+    ));
+    header[7..16].fill(0);
+}
+
+/// What an iNES or NES 2.0 header says about a cartridge. The [std::fmt::Display] implementation
+/// lists every field, one per line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomInfo {
+    uses_nes_20: bool,
+    uses_exponent_size: bool,
+    prg_rom_blocks: u16,
+    chr_rom_blocks: u16,
+    has_persistent_prg_ram: bool,
+    prg_ram_size: usize,
+    has_chr_ram: bool,
+    mirror_flag: u8,
+    uses_alternate_nametable_layout: bool,
+    contains_trainer: bool,
+    mapper_id: u8,
+    region: Option<Region>,
+}
+
+impl RomInfo {
+    pub fn new(header: &[u8; 16]) -> Self {
+        let uses_nes_20 = {
+            let byte = header[7];
+            !is_bit_set(byte, 2) && is_bit_set(byte, 3)
+        };
+        // NES 2.0 stores the upper bits of the ROM sizes in byte 9. A nibble of 0xF indicates that
+        // the size uses the exponent-multiplier notation instead.
+        let (prg_rom_msb, chr_rom_msb) = if uses_nes_20 {
+            (header[9] & 0x0F, header[9] >> 4)
+        } else {
+            (0, 0)
+        };
+        let uses_exponent_size = prg_rom_msb == 0x0F || chr_rom_msb == 0x0F;
+        let prg_rom_blocks = (prg_rom_msb as u16) << 8 | header[4] as u16;
+        let chr_rom_blocks = (chr_rom_msb as u16) << 8 | header[5] as u16;
+        let has_persistent_prg_ram = header[6] & 0x02 != 0;
+        let prg_ram_size = if uses_nes_20 {
+            // Volatile and battery-backed sizes are each stored as a shift count, where 0 means
+            // there is none.
+            let size = |shift: u8| if shift == 0 { 0 } else { 64 << shift };
+            size(header[10] & 0x0F) + size(header[10] >> 4)
+        } else {
+            // Stored in 8k units. Hardly any ROMs set this, so 0 is treated as 8k.
+            header[8].max(1) as usize * 8 * 1024
+        };
+        let has_chr_ram = chr_rom_blocks == 0;
+        let mirror_flag = header[6] & 0x01;
+        let uses_alternate_nametable_layout = header[6] & 0x08 != 0;
+        let contains_trainer = header[6] & 0x04 != 0;
+        let mapper_id = header[6] >> 4 | (header[7] & 0xF0);
+        let region = if uses_nes_20 {
+            match header[12] & 0x03 {
+                0 => Some(Region::Ntsc),
+                1 => Some(Region::Pal),
+                // Dendy consoles aren't emulated, but run at PAL's frame rate.
+                3 => Some(Region::Pal),
+                // Multi-region.
+                _ => None,
+            }
+        } else if header[9] & 0x01 != 0 || header[10] & 0x03 == 2 {
+            Some(Region::Pal)
+        } else {
+            // An unset flag is the default rather than a sign the ROM is NTSC-only.
+            None
+        };
+
+        Self {
+            uses_nes_20,
+            uses_exponent_size,
+            prg_rom_blocks,
+            chr_rom_blocks,
+            has_persistent_prg_ram,
+            prg_ram_size,
+            has_chr_ram,
+            mirror_flag,
+            uses_alternate_nametable_layout,
+            contains_trainer,
+            mapper_id,
+            region,
+        }
+    }
+    /// Returns the amount of PRG RAM on an MMC1 board, which is 8k except on SOROM (16k) and
+    /// SXROM (32k) boards.
+    ///
+    /// Both of those boards have CHR RAM, so an iNES 1.0 header's PRG RAM size is only trusted
+    /// alongside CHR RAM, as it's often left over from other boards. Sizes from NES 2.0 headers
+    /// are trusted, but can add up to amounts no board has, such as 8k of battery-backed RAM
+    /// plus 2k of work RAM, so they're rounded up to the nearest board.
+    fn mmc1_prg_ram_size(&self) -> usize {
+        let size = if self.uses_nes_20 || self.has_chr_ram {
+            self.prg_ram_size
+        } else {
+            0
+        };
+        match size {
+            0..=0x2000 => 8 * 1024,
+            0x2001..=0x4000 => 16 * 1024,
+            _ => 32 * 1024,
+        }
+    }
+}
+
+impl std::fmt::Display for RomInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "uses nes 2.0 format: {}", self.uses_nes_20)?;
+        writeln!(f, "prg rom size: {}k", self.prg_rom_blocks as usize * 16)?;
+        writeln!(f, "chr rom size: {}k", self.chr_rom_blocks as usize * 8)?;
+        writeln!(f, "has persistent prg ram: {}", self.has_persistent_prg_ram)?;
+        writeln!(f, "prg ram size: {}k", self.prg_ram_size / 1024)?;
+        writeln!(f, "has chr ram: {}", self.has_chr_ram)?;
+        writeln!(
+            f,
+            "nametable layout (if hardwired): {}",
+            if self.mirror_flag == 0 {
+                "vertical"
+            } else {
+                "horizontal"
+            }
+        )?;
+        writeln!(
+            f,
+            "uses alternate nametable layout: {}",
+            self.uses_alternate_nametable_layout
+        )?;
+        writeln!(f, "contains trainer: {}", self.contains_trainer)?;
+        writeln!(f, "mapper id: {}", self.mapper_id)?;
+        match self.region {
+            Some(region) => write!(f, "region: {region}")?,
+            None => write!(f, "region: unspecified")?,
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chr_banks() {
+        // An MMC3 with 32k of PRG ROM and 32k of CHR ROM, with each 1k bank filled with its number.
+        let mut rom = b"NES\x1a\x02\x04\x40\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        rom.resize(16 + 32 * 1024, 0);
+        rom.extend((0..32).flat_map(|bank| [bank as u8; CHR_BANK_SIZE]));
+        let mut cartridge = Cartridge::new(&rom).unwrap();
+        assert_eq!(cartridge.chr().len(), 32 * 1024);
+        assert_eq!(cartridge.chr_bank_count(), 32);
+        assert_eq!(cartridge.chr_bank(31), Some(&[31; CHR_BANK_SIZE][..]));
+        assert_eq!(cartridge.chr_bank(32), None);
+
+        // Map bank 6-7 into $0000 and bank 21 into $1C00.
+        for (register, bank) in [(0, 6), (5, 21)] {
+            cartridge.cpu_write(0x8000, register);
+            cartridge.cpu_write(0x8001, bank);
+        }
+        let banks = cartridge.banked_chr_banks();
+        assert_eq!(banks[..2], [6, 7]);
+        assert_eq!(banks[7], 21);
+        assert_eq!(cartridge.ppu_read(0x1C00), 21);
+
+        // CHR inversion swaps the halves.
+        cartridge.cpu_write(0x8000, 0x80);
+        assert_eq!(cartridge.banked_chr_banks()[4..6], [6, 7]);
+
+        let rom_info = cartridge.rom_info().to_string();
+        assert!(rom_info.contains("chr rom size: 32k"), "{rom_info}");
+        assert!(rom_info.contains("mapper id: 4"), "{rom_info}");
+    }
+
+    #[test]
+    fn mmc1_prg_ram_sizes() {
+        // A battery-backed MMC1 with 256k of PRG ROM.
+        let prg_ram_size = |chr_blocks: u8, flags_7: u8, byte_8: u8, byte_10: u8| {
+            let mut rom = b"NES\x1a\x10\x00\x12\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+            rom[5] = chr_blocks;
+            rom[7] = flags_7;
+            rom[8] = byte_8;
+            rom[10] = byte_10;
+            rom.resize(16 + 256 * 1024 + chr_blocks as usize * 8 * 1024, 0);
+            Cartridge::new(&rom).unwrap().battery_ram().unwrap().len() / 1024
+        };
+
+        // iNES 1.0: SNROM, SOROM and SXROM, told apart by the PRG RAM size.
+        assert_eq!(prg_ram_size(0, 0, 0, 0), 8);
+        assert_eq!(prg_ram_size(0, 0, 1, 0), 8);
+        assert_eq!(prg_ram_size(0, 0, 2, 0), 16);
+        assert_eq!(prg_ram_size(0, 0, 4, 0), 32);
+        assert_eq!(prg_ram_size(0, 0, 3, 0), 32);
+        // Boards with CHR ROM only have 8k, whatever the header says.
+        assert_eq!(prg_ram_size(2, 0, 4, 0), 8);
+
+        // NES 2.0, with work RAM in the low nibble and battery-backed RAM in the high one, each as
+        // a shift count of 64 bytes.
+        let nes_20 = |chr_blocks, work: u8, battery: u8| {
+            prg_ram_size(chr_blocks, 0x08, 0, battery << 4 | work)
+        };
+        assert_eq!(nes_20(0, 0, 7), 8);
+        assert_eq!(nes_20(0, 7, 7), 16);
+        assert_eq!(nes_20(0, 8, 8), 32);
+        assert_eq!(nes_20(2, 0, 9), 32);
+        // Sizes no board has are rounded up rather than rejected.
+        assert_eq!(nes_20(0, 1, 0), 8);
+        assert_eq!(nes_20(0, 5, 7), 16);
+        assert_eq!(nes_20(0, 1, 8), 32);
+        assert_eq!(nes_20(0, 0, 10), 32);
+    }
+
+    #[test]
+    fn region_detection() {
+        let cartridge = |header: [u8; 16]| {
+            let mut rom = header.to_vec();
+            rom.resize(16 + 16 * 1024, 0);
+            Cartridge::new(&rom).unwrap()
+        };
+        let mut header = *b"NES\x1a\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00";
+
+        // Nothing in the header, so the file name decides.
+        let ines = cartridge(header);
+        assert_eq!(ines.detect_region(None), Region::Ntsc);
+        assert_eq!(ines.detect_region(Some("Game (E).nes")), Region::Pal);
+
+        header[9] = 0x01;
+        assert_eq!(cartridge(header).detect_region(None), Region::Pal);
+        // Junk at the end of the header makes the iNES flags meaningless.
+        header[12..16].copy_from_slice(b"Dude");
+        assert_eq!(cartridge(header).header_region(), None);
+
+        // NES 2.0 headers are trusted over the file name.
+        header[7] = 0x08;
+        header[9] = 0x00;
+        header[12..16].fill(0);
+        header[12] = 0x01;
+        let nes_20 = cartridge(header);
+        assert_eq!(nes_20.detect_region(Some("Game (U).nes")), Region::Pal);
+    }
+
+    #[test]
+    fn malformed_headers() {
+        let mut rom = b"NES\x1a\x01\x01\x00DiskDude!".to_vec();
+        rom.extend((0..16 * 1024).map(|i| (i / 1024) as u8));
+        rom.resize(16 + 24 * 1024, 0xCC);
+
+        // The "D" would otherwise make this mapper 64.
+        let mut cartridge = Cartridge::new(&rom).unwrap();
+        assert_eq!(cartridge.mapper_id(), 0);
+        let warnings = cartridge.drain_warnings();
+        assert!(warnings[0].contains("DiskDude!"), "{warnings:?}");
+        assert!(cartridge.drain_warnings().is_empty());
+        assert_eq!(cartridge.cpu_read(0x8400), 1);
+
+        // A trainer comes before PRG ROM.
+        let mut trainer_rom = rom[..16].to_vec();
+        trainer_rom[6] |= 0x04;
+        trainer_rom.extend_from_slice(&[0xEE; TRAINER_SIZE]);
+        trainer_rom.extend_from_slice(&rom[16..]);
+        let cartridge = Cartridge::new(&trainer_rom).unwrap();
+        assert_eq!(cartridge.cpu_read(0x8400), 1);
+        assert_eq!(
+            cartridge.rom_crc32(),
+            Cartridge::new(&rom).unwrap().rom_crc32()
+        );
+
+        let err = Cartridge::new(&rom[..16 + 20 * 1024]).err().unwrap();
+        assert!(err.contains("chr rom is cut short"), "{err}");
+        let err = Cartridge::new(&rom[..16 + 1024]).err().unwrap();
+        assert!(err.contains("prg rom is cut short"), "{err}");
+
+        // Extra data at the end is ignored.
+        rom.extend_from_slice(&[0; 128]);
+        let warnings = Cartridge::new(&rom).unwrap().drain_warnings();
+        assert!(
+            warnings[1].contains("ignoring the extra 128 bytes"),
+            "{warnings:?}"
+        );
+    }
+}
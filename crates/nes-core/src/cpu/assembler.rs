@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+
+use super::{AddressingMode, OpcodeInfo, OPCODE_TABLE};
+
+/// Assembles a program to be loaded at `origin`, for writing test programs and demos without
+/// hand-assembling them.
+///
+/// Each line holds an optional `label:`, then an instruction or a `.byte` directive, then an
+/// optional `; comment`. Operands use the usual 6502 syntax (`#$10`, `$10,X`, `($10),Y`,
+/// `($1234)`, and so on), and labels can be used anywhere an address can. Numbers can be written
+/// in hex (`$FF`), binary (`%1010`), or decimal. Addresses below $100 use zero page addressing
+/// where possible, unless they're written as hex with more than 2 digits (`$0010`).
+pub fn assemble(origin: u16, source: &str) -> Result<Vec<u8>, String> {
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut addr = origin;
+
+    // Lay out every statement first, so that labels can be used before they're defined.
+    for (i, line) in source.lines().enumerate() {
+        let error = |message: String| format!("line {}: {message}", i + 1);
+        let line = line.split(';').next().unwrap_or_default().trim();
+        let line = match line.split_once(':') {
+            Some((label, rest)) => {
+                let label = label.trim();
+                if !is_identifier(label) {
+                    return Err(error(format!("invalid label `{label}`")));
+                }
+                if labels.insert(label, addr).is_some() {
+                    return Err(error(format!("label `{label}` is already defined")));
+                }
+                rest.trim()
+            }
+            None => line,
+        };
+        if line.is_empty() {
+            continue;
+        }
+
+        let statement = Statement::parse(line).map_err(error)?;
+        let length = statement.length();
+        statements.push((i + 1, addr, statement));
+        addr = addr.wrapping_add(length);
+    }
+
+    let mut bytes = Vec::new();
+    for (line, addr, statement) in statements {
+        statement
+            .emit(addr, &labels, &mut bytes)
+            .map_err(|message| format!("line {line}: {message}"))?;
+    }
+    Ok(bytes)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Value<'a> {
+    /// A number, and whether it was written in a way that asks for a 16-bit operand.
+    Number {
+        value: u16,
+        is_wide: bool,
+    },
+    Label(&'a str),
+}
+
+#[derive(Debug)]
+enum Statement<'a> {
+    Instruction {
+        opcode: u8,
+        addr_mode: AddressingMode,
+        operand: Option<Value<'a>>,
+    },
+    Bytes(Vec<u8>),
+}
+
+impl<'a> Statement<'a> {
+    fn parse(line: &'a str) -> Result<Self, String> {
+        let (mnemonic, operand) = match line.split_once(char::is_whitespace) {
+            Some((mnemonic, operand)) => (mnemonic, operand.trim()),
+            None => (line, ""),
+        };
+
+        if mnemonic.eq_ignore_ascii_case(".byte") {
+            let bytes = operand
+                .split(',')
+                .map(|value| match parse_value(value.trim())? {
+                    Value::Number { value, .. } if value <= 0xFF => Ok(value as u8),
+                    _ => Err(format!("`{}` isn't a byte", value.trim())),
+                })
+                .collect::<Result<_, _>>()?;
+            return Ok(Self::Bytes(bytes));
+        }
+
+        let mnemonic = mnemonic.to_ascii_uppercase();
+        let find = |addr_mode| {
+            OPCODE_TABLE
+                .iter()
+                .flatten()
+                .filter(|info| {
+                    info.instruction.mnemonic() == mnemonic && info.addr_mode == addr_mode
+                })
+                // Prefer the official opcode where illegal opcodes do the same thing.
+                .min_by_key(|info| !info.is_official)
+        };
+
+        use AddressingMode::*;
+        let (addr_modes, value): (&[AddressingMode], _) =
+            if operand.is_empty() || operand.eq_ignore_ascii_case("a") {
+                (&[Implicit, Accumulator], None)
+            } else if let Some(value) = operand.strip_prefix('#') {
+                (&[Immediate], Some(value))
+            } else if let Some(inner) = operand.strip_prefix('(') {
+                if let Some(value) = strip_suffix(inner, ",x)") {
+                    (&[IndexedIndirect], Some(value))
+                } else if let Some(value) = strip_suffix(inner, "),y") {
+                    (&[IndirectIndexed], Some(value))
+                } else if let Some(value) = inner.strip_suffix(')') {
+                    (&[Indirect], Some(value))
+                } else {
+                    return Err(format!("invalid operand `{operand}`"));
+                }
+            } else if let Some(value) = strip_suffix(operand, ",x") {
+                (&[ZeroPageX, AbsoluteX], Some(value))
+            } else if let Some(value) = strip_suffix(operand, ",y") {
+                (&[ZeroPageY, AbsoluteY], Some(value))
+            } else {
+                (&[Relative, ZeroPage, Absolute], Some(operand))
+            };
+        let raw_operand = operand;
+        let operand = value.map(|value| parse_value(value.trim())).transpose()?;
+
+        let fits_in_zero_page = matches!(
+            operand,
+            Some(Value::Number { value, is_wide: false }) if value <= 0xFF
+        );
+        let info = addr_modes
+            .iter()
+            .filter(|&&addr_mode| {
+                !matches!(addr_mode, ZeroPage | ZeroPageX | ZeroPageY) || fits_in_zero_page
+            })
+            .find_map(|&addr_mode| find(addr_mode))
+            .ok_or_else(|| format!("`{mnemonic}` doesn't support the operand `{raw_operand}`"))?;
+
+        let OpcodeInfo {
+            opcode, addr_mode, ..
+        } = *info;
+        Ok(Self::Instruction {
+            opcode,
+            addr_mode,
+            operand,
+        })
+    }
+
+    fn length(&self) -> u16 {
+        match self {
+            Self::Instruction { addr_mode, .. } => 1 + addr_mode.operand_length() as u16,
+            Self::Bytes(bytes) => bytes.len() as u16,
+        }
+    }
+
+    fn emit(
+        &self,
+        addr: u16,
+        labels: &HashMap<&str, u16>,
+        bytes: &mut Vec<u8>,
+    ) -> Result<(), String> {
+        let (opcode, addr_mode, operand) = match self {
+            Self::Instruction {
+                opcode,
+                addr_mode,
+                operand,
+            } => (*opcode, *addr_mode, *operand),
+            Self::Bytes(data) => {
+                bytes.extend_from_slice(data);
+                return Ok(());
+            }
+        };
+        let value = match operand {
+            Some(Value::Number { value, .. }) => value,
+            Some(Value::Label(label)) => *labels
+                .get(label)
+                .ok_or_else(|| format!("unknown label `{label}`"))?,
+            None => 0,
+        };
+
+        bytes.push(opcode);
+        match addr_mode.operand_length() {
+            0 => (),
+            1 if addr_mode == AddressingMode::Relative => {
+                // Branch targets are relative to the end of the instruction.
+                let offset = value as i32 - (addr as i32 + 2);
+                let offset = i8::try_from(offset)
+                    .map_err(|_| format!("branch target ${value:04X} is out of range"))?;
+                bytes.push(offset as u8);
+            }
+            1 => {
+                let value = u8::try_from(value)
+                    .map_err(|_| format!("operand ${value:04X} doesn't fit in a byte"))?;
+                bytes.push(value);
+            }
+            _ => bytes.extend_from_slice(&value.to_le_bytes()),
+        }
+        Ok(())
+    }
+}
+
+fn parse_value(value: &str) -> Result<Value<'_>, String> {
+    let number = |digits: &str, radix| {
+        u16::from_str_radix(digits, radix).map_err(|_| format!("invalid number `{value}`"))
+    };
+    if let Some(digits) = value.strip_prefix('$') {
+        Ok(Value::Number {
+            value: number(digits, 16)?,
+            is_wide: digits.len() > 2,
+        })
+    } else if let Some(digits) = value.strip_prefix('%') {
+        Ok(Value::Number {
+            value: number(digits, 2)?,
+            is_wide: digits.len() > 8,
+        })
+    } else if value.starts_with(|char: char| char.is_ascii_digit()) {
+        Ok(Value::Number {
+            value: number(value, 10)?,
+            is_wide: false,
+        })
+    } else if is_identifier(value) {
+        Ok(Value::Label(value))
+    } else {
+        Err(format!("invalid operand `{value}`"))
+    }
+}
+
+/// Strips an index suffix like `,X`, ignoring case and whitespace around the comma.
+fn strip_suffix<'a>(operand: &'a str, suffix: &str) -> Option<&'a str> {
+    let (value, index) = operand.rsplit_once(',')?;
+    let (suffix_value, suffix_index) = suffix.rsplit_once(',')?;
+    let value = value.trim_end();
+    let value = value.strip_suffix(suffix_value)?;
+    index
+        .trim()
+        .eq_ignore_ascii_case(suffix_index)
+        .then_some(value)
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|char: char| char.is_ascii_alphabetic() || char == '_')
+        && name
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addressing_modes() {
+        let program = assemble(
+            0x8000,
+            "
+            lda #$02        ; Immediate.
+            sta $10         ; Zero page.
+            sta $0010       ; Absolute, because of the 4 digit address.
+            ldx $10,y       ; Zero page, Y.
+            lda ($10),Y     ; Indirect indexed.
+            lda ($10, x)    ; Indexed indirect.
+            asl             ; Accumulator.
+            jmp ($1234)     ; Indirect.
+            .byte 1, %11, $FF
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            program,
+            [
+                0xA9, 0x02, 0x85, 0x10, 0x8D, 0x10, 0x00, 0xB6, 0x10, 0xB1, 0x10, 0xA1, 0x10, 0x0A,
+                0x6C, 0x34, 0x12, 0x01, 0x03, 0xFF,
+            ]
+        );
+    }
+
+    #[test]
+    fn labels() {
+        let program = assemble(
+            0x8000,
+            "
+            start:
+                ldx #3
+            loop: dex
+                bne loop
+                beq end
+                nop
+            end: jmp start
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            program,
+            [0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0xF0, 0x01, 0xEA, 0x4C, 0x00, 0x80]
+        );
+
+        assert_eq!(
+            assemble(0, "lda #$100"),
+            Err("line 1: operand $0100 doesn't fit in a byte".into())
+        );
+        assert_eq!(
+            assemble(0, "\njmp nowhere"),
+            Err("line 2: unknown label `nowhere`".into())
+        );
+        assert!(assemble(0, "sta #$10").is_err());
+    }
+}
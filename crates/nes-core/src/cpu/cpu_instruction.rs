@@ -8,15 +8,16 @@ pub struct CpuInstruction {
 }
 
 impl CpuInstruction {
-    fn new(instruction: Instruction, addr_mode: AddressingMode) -> Self {
+    const fn new(instruction: Instruction, addr_mode: AddressingMode) -> Self {
         Self {
             instruction,
             addr_mode,
         }
     }
 
-    pub fn decode(opcode: u8) -> Self {
-        match opcode {
+    /// Decodes an opcode, returning `None` for the few illegal opcodes that aren't supported.
+    pub const fn try_decode(opcode: u8) -> Option<Self> {
+        let instruction = match opcode {
             0x00 => Self::new(Instruction::Brk, AddressingMode::Implicit),
             0x01 => Self::new(Instruction::Ora, AddressingMode::IndexedIndirect),
             0x05 => Self::new(Instruction::Ora, AddressingMode::ZeroPage),
@@ -250,7 +251,11 @@ impl CpuInstruction {
             0xFB => Self::new(Instruction::Isc, AddressingMode::AbsoluteY),
             0xFC => Self::new(Instruction::Nop, AddressingMode::AbsoluteX),
             0xFF => Self::new(Instruction::Isc, AddressingMode::AbsoluteX),
-            other => unimplemented!("unsupported illegal opcode: 0x{other:02X}"),
-        }
+            0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xB2 | 0xD2 | 0xF2 => {
+                Self::new(Instruction::Jam, AddressingMode::Implicit)
+            }
+            _ => return None,
+        };
+        Some(instruction)
     }
 }
@@ -0,0 +1,38 @@
+/// How many of the most recently executed instructions are kept for [CpuHalt::trace].
+pub(super) const TRACE_LENGTH: usize = 16;
+
+/// Why the CPU stopped executing instructions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HaltReason {
+    /// One of the JAM opcodes was executed, which locks up a real 6502 until it's reset.
+    Jam { opcode: u8 },
+    /// One of the few unstable illegal opcodes that isn't emulated was executed. Real consoles
+    /// keep running, but what these do varies between chips.
+    UnsupportedOpcode { opcode: u8 },
+    /// A BRK jumped to another BRK through the IRQ vector, which would repeat forever.
+    BrkLoop,
+}
+
+/// The state of a CPU that has stopped, as returned by [super::Cpu::halt].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuHalt {
+    pub reason: HaltReason,
+    /// The address of the instruction the CPU is stuck on.
+    pub program_counter: u16,
+    /// The addresses of the most recently executed instructions, oldest first, ending with the
+    /// one that halted the CPU.
+    pub trace: Vec<u16>,
+}
+
+impl std::fmt::Display for CpuHalt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.reason {
+            HaltReason::Jam { opcode } => write!(f, "jammed by opcode ${opcode:02X}")?,
+            HaltReason::UnsupportedOpcode { opcode } => {
+                write!(f, "stopped by unsupported opcode ${opcode:02X}")?
+            }
+            HaltReason::BrkLoop => write!(f, "stuck in a BRK loop")?,
+        }
+        write!(f, " at ${:04X}", self.program_counter)
+    }
+}
@@ -0,0 +1,170 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Lda,
+    Ldx,
+    Ldy,
+    Sta,
+    Stx,
+    Sty,
+    Tax,
+    Tay,
+    Txa,
+    Tya,
+    Tsx,
+    Txs,
+    Pha,
+    Php,
+    Pla,
+    Plp,
+    And,
+    Eor,
+    Ora,
+    Bit,
+    Adc,
+    Sbc,
+    Cmp,
+    Cpx,
+    Cpy,
+    Inc,
+    Inx,
+    Iny,
+    Dec,
+    Dex,
+    Dey,
+    Asl,
+    Lsr,
+    Rol,
+    Ror,
+    Jmp,
+    Jsr,
+    Rts,
+    Bcc,
+    Bcs,
+    Beq,
+    Bmi,
+    Bne,
+    Bpl,
+    Bvc,
+    Bvs,
+    Clc,
+    Cld,
+    Cli,
+    Clv,
+    Sec,
+    Sed,
+    Sei,
+    Brk,
+    Nop,
+    Rti,
+
+    // Illegal instructions.
+    Dcp,
+    Isc,
+    Lax,
+    Rla,
+    Rra,
+    Sax,
+    Slo,
+    Sre,
+    Usbc,
+    /// Locks up the CPU. Also known as KIL or HLT.
+    Jam,
+}
+
+impl Instruction {
+    /// Returns the instruction's assembly mnemonic in uppercase, such as `LDA`.
+    pub const fn mnemonic(self) -> &'static str {
+        match self {
+            Self::Lda => "LDA",
+            Self::Ldx => "LDX",
+            Self::Ldy => "LDY",
+            Self::Sta => "STA",
+            Self::Stx => "STX",
+            Self::Sty => "STY",
+            Self::Tax => "TAX",
+            Self::Tay => "TAY",
+            Self::Txa => "TXA",
+            Self::Tya => "TYA",
+            Self::Tsx => "TSX",
+            Self::Txs => "TXS",
+            Self::Pha => "PHA",
+            Self::Php => "PHP",
+            Self::Pla => "PLA",
+            Self::Plp => "PLP",
+            Self::And => "AND",
+            Self::Eor => "EOR",
+            Self::Ora => "ORA",
+            Self::Bit => "BIT",
+            Self::Adc => "ADC",
+            Self::Sbc => "SBC",
+            Self::Cmp => "CMP",
+            Self::Cpx => "CPX",
+            Self::Cpy => "CPY",
+            Self::Inc => "INC",
+            Self::Inx => "INX",
+            Self::Iny => "INY",
+            Self::Dec => "DEC",
+            Self::Dex => "DEX",
+            Self::Dey => "DEY",
+            Self::Asl => "ASL",
+            Self::Lsr => "LSR",
+            Self::Rol => "ROL",
+            Self::Ror => "ROR",
+            Self::Jmp => "JMP",
+            Self::Jsr => "JSR",
+            Self::Rts => "RTS",
+            Self::Bcc => "BCC",
+            Self::Bcs => "BCS",
+            Self::Beq => "BEQ",
+            Self::Bmi => "BMI",
+            Self::Bne => "BNE",
+            Self::Bpl => "BPL",
+            Self::Bvc => "BVC",
+            Self::Bvs => "BVS",
+            Self::Clc => "CLC",
+            Self::Cld => "CLD",
+            Self::Cli => "CLI",
+            Self::Clv => "CLV",
+            Self::Sec => "SEC",
+            Self::Sed => "SED",
+            Self::Sei => "SEI",
+            Self::Brk => "BRK",
+            Self::Nop => "NOP",
+            Self::Rti => "RTI",
+            Self::Dcp => "DCP",
+            Self::Isc => "ISC",
+            Self::Lax => "LAX",
+            Self::Rla => "RLA",
+            Self::Rra => "RRA",
+            Self::Sax => "SAX",
+            Self::Slo => "SLO",
+            Self::Sre => "SRE",
+            Self::Usbc => "USBC",
+            Self::Jam => "JAM",
+        }
+    }
+
+    /// Returns whether the instruction writes to the memory it addresses, either storing a
+    /// register or modifying the value it read.
+    pub const fn writes_memory(self) -> bool {
+        matches!(
+            self,
+            Self::Sta
+                | Self::Stx
+                | Self::Sty
+                | Self::Sax
+                | Self::Inc
+                | Self::Dec
+                | Self::Asl
+                | Self::Lsr
+                | Self::Rol
+                | Self::Ror
+                | Self::Dcp
+                | Self::Isc
+                | Self::Rla
+                | Self::Rra
+                | Self::Slo
+                | Self::Sre
+        )
+    }
+}
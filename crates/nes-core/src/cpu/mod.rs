@@ -1,15 +1,29 @@
+mod assembler;
 mod cpu_instruction;
+mod halt;
 mod instruction;
+mod opcode_table;
+#[cfg(test)]
+mod single_step_tests;
+mod snapshot;
 
 use std::{
     cell::RefCell,
     rc::{Rc, Weak},
 };
 
+pub use assembler::assemble;
 pub use cpu_instruction::CpuInstruction;
+pub use halt::{CpuHalt, HaltReason};
 pub use instruction::Instruction;
+pub use opcode_table::{OpcodeInfo, OPCODE_TABLE};
+pub use snapshot::CpuSnapshot;
 
-use crate::{concat_bytes, high_byte, is_bit_set, low_byte, savestate::CpuState, Bus};
+use crate::{
+    concat_bytes, high_byte, is_bit_set, low_byte,
+    savestate::{CpuState, StateSerialize},
+    Bus,
+};
 
 /// The 6502 CPU powering the NES.
 #[derive(Default)]
@@ -29,6 +43,18 @@ pub struct Cpu {
     instruction_number: usize,
     cycle_number: usize,
     cycle_wait: u8,
+    /// Set when an NMI is serviced and cleared by the next RTI.
+    is_in_nmi_handler: bool,
+    /// The addresses of recently executed instructions, as a ring buffer starting at
+    /// `trace_index`.
+    trace: [u16; halt::TRACE_LENGTH],
+    trace_index: usize,
+    halt: Option<CpuHalt>,
+    /// Halts since the warnings were last drained, described for the user.
+    warnings: Vec<String>,
+    /// Leaves out the accesses instructions make without using the result. See
+    /// [Cpu::set_dummy_accesses].
+    skip_dummy_accesses: bool,
     pub is_instruction_finished: bool,
 }
 
@@ -43,9 +69,15 @@ impl Cpu {
         self.program_counter = self.read_u16_absolute(0xFFFC);
         self.instruction_number = 0;
         self.cycle_number = 7;
+        self.halt = None;
     }
 
+    /// Services an NMI. This does nothing while the CPU is halted.
     pub fn nmi(&mut self) {
+        if self.halt.is_some() {
+            return;
+        }
+
         let pc_high = high_byte(self.program_counter);
         let pc_low = low_byte(self.program_counter);
 
@@ -57,11 +89,14 @@ impl Cpu {
         self.program_counter = self.read_u16_absolute(0xFFFA);
 
         self.cycle_wait = 8;
+        self.is_in_nmi_handler = true;
     }
 
-    pub fn irq(&mut self) {
-        if self.status.intersects(Status::I) {
-            return;
+    /// Requests an interrupt, returning whether it was serviced. IRQs are ignored while the
+    /// interrupt disable flag is set or the CPU is halted.
+    pub fn irq(&mut self) -> bool {
+        if self.status.intersects(Status::I) || self.halt.is_some() {
+            return false;
         }
 
         let pc_high = high_byte(self.program_counter);
@@ -75,12 +110,111 @@ impl Cpu {
         self.program_counter = self.read_u16_absolute(0xFFFE);
 
         self.cycle_wait = 8;
+        true
     }
 
     pub fn connect_bus(&mut self, bus: Weak<RefCell<Bus>>) {
         self.bus = bus;
     }
 
+    pub fn has_dummy_accesses(&self) -> bool {
+        !self.skip_dummy_accesses
+    }
+
+    /// Sets whether the reads that indexed addressing makes while carrying into the high byte of
+    /// the address, and the writes of the original value that read-modify-write instructions
+    /// make, are performed. These have side effects on some registers, such as PPUDATA, but
+    /// games rarely rely on them.
+    pub fn set_dummy_accesses(&mut self, is_enabled: bool) {
+        self.skip_dummy_accesses = !is_enabled;
+    }
+
+    /// Returns whether the CPU has serviced an NMI and not yet returned from it. Any RTI counts as
+    /// returning, so an IRQ handler nested within the NMI handler will end this early.
+    pub fn is_in_nmi_handler(&self) -> bool {
+        self.is_in_nmi_handler
+    }
+
+    /// Returns whether the CPU will start a new instruction on its next clock, rather than waiting
+    /// out the cycles of the current one.
+    pub fn is_at_instruction_boundary(&self) -> bool {
+        self.cycle_wait == 0
+    }
+
+    /// Takes descriptions of the halts since the last call, oldest first.
+    /// [crate::Bus::drain_warnings] includes these, so frontends don't usually need to call this
+    /// themselves.
+    pub fn drain_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Returns why the CPU has stopped executing instructions, if it has. A halted CPU stays
+    /// halted until it's reset, its program counter is set, or a savestate is loaded.
+    pub fn halt(&self) -> Option<&CpuHalt> {
+        self.halt.as_ref()
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    /// Jumps to the given address at the start of the next instruction, recovering from a halt.
+    pub fn set_program_counter(&mut self, addr: u16) {
+        self.program_counter = addr;
+        self.cycle_wait = 0;
+        self.halt = None;
+    }
+
+    /// Returns the accumulator.
+    pub fn a_register(&self) -> u8 {
+        self.accumulator
+    }
+
+    pub fn x_register(&self) -> u8 {
+        self.x_register
+    }
+
+    pub fn y_register(&self) -> u8 {
+        self.y_register
+    }
+
+    pub fn stack_pointer(&self) -> u8 {
+        self.stack_pointer
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    /// Returns the number of cycles executed since the last reset.
+    pub fn cycle_number(&self) -> usize {
+        self.cycle_number
+    }
+
+    /// Returns all of the registers at once.
+    pub fn snapshot(&self) -> CpuSnapshot {
+        CpuSnapshot {
+            accumulator: self.accumulator,
+            x_register: self.x_register,
+            y_register: self.y_register,
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            status: self.status,
+            cycle_number: self.cycle_number,
+        }
+    }
+
+    /// Overwrites the registers, for debuggers poking at a running program. The program counter
+    /// is set as with [Cpu::set_program_counter], and the cycle count is left alone.
+    pub fn set_registers(&mut self, snapshot: &CpuSnapshot) {
+        self.accumulator = snapshot.accumulator;
+        self.x_register = snapshot.x_register;
+        self.y_register = snapshot.y_register;
+        self.stack_pointer = snapshot.stack_pointer;
+        self.status = snapshot.status;
+        self.set_program_counter(snapshot.program_counter);
+    }
+
     fn bus(&self) -> Rc<RefCell<Bus>> {
         self.bus.upgrade().expect("bus not connected")
     }
@@ -95,37 +229,64 @@ impl Cpu {
 
     /// Runs a single clock cycle.
     pub fn clock(&mut self) {
+        if self.halt.is_some() {
+            // Nothing more will execute, so don't leave anyone waiting for the instruction to end.
+            self.is_instruction_finished = true;
+            return;
+        }
         self.is_instruction_finished = self.cycle_wait == 0;
         if self.is_instruction_finished {
             self.cycle_wait = self.execute_next();
         }
         self.cycle_wait -= 1;
     }
+}
+
+/// Internal RAM belongs to the bus, which stores it alongside the CPU's registers. The instruction
+/// trace and halt information are debugging state, and aren't saved.
+impl StateSerialize for Cpu {
+    type State<'a> = &'a CpuState;
 
-    pub fn apply_state(&mut self, state: &CpuState) {
+    fn apply_state(&mut self, state: &CpuState) {
         self.accumulator = state.accumulator;
         self.x_register = state.x_register;
         self.y_register = state.y_register;
         self.program_counter = state.program_counter;
         self.stack_pointer = state.stack_pointer;
         self.status = Status::from_bits_retain(state.status);
+        // The loaded state starts on an instruction boundary, and any halt belonged to the
+        // program being replaced.
+        self.cycle_wait = 0;
+        self.halt = None;
     }
 
-    pub fn save_state(&self, ram: &[u8]) -> Vec<u8> {
-        use crate::savestate::serialize;
-
-        let mut buffer = Vec::new();
+    fn save_state_into(&self, buffer: &mut Vec<u8>) {
+        use crate::savestate::serialize_into;
 
-        buffer.extend_from_slice(&serialize(&self.accumulator, "A"));
-        buffer.extend_from_slice(&serialize(&self.x_register, "X"));
-        buffer.extend_from_slice(&serialize(&self.y_register, "Y"));
-        buffer.extend_from_slice(&serialize(&self.program_counter, "PC"));
-        buffer.extend_from_slice(&serialize(&self.stack_pointer, "S"));
-        buffer.extend_from_slice(&serialize(&self.status.bits(), "P"));
-        buffer.extend_from_slice(&serialize(&0u8, "DB")); // Currently unused.
-        buffer.extend_from_slice(&serialize(&ram, "RAM"));
+        serialize_into(buffer, &self.accumulator, "A");
+        serialize_into(buffer, &self.x_register, "X");
+        serialize_into(buffer, &self.y_register, "Y");
+        serialize_into(buffer, &self.program_counter, "PC");
+        serialize_into(buffer, &self.stack_pointer, "S");
+        serialize_into(buffer, &self.status.bits(), "P");
+        serialize_into(buffer, &0u8, "DB"); // Currently unused.
+    }
+}
 
-        buffer
+impl Cpu {
+    /// Returns the same state as [StateSerialize::save_state], without serializing it. Internal
+    /// RAM belongs to the bus, which passes it in.
+    pub fn state(&self, ram: Box<[u8; 2048]>) -> CpuState {
+        CpuState {
+            accumulator: self.accumulator,
+            x_register: self.x_register,
+            y_register: self.y_register,
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            status: self.status.bits(),
+            data_bus: 0,
+            ram,
+        }
     }
 }
 
@@ -136,7 +297,12 @@ impl Cpu {
     /// Returns the number of cycles the instruction takes.
     pub fn execute_next(&mut self) -> u8 {
         let opcode = self.read(self.program_counter);
-        let instruction = CpuInstruction::decode(opcode);
+        let Some(instruction) = CpuInstruction::try_decode(opcode) else {
+            // Stop rather than guess, so a corrupt or malicious ROM can't crash the emulator.
+            self.record_trace();
+            self.halt_with(HaltReason::UnsupportedOpcode { opcode });
+            return 2;
+        };
         self.execute(instruction)
     }
 
@@ -155,27 +321,28 @@ impl Cpu {
     ///
     /// Returns the number of cycles the instruction takes.
     pub fn execute(&mut self, instruction: CpuInstruction) -> u8 {
-        self.instruction_number += 1;
+        self.record_trace();
 
         #[cfg(feature = "logging")]
         {
-            let a = self.accumulator;
-            let x = self.x_register;
-            let y = self.y_register;
-            let p = (self.status | Status::B).bits() | 1 << 5;
-            let sp = self.stack_pointer;
-            let pc = self.program_counter;
+            let snapshot = self.snapshot();
+            let pc = snapshot.program_counter;
             let instruction_number = self.instruction_number;
-            let cycle_number = self.cycle_number;
             let addr = self.absolute_address;
+            let (bus_cycle, time) = self.bus.upgrade().map_or((0, 0.0), |bus| {
+                let bus = bus.borrow();
+                let cycle = bus.cycle();
+                (cycle, bus.timing().cpu_cycles_to_micros(cycle as u64))
+            });
 
             println!(
-            "{instruction_number} {pc:04X} {:?} {addr:04X}    A:{a:02X} X:{x:02X} Y:{y:02X} P:{p:02X} SP:{sp:02X} CYC:{cycle_number}",
-            instruction.instruction,
-        );
+                "{instruction_number} {pc:04X} {:?} {addr:04X}    {snapshot} {} BUS:{bus_cycle} T:{time:.2}us",
+                instruction.instruction,
+                snapshot.status,
+            );
         }
 
-        self.program_counter += 1;
+        self.program_counter = self.program_counter.wrapping_add(1);
         let addr_mode_cycles = match instruction.addr_mode {
             AddressingMode::Implicit => self.implicit(),
             AddressingMode::Accumulator => self.accumulator(),
@@ -191,8 +358,14 @@ impl Cpu {
             AddressingMode::IndexedIndirect => self.indexed_indirect(),
             AddressingMode::IndirectIndexed => self.indirect_indexed(),
         };
+        if matches!(
+            instruction.addr_mode,
+            AddressingMode::AbsoluteX | AddressingMode::AbsoluteY | AddressingMode::IndirectIndexed
+        ) {
+            self.indexed_dummy_read(instruction.instruction);
+        }
 
-        self.program_counter += 1;
+        self.program_counter = self.program_counter.wrapping_add(1);
         let instruction_cycles = match instruction.instruction {
             Instruction::Adc => self.adc(),
             Instruction::And => self.and(),
@@ -261,6 +434,7 @@ impl Cpu {
             Instruction::Slo => self.slo(),
             Instruction::Sre => self.sre(),
             Instruction::Usbc => self.sbc(),
+            Instruction::Jam => self.jam(),
         };
 
         self.address_will_not_cross_page = false;
@@ -395,6 +569,10 @@ impl Cpu {
         } else {
             let data = self.read(self.absolute_address);
             let result = data.wrapping_add_signed(value);
+            // Read-modify-write instructions write the original value back while modifying it.
+            if !self.skip_dummy_accesses {
+                self.write(self.absolute_address, data);
+            }
             self.write(self.absolute_address, result);
 
             // This instruction should always take the page crossing penalty when using indexed
@@ -452,6 +630,10 @@ impl Cpu {
         if self.operate_on_accumulator {
             self.accumulator = result;
         } else {
+            // Read-modify-write instructions write the original value back while modifying it.
+            if !self.skip_dummy_accesses {
+                self.write(self.absolute_address, data);
+            }
             self.write(self.absolute_address, result);
         }
 
@@ -551,8 +733,9 @@ impl Cpu {
     }
 
     fn brk(&mut self) -> u8 {
-        let pc_high = high_byte(self.program_counter + 1);
-        let pc_low = low_byte(self.program_counter + 1);
+        let brk_addr = self.program_counter.wrapping_sub(1);
+        let pc_high = high_byte(self.program_counter.wrapping_add(1));
+        let pc_low = low_byte(self.program_counter.wrapping_add(1));
         // The break flag is set when pushing.
         let status = (self.status | Status::B).bits();
 
@@ -565,6 +748,12 @@ impl Cpu {
         // Jump to the address stored at the IRQ vector (0xFFFE-0xFFFF).
         self.program_counter = self.read_u16_absolute(0xFFFE);
 
+        // A BRK that's its own handler repeats forever. This is usually the result of jumping
+        // into empty memory with the IRQ vector also pointing there.
+        if self.program_counter == brk_addr {
+            self.halt_with(HaltReason::BrkLoop);
+        }
+
         7
     }
 
@@ -642,8 +831,8 @@ impl Cpu {
     }
 
     fn jsr(&mut self) -> u8 {
-        let high = high_byte(self.program_counter - 1);
-        let low = low_byte(self.program_counter - 1);
+        let high = high_byte(self.program_counter.wrapping_sub(1));
+        let low = low_byte(self.program_counter.wrapping_sub(1));
 
         self.push(high);
         self.push(low);
@@ -673,6 +862,34 @@ impl Cpu {
         2
     }
 
+    fn jam(&mut self) -> u8 {
+        // Stay on the jamming instruction.
+        self.program_counter = self.program_counter.wrapping_sub(1);
+        let opcode = self.read(self.program_counter);
+        self.halt_with(HaltReason::Jam { opcode });
+        2
+    }
+
+    /// Adds the instruction about to be executed to the trace kept for [CpuHalt::trace].
+    fn record_trace(&mut self) {
+        self.instruction_number += 1;
+        self.trace[self.trace_index] = self.program_counter;
+        self.trace_index = (self.trace_index + 1) % self.trace.len();
+    }
+
+    fn halt_with(&mut self, reason: HaltReason) {
+        let (newest, oldest) = self.trace.split_at(self.trace_index);
+        // Leave out slots that haven't been filled since the CPU was reset.
+        let skip = self.trace.len() - self.instruction_number.min(self.trace.len());
+        let halt = CpuHalt {
+            reason,
+            program_counter: self.program_counter,
+            trace: oldest.iter().chain(newest).skip(skip).copied().collect(),
+        };
+        self.warnings.push(format!("cpu {halt}"));
+        self.halt = Some(halt);
+    }
+
     fn ora(&mut self) -> u8 {
         self.bitwise(BitwiseOperation::Or)
     }
@@ -720,6 +937,7 @@ impl Cpu {
         // The break flag is unset when pulling.
         self.status = Status::from_bits_retain(status) & !Status::B;
         self.program_counter = concat_bytes(pc_low, pc_high);
+        self.is_in_nmi_handler = false;
 
         6
     }
@@ -728,7 +946,7 @@ impl Cpu {
         let pc_low = self.pull();
         let pc_high = self.pull();
 
-        self.program_counter = concat_bytes(pc_low, pc_high) + 1;
+        self.program_counter = concat_bytes(pc_low, pc_high).wrapping_add(1);
 
         6
     }
@@ -853,14 +1071,14 @@ impl Cpu {
     /// Reads a 16-bit value at the program counter.
     fn read_u16(&mut self) -> u16 {
         let result = self.read_u16_absolute(self.program_counter);
-        self.program_counter += 1;
+        self.program_counter = self.program_counter.wrapping_add(1);
         result
     }
 
     /// Reads a 16-bit value at a specific address.
     fn read_u16_absolute(&mut self, address: u16) -> u16 {
         let low = self.read(address);
-        let high = self.read(address + 1);
+        let high = self.read(address.wrapping_add(1));
 
         concat_bytes(low, high)
     }
@@ -887,6 +1105,23 @@ impl Cpu {
         }
     }
 
+    /// Performs the read indexed addressing modes make while the high byte of the address is
+    /// still being carried into. This reads from the wrong page if the index crossed one, which
+    /// instructions that only read skip the rest of the time, while writes always make it.
+    ///
+    /// The value is discarded, but the read still has side effects on registers such as PPUDATA.
+    fn indexed_dummy_read(&mut self, instruction: Instruction) {
+        if self.skip_dummy_accesses {
+            return;
+        }
+        let crosses_page = !self.address_will_not_cross_page;
+        if crosses_page {
+            self.read(self.absolute_address.wrapping_sub(0x100));
+        } else if instruction.writes_memory() {
+            self.read(self.absolute_address);
+        }
+    }
+
     /// Powers the zero-page,X and zero-page,Y addressing modes.
     fn zero_page_indexed(&mut self, register: Register) -> u8 {
         let register = self.get_register(register);
@@ -904,14 +1139,14 @@ impl Cpu {
     fn implicit(&mut self) -> u8 {
         // Incrementing program counter is unnecessary for implicit addressing; revert addition at
         // call site.
-        self.program_counter -= 1;
+        self.program_counter = self.program_counter.wrapping_sub(1);
         0
     }
 
     fn accumulator(&mut self) -> u8 {
         // Incrementing program counter is unnecessary when operating on accumulator; revert
         // addition at call site.
-        self.program_counter -= 1;
+        self.program_counter = self.program_counter.wrapping_sub(1);
         self.operate_on_accumulator = true;
         0
     }
@@ -942,7 +1177,8 @@ impl Cpu {
 
         // If the target address crosses a memory page, the instruction can potentially take one
         // extra cycle.
-        self.branch_will_cross_page = high_byte(address) != high_byte(self.program_counter + 1);
+        self.branch_will_cross_page =
+            high_byte(address) != high_byte(self.program_counter.wrapping_add(1));
         1
     }
 
@@ -1064,7 +1300,7 @@ bitflags::bitflags! {
     /// CPU status flags.
     ///
     /// See <https://www.nesdev.org/wiki/Status_flags>.
-    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
     pub struct Status: u8 {
         /// Carry flag.
         const C = 1 << 0;
@@ -1085,6 +1321,31 @@ bitflags::bitflags! {
     }
 }
 
+impl std::fmt::Display for Status {
+    /// Formats the flags as `NV-BDIZC`, with set flags in uppercase and clear flags in lowercase.
+    /// Bit 5 isn't stored, so it's always shown as `-`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let flags = [
+            (Status::N, 'n'),
+            (Status::V, 'v'),
+            (Status::empty(), '-'),
+            (Status::B, 'b'),
+            (Status::D, 'd'),
+            (Status::I, 'i'),
+            (Status::Z, 'z'),
+            (Status::C, 'c'),
+        ];
+        for (flag, name) in flags {
+            if !flag.is_empty() && self.contains(flag) {
+                write!(f, "{}", name.to_ascii_uppercase())?;
+            } else {
+                write!(f, "{name}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::rc::Rc;
@@ -1348,7 +1609,7 @@ mod tests {
             0x40, // RTI
         ];
         // Set IRQ vector to 0x0007.
-        let vectors = [0x00, 0x00, 0x00, 0x00, 0x07, 0x00];
+        let vectors = [0x0000, 0x0000, 0x0007];
         let (cpu, _bus) = setup(program, Some(vectors));
         let mut cpu = cpu.borrow_mut();
 
@@ -1443,6 +1704,72 @@ mod tests {
         assert!(cpu.status.bits() & 1 << 5 == 0);
     }
 
+    #[test]
+    fn stack_wraparound() {
+        let program = assemble(
+            0,
+            "ldx #$00\ntxs\nlda #$11\npha\nlda #$22\npha\npla\npla\npla",
+        )
+        .unwrap();
+        let (cpu, _bus) = setup(program, None);
+        let mut cpu = cpu.borrow_mut();
+        cpu.step(2);
+
+        // Pushing with the stack pointer at $00 writes to $0100 and wraps around to $FF.
+        cpu.step(2);
+        assert_eq!(cpu.stack_pointer, 0xFF);
+        assert_eq!(cpu.read(0x0100), 0x11);
+        // The program at $0000 is left alone.
+        assert_eq!(cpu.read(0x0000), 0xA2);
+
+        // The next push stays on page 1.
+        cpu.step(2);
+        assert_eq!(cpu.stack_pointer, 0xFE);
+        assert_eq!(cpu.read(0x01FF), 0x22);
+        assert_eq!(cpu.read(0x0200), 0x00);
+
+        // Pulling walks back across the boundary in the other direction.
+        cpu.step(1);
+        assert_eq!(cpu.accumulator, 0x22);
+        assert_eq!(cpu.stack_pointer, 0xFF);
+        cpu.step(1);
+        assert_eq!(cpu.accumulator, 0x11);
+        assert_eq!(cpu.stack_pointer, 0x00);
+
+        // Pulling from an "empty" stack reads $0101.
+        cpu.write(0x0101, 0x33);
+        cpu.step(1);
+        assert_eq!(cpu.accumulator, 0x33);
+        assert_eq!(cpu.stack_pointer, 0x01);
+    }
+
+    #[test]
+    fn status_bit_5() {
+        let program = assemble(
+            0,
+            "ldx #$FF\ntxs\nlda #$FF\npha\nplp\nphp\nlda #$00\npha\nplp\nphp",
+        )
+        .unwrap();
+        let (cpu, _bus) = setup(program, None);
+        let mut cpu = cpu.borrow_mut();
+        cpu.step(2);
+
+        // Pulling $FF drops the break flag and bit 5, but keeps everything else.
+        cpu.step(3);
+        assert_eq!(cpu.status.bits(), 0xCF);
+        assert_eq!(cpu.status.to_string(), "NV-bDIZC");
+
+        // Pushing always sets both again.
+        cpu.step(1);
+        assert_eq!(cpu.read(0x01FF), 0xFF);
+
+        // Even when the pulled value had them clear.
+        cpu.step(4);
+        assert_eq!(cpu.status, Status::empty());
+        assert_eq!(cpu.status.to_string(), "nv-bdizc");
+        assert_eq!(cpu.read(0x01FE), 0x30);
+    }
+
     #[test]
     fn subroutines() {
         let program = vec![
@@ -1612,20 +1939,253 @@ mod tests {
         assert_eq!(cpu.absolute_address, 0x108);
     }
 
-    fn setup(program: Vec<u8>, vectors: Option<[u8; 6]>) -> (Rc<RefCell<Cpu>>, Rc<RefCell<Bus>>) {
-        // Minimal iNES header for basic roms.
-        const HEADER: [u8; 16] = [0x4E, 0x45, 0x53, 0x1A, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    #[test]
+    fn open_bus() {
+        let program = vec![
+            0xAD, 0x18, 0x40, // LDA $4018 ; Unmapped.
+            0xAD, 0x00, 0x40, // LDA $4000 ; Write-only APU register.
+            0xAD, 0x16, 0x40, // LDA $4016 ; Controller port.
+        ];
+        let (cpu, _bus) = setup(program, None);
+        let mut cpu = cpu.borrow_mut();
+
+        // The last value on the data bus is the high byte of the operand.
+        cpu.step(1);
+        assert_eq!(cpu.accumulator, 0x40);
+        cpu.step(1);
+        assert_eq!(cpu.accumulator, 0x40);
+        // Only the low bits are driven by the controller port, and no buttons are pressed.
+        cpu.step(1);
+        assert_eq!(cpu.accumulator, 0x40);
+    }
+
+    #[test]
+    fn halt() {
+        let program = vec![
+            0xA9, 0x01, // LDA #$01
+            0x02, // JAM
+            0xA9, 0x02, // LDA #$02
+        ];
+        let (cpu, _bus) = setup(program, None);
+        let mut cpu = cpu.borrow_mut();
+
+        for _ in 0..20 {
+            cpu.clock();
+        }
+        let halt = cpu.halt().unwrap();
+        assert_eq!(halt.reason, HaltReason::Jam { opcode: 0x02 });
+        assert_eq!(halt.program_counter, 0x0002);
+        assert_eq!(halt.trace, [0x0000, 0x0002]);
+        assert_eq!(cpu.accumulator, 0x01);
+
+        // Jumping past the JAM resumes execution.
+        cpu.set_program_counter(0x0003);
+        cpu.clock();
+        assert!(cpu.halt().is_none());
+        assert_eq!(cpu.accumulator, 0x02);
 
+        // A BRK in empty memory with the IRQ vector pointing at it would repeat forever.
+        let (cpu, bus) = setup(vec![0x00], None);
+        let mut cpu = cpu.borrow_mut();
+        cpu.clock();
+        assert_eq!(cpu.halt().unwrap().reason, HaltReason::BrkLoop);
+        cpu.reset();
+        assert!(cpu.halt().is_none());
+        drop(cpu);
+        assert_eq!(
+            bus.borrow_mut().drain_warnings(),
+            ["cpu stuck in a BRK loop at $0000"]
+        );
+
+        // A BRK whose handler starts with another BRK only loops once it reaches that one.
+        let (cpu, _bus) = setup(vec![0x00; 0x20], Some([0, 0x8000, 0x0010]));
+        let mut cpu = cpu.borrow_mut();
+        cpu.set_program_counter(0x0000);
+        cpu.step(1);
+        assert_eq!(cpu.program_counter(), 0x0010);
+        assert!(cpu.halt().is_none());
+        cpu.step(1);
+        assert_eq!(cpu.halt().unwrap().reason, HaltReason::BrkLoop);
+        assert_eq!(cpu.halt().unwrap().program_counter, 0x0010);
+
+        // Unsupported illegal opcodes stop the CPU instead of panicking.
+        let (cpu, _bus) = setup(vec![0xEA, 0x8B, 0x00], None);
+        let mut cpu = cpu.borrow_mut();
+        for _ in 0..10 {
+            cpu.clock();
+        }
+        let halt = cpu.halt().unwrap();
+        assert_eq!(halt.reason, HaltReason::UnsupportedOpcode { opcode: 0x8B });
+        assert_eq!(halt.trace, [0x0000, 0x0001]);
+    }
+
+    #[test]
+    fn load_state_after_halt() {
+        let program = vec![
+            0xA9, 0x01, // LDA #$01
+            0x02, // JAM
+        ];
+        let (cpu, bus) = setup(program, None);
+        let state = bus.borrow().save_state();
+        for _ in 0..20 {
+            cpu.borrow_mut().clock();
+        }
+        assert!(cpu.borrow().halt().is_some());
+
+        // Loading a state from before the JAM discards the halt, and execution resumes.
+        bus.borrow_mut().load_state(&state).unwrap();
+        let mut cpu = cpu.borrow_mut();
+        assert!(cpu.halt().is_none());
+        assert_eq!(cpu.accumulator, 0x00);
+        cpu.clock();
+        assert_eq!((cpu.accumulator, cpu.program_counter), (0x01, 0x0002));
+    }
+
+    #[test]
+    fn opcode_table_cycles() {
+        for info in OPCODE_TABLE.iter().flatten() {
+            // Branches take extra cycles when taken, and JAM never finishes.
+            if info.addr_mode == AddressingMode::Relative || info.instruction == Instruction::Jam {
+                continue;
+            }
+            // Operands point at $0010, which points back to $0000, so nothing crosses a page.
+            let (cpu, _bus) = setup(vec![info.opcode, 0x10, 0x00], None);
+            let cycles = cpu.borrow_mut().execute_next();
+            assert_eq!(
+                cycles, info.cycles,
+                "cycles for opcode ${:02X}",
+                info.opcode
+            );
+        }
+        assert_eq!(OPCODE_TABLE[0xA9].unwrap().instruction.mnemonic(), "LDA");
+        assert_eq!(OPCODE_TABLE[0x6C].unwrap().length, 3);
+        assert!(!OPCODE_TABLE[0x1A].unwrap().is_official);
+    }
+
+    #[test]
+    fn dummy_accesses() {
+        let program = assemble(
+            0,
+            "lda #$20
+            sta $2006
+            lda #$00
+            sta $2006
+            lda #$11
+            sta $2007
+            lda #$22
+            sta $2007
+            lda #$33
+            sta $2007
+            lda #$20
+            sta $2006
+            lda #$00
+            sta $2006
+            ldx #$08
+            lda $20FF,X
+            lda $2007
+            inc $2007
+            lda #$20
+            sta $2006
+            lda #$04
+            sta $2006
+            lda $2007
+            ldx $2007
+            ldy $2007",
+        )
+        .unwrap();
+        let (cpu, _bus) = setup(program, None);
+        let mut cpu = cpu.borrow_mut();
+
+        // Crossing a page reads $2007 before $2107, so the two reads advance the PPU's address
+        // twice, and the next read gets the second byte.
+        cpu.step(17);
+        assert_eq!(cpu.a_register(), 0x22);
+
+        // INC reads $2003, then writes back the original value to $2004 before writing the
+        // incremented value to $2005.
+        cpu.step(8);
+        assert_eq!(cpu.x_register(), 0x33);
+        assert_eq!(cpu.y_register(), 0x34);
+    }
+
+    #[test]
+    fn register_accessors() {
+        let program = assemble(0, "lda #$40\nldx #$01\nldy #$02").unwrap();
+        let (cpu, _bus) = setup(program, None);
+        let mut cpu = cpu.borrow_mut();
+        cpu.step(3);
+
+        let snapshot = cpu.snapshot();
+        assert_eq!(snapshot.accumulator, 0x40);
+        assert_eq!(cpu.x_register(), 0x01);
+        assert_eq!(cpu.y_register(), 0x02);
+        assert_eq!(cpu.program_counter(), 0x0006);
+        assert_eq!(cpu.status(), Status::I);
+        assert_eq!(cpu.cycle_number(), 13);
+        assert_eq!(
+            snapshot.to_string(),
+            format!("A:40 X:01 Y:02 P:34 SP:{:02X} CYC:13", cpu.stack_pointer())
+        );
+
+        cpu.set_registers(&CpuSnapshot {
+            accumulator: 0x12,
+            status: Status::C,
+            ..snapshot
+        });
+        assert_eq!(cpu.a_register(), 0x12);
+        assert_eq!(cpu.status(), Status::C);
+        assert_eq!(cpu.x_register(), 0x01);
+    }
+
+    #[test]
+    fn raw_prg() {
+        let program = assemble(0xC000, "lda #$42\nsta $10\nloop: jmp loop").unwrap();
+        let cartridge = Cartridge::from_raw_prg(&program, 0xC000, [0, 0xC000, 0]).unwrap();
+        let cartridge = Rc::new(RefCell::new(cartridge));
+        let cpu = Rc::new(RefCell::new(Cpu::new()));
+        let ppu = Rc::new(RefCell::new(Ppu::new(cartridge.clone())));
+        let apu = Rc::new(RefCell::new(Apu::new()));
+        let _bus = Bus::new(cpu.clone(), crate::new_boxed_array(), ppu, apu, cartridge);
+
+        let mut cpu = cpu.borrow_mut();
+        cpu.reset();
+        assert_eq!(cpu.program_counter, 0xC000);
+        cpu.step(3);
+        assert_eq!(cpu.read(0x10), 0x42);
+        assert_eq!(cpu.program_counter, 0xC004);
+
+        assert!(Cartridge::from_raw_prg(&[0xEA], 0x6000, [0; 3]).is_err());
+        assert!(Cartridge::from_raw_prg(&[0xEA; 2], 0xFFF9, [0; 3]).is_err());
+    }
+
+    #[test]
+    fn program_counter_wraps() {
+        let (cpu, bus) = setup(Vec::new(), None);
+        let mut memory = crate::new_boxed_array::<u8, 0x10000>();
+        // LDA #$42 with its operand wrapped around to $0000, then RTS back to $FFFF + 1.
+        memory[0xFFFF] = 0xA9;
+        memory[0x0000] = 0x42;
+        memory[0x0001] = 0x60;
+        memory[0x01FE..0x0200].copy_from_slice(&[0xFF, 0xFF]);
+        bus.borrow_mut().flat_memory = Some(memory);
+
+        let mut cpu = cpu.borrow_mut();
+        cpu.set_program_counter(0xFFFF);
+        cpu.stack_pointer = 0xFD;
+        cpu.step(1);
+        assert_eq!((cpu.accumulator, cpu.program_counter), (0x42, 0x0001));
+        cpu.step(1);
+        assert_eq!(cpu.program_counter, 0x0000);
+    }
+
+    fn setup(program: Vec<u8>, vectors: Option<[u16; 3]>) -> (Rc<RefCell<Cpu>>, Rc<RefCell<Bus>>) {
         // Load the program directly into internal RAM.
         let mut ram = crate::new_boxed_array();
         ram[0..program.len()].copy_from_slice(&program);
 
-        // Construct a basic iNES ROM file to load.
-        let mut rom = vec![0; 16 * 1024 + HEADER.len()];
-        rom[0..HEADER.len()].copy_from_slice(&HEADER);
-        rom[0x3FFA + HEADER.len()..].copy_from_slice(&vectors.unwrap_or_default());
-
-        let cartridge = Rc::new(RefCell::new(Cartridge::new(&rom).unwrap()));
+        // An empty cartridge that only supplies the vectors.
+        let cartridge = Cartridge::from_raw_prg(&[], 0x8000, vectors.unwrap_or_default()).unwrap();
+        let cartridge = Rc::new(RefCell::new(cartridge));
         let cpu = Rc::new(RefCell::new(Cpu::new()));
         let ppu = Rc::new(RefCell::new(Ppu::new(cartridge.clone())));
         let apu = Rc::new(RefCell::new(Apu::new()));
@@ -0,0 +1,101 @@
+use super::{AddressingMode, CpuInstruction, Instruction};
+
+/// The number of cycles each opcode takes, not counting the extra cycles for crossing a page or
+/// taking a branch.
+#[rustfmt::skip]
+const BASE_CYCLES: [u8; 256] = [
+    7, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 4, 4, 6, 6, // 0x00
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 0x10
+    6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 4, 4, 6, 6, // 0x20
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 0x30
+    6, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 3, 4, 6, 6, // 0x40
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 0x50
+    6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 5, 4, 6, 6, // 0x60
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 0x70
+    2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4, // 0x80
+    2, 6, 2, 6, 4, 4, 4, 4, 2, 5, 2, 5, 5, 5, 5, 5, // 0x90
+    2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4, // 0xA0
+    2, 5, 2, 5, 4, 4, 4, 4, 2, 4, 2, 4, 4, 4, 4, 4, // 0xB0
+    2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6, // 0xC0
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 0xD0
+    2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6, // 0xE0
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 0xF0
+];
+
+/// Everything known about an opcode without executing it, for assemblers, disassemblers, and
+/// debuggers built on the crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    pub opcode: u8,
+    pub instruction: Instruction,
+    pub addr_mode: AddressingMode,
+    /// The length of the instruction in bytes, including the opcode.
+    pub length: u8,
+    /// The number of cycles the instruction takes, not counting the extra cycle when an indexed
+    /// read crosses a page, or the 1-2 extra cycles when a branch is taken.
+    pub cycles: u8,
+    /// Whether the opcode is documented, rather than an illegal opcode that only works due to
+    /// quirks of the 6502's decoding logic.
+    pub is_official: bool,
+}
+
+impl OpcodeInfo {
+    const fn new(opcode: u8, instruction: CpuInstruction) -> Self {
+        let is_official = match instruction.instruction {
+            Instruction::Dcp
+            | Instruction::Isc
+            | Instruction::Lax
+            | Instruction::Rla
+            | Instruction::Rra
+            | Instruction::Sax
+            | Instruction::Slo
+            | Instruction::Sre
+            | Instruction::Usbc
+            | Instruction::Jam => false,
+            // Every NOP other than $EA is illegal.
+            Instruction::Nop => opcode == 0xEA,
+            _ => true,
+        };
+
+        Self {
+            opcode,
+            instruction: instruction.instruction,
+            addr_mode: instruction.addr_mode,
+            length: 1 + instruction.addr_mode.operand_length(),
+            cycles: BASE_CYCLES[opcode as usize],
+            is_official,
+        }
+    }
+}
+
+/// Every opcode the CPU can execute, indexed by opcode. Entries are `None` for the few illegal
+/// opcodes that aren't supported.
+pub const OPCODE_TABLE: [Option<OpcodeInfo>; 256] = {
+    let mut table = [None; 256];
+    let mut opcode = 0;
+    while opcode < 256 {
+        table[opcode] = match CpuInstruction::try_decode(opcode as u8) {
+            Some(instruction) => Some(OpcodeInfo::new(opcode as u8, instruction)),
+            None => None,
+        };
+        opcode += 1;
+    }
+    table
+};
+
+impl AddressingMode {
+    /// Returns the number of operand bytes following the opcode.
+    pub const fn operand_length(self) -> u8 {
+        match self {
+            Self::Implicit | Self::Accumulator => 0,
+            Self::Immediate
+            | Self::ZeroPage
+            | Self::ZeroPageX
+            | Self::ZeroPageY
+            | Self::Relative
+            | Self::IndexedIndirect
+            | Self::IndirectIndexed => 1,
+            Self::Absolute | Self::AbsoluteX | Self::AbsoluteY | Self::Indirect => 2,
+        }
+    }
+}
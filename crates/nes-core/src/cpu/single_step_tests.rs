@@ -0,0 +1,311 @@
+//! Runs the per-opcode test vectors from Tom Harte's SingleStepTests against the CPU. Each
+//! opcode has 10,000 tests giving the registers and memory before and after executing a single
+//! instruction, which covers flag and memory behavior far more thoroughly than nestest.
+//!
+//! The vectors are too large to include in the repository. Download the `nes6502` set from
+//! <https://github.com/SingleStepTests/65x02> so that the files are at
+//! `test_roms/65x02/nes6502/v1/00.json` and so on, then run
+//! `cargo test single_step -- --ignored --nocapture` for a per-opcode report.
+
+use std::{cell::RefCell, rc::Rc};
+
+use super::{CpuSnapshot, Instruction, Status, OPCODE_TABLE};
+use crate::{Apu, Bus, Cartridge, Cpu, Ppu};
+
+const TEST_DIR: &str = "./test_roms/65x02/nes6502/v1";
+
+#[test]
+#[ignore = "needs the SingleStepTests vectors in test_roms/65x02"]
+fn single_step() {
+    let (cpu, bus) = setup();
+    let mut failed_opcodes = Vec::new();
+
+    for (opcode, info) in OPCODE_TABLE.iter().enumerate() {
+        let Some(info) = info else {
+            println!("{opcode:02X}: unsupported");
+            continue;
+        };
+        // The CPU halts on these, so there's no meaningful state after them.
+        if info.instruction == Instruction::Jam {
+            println!("{opcode:02X} {:?}: skipped", info.instruction);
+            continue;
+        }
+        let path = format!("{TEST_DIR}/{opcode:02x}.json");
+        let Ok(text) = std::fs::read_to_string(&path) else {
+            println!(
+                "{opcode:02X} {:?}: skipped, {path} not found",
+                info.instruction
+            );
+            continue;
+        };
+        let tests = Json::parse(&text).unwrap();
+        let tests = tests.as_array();
+
+        let mut passed = 0;
+        let mut first_failure = None;
+        for test in tests {
+            match run_test(&cpu, &bus, test) {
+                Ok(()) => passed += 1,
+                Err(err) => {
+                    first_failure
+                        .get_or_insert_with(|| format!("{}: {err}", test["name"].as_str()));
+                }
+            }
+        }
+
+        let total = tests.len();
+        println!(
+            "{opcode:02X} {:?}: {passed}/{total} passed",
+            info.instruction
+        );
+        if let Some(failure) = first_failure {
+            println!("    first failure: {failure}");
+            // Illegal opcodes are reported, but not all of them are expected to be exact.
+            if info.is_official {
+                failed_opcodes.push(opcode);
+            }
+        }
+    }
+
+    assert!(
+        failed_opcodes.is_empty(),
+        "official opcodes failed: {failed_opcodes:02X?}"
+    );
+}
+
+/// Runs a single test, returning a description of the first difference from the expected state.
+fn run_test(cpu: &Rc<RefCell<Cpu>>, bus: &Rc<RefCell<Bus>>, test: &Json) -> Result<(), String> {
+    let (initial, expected) = (&test["initial"], &test["final"]);
+    {
+        let mut bus = bus.borrow_mut();
+        let memory = bus.flat_memory.as_mut().unwrap();
+        for entry in initial["ram"].as_array() {
+            memory[entry[0].as_u64() as usize] = entry[1].as_u64() as u8;
+        }
+    }
+
+    let mut cpu = cpu.borrow_mut();
+    cpu.set_registers(&registers(initial));
+    let cycles = cpu.step(1) as usize;
+
+    // The B flag and bit 5 only exist when the status is pushed to the stack, and the cycle count
+    // isn't part of the vectors' state.
+    let masked = |snapshot: CpuSnapshot| CpuSnapshot {
+        status: snapshot.status - Status::B - Status::from_bits_retain(1 << 5),
+        cycle_number: 0,
+        ..snapshot
+    };
+    let (actual, expected_registers) = (masked(cpu.snapshot()), masked(registers(expected)));
+    if actual != expected_registers {
+        return Err(format!(
+            "expected registers {expected_registers}, got {actual}"
+        ));
+    }
+
+    let expected_cycles = test["cycles"].as_array().len();
+    if cycles != expected_cycles {
+        return Err(format!("expected {expected_cycles} cycles, got {cycles}"));
+    }
+
+    let bus = bus.borrow();
+    let memory = bus.flat_memory.as_ref().unwrap();
+    for entry in expected["ram"].as_array() {
+        let addr = entry[0].as_u64() as usize;
+        let (expected, actual) = (entry[1].as_u64() as u8, memory[addr]);
+        if actual != expected {
+            return Err(format!(
+                "expected ${addr:04X} = {expected:02X}, got {actual:02X}"
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn registers(state: &Json) -> CpuSnapshot {
+    CpuSnapshot {
+        accumulator: state["a"].as_u64() as u8,
+        x_register: state["x"].as_u64() as u8,
+        y_register: state["y"].as_u64() as u8,
+        program_counter: state["pc"].as_u64() as u16,
+        stack_pointer: state["s"].as_u64() as u8,
+        status: Status::from_bits_retain(state["p"].as_u64() as u8),
+        cycle_number: 0,
+    }
+}
+
+fn setup() -> (Rc<RefCell<Cpu>>, Rc<RefCell<Bus>>) {
+    let cartridge = Cartridge::from_raw_prg(&[], 0x8000, [0; 3]).unwrap();
+    let cartridge = Rc::new(RefCell::new(cartridge));
+    let cpu = Rc::new(RefCell::new(Cpu::new()));
+    let ppu = Rc::new(RefCell::new(Ppu::new(cartridge.clone())));
+    let apu = Rc::new(RefCell::new(Apu::new()));
+    let bus = Bus::new(cpu.clone(), crate::new_boxed_array(), ppu, apu, cartridge);
+    bus.borrow_mut().flat_memory = Some(crate::new_boxed_array());
+
+    (cpu, bus)
+}
+
+/// Just enough of JSON for the test vectors, which only contain objects, arrays, strings, and
+/// non-negative integers.
+#[derive(Debug)]
+enum Json {
+    Number(u64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            pos: 0,
+        };
+        parser.value()
+    }
+
+    fn as_u64(&self) -> u64 {
+        match self {
+            Self::Number(number) => *number,
+            _ => panic!("expected a number, got {self:?}"),
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        match self {
+            Self::String(string) => string,
+            _ => panic!("expected a string, got {self:?}"),
+        }
+    }
+
+    fn as_array(&self) -> &[Json] {
+        match self {
+            Self::Array(array) => array,
+            _ => panic!("expected an array, got {self:?}"),
+        }
+    }
+}
+
+impl std::ops::Index<usize> for Json {
+    type Output = Json;
+
+    fn index(&self, index: usize) -> &Json {
+        &self.as_array()[index]
+    }
+}
+
+impl std::ops::Index<&str> for Json {
+    type Output = Json;
+
+    fn index(&self, key: &str) -> &Json {
+        let Self::Object(fields) = self else {
+            panic!("expected an object, got {self:?}");
+        };
+        fields
+            .iter()
+            .find_map(|(name, value)| (name == key).then_some(value))
+            .unwrap_or_else(|| panic!("missing field `{key}`"))
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn value(&mut self) -> Result<Json, String> {
+        match self.peek() {
+            Some(b'{') => self
+                .list(b'}', |parser| {
+                    let key = parser.string()?;
+                    match parser.next() {
+                        Some(b':') => Ok((key, parser.value()?)),
+                        _ => Err(format!("expected `:` at {}", parser.pos)),
+                    }
+                })
+                .map(Json::Object),
+            Some(b'[') => self.list(b']', Self::value).map(Json::Array),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'0'..=b'9') => {
+                let start = self.pos;
+                while self.bytes.get(self.pos).is_some_and(u8::is_ascii_digit) {
+                    self.pos += 1;
+                }
+                let digits = std::str::from_utf8(&self.bytes[start..self.pos]).unwrap();
+                digits
+                    .parse()
+                    .map(Json::Number)
+                    .map_err(|err| err.to_string())
+            }
+            _ => Err(format!("unexpected character at {}", self.pos)),
+        }
+    }
+
+    /// Parses the items of an array or object, starting at its opening bracket.
+    fn list<T>(
+        &mut self,
+        close: u8,
+        mut item: impl FnMut(&mut Self) -> Result<T, String>,
+    ) -> Result<Vec<T>, String> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        if self.peek() == Some(close) {
+            self.pos += 1;
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            match self.next() {
+                Some(b',') => (),
+                Some(byte) if byte == close => return Ok(items),
+                _ => {
+                    return Err(format!(
+                        "expected `,` or `{}` at {}",
+                        close as char, self.pos
+                    ))
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.next() != Some(b'"') {
+            return Err(format!("expected a string at {}", self.pos));
+        }
+        let start = self.pos;
+        while self.bytes.get(self.pos).is_some_and(|&byte| byte != b'"') {
+            self.pos += 1;
+        }
+        let string = String::from_utf8_lossy(&self.bytes[start..self.pos]).into_owned();
+        self.pos += 1;
+        Ok(string)
+    }
+
+    /// Skips whitespace and returns the next byte without consuming it.
+    fn peek(&mut self) -> Option<u8> {
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.pos += 1;
+        }
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.pos += 1;
+        Some(byte)
+    }
+}
+
+#[test]
+fn json_parser() {
+    let json = Json::parse(r#" {"name": "a9 01", "ram": [[1, 2], []], "empty": {}} "#).unwrap();
+    assert_eq!(json["name"].as_str(), "a9 01");
+    assert_eq!(json["ram"][0][1].as_u64(), 2);
+    assert!(json["ram"][1].as_array().is_empty());
+    assert!(Json::parse("[1 2]").is_err());
+}
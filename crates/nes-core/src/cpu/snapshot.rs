@@ -0,0 +1,31 @@
+use super::Status;
+
+/// The CPU's registers at a point in time, as returned by [super::Cpu::snapshot], for debuggers,
+/// trace logs, and tests.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CpuSnapshot {
+    pub accumulator: u8,
+    pub x_register: u8,
+    pub y_register: u8,
+    pub program_counter: u16,
+    pub stack_pointer: u8,
+    pub status: Status,
+    /// The number of cycles executed since the last reset.
+    pub cycle_number: usize,
+}
+
+impl std::fmt::Display for CpuSnapshot {
+    /// Formats the registers like nestest's log, with the status as pushed to the stack.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let p = (self.status | Status::B).bits() | 1 << 5;
+        write!(
+            f,
+            "A:{:02X} X:{:02X} Y:{:02X} P:{p:02X} SP:{:02X} CYC:{}",
+            self.accumulator,
+            self.x_register,
+            self.y_register,
+            self.stack_pointer,
+            self.cycle_number
+        )
+    }
+}
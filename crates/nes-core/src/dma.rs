@@ -0,0 +1,99 @@
+use crate::concat_bytes;
+
+/// The OAM DMA unit, which copies a page of CPU memory into OAM while halting the CPU.
+///
+/// Writing a page number to $4014 starts a transfer. The CPU is halted for one cycle, plus
+/// another if the write landed on an odd cycle, and then the unit alternates between reading a
+/// byte on even cycles and writing it to OAMDATA on odd cycles, for 513 or 514 cycles in total.
+/// The unit only decides what to access each cycle; the bus performs the accesses.
+///
+/// See <https://www.nesdev.org/wiki/DMA>.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct OamDma {
+    state: DmaState,
+    page: u8,
+    /// The number of bytes already written to OAM.
+    count: u16,
+    data: u8,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum DmaState {
+    #[default]
+    Idle,
+    /// Waiting on the halt and alignment cycles before the transfer.
+    Halted,
+    Transferring,
+}
+
+/// DMA activity during a frame, as returned by [crate::Bus::dma_stats], for spotting DMA taking
+/// CPU time a game was counting on, such as during a timed raster effect.
+///
+/// Only OAM DMA is emulated so far; DMC sample fetches will be counted here once the DMC is.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DmaStats {
+    /// The number of cycles the CPU was halted for.
+    pub stall_cycles: u32,
+    /// The number of OAM DMA transfers started.
+    pub oam_transfers: u32,
+    /// The number of bytes read from CPU memory.
+    pub bytes_read: u32,
+    /// The address of the last byte read, if any were.
+    pub last_read_addr: Option<u16>,
+}
+
+/// What the bus should do on a cycle during which the DMA unit holds the CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaAccess {
+    /// A halt or alignment cycle, where nothing is transferred.
+    Wait,
+    /// Read the byte at this address, then pass it to [OamDma::latch].
+    Read(u16),
+    /// Write this byte to OAMDATA.
+    Write(u8),
+}
+
+impl OamDma {
+    /// Starts copying the page `page` to OAM, starting from the next cycle.
+    pub fn start(&mut self, page: u8) {
+        self.state = DmaState::Halted;
+        self.page = page;
+        self.count = 0;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.state != DmaState::Idle
+    }
+
+    /// Advances by one CPU cycle, returning the access to perform, or `None` if the CPU is free to
+    /// run.
+    pub fn clock(&mut self, cycle: usize) -> Option<DmaAccess> {
+        match self.state {
+            DmaState::Idle => None,
+            DmaState::Halted => {
+                // Reads have to line up with even cycles, so the transfer starts after the next
+                // odd one.
+                if cycle % 2 == 1 {
+                    self.state = DmaState::Transferring;
+                }
+                Some(DmaAccess::Wait)
+            }
+            DmaState::Transferring if cycle.is_multiple_of(2) => {
+                let addr = concat_bytes(self.count as u8, self.page);
+                Some(DmaAccess::Read(addr))
+            }
+            DmaState::Transferring => {
+                self.count += 1;
+                if self.count == 256 {
+                    self.state = DmaState::Idle;
+                }
+                Some(DmaAccess::Write(self.data))
+            }
+        }
+    }
+
+    /// Stores the byte read for a [DmaAccess::Read].
+    pub fn latch(&mut self, data: u8) {
+        self.data = data;
+    }
+}
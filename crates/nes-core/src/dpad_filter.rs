@@ -0,0 +1,143 @@
+use crate::Controller;
+
+/// The bits of each D-pad axis in a [Controller]'s byte: up and down, then left and right.
+const AXES: [u8; 2] = [0x30, 0xC0];
+
+/// How a [DpadFilter] resolves opposite directions held at the same time.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DpadPolicy {
+    /// Passes both directions through, which a real D-pad can't do.
+    #[default]
+    Allow,
+    /// Releases both directions.
+    Neutral,
+    /// Keeps the direction pressed most recently.
+    LastPressed,
+    /// Keeps the direction that was already held.
+    FirstPressed,
+}
+
+impl std::str::FromStr for DpadPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "allow" => Ok(Self::Allow),
+            "neutral" => Ok(Self::Neutral),
+            "last" => Ok(Self::LastPressed),
+            "first" => Ok(Self::FirstPressed),
+            _ => Err(format!("unknown d-pad policy `{s}`")),
+        }
+    }
+}
+
+impl std::fmt::Display for DpadPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Allow => write!(f, "allow"),
+            Self::Neutral => write!(f, "neutral"),
+            Self::LastPressed => write!(f, "last"),
+            Self::FirstPressed => write!(f, "first"),
+        }
+    }
+}
+
+/// Keeps a controller's input to what a real D-pad can press, applied before it reaches the
+/// console.
+///
+/// Keyboards can hold Left and Right, or Up and Down, at the same time, which some games don't
+/// expect and glitch on. Each pair is resolved according to a [DpadPolicy], which allows both by
+/// default. Like [crate::AutoHold], [DpadFilter::apply] should be called before recording a
+/// replay, so that movies only contain input a real controller could make.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DpadFilter {
+    policy: DpadPolicy,
+    previous_input: u8,
+    previous_output: u8,
+}
+
+impl DpadFilter {
+    pub fn new(policy: DpadPolicy) -> Self {
+        Self {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    pub fn policy(&self) -> DpadPolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: DpadPolicy) {
+        self.policy = policy;
+    }
+
+    /// Transforms a frame of input. Directions pressed on the same frame as their opposite
+    /// cancel out under [DpadPolicy::LastPressed] and [DpadPolicy::FirstPressed], as neither was
+    /// pressed first.
+    pub fn apply(&mut self, input: Controller) -> Controller {
+        let input = u8::from(input);
+        let mut output = input;
+        for axis in AXES {
+            if input & axis != axis {
+                continue;
+            }
+            let previous = self.previous_input & axis;
+            let kept = match self.policy {
+                DpadPolicy::Allow => axis,
+                DpadPolicy::Neutral => 0,
+                // One direction was held, and the other has just been pressed.
+                DpadPolicy::LastPressed if previous != 0 && previous != axis => axis & !previous,
+                DpadPolicy::FirstPressed if previous != 0 && previous != axis => previous,
+                // Both were already held, so keep whichever won before.
+                DpadPolicy::LastPressed | DpadPolicy::FirstPressed if previous == axis => {
+                    self.previous_output & axis
+                }
+                DpadPolicy::LastPressed | DpadPolicy::FirstPressed => 0,
+            };
+            output = (output & !axis) | kept;
+        }
+
+        self.previous_input = input;
+        self.previous_output = output;
+        Controller::from(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opposite_directions() {
+        let left = Controller::new().with_left(true);
+        let right = Controller::new().with_right(true);
+        let both = left.with_right(true);
+        let run = |policy, inputs: &[Controller]| {
+            let mut filter = DpadFilter::new(policy);
+            inputs
+                .iter()
+                .map(|&input| filter.apply(input))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(run(DpadPolicy::Allow, &[left, both]), [left, both]);
+        assert_eq!(
+            run(DpadPolicy::Neutral, &[left, both.with_up(true)]),
+            [left, Controller::new().with_up(true)]
+        );
+        assert_eq!(
+            run(DpadPolicy::LastPressed, &[left, both, both, right]),
+            [left, right, right, right]
+        );
+        assert_eq!(
+            run(DpadPolicy::FirstPressed, &[left, both, both, right]),
+            [left, left, left, right]
+        );
+        // Pressed together, neither wins.
+        assert_eq!(
+            run(DpadPolicy::LastPressed, &[both, both]),
+            [Controller::new(); 2]
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use crate::PixelFormat;
+
+/// How bright the gaps between scanlines are, relative to the scanlines themselves.
+const SCANLINE_BRIGHTNESS: u16 = 160;
+
+/// A software video filter that upscales a 256x240 frame, for frontends that can't use shaders.
+///
+/// Filters work on frames in any [PixelFormat] and leave the alpha channel of RGBA frames alone.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum VideoFilter {
+    /// Passes the frame through unchanged.
+    #[default]
+    None,
+    /// Doubles the frame in size, darkening every other row like the gaps between a CRT's
+    /// scanlines.
+    Scanlines,
+    /// Doubles the frame in size with the EPX (Scale2x) algorithm, which smooths diagonal edges
+    /// without blurring.
+    Epx,
+}
+
+impl VideoFilter {
+    /// Returns the next filter, wrapping around, for frontends that cycle through them.
+    pub fn next(self) -> Self {
+        match self {
+            Self::None => Self::Scanlines,
+            Self::Scanlines => Self::Epx,
+            Self::Epx => Self::None,
+        }
+    }
+
+    /// Returns how many times larger the filtered frame is in each dimension.
+    pub fn scale(self) -> usize {
+        match self {
+            Self::None => 1,
+            Self::Scanlines | Self::Epx => 2,
+        }
+    }
+
+    /// Filters a 256x240 frame, such as [crate::Ppu::buffer], into `output`, which is resized to
+    /// fit the upscaled frame.
+    pub fn apply(self, frame: &[u8], pixel_format: PixelFormat, output: &mut Vec<u8>) {
+        let bytes_per_pixel = pixel_format.bytes_per_pixel();
+        let scale = self.scale();
+        output.resize(frame.len() * scale * scale, 0);
+
+        let pixel = |x: usize, y: usize| {
+            let index = (x + y * 256) * bytes_per_pixel;
+            &frame[index..index + bytes_per_pixel]
+        };
+        let mut set_pixel = |x: usize, y: usize, color: &[u8]| {
+            let index = (x + y * 256 * scale) * bytes_per_pixel;
+            output[index..index + bytes_per_pixel].copy_from_slice(color);
+        };
+
+        match self {
+            Self::None => output.copy_from_slice(frame),
+            Self::Scanlines => {
+                let mut dimmed = vec![0; bytes_per_pixel];
+                for y in 0..240 {
+                    for x in 0..256 {
+                        let color = pixel(x, y);
+                        for (dimmed, &channel) in dimmed.iter_mut().zip(color).take(3) {
+                            *dimmed = (channel as u16 * SCANLINE_BRIGHTNESS / 255) as u8;
+                        }
+                        if let Some(alpha) = color.get(3) {
+                            dimmed[3] = *alpha;
+                        }
+                        set_pixel(x * 2, y * 2, color);
+                        set_pixel(x * 2 + 1, y * 2, color);
+                        set_pixel(x * 2, y * 2 + 1, &dimmed);
+                        set_pixel(x * 2 + 1, y * 2 + 1, &dimmed);
+                    }
+                }
+            }
+            Self::Epx => {
+                for y in 0..240 {
+                    for x in 0..256 {
+                        // Neighbors off the edge of the frame are treated as copies of the center.
+                        let center = pixel(x, y);
+                        let up = if y > 0 { pixel(x, y - 1) } else { center };
+                        let down = if y < 239 { pixel(x, y + 1) } else { center };
+                        let left = if x > 0 { pixel(x - 1, y) } else { center };
+                        let right = if x < 255 { pixel(x + 1, y) } else { center };
+
+                        let corner = |a, b, c, d| epx_corner(center, a, b, c, d);
+                        set_pixel(x * 2, y * 2, corner(left, up, down, right));
+                        set_pixel(x * 2 + 1, y * 2, corner(up, right, left, down));
+                        set_pixel(x * 2, y * 2 + 1, corner(down, left, right, up));
+                        set_pixel(x * 2 + 1, y * 2 + 1, corner(right, down, up, left));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Picks the color of one corner of an upscaled pixel, given the two neighbors on either side of
+/// that corner (`a` and `b`) and the two opposite them (`c` and `d`).
+fn epx_corner<'a>(center: &'a [u8], a: &'a [u8], b: &'a [u8], c: &[u8], d: &[u8]) -> &'a [u8] {
+    if a == b && a != c && b != d {
+        a
+    } else {
+        center
+    }
+}
+
+impl std::fmt::Display for VideoFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::None => "none",
+            Self::Scanlines => "scanlines",
+            Self::Epx => "EPX",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upscaling() {
+        // A white frame with a black pixel at (1, 1) and another diagonally below it to the right.
+        let mut frame = vec![0xFF; 256 * 240 * 3];
+        for (x, y) in [(1, 1), (2, 2)] {
+            let index = (x + y * 256) * 3;
+            frame[index..index + 3].fill(0);
+        }
+        let pixel = |output: &[u8], x: usize, y: usize| output[(x + y * 512) * 3];
+
+        let mut output = Vec::new();
+        VideoFilter::Scanlines.apply(&frame, PixelFormat::Rgb24, &mut output);
+        assert_eq!(output.len(), frame.len() * 4);
+        assert_eq!(pixel(&output, 0, 0), 0xFF);
+        assert_eq!(pixel(&output, 0, 1), SCANLINE_BRIGHTNESS as u8);
+
+        // EPX fills in the corners of the white pixels between the two black pixels, joining them
+        // into a line, but leaves the corners facing away from the line alone.
+        VideoFilter::Epx.apply(&frame, PixelFormat::Rgb24, &mut output);
+        assert_eq!(pixel(&output, 3, 4), 0x00);
+        assert_eq!(pixel(&output, 4, 3), 0x00);
+        assert_eq!(pixel(&output, 4, 2), 0xFF);
+        assert_eq!(pixel(&output, 2, 2), 0x00);
+    }
+}
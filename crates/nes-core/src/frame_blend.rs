@@ -0,0 +1,100 @@
+/// How much of the previous frame is kept by default, which evens out 30Hz flicker completely.
+const DEFAULT_WEIGHT: f32 = 0.5;
+
+/// A post-processing filter that blends each frame with the one before it, imitating the
+/// persistence of a CRT's phosphors.
+///
+/// Games that flicker sprites on alternating frames to get around the PPU's sprite limit look
+/// steadier this way, as they would have on a CRT. The filter works on frames in any
+/// [crate::PixelFormat], since it blends each byte independently. [FrameBlend::push] should be
+/// called once per emulated frame, rather than once per displayed frame, so that pausing doesn't
+/// blend a frame with itself.
+#[derive(Debug, Clone)]
+pub struct FrameBlend {
+    weight: f32,
+    previous: Vec<u8>,
+    output: Vec<u8>,
+}
+
+impl FrameBlend {
+    /// Creates a filter that keeps `weight` of the previous frame, from 0 (off) to 1.
+    pub fn new(weight: f32) -> Self {
+        Self {
+            weight: weight.clamp(0.0, 1.0),
+            previous: Vec::new(),
+            output: Vec::new(),
+        }
+    }
+
+    pub fn weight(&self) -> f32 {
+        self.weight
+    }
+
+    pub fn set_weight(&mut self, weight: f32) {
+        self.weight = weight.clamp(0.0, 1.0);
+    }
+
+    /// Blends a newly completed frame, such as [crate::Ppu::buffer], with the previous one.
+    pub fn push(&mut self, frame: &[u8]) {
+        // There's nothing to blend with after starting or switching pixel formats.
+        if self.previous.len() != frame.len() {
+            self.previous = frame.to_vec();
+            self.output = frame.to_vec();
+            return;
+        }
+
+        let weight = self.weight;
+        for ((output, previous), &current) in self
+            .output
+            .iter_mut()
+            .zip(self.previous.iter_mut())
+            .zip(frame)
+        {
+            let blended = current as f32 * (1.0 - weight) + *previous as f32 * weight;
+            *output = blended.round() as u8;
+            *previous = current;
+        }
+    }
+
+    /// Returns the most recent blended frame, or `None` if no frames have been pushed yet.
+    pub fn output(&self) -> Option<&[u8]> {
+        (!self.output.is_empty()).then_some(self.output.as_slice())
+    }
+
+    /// Forgets the previous frame, such as after loading a savestate, so it isn't blended into
+    /// an unrelated one.
+    pub fn reset(&mut self) {
+        self.previous.clear();
+        self.output.clear();
+    }
+}
+
+impl Default for FrameBlend {
+    fn default() -> Self {
+        Self::new(DEFAULT_WEIGHT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blends_with_unfiltered_previous_frame() {
+        let mut blend = FrameBlend::default();
+        assert_eq!(blend.output(), None);
+
+        blend.push(&[0, 200]);
+        assert_eq!(blend.output(), Some([0, 200].as_slice()));
+
+        // A pixel flickering between two values every frame settles halfway between them.
+        blend.push(&[200, 0]);
+        assert_eq!(blend.output(), Some([100, 100].as_slice()));
+        blend.push(&[0, 200]);
+        assert_eq!(blend.output(), Some([100, 100].as_slice()));
+
+        blend.set_weight(0.25);
+        blend.push(&[200, 0]);
+        assert_eq!(blend.output(), Some([150, 50].as_slice()));
+    }
+}
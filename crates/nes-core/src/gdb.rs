@@ -0,0 +1,695 @@
+use std::{
+    cell::RefCell,
+    io::ErrorKind,
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    num::NonZeroUsize,
+    rc::Rc,
+};
+
+use gdbstub::{
+    arch::Arch,
+    common::Signal,
+    conn::ConnectionExt,
+    stub::{state_machine::GdbStubStateMachine, GdbStub, SingleThreadStopReason},
+    target::{
+        ext::{
+            base::{
+                single_register_access::{SingleRegisterAccess, SingleRegisterAccessOps},
+                singlethread::{
+                    SingleThreadBase, SingleThreadResume, SingleThreadResumeOps,
+                    SingleThreadSingleStep, SingleThreadSingleStepOps,
+                },
+                BaseOps,
+            },
+            breakpoints::{
+                Breakpoints, BreakpointsOps, HwBreakpoint, HwBreakpointOps, SwBreakpoint,
+                SwBreakpointOps,
+            },
+        },
+        Target, TargetError, TargetResult,
+    },
+};
+
+use crate::{
+    cpu::{CpuSnapshot, Status},
+    Apu, Bus, Cpu, Ppu,
+};
+
+/// The most clocks a single step can take, which covers the longest instruction plus an
+/// interrupt being serviced straight after it.
+const MAX_STEP_CLOCKS: usize = 16;
+
+type Session = GdbStubStateMachine<'static, Console, TcpStream>;
+
+/// A remote debugging server speaking GDB's remote serial protocol over TCP, so NES programs can
+/// be debugged from any editor or IDE with a GDB client.
+///
+/// The server supports breakpoints, single-stepping, and reading and writing memory and
+/// registers. Registers are numbered A, X, Y, P, SP, then PC, with PC being 16 bits. Memory reads
+/// go through [Bus::peek], so inspecting registers doesn't disturb the program.
+///
+/// Frontends call [GdbServer::poll] once per displayed frame to handle requests from the client,
+/// then [GdbServer::run_frame] in place of clocking the system themselves. While the client has
+/// the program stopped, `run_frame` does nothing.
+pub struct GdbServer {
+    listener: TcpListener,
+    session: Option<Session>,
+    console: Console,
+    warnings: Vec<String>,
+}
+
+/// The console as seen by the debugger, along with the debugger's own state.
+struct Console {
+    bus: Rc<RefCell<Bus>>,
+    cpu: Rc<RefCell<Cpu>>,
+    ppu: Rc<RefCell<Ppu>>,
+    apu: Rc<RefCell<Apu>>,
+    breakpoints: Vec<u16>,
+    execution: Execution,
+    /// The breakpoint the program was stopped at, which shouldn't stop it again on resuming.
+    resume_addr: Option<u16>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Execution {
+    Stopped,
+    Step,
+    Continue,
+}
+
+impl GdbServer {
+    /// Starts listening for a client on the given address, such as `127.0.0.1:9001`, to debug the
+    /// given console. The program keeps running until a client connects and stops it.
+    pub fn bind(
+        addr: impl ToSocketAddrs,
+        bus: Rc<RefCell<Bus>>,
+        cpu: Rc<RefCell<Cpu>>,
+        ppu: Rc<RefCell<Ppu>>,
+        apu: Rc<RefCell<Apu>>,
+    ) -> Result<Self, String> {
+        let listener = TcpListener::bind(addr).map_err(|err| err.to_string())?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|err| err.to_string())?;
+
+        Ok(Self {
+            listener,
+            session: None,
+            console: Console {
+                bus,
+                cpu,
+                ppu,
+                apu,
+                breakpoints: Vec::new(),
+                execution: Execution::Continue,
+                resume_addr: None,
+            },
+            warnings: Vec::new(),
+        })
+    }
+
+    /// Returns whether the client has stopped the program.
+    pub fn is_stopped(&self) -> bool {
+        self.session.is_some() && self.console.execution != Execution::Continue
+    }
+
+    /// Returns and clears the problems the server has run into since last called, such as a
+    /// client's connection being lost.
+    pub fn drain_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Accepts a waiting client and handles any requests it has sent.
+    pub fn poll(&mut self) {
+        if self.session.is_none() {
+            match self.listener.accept() {
+                Ok((stream, _)) => self.connect(stream),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return,
+                Err(err) => {
+                    self.warnings
+                        .push(format!("gdb: failed to accept connection: {err}"));
+                    return;
+                }
+            }
+        }
+
+        let Some(mut session) = self.session.take() else {
+            return;
+        };
+        let console = &mut self.console;
+        let result = loop {
+            session = match session {
+                GdbStubStateMachine::Idle(mut gdb) => match next_byte(gdb.borrow_conn()) {
+                    Ok(Some(byte)) => match gdb.incoming_data(console, byte) {
+                        Ok(session) => session,
+                        Err(err) => break Err(err.to_string()),
+                    },
+                    Ok(None) => break Ok(gdb.into()),
+                    Err(err) => break Err(err.to_string()),
+                },
+                GdbStubStateMachine::Running(mut gdb) => {
+                    if console.execution == Execution::Step {
+                        console.step();
+                        match gdb.report_stop(console, SingleThreadStopReason::DoneStep) {
+                            Ok(session) => session,
+                            Err(err) => break Err(err.to_string()),
+                        }
+                    } else {
+                        match next_byte(gdb.borrow_conn()) {
+                            Ok(Some(byte)) => match gdb.incoming_data(console, byte) {
+                                Ok(session) => session,
+                                Err(err) => break Err(err.to_string()),
+                            },
+                            Ok(None) => break Ok(gdb.into()),
+                            Err(err) => break Err(err.to_string()),
+                        }
+                    }
+                }
+                GdbStubStateMachine::CtrlCInterrupt(gdb) => {
+                    console.stop();
+                    let reason = SingleThreadStopReason::Signal(Signal::SIGINT);
+                    match gdb.interrupt_handled(console, Some(reason)) {
+                        Ok(session) => session,
+                        Err(err) => break Err(err.to_string()),
+                    }
+                }
+                GdbStubStateMachine::Disconnected(_) => {
+                    self.disconnect();
+                    return;
+                }
+            };
+        };
+
+        match result {
+            Ok(session) => self.session = Some(session),
+            Err(err) => {
+                self.warnings.push(format!("gdb: connection lost: {err}"));
+                self.disconnect();
+            }
+        }
+    }
+
+    /// Clocks the system until the end of the frame, stopping early if a breakpoint is hit.
+    ///
+    /// Returns whether the frame finished, in which case [Ppu::is_frame_ready] is left set for
+    /// the caller to clear as usual.
+    pub fn run_frame(&mut self) -> bool {
+        if self.is_stopped() {
+            return false;
+        }
+        let console = &mut self.console;
+        while !console.ppu.borrow().is_frame_ready {
+            if console.is_at_breakpoint() {
+                self.report_stop(SingleThreadStopReason::SwBreak(()));
+                return false;
+            }
+            console.clock();
+            if self.session.is_some() && console.cpu.borrow().halt().is_some() {
+                self.report_stop(SingleThreadStopReason::Signal(Signal::SIGILL));
+                return false;
+            }
+        }
+        true
+    }
+
+    fn connect(&mut self, stream: TcpStream) {
+        // Clients expect the program to be stopped when they attach.
+        self.console.stop();
+        match GdbStub::new(stream).run_state_machine(&mut self.console) {
+            Ok(session) => self.session = Some(session),
+            Err(err) => {
+                self.warnings
+                    .push(format!("gdb: failed to set up connection: {err}"));
+                self.disconnect();
+            }
+        }
+    }
+
+    fn report_stop(&mut self, reason: SingleThreadStopReason<u16>) {
+        self.console.stop();
+        let result = match self.session.take() {
+            Some(GdbStubStateMachine::Running(gdb)) => gdb.report_stop(&mut self.console, reason),
+            session => {
+                self.session = session;
+                return;
+            }
+        };
+        match result {
+            Ok(session) => self.session = Some(session),
+            Err(err) => {
+                self.warnings.push(format!("gdb: connection lost: {err}"));
+                self.disconnect();
+            }
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.session = None;
+        self.console.breakpoints.clear();
+        self.console.execution = Execution::Continue;
+    }
+}
+
+/// Reads the next byte the client has sent, if there is one.
+fn next_byte(stream: &mut TcpStream) -> std::io::Result<Option<u8>> {
+    match ConnectionExt::peek(stream)? {
+        Some(_) => ConnectionExt::read(stream).map(Some),
+        None => Ok(None),
+    }
+}
+
+impl Console {
+    fn clock(&self) {
+        Bus::clock(
+            self.bus.clone(),
+            self.cpu.clone(),
+            self.ppu.clone(),
+            self.apu.clone(),
+        );
+    }
+
+    fn stop(&mut self) {
+        self.execution = Execution::Stopped;
+        self.resume_addr = Some(self.cpu.borrow().program_counter());
+    }
+
+    fn is_at_breakpoint(&mut self) -> bool {
+        let cpu = self.cpu.borrow();
+        if !cpu.is_at_instruction_boundary() {
+            return false;
+        }
+        let addr = cpu.program_counter();
+        // Resuming from a breakpoint runs the instruction it's on.
+        if self.resume_addr.take() == Some(addr) {
+            return false;
+        }
+        self.breakpoints.contains(&addr)
+    }
+
+    /// Runs exactly one instruction, servicing any interrupt that becomes due along the way.
+    fn step(&mut self) {
+        for _ in 0..MAX_STEP_CLOCKS {
+            self.clock();
+            let cpu = self.cpu.borrow();
+            if cpu.is_at_instruction_boundary() || cpu.halt().is_some() {
+                break;
+            }
+        }
+        // Steps don't finish frames for the frontend, so don't leave one waiting to be shown.
+        self.ppu.borrow_mut().is_frame_ready = false;
+        self.stop();
+    }
+}
+
+/// The 6502 as described to GDB, which has no built in definition for it.
+enum Mos6502 {}
+
+impl Arch for Mos6502 {
+    type Usize = u16;
+    type Registers = Registers;
+    type BreakpointKind = usize;
+    type RegId = Register;
+}
+
+/// The registers in the order the client expects: A, X, Y, P, SP, then PC.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Registers {
+    accumulator: u8,
+    x_register: u8,
+    y_register: u8,
+    status: u8,
+    stack_pointer: u8,
+    program_counter: u16,
+}
+
+impl Registers {
+    fn to_bytes(&self) -> [u8; 7] {
+        let [pc_low, pc_high] = self.program_counter.to_le_bytes();
+        [
+            self.accumulator,
+            self.x_register,
+            self.y_register,
+            self.status,
+            self.stack_pointer,
+            pc_low,
+            pc_high,
+        ]
+    }
+
+    fn from_bytes(bytes: [u8; 7]) -> Self {
+        Self {
+            accumulator: bytes[0],
+            x_register: bytes[1],
+            y_register: bytes[2],
+            status: bytes[3],
+            stack_pointer: bytes[4],
+            program_counter: u16::from_le_bytes([bytes[5], bytes[6]]),
+        }
+    }
+}
+
+impl gdbstub::arch::Registers for Registers {
+    type ProgramCounter = u16;
+
+    fn pc(&self) -> u16 {
+        self.program_counter
+    }
+
+    fn gdb_serialize(&self, mut write_byte: impl FnMut(Option<u8>)) {
+        for byte in self.to_bytes() {
+            write_byte(Some(byte));
+        }
+    }
+
+    fn gdb_deserialize(&mut self, bytes: &[u8]) -> Result<(), ()> {
+        *self = Self::from_bytes(bytes.try_into().map_err(|_| ())?);
+        Ok(())
+    }
+}
+
+/// A single register, numbered as in [Registers].
+#[derive(Debug, Clone, Copy)]
+struct Register(usize);
+
+impl gdbstub::arch::RegId for Register {
+    fn from_raw_id(id: usize) -> Option<(Self, Option<NonZeroUsize>)> {
+        let size = match id {
+            0..=4 => 1,
+            5 => 2,
+            _ => return None,
+        };
+        Some((Self(id), NonZeroUsize::new(size)))
+    }
+}
+
+impl Target for Console {
+    type Arch = Mos6502;
+    type Error = &'static str;
+
+    fn base_ops(&mut self) -> BaseOps<'_, Mos6502, &'static str> {
+        BaseOps::SingleThread(self)
+    }
+
+    fn support_breakpoints(&mut self) -> Option<BreakpointsOps<'_, Self>> {
+        Some(self)
+    }
+}
+
+impl SingleThreadBase for Console {
+    fn read_registers(&mut self, registers: &mut Registers) -> TargetResult<(), Self> {
+        let snapshot = self.cpu.borrow().snapshot();
+        *registers = Registers {
+            accumulator: snapshot.accumulator,
+            x_register: snapshot.x_register,
+            y_register: snapshot.y_register,
+            status: snapshot.status.bits(),
+            stack_pointer: snapshot.stack_pointer,
+            program_counter: snapshot.program_counter,
+        };
+        Ok(())
+    }
+
+    fn write_registers(&mut self, registers: &Registers) -> TargetResult<(), Self> {
+        let mut cpu = self.cpu.borrow_mut();
+        let snapshot = cpu.snapshot();
+        cpu.set_registers(&CpuSnapshot {
+            accumulator: registers.accumulator,
+            x_register: registers.x_register,
+            y_register: registers.y_register,
+            status: Status::from_bits_retain(registers.status),
+            stack_pointer: registers.stack_pointer,
+            program_counter: registers.program_counter,
+            ..snapshot
+        });
+        self.resume_addr = Some(registers.program_counter);
+        Ok(())
+    }
+
+    fn support_single_register_access(&mut self) -> Option<SingleRegisterAccessOps<'_, (), Self>> {
+        Some(self)
+    }
+
+    fn read_addrs(&mut self, start_addr: u16, data: &mut [u8]) -> TargetResult<usize, Self> {
+        let bus = self.bus.borrow();
+        for (offset, byte) in data.iter_mut().enumerate() {
+            *byte = bus.peek(start_addr.wrapping_add(offset as u16));
+        }
+        Ok(data.len())
+    }
+
+    fn write_addrs(&mut self, start_addr: u16, data: &[u8]) -> TargetResult<(), Self> {
+        let mut bus = self.bus.borrow_mut();
+        for (offset, &byte) in data.iter().enumerate() {
+            bus.cpu_write(start_addr.wrapping_add(offset as u16), byte);
+        }
+        Ok(())
+    }
+
+    fn support_resume(&mut self) -> Option<SingleThreadResumeOps<'_, Self>> {
+        Some(self)
+    }
+}
+
+impl SingleRegisterAccess<()> for Console {
+    fn read_register(
+        &mut self,
+        _: (),
+        register: Register,
+        buffer: &mut [u8],
+    ) -> TargetResult<usize, Self> {
+        let mut registers = Registers::default();
+        self.read_registers(&mut registers)?;
+        let bytes = registers.to_bytes();
+        let length = if register.0 == 5 { 2 } else { 1 };
+        let value = &bytes[register.0..register.0 + length];
+        buffer
+            .get_mut(..length)
+            .ok_or(TargetError::NonFatal)?
+            .copy_from_slice(value);
+        Ok(length)
+    }
+
+    fn write_register(
+        &mut self,
+        _: (),
+        register: Register,
+        value: &[u8],
+    ) -> TargetResult<(), Self> {
+        let mut registers = Registers::default();
+        self.read_registers(&mut registers)?;
+        let mut bytes = registers.to_bytes();
+        bytes
+            .get_mut(register.0..register.0 + value.len())
+            .ok_or(TargetError::NonFatal)?
+            .copy_from_slice(value);
+        self.write_registers(&Registers::from_bytes(bytes))
+    }
+}
+
+impl SingleThreadResume for Console {
+    fn resume(&mut self, _: Option<Signal>) -> Result<(), &'static str> {
+        self.execution = Execution::Continue;
+        Ok(())
+    }
+
+    fn support_single_step(&mut self) -> Option<SingleThreadSingleStepOps<'_, Self>> {
+        Some(self)
+    }
+}
+
+impl SingleThreadSingleStep for Console {
+    fn step(&mut self, _: Option<Signal>) -> Result<(), &'static str> {
+        self.execution = Execution::Step;
+        Ok(())
+    }
+}
+
+// Software and hardware breakpoints are treated the same.
+impl Breakpoints for Console {
+    fn support_sw_breakpoint(&mut self) -> Option<SwBreakpointOps<'_, Self>> {
+        Some(self)
+    }
+
+    fn support_hw_breakpoint(&mut self) -> Option<HwBreakpointOps<'_, Self>> {
+        Some(self)
+    }
+}
+
+impl SwBreakpoint for Console {
+    fn add_sw_breakpoint(&mut self, addr: u16, _: usize) -> TargetResult<bool, Self> {
+        if !self.breakpoints.contains(&addr) {
+            self.breakpoints.push(addr);
+        }
+        Ok(true)
+    }
+
+    fn remove_sw_breakpoint(&mut self, addr: u16, _: usize) -> TargetResult<bool, Self> {
+        let length = self.breakpoints.len();
+        self.breakpoints.retain(|&breakpoint| breakpoint != addr);
+        Ok(self.breakpoints.len() != length)
+    }
+}
+
+impl HwBreakpoint for Console {
+    fn add_hw_breakpoint(&mut self, addr: u16, kind: usize) -> TargetResult<bool, Self> {
+        self.add_sw_breakpoint(addr, kind)
+    }
+
+    fn remove_hw_breakpoint(&mut self, addr: u16, kind: usize) -> TargetResult<bool, Self> {
+        self.remove_sw_breakpoint(addr, kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{Cartridge, System};
+
+    /// A GDB client talking to the server over a real socket, polling the server while waiting
+    /// for its replies since both run on the test's thread.
+    struct Client {
+        stream: TcpStream,
+        buffer: Vec<u8>,
+    }
+
+    impl Client {
+        fn connect(server: &mut GdbServer) -> Self {
+            let addr = server.listener.local_addr().unwrap();
+            let stream = TcpStream::connect(addr).unwrap();
+            stream.set_nonblocking(true).unwrap();
+            server.poll();
+            assert!(server.session.is_some());
+            Self {
+                stream,
+                buffer: Vec::new(),
+            }
+        }
+
+        fn send(&mut self, data: &str) {
+            let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+            write!(self.stream, "${data}#{checksum:02x}").unwrap();
+        }
+
+        /// Polls the server until it replies, returning the reply without its framing.
+        fn reply(&mut self, server: &mut GdbServer) -> String {
+            for _ in 0..1000 {
+                server.poll();
+                let mut chunk = [0; 1024];
+                match Read::read(&mut self.stream, &mut chunk) {
+                    Ok(length) => self.buffer.extend_from_slice(&chunk[..length]),
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                    Err(err) => panic!("{err}"),
+                }
+                // Skip acknowledgements.
+                while self.buffer.first() == Some(&b'+') {
+                    self.buffer.remove(0);
+                }
+                if let Some(end) = self.buffer.iter().position(|&byte| byte == b'#') {
+                    if self.buffer.len() >= end + 3 {
+                        let packet = run_length_decode(&self.buffer[1..end]);
+                        self.buffer.drain(..end + 3);
+                        self.stream.write_all(b"+").unwrap();
+                        return packet;
+                    }
+                }
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            panic!("no reply from the server");
+        }
+
+        /// Sends a request to resume the program, polling the server until it has resumed.
+        fn resume(&mut self, server: &mut GdbServer, data: &str) {
+            self.send(data);
+            for _ in 0..1000 {
+                server.poll();
+                if !server.is_stopped() {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            panic!("the server didn't resume the program");
+        }
+
+        fn request(&mut self, server: &mut GdbServer, data: &str) -> String {
+            self.send(data);
+            self.reply(server)
+        }
+    }
+
+    /// Expands the runs the server compresses replies with, where `*` repeats the previous
+    /// character as many times as the next character's value less 29.
+    fn run_length_decode(data: &[u8]) -> String {
+        let mut decoded = Vec::new();
+        let mut bytes = data.iter();
+        while let Some(&byte) = bytes.next() {
+            if byte == b'*' {
+                let count = bytes.next().unwrap() - 29;
+                let previous = *decoded.last().unwrap();
+                decoded.extend(std::iter::repeat_n(previous, count as usize));
+            } else {
+                decoded.push(byte);
+            }
+        }
+        String::from_utf8(decoded).unwrap()
+    }
+
+    #[test]
+    fn session() {
+        let program = [
+            0xA9, 0x42, //       LDA #$42
+            0x85, 0x10, //       STA $10
+            0xE8, //             INX
+            0x4C, 0x04, 0x80, // JMP $8004
+        ];
+        let cartridge = Cartridge::from_raw_prg(&program, 0x8000, [0x8000; 3]).unwrap();
+        let system = System::new(cartridge);
+        let mut server = GdbServer::bind(
+            "127.0.0.1:0",
+            system.bus.clone(),
+            system.cpu.clone(),
+            system.ppu.clone(),
+            system.apu.clone(),
+        )
+        .unwrap();
+
+        let mut client = Client::connect(&mut server);
+        assert!(server.is_stopped());
+        assert!(!server.run_frame());
+        assert_eq!(client.request(&mut server, "Z0,8004,1"), "OK");
+
+        client.resume(&mut server, "c");
+        assert!(!server.run_frame());
+        assert!(server.is_stopped());
+        assert!(client.reply(&mut server).starts_with("T05"));
+
+        // A, X, Y, P, SP, then PC.
+        let registers = client.request(&mut server, "g");
+        assert!(registers.starts_with("420000"));
+        assert!(registers.ends_with("0480"));
+        assert_eq!(client.request(&mut server, "p5"), "0480");
+        assert_eq!(client.request(&mut server, "m10,1"), "42");
+
+        // Continuing from the breakpoint runs the loop once before stopping on it again.
+        client.resume(&mut server, "c");
+        assert!(!server.run_frame());
+        assert!(client.reply(&mut server).starts_with("T05"));
+        assert!(client.request(&mut server, "g").starts_with("420100"));
+
+        assert_eq!(client.request(&mut server, "s"), "S05");
+        assert!(client.request(&mut server, "g").ends_with("0580"));
+        assert_eq!(client.request(&mut server, "M10,1:07"), "OK");
+        assert_eq!(client.request(&mut server, "m10,1"), "07");
+
+        // Once the client detaches, the program runs freely.
+        assert_eq!(client.request(&mut server, "D"), "OK");
+        server.poll();
+        assert!(!server.is_stopped());
+        assert!(server.run_frame());
+        assert!(server.drain_warnings().is_empty());
+    }
+}
@@ -0,0 +1,131 @@
+mod apu;
+mod apu_log;
+pub mod audio;
+mod auto_hold;
+mod bus;
+mod capabilities;
+mod cartridge;
+pub mod cpu;
+mod dma;
+mod dpad_filter;
+mod filter;
+mod frame_blend;
+mod game_genie;
+#[cfg(feature = "gdb")]
+mod gdb;
+mod lockstep;
+pub mod mapper;
+mod memory_device;
+mod osd;
+pub mod patch;
+pub mod ppu;
+mod replay;
+mod replay_player;
+mod resampler;
+mod rewind;
+pub mod savestate;
+mod serial_device;
+mod stats;
+mod system;
+mod test_hook;
+mod timing;
+
+pub use apu::{Apu, ChannelGains, FastForwardAudio};
+pub use apu_log::{to_vgm, ApuWrite};
+pub use audio::{AudioSink, BufferWatermark, BufferWatermarks};
+pub use auto_hold::AutoHold;
+pub use bus::{Bus, FastBoot, Interrupt, MemoryHandler, MemoryRegion, StopCondition};
+pub use capabilities::{AccuracyOption, AccuracyProfile, Capabilities, VERSION};
+pub use cartridge::{Cartridge, RomInfo, CHR_BANK_SIZE};
+pub use cpu::Cpu;
+pub use dma::DmaStats;
+pub use dpad_filter::{DpadFilter, DpadPolicy};
+pub use filter::VideoFilter;
+pub use frame_blend::FrameBlend;
+pub use game_genie::{GameGenie, GameGenieCode};
+#[cfg(feature = "gdb")]
+pub use gdb::GdbServer;
+pub use lockstep::{find_divergence, Divergence};
+pub use memory_device::MemoryDevice;
+pub use osd::Osd;
+pub use ppu::{
+    BackgroundPixel, PixelFormat, PixelSource, Ppu, PpuWarning, PpuWarningKind, ScanlinePixel,
+    ScanlineRender, SpritePixel,
+};
+pub use replay::{format_input_line, InputCommand, Replay, ReplayMetadata};
+pub use replay_player::{CapturedFrame, FrameMismatch, FrameReference, ReplayPlayer};
+pub use resampler::Resampler;
+pub use rewind::RewindBuffer;
+pub use savestate::{Savestate, SavestateError, SavestateMetadata, SystemState};
+pub use serial_device::{ControllerPort, FamilyKey, FamilyKeyboard, FourScore, SerialDevice};
+pub use stats::{Frameskip, PerfMonitor, PerfStats};
+pub use system::System;
+pub use test_hook::{TestEvent, TestHook};
+pub use timing::{FrameTiming, Region};
+
+#[inline]
+pub const fn is_bit_set(byte: u8, index: u8) -> bool {
+    (byte >> index & 1) != 0
+}
+
+#[inline]
+pub const fn concat_bytes(low: u8, high: u8) -> u16 {
+    (high as u16) << 8 | low as u16
+}
+
+#[inline]
+pub const fn low_byte(word: u16) -> u8 {
+    word as u8
+}
+
+#[inline]
+pub const fn high_byte(word: u16) -> u8 {
+    (word >> 8) as u8
+}
+
+/// Computes the standard CRC-32 checksum of some data, as used by zip files and BPS patches.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = flate2::Crc::new();
+    crc.update(data);
+    crc.sum()
+}
+
+#[bitfield_struct::bitfield(u8)]
+#[derive(PartialEq, Eq)]
+pub struct Controller {
+    pub a: bool,
+    pub b: bool,
+    pub select: bool,
+    pub start: bool,
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+impl std::fmt::Display for Controller {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let format_input = |input: bool, str: &'static str| if input { str } else { "." };
+
+        let right = format_input(self.right(), "R");
+        let left = format_input(self.left(), "L");
+        let down = format_input(self.down(), "D");
+        let up = format_input(self.up(), "U");
+        let start = format_input(self.start(), "T");
+        let select = format_input(self.select(), "S");
+        let b = format_input(self.b(), "B");
+        let a = format_input(self.a(), "A");
+
+        write!(f, "{right}{left}{down}{up}{start}{select}{b}{a}")
+    }
+}
+
+/// Creates a new array directly on the heap without going through the stack.
+///
+/// This is a workaround to avoid stack overflows in debug builds, as without optimizations,
+/// `Box::new([T; N])` allocates the array on the stack before moving to the heap.
+pub fn new_boxed_array<T: Default + Clone, const N: usize>() -> Box<[T; N]> {
+    // SAFETY: A Box<[T]> obtained from a Vec<T> with N elements is guaranteed to be safe to cast to
+    // a Box<[T; N]>.
+    unsafe { Box::from_raw(Box::into_raw(vec![T::default(); N].into_boxed_slice()) as *mut [T; N]) }
+}
@@ -0,0 +1,140 @@
+//! Runs two consoles side by side on the same input to find where they stop agreeing, such as
+//! when checking whether an accuracy option changes how a game runs.
+//!
+//! Each console's state is compared after every frame. Once a frame ends differently, both
+//! consoles are powered on again and replayed up to the start of that frame, then clocked one CPU
+//! cycle at a time to pin down the first cycle their states differ on. Only state that the game
+//! can observe is compared, the same state a savestate holds, so settings like the accuracy
+//! profile itself don't count as a difference.
+
+use crate::{Bus, Controller, System};
+
+/// Where two systems first disagreed, as returned by [find_divergence].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The frame the systems diverged during, counting from 0 at power-on.
+    pub frame: u32,
+    /// The CPU cycle after which the systems' states first differed, as returned by
+    /// [Bus::cycle] on the first system.
+    pub cycle: usize,
+    /// The components whose states differed at that cycle, such as `CPU` or `RAM`.
+    pub components: Vec<&'static str>,
+}
+
+/// Runs the systems built by `setup_a` and `setup_b` in lockstep, feeding both the same
+/// controller input each frame, and returns where their states first differ, or `None` if they
+/// agree through every frame of `inputs`.
+///
+/// Each setup function is called twice, so it has to build an identical system each time, such
+/// as by loading the same ROM and applying the same settings.
+///
+/// # Errors
+///
+/// Returns an error if either setup function does, or if the systems differed at the end of a
+/// frame but not after any cycle of it when stepped through again, such as when the difference
+/// only comes from setup functions that don't build identical systems each time.
+pub fn find_divergence(
+    setup_a: impl Fn() -> Result<System, String>,
+    setup_b: impl Fn() -> Result<System, String>,
+    inputs: &[(Controller, Controller)],
+) -> Result<Option<Divergence>, String> {
+    let (a, b) = (setup_a()?, setup_b()?);
+    let mut frame_components = Vec::new();
+    let Some(frame) = inputs.iter().position(|&input| {
+        a.run_frame(input, |_, _| {});
+        b.run_frame(input, |_, _| {});
+        frame_components = a.diff(&b);
+        !frame_components.is_empty()
+    }) else {
+        return Ok(None);
+    };
+
+    // Replay up to the start of the frame, then step through it a cycle at a time.
+    let (a, b) = (setup_a()?, setup_b()?);
+    for &input in &inputs[..frame] {
+        a.run_frame(input, |_, _| {});
+        b.run_frame(input, |_, _| {});
+    }
+    let (controller_1, controller_2) = inputs[frame];
+    for system in [&a, &b] {
+        system
+            .bus
+            .borrow_mut()
+            .set_controller_state(controller_1, controller_2);
+    }
+    // Give up after the frame should have ended, with a frame's worth of cycles to spare.
+    let max_cycles = a.ppu.borrow().timing().cpu_cycles_per_frame().ceil() as usize * 2;
+    for _ in 0..max_cycles {
+        for system in [&a, &b] {
+            Bus::run_cycles(
+                system.bus.clone(),
+                system.cpu.clone(),
+                system.ppu.clone(),
+                system.apu.clone(),
+                1,
+            );
+        }
+        let components = a.diff(&b);
+        if !components.is_empty() {
+            return Ok(Some(Divergence {
+                frame: frame as u32,
+                cycle: a.bus.borrow().cycle(),
+                components,
+            }));
+        }
+    }
+    Err(format!(
+        "{} differed at the end of frame {frame}, but not after any of its cycles",
+        frame_components.join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cpu::assemble, Cartridge};
+
+    #[test]
+    fn divergence() {
+        let setup = |source: &'static str| {
+            move || {
+                let program = assemble(0x8000, source)?;
+                let cartridge = Cartridge::from_raw_prg(&program, 0x8000, [0x8000; 3])?;
+                Ok(System::new(cartridge))
+            }
+        };
+        let inputs = [(Controller::new(), Controller::new()); 3];
+        let counter = "loop: inc $10\njmp loop";
+
+        assert_eq!(
+            find_divergence(setup(counter), setup(counter), &inputs),
+            Ok(None)
+        );
+
+        // Storing to a different address changes RAM from the very first write.
+        let divergence = find_divergence(setup(counter), setup("loop: inc $11\njmp loop"), &inputs)
+            .unwrap()
+            .unwrap();
+        assert_eq!(divergence.frame, 0);
+        assert_eq!(divergence.components, ["RAM"]);
+        assert!(divergence.cycle < 20);
+
+        // A setup that builds a different system the first time only differs at the end of the
+        // first pass's frame, so stepping through it again gives up rather than running forever.
+        let builds = std::cell::Cell::new(0);
+        let changing = || {
+            builds.set(builds.get() + 1);
+            let source = if builds.get() == 1 {
+                "loop: inc $11\njmp loop"
+            } else {
+                counter
+            };
+            setup(source)()
+        };
+        let error = find_divergence(setup(counter), changing, &inputs).unwrap_err();
+        assert_eq!(
+            error,
+            "RAM differed at the end of frame 0, but not after any of its cycles"
+        );
+    }
+}
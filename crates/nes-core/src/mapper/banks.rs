@@ -0,0 +1,100 @@
+//! Shared bank switching logic for mappers.
+//!
+//! Mappers divide the CPU and PPU address spaces into windows, each of which has a bank of PRG or
+//! CHR memory selected into it. A mapper only has to decide which bank goes in which window; the
+//! address translation is handled here.
+
+/// Counts the number of banks of a given size in a ROM, returning an error if the ROM is empty or
+/// its size isn't a whole multiple of the bank size.
+///
+/// Bank numbers written by games are wrapped by this count rather than masked, which keeps
+/// addressing correct for ROMs whose bank count isn't a power of two.
+pub fn count_banks(kind: &str, rom: &[u8], bank_size: usize) -> Result<usize, String> {
+    if rom.is_empty() || !rom.len().is_multiple_of(bank_size) {
+        return Err(format!(
+            "{kind} size of {} bytes is not a multiple of {}k",
+            rom.len(),
+            bank_size / 1024
+        ));
+    }
+    Ok(rom.len() / bank_size)
+}
+
+/// The size of a window that banks are mapped into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    Size1K,
+    Size2K,
+    Size4K,
+    Size8K,
+    Size16K,
+    Size32K,
+}
+
+/// Which bank to map into a window, counted in units of the window's size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bank {
+    /// A bank counted from the start of memory.
+    Index(usize),
+    /// A bank counted from the end of memory, where 0 is the last bank.
+    FromEnd(usize),
+}
+
+impl Window {
+    pub const fn size(self) -> usize {
+        match self {
+            Self::Size1K => 0x0400,
+            Self::Size2K => 0x0800,
+            Self::Size4K => 0x1000,
+            Self::Size8K => 0x2000,
+            Self::Size16K => 0x4000,
+            Self::Size32K => 0x8000,
+        }
+    }
+
+    /// Translates an address within this window to an index into memory of the given length,
+    /// with the given bank mapped into it.
+    ///
+    /// Banks past the end of memory wrap around to the start, which mirrors memory smaller than
+    /// the window and handles bank counts that aren't a power of two.
+    pub fn map(self, bank: Bank, addr: u16, memory_len: usize) -> usize {
+        let size = self.size();
+        let bank = match bank {
+            Bank::Index(bank) => bank,
+            Bank::FromEnd(bank) => (memory_len / size).saturating_sub(bank + 1),
+        };
+
+        (bank * size + (addr as usize & (size - 1))) % memory_len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bank_counts() {
+        assert_eq!(count_banks("prg rom", &[0; 48 * 1024], 16 * 1024), Ok(3));
+        assert_eq!(
+            count_banks("prg rom", &[0; 20 * 1024], 16 * 1024),
+            Err("prg rom size of 20480 bytes is not a multiple of 16k".to_string())
+        );
+        assert!(count_banks("chr rom", &[], 8 * 1024).is_err());
+    }
+
+    #[test]
+    fn window_mapping() {
+        // 48k of memory, which has a bank count that isn't a power of two.
+        let len = 48 * 1024;
+
+        assert_eq!(Window::Size16K.map(Bank::Index(1), 0x8123, len), 0x4123);
+        assert_eq!(Window::Size16K.map(Bank::FromEnd(0), 0xC123, len), 0x8123);
+        // Banks past the end wrap around rather than being masked.
+        assert_eq!(Window::Size16K.map(Bank::Index(4), 0x8123, len), 0x4123);
+        // Memory smaller than the window is mirrored throughout it.
+        assert_eq!(
+            Window::Size32K.map(Bank::Index(0), 0xC123, 16 * 1024),
+            0x0123
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use crate::savestate::{self, CartridgeState, MapperRegisters, MapperState};
+
+use super::{
+    banks::{count_banks, Bank, Window},
+    Mapper, Mirroring,
+};
+
+pub struct Mapper0 {
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    mirror_flag: u8,
+    has_chr_ram: bool,
+}
+
+impl Mapper0 {
+    pub fn new(prg_rom: &[u8], chr_rom: &[u8], mirror_flag: u8) -> Result<Self, String> {
+        // NROM-128 and NROM-256 respectively.
+        match count_banks("prg rom", prg_rom, 16 * 1024)? {
+            1 | 2 => (),
+            blocks => return Err(format!("{blocks} is not a valid block size for mapper 0")),
+        }
+
+        let has_chr_ram = chr_rom.is_empty();
+        let chr_rom = if has_chr_ram {
+            vec![0; 8 * 1024]
+        } else {
+            // There's no CHR banking, so anything past the first bank couldn't be reached.
+            match count_banks("chr rom", chr_rom, 8 * 1024)? {
+                1 => chr_rom.into(),
+                blocks => {
+                    return Err(format!(
+                        "{blocks} is not a valid chr rom block count for mapper 0"
+                    ))
+                }
+            }
+        };
+
+        Ok(Self {
+            prg_rom: prg_rom.into(),
+            chr_rom,
+            mirror_flag,
+            has_chr_ram,
+        })
+    }
+
+    fn map_addr(&self, addr: u16) -> usize {
+        // NROM-128 is mirrored into both halves of the window.
+        Window::Size32K.map(Bank::Index(0), addr, self.prg_rom.len())
+    }
+}
+
+impl Mapper for Mapper0 {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        let addr = self.map_addr(addr);
+        self.prg_rom[addr]
+    }
+
+    fn cpu_write(&mut self, _addr: u16, _data: u8) {}
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr_rom[self.map_chr_addr(addr)]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.has_chr_ram {
+            let addr = self.map_chr_addr(addr);
+            self.chr_rom[addr] = data;
+        }
+    }
+
+    fn chr(&self) -> &[u8] {
+        &self.chr_rom
+    }
+
+    fn map_chr_addr(&self, addr: u16) -> usize {
+        Window::Size8K.map(Bank::Index(0), addr, self.chr_rom.len())
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.mirror_flag == 0 {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        }
+    }
+
+    fn apply_state(&mut self, state: MapperState, warnings: &mut Vec<String>) {
+        for (description, section) in state {
+            match description {
+                "CHRR" => {
+                    if !self.has_chr_ram {
+                        continue;
+                    }
+                    let Ok(chr_ram) = savestate::deserialize::<Vec<u8>>(section) else {
+                        continue;
+                    };
+                    if chr_ram.len() == self.chr_rom.len() {
+                        self.chr_rom = chr_ram;
+                    }
+                }
+                _ => warnings.push(savestate::unrecognized_section("mapper", description)),
+            }
+        }
+    }
+
+    fn save_state_into(&self, buffer: &mut Vec<u8>) {
+        use crate::savestate::serialize_into;
+
+        if self.has_chr_ram {
+            serialize_into(buffer, &self.chr_rom, "CHRR");
+        }
+    }
+
+    fn state(&self) -> CartridgeState {
+        CartridgeState {
+            chr_ram: self.has_chr_ram.then(|| self.chr_rom.clone()),
+            prg_ram: None,
+            registers: MapperRegisters::Mapper0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rom_sizes() {
+        let chr_rom = [0; 8 * 1024];
+        assert!(Mapper0::new(&[0; 16 * 1024], &chr_rom, 0).is_ok());
+        assert_eq!(
+            Mapper0::new(&[0; 48 * 1024], &chr_rom, 0).err(),
+            Some("3 is not a valid block size for mapper 0".to_string())
+        );
+        assert_eq!(
+            Mapper0::new(&[0; 16 * 1024], &[0; 16 * 1024], 0).err(),
+            Some("2 is not a valid chr rom block count for mapper 0".to_string())
+        );
+    }
+}
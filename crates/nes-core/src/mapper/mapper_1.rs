@@ -0,0 +1,372 @@
+use crate::{
+    is_bit_set,
+    savestate::{self, CartridgeState, MapperRegisters, MapperState},
+};
+
+use super::{
+    banks::{count_banks, Bank, Window},
+    Mapper, Mirroring,
+};
+
+/// The size of the PRG ROM pages selected by bit 4 of the CHR bank registers on boards with 512k
+/// of PRG ROM.
+const PRG_PAGE_SIZE: usize = 256 * 1024;
+
+/// The MMC1, along with its board variants.
+///
+/// Boards with more than 256k of PRG ROM (SUROM and SXROM) repurpose bit 4 of the CHR bank
+/// registers to select a 256k page of PRG ROM, and boards with more than 8k of PRG RAM (SOROM and
+/// SXROM) repurpose bits 2-3 to select a bank of PRG RAM. Both registers are written with the
+/// same value by games using these boards, so the first one is used.
+pub struct Mapper1 {
+    prg_ram: Vec<u8>,
+    is_prg_ram_dirty: bool,
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    has_chr_ram: bool,
+
+    shift: u8,
+    shift_count: u8,
+    control: Control,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+    prg_bank: u8,
+}
+
+impl Mapper1 {
+    /// Creates an MMC1 with the given amount of PRG RAM, which must be 8k, 16k, or 32k.
+    pub fn new(prg_rom: &[u8], chr_rom: &[u8], prg_ram_size: usize) -> Result<Self, String> {
+        let prg_banks = count_banks("prg rom", prg_rom, 16 * 1024)?;
+        if prg_rom.len() > 2 * PRG_PAGE_SIZE {
+            return Err(format!(
+                "prg rom size of {}k is too large",
+                prg_rom.len() / 1024
+            ));
+        }
+        if ![8 * 1024, 16 * 1024, 32 * 1024].contains(&prg_ram_size) {
+            return Err(format!(
+                "unsupported prg ram size of {}k",
+                prg_ram_size / 1024
+            ));
+        }
+        let has_chr_ram = chr_rom.is_empty();
+        let chr_rom = if has_chr_ram {
+            vec![0; 8 * 1024]
+        } else {
+            count_banks("chr rom", chr_rom, 4 * 1024)?;
+            chr_rom.into()
+        };
+
+        Ok(Self {
+            prg_ram: vec![0; prg_ram_size],
+            is_prg_ram_dirty: false,
+            prg_rom: prg_rom.into(),
+            chr_rom,
+            has_chr_ram,
+
+            shift: 0,
+            shift_count: 0,
+            control: Control::default(),
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: (prg_banks - 1) as u8,
+        })
+    }
+
+    /// Translates an address in PRG RAM, accounting for PRG RAM banking.
+    fn map_prg_ram_addr(&self, addr: u16) -> usize {
+        let bank = match self.prg_ram.len() / (8 * 1024) {
+            // SXROM.
+            4 => (self.chr_bank_0 >> 2) & 0x03,
+            // SOROM.
+            2 => (self.chr_bank_0 >> 3) & 0x01,
+            _ => 0,
+        };
+        Window::Size8K.map(Bank::Index(bank as usize), addr, self.prg_ram.len())
+    }
+
+    fn map_cpu_addr(&self, addr: u16) -> usize {
+        let prg_bank = self.prg_bank as usize;
+        let (window, bank) = match self.control.prg_bank_mode() {
+            // The low bit is ignored in 32k mode.
+            0 | 1 => (Window::Size32K, Bank::Index((prg_bank & 0x0E) >> 1)),
+            // Fix the first bank at $8000.
+            2 if addr < 0xC000 => (Window::Size16K, Bank::Index(0)),
+            2 => (Window::Size16K, Bank::Index(prg_bank & 0x0F)),
+            // Fix the last bank at $C000.
+            3 if addr >= 0xC000 => (Window::Size16K, Bank::FromEnd(0)),
+            3 => (Window::Size16K, Bank::Index(prg_bank & 0x0F)),
+            _ => unreachable!(),
+        };
+
+        // Banks are selected within a 256k page, including the fixed banks.
+        let page_len = self.prg_rom.len().min(PRG_PAGE_SIZE);
+        let page = if self.prg_rom.len() > PRG_PAGE_SIZE {
+            (self.chr_bank_0 as usize >> 4) & 0x01
+        } else {
+            0
+        };
+        page * PRG_PAGE_SIZE + window.map(bank, addr, page_len)
+    }
+
+    fn map_ppu_addr(&self, addr: u16) -> usize {
+        let (window, bank) = if self.control.chr_bank_mode() == 0 {
+            // The low bit is ignored in 8k mode.
+            (Window::Size8K, self.chr_bank_0 >> 1)
+        } else if addr < 0x1000 {
+            (Window::Size4K, self.chr_bank_0)
+        } else {
+            (Window::Size4K, self.chr_bank_1)
+        };
+
+        window.map(Bank::Index(bank as usize), addr, self.chr_rom.len())
+    }
+}
+
+impl Mapper for Mapper1 {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[self.map_prg_ram_addr(addr)],
+            0x8000..=0xFFFF => {
+                let addr = self.map_cpu_addr(addr);
+                self.prg_rom[addr]
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF => {
+                let addr = self.map_prg_ram_addr(addr);
+                self.is_prg_ram_dirty |= self.prg_ram[addr] != data;
+                self.prg_ram[addr] = data;
+            }
+            0x8000..=0xFFFF => {
+                if is_bit_set(data, 7) {
+                    self.shift = 0;
+                    self.shift_count = 0;
+                } else if self.shift_count < 5 {
+                    self.shift |= (data & 0x01) << 5;
+                    self.shift >>= 1;
+                    self.shift_count += 1;
+                }
+                if self.shift_count == 5 {
+                    match addr {
+                        0x8000..=0x9FFF => self.control.0 = self.shift,
+                        0xA000..=0xBFFF => self.chr_bank_0 = self.shift,
+                        0xC000..=0xDFFF => self.chr_bank_1 = self.shift,
+                        0xE000..=0xFFFF => self.prg_bank = self.shift,
+                        _ => unreachable!(),
+                    }
+                    self.shift = 0;
+                    self.shift_count = 0;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        let addr = self.map_ppu_addr(addr);
+        self.chr_rom[addr]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.has_chr_ram {
+            let addr = self.map_ppu_addr(addr);
+            self.chr_rom[addr] = data;
+        }
+    }
+
+    fn chr(&self) -> &[u8] {
+        &self.chr_rom
+    }
+
+    fn map_chr_addr(&self, addr: u16) -> usize {
+        self.map_ppu_addr(addr)
+    }
+
+    fn mirroring(&self) -> super::Mirroring {
+        match self.control.mirroring() {
+            0 => Mirroring::SingleScreen,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            3 => Mirroring::Horizontal,
+            _ => unreachable!(),
+        }
+    }
+
+    fn maps_cpu_addr(&self, addr: u16) -> bool {
+        // PRG RAM is always mapped.
+        addr >= 0x6000
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.is_prg_ram_dirty)
+    }
+
+    fn apply_state(&mut self, state: MapperState, warnings: &mut Vec<String>) {
+        for (description, section) in state {
+            match description {
+                "DREG" => {
+                    [
+                        self.control.0,
+                        self.chr_bank_0,
+                        self.chr_bank_1,
+                        self.prg_bank,
+                    ] = savestate::deserialize(section).unwrap_or_default()
+                }
+                "LRST" => {
+                    // Internal timestamp used by FCEUX to determine if the mapper should accept a
+                    // write.
+                }
+                "BFFR" => self.shift = savestate::deserialize(section).unwrap_or_default(),
+                "BFRS" => {
+                    // Anything past 4 writes would never complete, so start over instead.
+                    let shift_count = savestate::deserialize(section).unwrap_or_default();
+                    self.shift_count = if shift_count < 5 { shift_count } else { 0 };
+                }
+                "WRAM" => {
+                    let Ok(prg_ram) = savestate::deserialize::<Vec<u8>>(section) else {
+                        continue;
+                    };
+                    if prg_ram.len() == self.prg_ram.len() {
+                        self.is_prg_ram_dirty |= prg_ram != self.prg_ram;
+                        self.prg_ram = prg_ram;
+                    }
+                }
+                "CHRR" => {
+                    if !self.has_chr_ram {
+                        continue;
+                    }
+                    let Ok(chr_ram) = savestate::deserialize::<Vec<u8>>(section) else {
+                        continue;
+                    };
+                    if chr_ram.len() == self.chr_rom.len() {
+                        self.chr_rom = chr_ram;
+                    }
+                }
+                _ => warnings.push(savestate::unrecognized_section("mapper", description)),
+            }
+        }
+    }
+
+    fn save_state_into(&self, buffer: &mut Vec<u8>) {
+        use crate::savestate::serialize_into;
+
+        if self.has_chr_ram {
+            serialize_into(buffer, &self.chr_rom, "CHRR");
+        }
+
+        serialize_into(buffer, &self.prg_ram, "WRAM");
+        serialize_into(
+            buffer,
+            &[
+                self.control.0,
+                self.chr_bank_0,
+                self.chr_bank_1,
+                self.prg_bank,
+            ],
+            "DREG",
+        );
+        serialize_into(buffer, &self.shift, "BFFR");
+        serialize_into(buffer, &self.shift_count, "BFRS");
+    }
+
+    fn state(&self) -> CartridgeState {
+        CartridgeState {
+            chr_ram: self.has_chr_ram.then(|| self.chr_rom.clone()),
+            prg_ram: Some(self.prg_ram.clone()),
+            registers: MapperRegisters::Mapper1 {
+                control: self.control.0,
+                chr_bank_0: self.chr_bank_0,
+                chr_bank_1: self.chr_bank_1,
+                prg_bank: self.prg_bank,
+                shift: self.shift,
+                shift_count: self.shift_count,
+            },
+        }
+    }
+}
+
+#[bitfield_struct::bitfield(u8)]
+#[derive(PartialEq, Eq)]
+struct Control {
+    #[bits(2)]
+    mirroring: u8,
+    #[bits(2)]
+    prg_bank_mode: u8,
+    #[bits(1)]
+    chr_bank_mode: u8,
+    #[bits(3)]
+    __: u8,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn surom_prg_pages() {
+        // 512k of PRG ROM, where the first byte of each 16k bank is the bank's index.
+        let mut prg_rom = vec![0; 512 * 1024];
+        for bank in 0..32 {
+            prg_rom[bank * 16 * 1024] = bank as u8;
+        }
+        let mut mapper = Mapper1::new(&prg_rom, &[], 8 * 1024).unwrap();
+
+        // Fix the last bank of the page at $C000 and switch $8000.
+        write_register(&mut mapper, 0x8000, 0x0C);
+        assert_eq!(mapper.cpu_read(0xC000), 15);
+        write_register(&mut mapper, 0xE000, 2);
+        assert_eq!(mapper.cpu_read(0x8000), 2);
+
+        // Switching to the second page also moves the fixed bank.
+        write_register(&mut mapper, 0xA000, 0x10);
+        assert_eq!(mapper.cpu_read(0x8000), 18);
+        assert_eq!(mapper.cpu_read(0xC000), 31);
+    }
+
+    #[test]
+    fn sxrom_prg_ram_banks() {
+        let mut mapper = Mapper1::new(&[0; 512 * 1024], &[], 32 * 1024).unwrap();
+        for bank in 0..4 {
+            write_register(&mut mapper, 0xA000, bank << 2);
+            mapper.cpu_write(0x6000, bank);
+        }
+        for bank in 0..4 {
+            write_register(&mut mapper, 0xA000, bank << 2);
+            assert_eq!(mapper.cpu_read(0x6000), bank);
+        }
+    }
+
+    #[test]
+    fn prg_ram_dirty_tracking() {
+        let mut mapper = Mapper1::new(&[0; 32 * 1024], &[], 8 * 1024).unwrap();
+        assert!(!mapper.take_dirty());
+
+        mapper.cpu_write(0x6000, 0x42);
+        assert!(mapper.take_dirty());
+        assert!(!mapper.take_dirty());
+
+        // Writing the value that's already there doesn't need to be saved again.
+        mapper.cpu_write(0x6000, 0x42);
+        assert!(!mapper.take_dirty());
+    }
+
+    /// Writes a value to an MMC1 register through the serial port.
+    fn write_register(mapper: &mut Mapper1, addr: u16, value: u8) {
+        for bit in 0..5 {
+            mapper.cpu_write(addr, (value >> bit) & 0x01);
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::savestate::{self, CartridgeState, MapperRegisters, MapperState};
+
+use super::{
+    banks::{count_banks, Bank, Window},
+    Mapper, Mirroring,
+};
+
+pub struct Mapper2 {
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    has_chr_ram: bool,
+
+    prg_bank: u8,
+    mirroring: Mirroring,
+}
+
+impl Mapper2 {
+    pub fn new(prg_rom: &[u8], chr_rom: &[u8], mirror_flag: u8) -> Result<Self, String> {
+        count_banks("prg rom", prg_rom, 16 * 1024)?;
+        let has_chr_ram = chr_rom.is_empty();
+        let chr_rom = if has_chr_ram {
+            vec![0; 8 * 1024]
+        } else {
+            // There's no CHR banking, so anything past the first bank couldn't be reached.
+            match count_banks("chr rom", chr_rom, 8 * 1024)? {
+                1 => chr_rom.into(),
+                blocks => {
+                    return Err(format!(
+                        "{blocks} is not a valid chr rom block count for mapper 2"
+                    ))
+                }
+            }
+        };
+
+        let mirroring = if mirror_flag == 0 {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        };
+
+        Ok(Self {
+            prg_rom: prg_rom.into(),
+            chr_rom,
+            has_chr_ram,
+            mirroring,
+            prg_bank: 0,
+        })
+    }
+
+    fn map_addr(&self, addr: u16) -> usize {
+        let bank = match addr {
+            0x8000..=0xBFFF => Bank::Index(self.prg_bank as usize),
+            _ => Bank::FromEnd(0),
+        };
+
+        Window::Size16K.map(bank, addr, self.prg_rom.len())
+    }
+}
+
+impl Mapper for Mapper2 {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        let addr = self.map_addr(addr);
+        self.prg_rom[addr]
+    }
+
+    fn cpu_write(&mut self, _addr: u16, data: u8) {
+        self.prg_bank = data & 0x0F;
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr_rom[self.map_chr_addr(addr)]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.has_chr_ram {
+            let addr = self.map_chr_addr(addr);
+            self.chr_rom[addr] = data;
+        }
+    }
+
+    fn chr(&self) -> &[u8] {
+        &self.chr_rom
+    }
+
+    fn map_chr_addr(&self, addr: u16) -> usize {
+        Window::Size8K.map(Bank::Index(0), addr, self.chr_rom.len())
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn apply_state(&mut self, state: MapperState, warnings: &mut Vec<String>) {
+        use savestate::deserialize;
+
+        for (description, section) in state {
+            match description {
+                "BUSC" => (), // Bus conflict.
+                "LATC" => self.prg_bank = deserialize(section).unwrap_or_default(),
+                "CHRR" => {
+                    if !self.has_chr_ram {
+                        continue;
+                    }
+                    let Ok(chr_ram) = savestate::deserialize::<Vec<u8>>(section) else {
+                        continue;
+                    };
+                    if chr_ram.len() == self.chr_rom.len() {
+                        self.chr_rom = chr_ram;
+                    }
+                }
+                _ => warnings.push(savestate::unrecognized_section("mapper", description)),
+            }
+        }
+    }
+
+    fn save_state_into(&self, buffer: &mut Vec<u8>) {
+        use savestate::serialize_into;
+
+        if self.has_chr_ram {
+            serialize_into(buffer, &self.chr_rom, "CHRR");
+        }
+
+        serialize_into(buffer, &self.prg_bank, "LATC");
+    }
+
+    fn state(&self) -> CartridgeState {
+        CartridgeState {
+            chr_ram: self.has_chr_ram.then(|| self.chr_rom.clone()),
+            prg_ram: None,
+            registers: MapperRegisters::Mapper2 {
+                prg_bank: self.prg_bank,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bank_wrapping() {
+        // 3 banks, each filled with its own number.
+        let prg_rom: Vec<u8> = (0..3).flat_map(|bank| [bank; 16 * 1024]).collect();
+        let mut mapper = Mapper2::new(&prg_rom, &[], 0).unwrap();
+        assert_eq!(mapper.cpu_read(0xC000), 2);
+
+        mapper.cpu_write(0x8000, 1);
+        assert_eq!(mapper.cpu_read(0x8000), 1);
+        // Bank 3 doesn't exist, so it wraps around to bank 0 rather than being masked to 1.
+        mapper.cpu_write(0x8000, 3);
+        assert_eq!(mapper.cpu_read(0x8000), 0);
+        assert_eq!(mapper.cpu_read(0xFFFF), 2);
+    }
+
+    #[test]
+    fn rom_sizes() {
+        let prg_rom = [0; 32 * 1024];
+        assert!(Mapper2::new(&prg_rom, &[0; 8 * 1024], 0).is_ok());
+        assert_eq!(
+            Mapper2::new(&prg_rom, &[0; 16 * 1024], 0).err(),
+            Some("2 is not a valid chr rom block count for mapper 2".to_string())
+        );
+        assert!(Mapper2::new(&prg_rom[..10 * 1024], &[], 0).is_err());
+    }
+}
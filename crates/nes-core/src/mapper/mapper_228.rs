@@ -0,0 +1,207 @@
+use crate::savestate::{self, CartridgeState, MapperRegisters, MapperState};
+
+use super::{
+    banks::{count_banks, Bank, Window},
+    Mapper, Mirroring,
+};
+
+/// The size of each of the PRG ROM chips selected between by the high bits of the latch.
+const PRG_CHIP_SIZE: usize = 512 * 1024;
+
+/// Action 52 and Cheetahmen II.
+///
+/// Writes anywhere in $8000-$FFFF latch both the address and the data, which together select the
+/// PRG and CHR banks. The board has 3 PRG ROM chips on chip selects 0, 1 and 3, plus 4 nibbles of
+/// RAM at $5000-$5FFF that the Action 52 menu uses.
+pub struct Mapper228 {
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    has_chr_ram: bool,
+
+    addr_latch: u16,
+    data_latch: u8,
+    ram: [u8; 4],
+}
+
+impl Mapper228 {
+    pub fn new(prg_rom: &[u8], chr_rom: &[u8]) -> Result<Self, String> {
+        count_banks("prg rom", prg_rom, 16 * 1024)?;
+        let has_chr_ram = chr_rom.is_empty();
+        let chr_rom = if has_chr_ram {
+            vec![0; 8 * 1024]
+        } else {
+            count_banks("chr rom", chr_rom, 8 * 1024)?;
+            chr_rom.into()
+        };
+
+        Ok(Self {
+            prg_rom: prg_rom.into(),
+            chr_rom,
+            has_chr_ram,
+            addr_latch: 0,
+            data_latch: 0,
+            ram: [0; 4],
+        })
+    }
+
+    fn map_prg_addr(&self, addr: u16) -> usize {
+        // There's no chip 2, so chip 3 is stored third in the ROM.
+        let chip = ((self.addr_latch >> 11) & 0x03).min(2) as usize;
+        let bank = chip * PRG_CHIP_SIZE / Window::Size16K.size()
+            + ((self.addr_latch >> 6) & 0x1F) as usize;
+        let is_16k_mode = self.addr_latch & 0x20 != 0;
+
+        let bank = match (is_16k_mode, addr) {
+            (true, _) => bank,
+            (false, 0x8000..=0xBFFF) => bank & !1,
+            (false, _) => bank | 1,
+        };
+        Window::Size16K.map(Bank::Index(bank), addr, self.prg_rom.len())
+    }
+}
+
+impl Mapper for Mapper228 {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x5000..=0x5FFF => self.ram[addr as usize & 0x03],
+            _ => self.prg_rom[self.map_prg_addr(addr)],
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x5000..=0x5FFF => self.ram[addr as usize & 0x03] = data & 0x0F,
+            0x8000..=0xFFFF => {
+                self.addr_latch = addr;
+                self.data_latch = data;
+            }
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr_rom[self.map_chr_addr(addr)]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.has_chr_ram {
+            let addr = self.map_chr_addr(addr);
+            self.chr_rom[addr] = data;
+        }
+    }
+
+    fn chr(&self) -> &[u8] {
+        &self.chr_rom
+    }
+
+    fn map_chr_addr(&self, addr: u16) -> usize {
+        let bank = ((self.addr_latch & 0x0F) << 2) as usize | (self.data_latch & 0x03) as usize;
+        Window::Size8K.map(Bank::Index(bank), addr, self.chr_rom.len())
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.addr_latch & 0x2000 != 0 {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        }
+    }
+
+    fn maps_cpu_addr(&self, addr: u16) -> bool {
+        matches!(addr, 0x5000..=0x5FFF | 0x8000..=0xFFFF)
+    }
+
+    fn apply_state(&mut self, state: MapperState, warnings: &mut Vec<String>) {
+        use savestate::deserialize;
+
+        for (description, section) in state {
+            match description {
+                "LATA" => self.addr_latch = deserialize(section).unwrap_or_default(),
+                "LATD" => self.data_latch = deserialize(section).unwrap_or_default(),
+                "MRAM" => self.ram = deserialize(section).unwrap_or_default(),
+                "CHRR" => {
+                    if !self.has_chr_ram {
+                        continue;
+                    }
+                    let Ok(chr_ram) = savestate::deserialize::<Vec<u8>>(section) else {
+                        continue;
+                    };
+                    if chr_ram.len() == self.chr_rom.len() {
+                        self.chr_rom = chr_ram;
+                    }
+                }
+                _ => warnings.push(savestate::unrecognized_section("mapper", description)),
+            }
+        }
+    }
+
+    fn save_state_into(&self, buffer: &mut Vec<u8>) {
+        use savestate::serialize_into;
+
+        if self.has_chr_ram {
+            serialize_into(buffer, &self.chr_rom, "CHRR");
+        }
+
+        serialize_into(buffer, &self.ram, "MRAM");
+        serialize_into(buffer, &self.addr_latch, "LATA");
+        serialize_into(buffer, &self.data_latch, "LATD");
+    }
+
+    fn state(&self) -> CartridgeState {
+        CartridgeState {
+            chr_ram: self.has_chr_ram.then(|| self.chr_rom.clone()),
+            prg_ram: Some(self.ram.to_vec()),
+            registers: MapperRegisters::Mapper228 {
+                addr_latch: self.addr_latch,
+                data_latch: self.data_latch,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn action_52_banks() {
+        // 1.5M of PRG ROM and 512k of CHR ROM, where the first byte of each bank is its index.
+        let mut prg_rom = vec![0; 3 * PRG_CHIP_SIZE];
+        for bank in 0..96 {
+            prg_rom[bank * 16 * 1024] = bank as u8;
+        }
+        let mut chr_rom = vec![0; 512 * 1024];
+        for bank in 0..64 {
+            chr_rom[bank * 8 * 1024] = bank as u8;
+        }
+        let mut mapper = Mapper228::new(&prg_rom, &chr_rom).unwrap();
+
+        // 32k mode with 16k bank 5 selected in chip 1, which rounds down to an even bank.
+        mapper.cpu_write(0x8000 | 1 << 11 | 5 << 6, 0x00);
+        assert_eq!(mapper.cpu_read(0x8000), 36);
+        assert_eq!(mapper.cpu_read(0xC000), 37);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+
+        // 16k mode in chip 3, which is the third chip in the ROM, with horizontal mirroring.
+        mapper.cpu_write(0x8000 | 1 << 13 | 3 << 11 | 5 << 6 | 0x20, 0x00);
+        assert_eq!(mapper.cpu_read(0x8000), 69);
+        assert_eq!(mapper.cpu_read(0xC000), 69);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+
+        // The CHR bank takes its high bits from the address and its low bits from the data.
+        mapper.cpu_write(0x8000 | 0x0A, 0x03);
+        assert_eq!(mapper.ppu_read(0x0000), 43);
+
+        // Only the low nibble of the RAM is stored.
+        mapper.cpu_write(0x5FF2, 0xAB);
+        assert_eq!(mapper.cpu_read(0x5002), 0x0B);
+
+        let state = mapper.save_state();
+        let mut restored = Mapper228::new(&prg_rom, &chr_rom).unwrap();
+        let mut warnings = Vec::new();
+        restored.apply_state(MapperState::new(&state).unwrap(), &mut warnings);
+        assert!(warnings.is_empty());
+        assert_eq!(restored.ppu_read(0x0000), 43);
+        assert_eq!(restored.cpu_read(0x5002), 0x0B);
+    }
+}
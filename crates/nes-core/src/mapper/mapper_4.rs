@@ -0,0 +1,382 @@
+use crate::savestate::{self, CartridgeState, MapperRegisters, MapperState};
+
+use super::{
+    banks::{count_banks, Bank, Window},
+    Mapper, Mirroring,
+};
+
+pub struct Mapper4 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    is_prg_ram_dirty: bool,
+    chr_rom: Vec<u8>,
+    has_chr_ram: bool,
+
+    bank_register: [u8; 8],
+    bank_select: BankSelect,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    is_irq_enabled: bool,
+    emit_irq: bool,
+    mirroring: Mirroring,
+    prg_ram_protect: u8,
+}
+
+impl Mapper4 {
+    pub fn new(prg_rom: &[u8], chr_rom: &[u8]) -> Result<Self, String> {
+        count_banks("prg rom", prg_rom, 8 * 1024)?;
+        let has_chr_ram = chr_rom.is_empty();
+        let chr_rom = if has_chr_ram {
+            vec![0; 8 * 1024]
+        } else {
+            count_banks("chr rom", chr_rom, 1024)?;
+            chr_rom.into()
+        };
+
+        Ok(Self {
+            prg_rom: prg_rom.into(),
+            prg_ram: vec![0; 8 * 1024],
+            is_prg_ram_dirty: false,
+            chr_rom,
+            has_chr_ram,
+
+            bank_register: [0; 8],
+            bank_select: BankSelect::default(),
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            is_irq_enabled: false,
+            emit_irq: false,
+            mirroring: Mirroring::Vertical,
+            prg_ram_protect: 0x80,
+        })
+    }
+
+    fn map_cpu_addr(&self, addr: u16) -> usize {
+        let prg_bank_mode = self.bank_select.prg_bank_mode();
+        // The top two bits of the PRG bank registers are ignored.
+        let switchable_bank =
+            |register: usize| Bank::Index(self.bank_register[register] as usize & 0x3F);
+        let bank = match addr {
+            0x8000..=0x9FFF if prg_bank_mode == 0 => switchable_bank(6),
+            0x8000..=0x9FFF => Bank::FromEnd(1),
+            0xA000..=0xBFFF => switchable_bank(7),
+            0xC000..=0xDFFF if prg_bank_mode != 0 => switchable_bank(6),
+            0xC000..=0xDFFF => Bank::FromEnd(1),
+            _ => Bank::FromEnd(0),
+        };
+
+        Window::Size8K.map(bank, addr, self.prg_rom.len())
+    }
+
+    fn map_ppu_addr(&self, addr: u16) -> usize {
+        // CHR inversion swaps the 2k and 1k banks between the two pattern tables.
+        let uninverted_addr = if self.bank_select.chr_inversion() == 0 {
+            addr
+        } else {
+            addr ^ 0x1000
+        };
+
+        // The low bit is ignored for 2k banks.
+        let (window, bank) = match uninverted_addr & 0x1FFF {
+            0x0000..=0x07FF => (Window::Size2K, self.bank_register[0] >> 1),
+            0x0800..=0x0FFF => (Window::Size2K, self.bank_register[1] >> 1),
+            0x1000..=0x13FF => (Window::Size1K, self.bank_register[2]),
+            0x1400..=0x17FF => (Window::Size1K, self.bank_register[3]),
+            0x1800..=0x1BFF => (Window::Size1K, self.bank_register[4]),
+            _ => (Window::Size1K, self.bank_register[5]),
+        };
+
+        window.map(Bank::Index(bank as usize), addr, self.chr_rom.len())
+    }
+}
+
+impl Mapper for Mapper4 {
+    fn cpu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[addr as usize & 0x1FFF],
+            0x8000..=0xFFFF => {
+                let addr = self.map_cpu_addr(addr);
+                self.prg_rom[addr]
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF => {
+                let addr = addr as usize & 0x1FFF;
+                self.is_prg_ram_dirty |= self.prg_ram[addr] != data;
+                self.prg_ram[addr] = data;
+            }
+            0x8000..=0x9FFF => {
+                if addr & 1 == 0 {
+                    self.bank_select.0 = data;
+                } else {
+                    self.bank_register[self.bank_select.bank_register() as usize] = data;
+                }
+            }
+            0xA000..=0xBFFF => {
+                if addr & 1 == 0 {
+                    if data & 1 == 0 {
+                        self.mirroring = Mirroring::Vertical;
+                    } else {
+                        self.mirroring = Mirroring::Horizontal;
+                    }
+                } else {
+                    self.prg_ram_protect = data & 0xC0;
+                }
+            }
+            0xC000..=0xDFFF => {
+                if addr & 1 == 0 {
+                    self.irq_latch = data;
+                } else {
+                    self.irq_reload = true;
+                }
+            }
+            0xE000..=0xFFFF => {
+                self.is_irq_enabled = addr & 1 != 0;
+                // Disabling IRQs also acknowledges any pending one.
+                if !self.is_irq_enabled {
+                    self.emit_irq = false;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        let addr = self.map_ppu_addr(addr);
+        self.chr_rom[addr]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.has_chr_ram {
+            let addr = self.map_ppu_addr(addr);
+            self.chr_rom[addr] = data;
+        }
+    }
+
+    fn chr(&self) -> &[u8] {
+        &self.chr_rom
+    }
+
+    fn map_chr_addr(&self, addr: u16) -> usize {
+        self.map_ppu_addr(addr)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn maps_cpu_addr(&self, addr: u16) -> bool {
+        // PRG RAM is always mapped.
+        addr >= 0x6000
+    }
+
+    fn check_irq(&self) -> bool {
+        self.emit_irq
+    }
+
+    fn count_scanline(&mut self) {
+        self.emit_irq = false;
+
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+        if self.irq_counter == 0 && self.is_irq_enabled {
+            self.emit_irq = true;
+        }
+    }
+
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+
+    fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.is_prg_ram_dirty)
+    }
+
+    fn apply_state(&mut self, state: MapperState, warnings: &mut Vec<String>) {
+        for (description, section) in state {
+            match description {
+                "REGS" => self.bank_register = savestate::deserialize(section).unwrap_or_default(),
+                "CMD" => self.bank_select.0 = savestate::deserialize(section).unwrap_or_default(),
+                "A000" => {
+                    self.mirroring =
+                        if savestate::deserialize::<u8>(section).unwrap_or_default() == 0 {
+                            Mirroring::Vertical
+                        } else {
+                            Mirroring::Horizontal
+                        }
+                }
+                "A001" => {
+                    self.prg_ram_protect = savestate::deserialize(section).unwrap_or_default()
+                }
+                "IRQR" => self.irq_reload = savestate::deserialize(section).unwrap_or_default(),
+                "IRQC" => self.irq_counter = savestate::deserialize(section).unwrap_or_default(),
+                "IRQL" => self.irq_latch = savestate::deserialize(section).unwrap_or_default(),
+                "IRQA" => self.is_irq_enabled = savestate::deserialize(section).unwrap_or_default(),
+                "WRAM" => {
+                    let Ok(prg_ram) = savestate::deserialize::<Vec<u8>>(section) else {
+                        continue;
+                    };
+                    if prg_ram.len() == self.prg_ram.len() {
+                        self.is_prg_ram_dirty |= prg_ram != self.prg_ram;
+                        self.prg_ram = prg_ram;
+                    }
+                }
+                "CHRR" => {
+                    if !self.has_chr_ram {
+                        continue;
+                    }
+                    let Ok(chr_ram) = savestate::deserialize::<Vec<u8>>(section) else {
+                        continue;
+                    };
+                    if chr_ram.len() == self.chr_rom.len() {
+                        self.chr_rom = chr_ram;
+                    }
+                }
+                _ => warnings.push(savestate::unrecognized_section("mapper", description)),
+            }
+        }
+    }
+
+    fn save_state_into(&self, buffer: &mut Vec<u8>) {
+        use crate::savestate::serialize_into;
+
+        if self.has_chr_ram {
+            serialize_into(buffer, &self.chr_rom, "CHRR");
+        }
+
+        serialize_into(buffer, &self.prg_ram, "WRAM");
+        serialize_into(buffer, &self.bank_register, "REGS");
+        serialize_into(buffer, &self.bank_select.0, "CMD");
+        serialize_into(
+            buffer,
+            &match self.mirroring {
+                Mirroring::Vertical => 0u8,
+                Mirroring::Horizontal => 1u8,
+                _ => unreachable!(),
+            },
+            "A000",
+        );
+        serialize_into(buffer, &self.prg_ram_protect, "A001");
+        serialize_into(buffer, &self.irq_reload, "IRQR");
+        serialize_into(buffer, &self.irq_counter, "IRQC");
+        serialize_into(buffer, &self.irq_latch, "IRQL");
+        serialize_into(buffer, &self.is_irq_enabled, "IRQA");
+    }
+
+    fn state(&self) -> CartridgeState {
+        CartridgeState {
+            chr_ram: self.has_chr_ram.then(|| self.chr_rom.clone()),
+            prg_ram: Some(self.prg_ram.clone()),
+            registers: MapperRegisters::Mapper4 {
+                bank_register: self.bank_register,
+                bank_select: self.bank_select.0,
+                mirroring: self.mirroring,
+                prg_ram_protect: self.prg_ram_protect,
+                irq_reload: self.irq_reload,
+                irq_counter: self.irq_counter,
+                irq_latch: self.irq_latch,
+                is_irq_enabled: self.is_irq_enabled,
+            },
+        }
+    }
+}
+
+#[bitfield_struct::bitfield(u8)]
+#[derive(PartialEq, Eq)]
+pub struct BankSelect {
+    #[bits(3)]
+    bank_register: u8,
+    #[bits(3)]
+    __: u8,
+    #[bits(1)]
+    prg_bank_mode: u8,
+    #[bits(1)]
+    chr_inversion: u8,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn irq_counter_reload() {
+        let mut mapper = setup();
+        mapper.cpu_write(0xC000, 2);
+        mapper.cpu_write(0xC001, 0);
+        mapper.cpu_write(0xE001, 0);
+
+        // The first scanline reloads the counter from the latch, then it counts down to 0.
+        assert_eq!(count_scanlines(&mut mapper, 3), [false, false, true]);
+        // Once the counter hits 0, the next scanline reloads it rather than wrapping around.
+        assert_eq!(count_scanlines(&mut mapper, 3), [false, false, true]);
+
+        // A new latch value only takes effect at the next reload.
+        mapper.cpu_write(0xC000, 1);
+        assert_eq!(count_scanlines(&mut mapper, 2), [false, true]);
+    }
+
+    #[test]
+    fn irq_reload_flag() {
+        let mut mapper = setup();
+        mapper.cpu_write(0xC000, 3);
+        mapper.cpu_write(0xC001, 0);
+        mapper.cpu_write(0xE001, 0);
+        count_scanlines(&mut mapper, 2);
+
+        // Writing $C001 forces a reload on the next scanline even though the counter isn't 0.
+        mapper.cpu_write(0xC001, 0);
+        assert_eq!(count_scanlines(&mut mapper, 4), [false, false, false, true]);
+
+        // With a latch of 0, every scanline reloads to 0 and fires an IRQ.
+        mapper.cpu_write(0xC000, 0);
+        mapper.cpu_write(0xC001, 0);
+        assert_eq!(count_scanlines(&mut mapper, 3), [true, true, true]);
+    }
+
+    #[test]
+    fn irq_disable_acknowledges() {
+        let mut mapper = setup();
+        mapper.cpu_write(0xC000, 1);
+        mapper.cpu_write(0xC001, 0);
+        mapper.cpu_write(0xE001, 0);
+        assert_eq!(count_scanlines(&mut mapper, 2), [false, true]);
+
+        // Writing $E000 clears the pending IRQ, and no more fire while disabled even though the
+        // counter keeps running.
+        mapper.cpu_write(0xE000, 0);
+        assert!(!mapper.check_irq());
+        assert_eq!(count_scanlines(&mut mapper, 2), [false, false]);
+
+        // Re-enabling picks up where the counter left off.
+        mapper.cpu_write(0xE001, 0);
+        assert_eq!(count_scanlines(&mut mapper, 2), [false, true]);
+    }
+
+    fn setup() -> Mapper4 {
+        Mapper4::new(&[0; 32 * 1024], &[]).unwrap()
+    }
+
+    /// Clocks the IRQ counter, returning whether an IRQ was raised after each scanline.
+    fn count_scanlines(mapper: &mut Mapper4, scanlines: usize) -> Vec<bool> {
+        (0..scanlines)
+            .map(|_| {
+                mapper.count_scanline();
+                mapper.check_irq()
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,102 @@
+pub mod banks;
+mod mapper_0;
+mod mapper_1;
+mod mapper_2;
+mod mapper_228;
+mod mapper_4;
+
+pub use mapper_0::Mapper0;
+pub use mapper_1::Mapper1;
+pub use mapper_2::Mapper2;
+pub use mapper_228::Mapper228;
+pub use mapper_4::Mapper4;
+
+use crate::savestate::{CartridgeState, MapperState};
+
+/// The iNES mapper numbers that cartridges can use.
+pub const SUPPORTED_MAPPERS: [u8; 5] = [0, 1, 2, 4, 228];
+
+pub trait Mapper {
+    fn cpu_read(&self, addr: u16) -> u8;
+    fn cpu_write(&mut self, addr: u16, data: u8);
+    fn ppu_read(&self, addr: u16) -> u8;
+    fn ppu_write(&mut self, addr: u16, data: u8);
+    /// Returns all of the cartridge's CHR ROM, or its CHR RAM, whether it's banked in or not.
+    fn chr(&self) -> &[u8];
+    /// Returns the index into [Mapper::chr] that a pattern table address is currently mapped to.
+    fn map_chr_addr(&self, addr: u16) -> usize;
+    fn mirroring(&self) -> Mirroring;
+    /// Returns whether the cartridge responds to CPU reads at the given address. Reads from
+    /// addresses that aren't mapped return open bus instead.
+    fn maps_cpu_addr(&self, addr: u16) -> bool {
+        addr >= 0x8000
+    }
+    fn check_irq(&self) -> bool {
+        false
+    }
+    fn count_scanline(&mut self) {}
+    /// Clocks the mapper's expansion audio once per CPU cycle, returning its current output level
+    /// on the same scale as the APU's channels, or `None` if it has no expansion audio.
+    ///
+    /// Any state used to generate audio should be included in [Mapper::save_state]. Only the
+    /// mapper's own state is saved: the APU's mixer settings, such as [crate::ChannelGains], are
+    /// left as the frontend set them when a savestate is applied.
+    fn clock_audio(&mut self) -> Option<i16> {
+        None
+    }
+    /// Returns the cartridge's PRG RAM, if it has any.
+    fn prg_ram(&self) -> Option<&[u8]> {
+        None
+    }
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+    /// Returns whether PRG RAM has changed since the last call, clearing the flag.
+    fn take_dirty(&mut self) -> bool {
+        false
+    }
+    /// Restores the mapper's state, adding a warning to `warnings` for each section it doesn't
+    /// recognize.
+    fn apply_state(&mut self, state: MapperState, warnings: &mut Vec<String>);
+    fn save_state_into(&self, buffer: &mut Vec<u8>);
+    /// Returns the same state as [Mapper::save_state], without serializing it.
+    fn state(&self) -> CartridgeState;
+    fn save_state(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        self.save_state_into(&mut buffer);
+        buffer
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    SingleScreen,
+    SingleScreenUpper,
+}
+
+impl Mirroring {
+    /// Returns which 1 KiB page of the PPU's nametable RAM backs each of the 4 nametables at
+    /// $2000, $2400, $2800, and $2C00. Nametables backed by the same page mirror each other.
+    pub const fn nametable_pages(self) -> [usize; 4] {
+        match self {
+            Self::Horizontal => [0, 0, 1, 1],
+            Self::Vertical => [0, 1, 0, 1],
+            Self::SingleScreen => [0; 4],
+            Self::SingleScreenUpper => [1; 4],
+        }
+    }
+}
+
+impl std::fmt::Display for Mirroring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Horizontal => "horizontal",
+            Self::Vertical => "vertical",
+            Self::SingleScreen => "single-screen (lower)",
+            Self::SingleScreenUpper => "single-screen (upper)",
+        };
+        write!(f, "{name}")
+    }
+}
@@ -0,0 +1,36 @@
+//! Hardware that can be mapped into the CPU address space alongside the console's own, such as
+//! a disk drive adapter's registers, expansion audio, or a stub for a test harness.
+
+use std::{cell::RefCell, rc::Rc};
+
+/// A device mapped into the CPU address space with [crate::Bus::map_device].
+///
+/// The device only sees accesses to its own region, and takes priority over the cartridge and
+/// open bus there. Its state isn't part of savestates.
+pub trait MemoryDevice {
+    /// Returns the value at `addr`, or `None` to leave the data bus floating, as with a
+    /// write-only register.
+    fn read(&mut self, addr: u16) -> Option<u8>;
+    /// Returns the value at `addr` without any side effects, for debuggers, or `None` if it can't
+    /// be read that way. Returns `None` unless overridden.
+    fn peek(&self, addr: u16) -> Option<u8> {
+        let _ = addr;
+        None
+    }
+    fn write(&mut self, addr: u16, data: u8);
+}
+
+/// Lets a frontend keep a handle to a device after mapping it, to inspect or update its state.
+impl<T: MemoryDevice> MemoryDevice for Rc<RefCell<T>> {
+    fn read(&mut self, addr: u16) -> Option<u8> {
+        self.borrow_mut().read(addr)
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        self.borrow().peek(addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.borrow_mut().write(addr, data);
+    }
+}
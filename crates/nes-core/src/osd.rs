@@ -0,0 +1,242 @@
+use std::collections::VecDeque;
+
+use crate::PixelFormat;
+
+const GLYPH_SIZE: usize = 8;
+/// How many messages can be on screen at once. Older messages are hidden until newer ones expire.
+const MAX_VISIBLE_MESSAGES: usize = 4;
+/// How many characters fit on a line, accounting for the margin.
+const MAX_LINE_LENGTH: usize = 256 / GLYPH_SIZE - 2;
+const TEXT_COLOR: [u8; 3] = [0xFF, 0xFF, 0xFF];
+const SHADOW_COLOR: [u8; 3] = [0x00, 0x00, 0x00];
+
+/// An on-screen display, which draws short text notifications over the emulator's output.
+///
+/// Messages are timed in frames rather than wall clock time, so they behave the same regardless of
+/// frontend. [Osd::tick] should be called once per displayed frame.
+#[derive(Debug, Default)]
+pub struct Osd {
+    messages: VecDeque<Message>,
+    status: Option<String>,
+}
+
+#[derive(Debug)]
+struct Message {
+    text: String,
+    frames_left: u32,
+}
+
+impl Osd {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a message to be shown for the given number of frames.
+    pub fn show(&mut self, text: impl Into<String>, frames: u32) {
+        self.messages.push_back(Message {
+            text: text.into(),
+            frames_left: frames,
+        });
+    }
+
+    /// Sets text that stays in the top left corner until cleared, such as an FPS counter.
+    pub fn set_status(&mut self, status: Option<String>) {
+        self.status = status;
+    }
+
+    /// Advances message timeouts by a frame, removing any messages that have expired.
+    pub fn tick(&mut self) {
+        // Only visible messages count down, so that a burst of messages are all seen.
+        for message in self.messages.iter_mut().take(MAX_VISIBLE_MESSAGES) {
+            message.frames_left = message.frames_left.saturating_sub(1);
+        }
+        self.messages.retain(|message| message.frames_left > 0);
+    }
+
+    /// Returns the text of each queued message, oldest first.
+    pub fn messages(&self) -> impl Iterator<Item = &str> {
+        self.messages.iter().map(|message| message.text.as_str())
+    }
+
+    /// Draws the status and the oldest messages onto a 256x240 frame in the given format, such as
+    /// a copy of [crate::Ppu::buffer].
+    pub fn draw(&self, buffer: &mut [u8], pixel_format: PixelFormat) {
+        if let Some(status) = self.status.as_ref() {
+            draw_text(buffer, pixel_format, status, GLYPH_SIZE, GLYPH_SIZE);
+        }
+
+        let visible = self.messages.len().min(MAX_VISIBLE_MESSAGES);
+        for (i, message) in self.messages.iter().take(visible).enumerate() {
+            // Stack messages upwards from the bottom of the screen, with the oldest at the top.
+            let y = 240 - GLYPH_SIZE * (2 + visible - i);
+            draw_text(buffer, pixel_format, &message.text, GLYPH_SIZE, y);
+        }
+    }
+}
+
+fn draw_text(buffer: &mut [u8], pixel_format: PixelFormat, text: &str, x: usize, y: usize) {
+    for (i, char) in text.chars().take(MAX_LINE_LENGTH).enumerate() {
+        let glyph = glyph(char);
+        let x = x + i * GLYPH_SIZE;
+        // Draw a drop shadow first to keep text legible over bright backgrounds.
+        draw_glyph(buffer, pixel_format, glyph, x + 1, y + 1, SHADOW_COLOR);
+        draw_glyph(buffer, pixel_format, glyph, x, y, TEXT_COLOR);
+    }
+}
+
+fn draw_glyph(
+    buffer: &mut [u8],
+    pixel_format: PixelFormat,
+    glyph: &[u8; GLYPH_SIZE],
+    x: usize,
+    y: usize,
+    color: [u8; 3],
+) {
+    let bytes_per_pixel = pixel_format.bytes_per_pixel();
+    for (row, bits) in glyph.iter().enumerate() {
+        for column in 0..GLYPH_SIZE {
+            // The leftmost pixel is stored in the lowest bit.
+            if bits & (1 << column) == 0 {
+                continue;
+            }
+            let (x, y) = (x + column, y + row);
+            if x >= 256 || y >= 240 {
+                continue;
+            }
+            let index = (x + y * 256) * bytes_per_pixel;
+            if let Some(pixel) = buffer.get_mut(index..index + bytes_per_pixel) {
+                pixel_format.write(pixel, color);
+            }
+        }
+    }
+}
+
+/// Returns the glyph for a character, substituting `?` for characters outside of printable ASCII.
+fn glyph(char: char) -> &'static [u8; GLYPH_SIZE] {
+    let index = match char {
+        ' '..='~' => char as usize - ' ' as usize,
+        _ => '?' as usize - ' ' as usize,
+    };
+    &FONT[index]
+}
+
+/// An 8x8 font covering printable ASCII, based on the public domain font8x8 by Daniel Hepper.
+const FONT: [[u8; GLYPH_SIZE]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // space
+    [0x18, 0x3C, 0x3C, 0x18, 0x18, 0x00, 0x18, 0x00], // !
+    [0x36, 0x36, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // "
+    [0x36, 0x36, 0x7F, 0x36, 0x7F, 0x36, 0x36, 0x00], // #
+    [0x0C, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x0C, 0x00], // $
+    [0x00, 0x63, 0x33, 0x18, 0x0C, 0x66, 0x63, 0x00], // %
+    [0x1C, 0x36, 0x1C, 0x6E, 0x3B, 0x33, 0x6E, 0x00], // &
+    [0x06, 0x06, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00], // '
+    [0x18, 0x0C, 0x06, 0x06, 0x06, 0x0C, 0x18, 0x00], // (
+    [0x06, 0x0C, 0x18, 0x18, 0x18, 0x0C, 0x06, 0x00], // )
+    [0x00, 0x66, 0x3C, 0xFF, 0x3C, 0x66, 0x00, 0x00], // *
+    [0x00, 0x0C, 0x0C, 0x3F, 0x0C, 0x0C, 0x00, 0x00], // +
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ,
+    [0x00, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00, 0x00], // -
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x00], // .
+    [0x60, 0x30, 0x18, 0x0C, 0x06, 0x03, 0x01, 0x00], // /
+    [0x3E, 0x63, 0x73, 0x7B, 0x6F, 0x67, 0x3E, 0x00], // 0
+    [0x0C, 0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x3F, 0x00], // 1
+    [0x1E, 0x33, 0x30, 0x1C, 0x06, 0x33, 0x3F, 0x00], // 2
+    [0x1E, 0x33, 0x30, 0x1C, 0x30, 0x33, 0x1E, 0x00], // 3
+    [0x38, 0x3C, 0x36, 0x33, 0x7F, 0x30, 0x78, 0x00], // 4
+    [0x3F, 0x03, 0x1F, 0x30, 0x30, 0x33, 0x1E, 0x00], // 5
+    [0x1C, 0x06, 0x03, 0x1F, 0x33, 0x33, 0x1E, 0x00], // 6
+    [0x3F, 0x33, 0x30, 0x18, 0x0C, 0x0C, 0x0C, 0x00], // 7
+    [0x1E, 0x33, 0x33, 0x1E, 0x33, 0x33, 0x1E, 0x00], // 8
+    [0x1E, 0x33, 0x33, 0x3E, 0x30, 0x18, 0x0E, 0x00], // 9
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x00], // :
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ;
+    [0x18, 0x0C, 0x06, 0x03, 0x06, 0x0C, 0x18, 0x00], // <
+    [0x00, 0x00, 0x3F, 0x00, 0x00, 0x3F, 0x00, 0x00], // =
+    [0x06, 0x0C, 0x18, 0x30, 0x18, 0x0C, 0x06, 0x00], // >
+    [0x1E, 0x33, 0x30, 0x18, 0x0C, 0x00, 0x0C, 0x00], // ?
+    [0x3E, 0x63, 0x7B, 0x7B, 0x7B, 0x03, 0x1E, 0x00], // @
+    [0x0C, 0x1E, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x00], // A
+    [0x3F, 0x66, 0x66, 0x3E, 0x66, 0x66, 0x3F, 0x00], // B
+    [0x3C, 0x66, 0x03, 0x03, 0x03, 0x66, 0x3C, 0x00], // C
+    [0x1F, 0x36, 0x66, 0x66, 0x66, 0x36, 0x1F, 0x00], // D
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x46, 0x7F, 0x00], // E
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x06, 0x0F, 0x00], // F
+    [0x3C, 0x66, 0x03, 0x03, 0x73, 0x66, 0x7C, 0x00], // G
+    [0x33, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x33, 0x00], // H
+    [0x1E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // I
+    [0x78, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E, 0x00], // J
+    [0x67, 0x66, 0x36, 0x1E, 0x36, 0x66, 0x67, 0x00], // K
+    [0x0F, 0x06, 0x06, 0x06, 0x46, 0x66, 0x7F, 0x00], // L
+    [0x63, 0x77, 0x7F, 0x7F, 0x6B, 0x63, 0x63, 0x00], // M
+    [0x63, 0x67, 0x6F, 0x7B, 0x73, 0x63, 0x63, 0x00], // N
+    [0x1C, 0x36, 0x63, 0x63, 0x63, 0x36, 0x1C, 0x00], // O
+    [0x3F, 0x66, 0x66, 0x3E, 0x06, 0x06, 0x0F, 0x00], // P
+    [0x1E, 0x33, 0x33, 0x33, 0x3B, 0x1E, 0x38, 0x00], // Q
+    [0x3F, 0x66, 0x66, 0x3E, 0x36, 0x66, 0x67, 0x00], // R
+    [0x1E, 0x33, 0x07, 0x0E, 0x38, 0x33, 0x1E, 0x00], // S
+    [0x3F, 0x2D, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // T
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x3F, 0x00], // U
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // V
+    [0x63, 0x63, 0x63, 0x6B, 0x7F, 0x77, 0x63, 0x00], // W
+    [0x63, 0x63, 0x36, 0x1C, 0x1C, 0x36, 0x63, 0x00], // X
+    [0x33, 0x33, 0x33, 0x1E, 0x0C, 0x0C, 0x1E, 0x00], // Y
+    [0x7F, 0x63, 0x31, 0x18, 0x4C, 0x66, 0x7F, 0x00], // Z
+    [0x1E, 0x06, 0x06, 0x06, 0x06, 0x06, 0x1E, 0x00], // [
+    [0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x40, 0x00], // \\
+    [0x1E, 0x18, 0x18, 0x18, 0x18, 0x18, 0x1E, 0x00], // ]
+    [0x08, 0x1C, 0x36, 0x63, 0x00, 0x00, 0x00, 0x00], // ^
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF], // _
+    [0x0C, 0x0C, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00], // `
+    [0x00, 0x00, 0x1E, 0x30, 0x3E, 0x33, 0x6E, 0x00], // a
+    [0x07, 0x06, 0x06, 0x3E, 0x66, 0x66, 0x3B, 0x00], // b
+    [0x00, 0x00, 0x1E, 0x33, 0x03, 0x33, 0x1E, 0x00], // c
+    [0x38, 0x30, 0x30, 0x3E, 0x33, 0x33, 0x6E, 0x00], // d
+    [0x00, 0x00, 0x1E, 0x33, 0x3F, 0x03, 0x1E, 0x00], // e
+    [0x1C, 0x36, 0x06, 0x0F, 0x06, 0x06, 0x0F, 0x00], // f
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x1F], // g
+    [0x07, 0x06, 0x36, 0x6E, 0x66, 0x66, 0x67, 0x00], // h
+    [0x0C, 0x00, 0x0E, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // i
+    [0x30, 0x00, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E], // j
+    [0x07, 0x06, 0x66, 0x36, 0x1E, 0x36, 0x67, 0x00], // k
+    [0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // l
+    [0x00, 0x00, 0x33, 0x7F, 0x7F, 0x6B, 0x63, 0x00], // m
+    [0x00, 0x00, 0x1F, 0x33, 0x33, 0x33, 0x33, 0x00], // n
+    [0x00, 0x00, 0x1E, 0x33, 0x33, 0x33, 0x1E, 0x00], // o
+    [0x00, 0x00, 0x3B, 0x66, 0x66, 0x3E, 0x06, 0x0F], // p
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x78], // q
+    [0x00, 0x00, 0x3B, 0x6E, 0x66, 0x06, 0x0F, 0x00], // r
+    [0x00, 0x00, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x00], // s
+    [0x08, 0x0C, 0x3E, 0x0C, 0x0C, 0x2C, 0x18, 0x00], // t
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6E, 0x00], // u
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // v
+    [0x00, 0x00, 0x63, 0x6B, 0x7F, 0x7F, 0x36, 0x00], // w
+    [0x00, 0x00, 0x63, 0x36, 0x1C, 0x36, 0x63, 0x00], // x
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x3E, 0x30, 0x1F], // y
+    [0x00, 0x00, 0x3F, 0x19, 0x0C, 0x26, 0x3F, 0x00], // z
+    [0x38, 0x0C, 0x0C, 0x07, 0x0C, 0x0C, 0x38, 0x00], // {
+    [0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00], // |
+    [0x07, 0x0C, 0x0C, 0x38, 0x0C, 0x0C, 0x07, 0x00], // }
+    [0x6E, 0x3B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ~
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_expire() {
+        let mut osd = Osd::new();
+        osd.show("first", 1);
+        for i in 0..MAX_VISIBLE_MESSAGES {
+            osd.show(format!("queued {i}"), 2);
+        }
+
+        osd.tick();
+        assert_eq!(osd.messages().next(), Some("queued 0"));
+        // The last message wasn't visible during the first tick, so it lasts a frame longer.
+        osd.tick();
+        assert_eq!(osd.messages().collect::<Vec<_>>(), ["queued 3"]);
+        osd.tick();
+        assert_eq!(osd.messages().count(), 0);
+    }
+}
@@ -0,0 +1,325 @@
+//! Applies IPS and BPS patches, as used to distribute translations and ROM hacks, to a ROM file
+//! before it's loaded.
+
+use crate::crc32;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const BPS_MAGIC: &[u8] = b"BPS1";
+/// The size of the source, target, and patch checksums at the end of a BPS patch.
+const BPS_FOOTER_SIZE: usize = 12;
+/// The largest ROM a BPS patch may produce. Far bigger than any real NES ROM, but small enough
+/// that a corrupt size can't exhaust memory.
+pub const MAX_BPS_TARGET_SIZE: usize = 16 * 1024 * 1024;
+
+/// Applies an IPS or BPS patch to a ROM, detecting the format from the patch's header.
+///
+/// # Errors
+///
+/// Returns an error if the patch is malformed or in an unknown format, or if a BPS patch was made
+/// for a different ROM.
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(rom, patch)
+    } else {
+        Err("unknown patch format".into())
+    }
+}
+
+pub fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = Reader::new(patch.strip_prefix(IPS_MAGIC).ok_or("not an ips patch")?);
+    let mut output = rom.to_vec();
+
+    loop {
+        let offset = reader.bytes(3)?;
+        if offset == IPS_EOF {
+            break;
+        }
+        let offset = u32::from_be_bytes([0, offset[0], offset[1], offset[2]]) as usize;
+        let size = reader.u16_be()? as usize;
+
+        // A size of 0 marks a run of a single repeated byte.
+        let (size, data) = match size {
+            0 => (reader.u16_be()? as usize, None),
+            size => (size, Some(reader.bytes(size)?)),
+        };
+        if output.len() < offset + size {
+            output.resize(offset + size, 0);
+        }
+        match data {
+            Some(data) => output[offset..offset + size].copy_from_slice(data),
+            None => output[offset..offset + size].fill(reader.byte()?),
+        }
+    }
+
+    // Some patches give a size to truncate the output to after the end marker.
+    if let Ok(size) = reader.bytes(3) {
+        let size = u32::from_be_bytes([0, size[0], size[1], size[2]]) as usize;
+        output.truncate(size);
+    }
+
+    Ok(output)
+}
+
+pub fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.len() < BPS_MAGIC.len() + BPS_FOOTER_SIZE || !patch.starts_with(BPS_MAGIC) {
+        return Err("not a bps patch".into());
+    }
+    let (body, footer) = patch.split_at(patch.len() - BPS_FOOTER_SIZE);
+    let checksum = |index: usize| {
+        let bytes = &footer[index * 4..index * 4 + 4];
+        u32::from_le_bytes(bytes.try_into().unwrap())
+    };
+    if crc32(&patch[..patch.len() - 4]) != checksum(2) {
+        return Err("bps patch is corrupt".into());
+    }
+    if crc32(rom) != checksum(0) {
+        return Err("bps patch was made for a different rom".into());
+    }
+
+    let mut reader = Reader::new(&body[BPS_MAGIC.len()..]);
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    let metadata_size = reader.varint()?;
+    reader.bytes(metadata_size)?;
+    if source_size != rom.len() {
+        return Err(format!(
+            "bps patch expects a {source_size} byte rom, but rom is {} bytes",
+            rom.len()
+        ));
+    }
+    if target_size > MAX_BPS_TARGET_SIZE {
+        return Err(format!(
+            "bps patch produces a {target_size} byte rom, over the limit of {MAX_BPS_TARGET_SIZE}"
+        ));
+    }
+
+    let mut output = Vec::with_capacity(rom.len());
+    let mut source_offset = 0;
+    let mut target_offset = 0;
+    while !reader.is_empty() {
+        let action = reader.varint()?;
+        let length = (action >> 2) + 1;
+        if output
+            .len()
+            .checked_add(length)
+            .is_none_or(|end| end > target_size)
+        {
+            return Err("bps patch writes past the end of the output".into());
+        }
+
+        match action & 0x03 {
+            // Source read.
+            0 => {
+                let start = output.len();
+                let data = rom
+                    .get(start..start + length)
+                    .ok_or("bps read past rom end")?;
+                output.extend_from_slice(data);
+            }
+            // Target read.
+            1 => output.extend_from_slice(reader.bytes(length)?),
+            // Source copy.
+            2 => {
+                source_offset = reader.relative_offset(source_offset)?;
+                let end = source_offset
+                    .checked_add(length)
+                    .ok_or("bps copy past rom end")?;
+                let data = rom.get(source_offset..end).ok_or("bps copy past rom end")?;
+                output.extend_from_slice(data);
+                source_offset = end;
+            }
+            // Target copy. The copied range may overlap the bytes being written, so copy one byte
+            // at a time.
+            _ => {
+                target_offset = reader.relative_offset(target_offset)?;
+                for _ in 0..length {
+                    let byte = *output
+                        .get(target_offset)
+                        .ok_or("bps copy past output end")?;
+                    output.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if output.len() != target_size {
+        return Err(format!(
+            "bps patch produced {} bytes, expected {target_size}",
+            output.len()
+        ));
+    }
+    if crc32(&output) != checksum(1) {
+        return Err("patched rom doesn't match bps checksum".into());
+    }
+
+    Ok(output)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.data.len() < len {
+            return Err("patch ended unexpectedly".into());
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16_be(&mut self) -> Result<u16, String> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Reads a BPS variable-length number.
+    fn varint(&mut self) -> Result<usize, String> {
+        let mut value: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = self.byte()?;
+            value = (byte as usize & 0x7F)
+                .checked_mul(shift)
+                .and_then(|part| value.checked_add(part))
+                .ok_or("bps number too large")?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_shl(7).ok_or("bps number too large")?;
+            value = value.checked_add(shift).ok_or("bps number too large")?;
+        }
+    }
+
+    /// Reads a signed BPS offset and applies it to `offset`.
+    fn relative_offset(&mut self, offset: usize) -> Result<usize, String> {
+        let data = self.varint()?;
+        let delta = data >> 1;
+        let offset = if data & 1 != 0 {
+            offset.checked_sub(delta)
+        } else {
+            offset.checked_add(delta)
+        };
+        offset.ok_or_else(|| "bps offset out of range".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ips() {
+        let rom = [0u8; 8];
+        let mut patch = b"PATCH".to_vec();
+        // Write 2 bytes at offset 1.
+        patch.extend([0, 0, 1, 0, 2, 0xAA, 0xBB]);
+        // Fill 3 bytes at offset 6 with 0xCC, extending the file by 1 byte.
+        patch.extend([0, 0, 6, 0, 0, 0, 3, 0xCC]);
+        patch.extend(b"EOF");
+
+        assert_eq!(
+            apply(&rom, &patch).unwrap(),
+            [0, 0xAA, 0xBB, 0, 0, 0, 0xCC, 0xCC, 0xCC]
+        );
+
+        // Truncate to 4 bytes.
+        patch.extend([0, 0, 4]);
+        assert_eq!(apply(&rom, &patch).unwrap(), [0, 0xAA, 0xBB, 0]);
+
+        assert!(apply(&rom, b"PATCH\x00\x00").is_err());
+    }
+
+    fn varint(mut value: usize, out: &mut Vec<u8>) {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(byte | 0x80);
+                return;
+            }
+            out.push(byte);
+            value -= 1;
+        }
+    }
+
+    #[test]
+    fn bps() {
+        let rom = *b"ABCDEFGH";
+        let target = b"ABCxyDEFFFFFF";
+        let mut patch = b"BPS1".to_vec();
+        varint(rom.len(), &mut patch);
+        varint(target.len(), &mut patch);
+        varint(0, &mut patch);
+        // Source read "ABC".
+        varint((3 - 1) << 2, &mut patch);
+        // Target read "xy".
+        varint((2 - 1) << 2 | 1, &mut patch);
+        patch.extend(b"xy");
+        // Source copy "DEF" from offset 3.
+        varint((3 - 1) << 2 | 2, &mut patch);
+        varint(3 << 1, &mut patch);
+        // Target copy "FFFFF" from offset 7, overlapping the bytes being written.
+        varint((5 - 1) << 2 | 3, &mut patch);
+        varint(7 << 1, &mut patch);
+        patch.extend(crc32(&rom).to_le_bytes());
+        patch.extend(crc32(target).to_le_bytes());
+        patch.extend(crc32(&patch).to_le_bytes());
+
+        assert_eq!(apply(&rom, &patch).unwrap(), target);
+        assert!(apply(b"ABCDEFGX", &patch).is_err());
+
+        let mut corrupt = patch.clone();
+        corrupt[8] ^= 0x01;
+        assert!(apply(&rom, &corrupt).is_err());
+    }
+
+    #[test]
+    fn bps_out_of_range() {
+        let rom = *b"ABCDEFGH";
+        let build = |target_size: usize, actions: &[usize]| {
+            let mut patch = b"BPS1".to_vec();
+            varint(rom.len(), &mut patch);
+            varint(target_size, &mut patch);
+            varint(0, &mut patch);
+            for &action in actions {
+                varint(action, &mut patch);
+            }
+            patch.extend(crc32(&rom).to_le_bytes());
+            patch.extend([0; 4]);
+            patch.extend(crc32(&patch).to_le_bytes());
+            patch
+        };
+
+        // A source copy from far past the end of the rom.
+        let patch = build(16, &[2, (usize::MAX >> 1) << 1]);
+        assert_eq!(
+            apply(&rom, &patch).err(),
+            Some("bps copy past rom end".to_string())
+        );
+
+        // A target copy repeating one byte into a huge output.
+        let patch = build(
+            usize::MAX >> 1,
+            &[1, b'A' as usize, (usize::MAX >> 3) << 2 | 3, 0],
+        );
+        assert!(apply(&rom, &patch).unwrap_err().contains("over the limit"));
+    }
+}
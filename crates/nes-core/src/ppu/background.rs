@@ -0,0 +1,195 @@
+//! The background pipeline: fetching tiles from the nametables, feeding them through the shift
+//! registers, and scrolling.
+
+use super::Ppu;
+
+/// A background tile loaded into the shift registers, kept for pixel inspection.
+#[derive(Debug, Default, Clone, Copy)]
+pub(super) struct BackgroundTile {
+    pub(super) nametable_addr: u16,
+    pub(super) tile: u8,
+    pub(super) pattern_addr: u16,
+}
+
+impl Ppu {
+    /// Runs the background fetches, shifts, and scroll updates for the current dot of a visible or
+    /// pre-render scanline.
+    pub(super) fn clock_background(&mut self) {
+        if (self.cycle >= 2 && self.cycle <= 257) || (self.cycle >= 321 && self.cycle <= 337) {
+            self.update_shift_registers();
+
+            match (self.cycle - 1) % 8 {
+                0 => {
+                    self.load_shift_registers();
+
+                    self.next_tile_addr = 0x2000 | (self.vram_addr.0 & 0x0FFF);
+                    self.next_tile_nametable = self.ppu_read(self.next_tile_addr);
+                }
+                2 => {
+                    self.next_tile_attrib = self.ppu_read(
+                        0x23C0
+                            | (self.vram_addr.nametable_y() << 11)
+                            | (self.vram_addr.nametable_x() << 10)
+                            | ((self.vram_addr.coarse_y() >> 2) << 3)
+                            | (self.vram_addr.coarse_x() >> 2),
+                    );
+
+                    if self.vram_addr.coarse_y() & 0x02 != 0 {
+                        self.next_tile_attrib >>= 4;
+                    }
+                    if self.vram_addr.coarse_x() & 0x02 != 0 {
+                        self.next_tile_attrib >>= 2;
+                    }
+                    self.next_tile_attrib &= 0x03;
+                }
+                4 => {
+                    self.next_tile_pattern_low = self.ppu_read(
+                        ((self.control.background_pattern() as u16) << 12)
+                            + ((self.next_tile_nametable as u16) << 4)
+                            + self.vram_addr.fine_y(),
+                    );
+                }
+                6 => {
+                    self.next_tile_pattern_high = self.ppu_read(
+                        ((self.control.background_pattern() as u16) << 12)
+                            + ((self.next_tile_nametable as u16) << 4)
+                            + self.vram_addr.fine_y()
+                            + 8,
+                    );
+                }
+                7 => {
+                    self.increment_x_scroll();
+                }
+                _ => (),
+            }
+        }
+        if self.cycle == 256 {
+            self.increment_y_scroll();
+        }
+        if self.cycle == 257 {
+            self.load_shift_registers();
+            self.update_x_scroll();
+        }
+        if self.cycle == 338 || self.cycle == 340 {
+            self.next_tile_nametable = self.ppu_read(0x2000 | self.vram_addr.0 & 0x0FFF);
+        }
+    }
+
+    /// Returns the palette and pattern value of the background pixel at the current dot.
+    pub(super) fn background_pixel(&self) -> (u8, u8) {
+        // Shift the current pixel's bit down to bit 0 of each register rather than masking and
+        // comparing each one separately.
+        let shift = 15 - self.fine_x_scroll as u16;
+        let bits = |low: u16, high: u16| (((high >> shift) & 1) << 1 | ((low >> shift) & 1)) as u8;
+
+        let palette = bits(
+            self.palette_attrib_shift_low,
+            self.palette_attrib_shift_high,
+        );
+        let pattern = bits(self.pattern_table_shift_low, self.pattern_table_shift_high);
+        (palette, pattern)
+    }
+
+    /// Returns the tile the background pixel at the current dot comes from.
+    pub(super) fn background_tile(&self) -> BackgroundTile {
+        self.background_tiles[((self.tile_slot_shift & self.fine_x_bit_mux()) > 0) as usize]
+    }
+
+    /// Copies the horizontal scroll position from the temporary address.
+    pub(super) fn update_x_scroll(&mut self) {
+        if self.mask.show_background() || self.mask.show_sprites() {
+            self.vram_addr
+                .set_nametable_x(self.temp_vram_addr.nametable_x());
+            self.vram_addr.set_coarse_x(self.temp_vram_addr.coarse_x());
+        }
+    }
+
+    /// Copies the vertical scroll position from the temporary address.
+    pub(super) fn update_y_scroll(&mut self) {
+        if self.mask.show_background() || self.mask.show_sprites() {
+            self.vram_addr
+                .set_nametable_y(self.temp_vram_addr.nametable_y());
+            self.vram_addr.set_coarse_y(self.temp_vram_addr.coarse_y());
+            self.vram_addr.set_fine_y(self.temp_vram_addr.fine_y());
+        }
+    }
+
+    /// Selects the bit of the shift registers for the current pixel, offset by fine X scroll.
+    fn fine_x_bit_mux(&self) -> u16 {
+        0x8000 >> self.fine_x_scroll as u16
+    }
+
+    pub(super) fn increment_x_scroll(&mut self) {
+        if self.mask.show_background() || self.mask.show_sprites() {
+            if self.vram_addr.coarse_x() == 31 {
+                self.vram_addr.set_coarse_x(0);
+                self.vram_addr
+                    .set_nametable_x(self.vram_addr.nametable_x() ^ 1);
+            } else {
+                self.vram_addr.set_coarse_x(self.vram_addr.coarse_x() + 1);
+            }
+        }
+    }
+
+    pub(super) fn increment_y_scroll(&mut self) {
+        if self.mask.show_background() || self.mask.show_sprites() {
+            if self.vram_addr.fine_y() < 7 {
+                self.vram_addr.set_fine_y(self.vram_addr.fine_y() + 1);
+            } else {
+                self.vram_addr.set_fine_y(0);
+                let mut y = self.vram_addr.coarse_y();
+                if y == 29 {
+                    y = 0;
+                    self.vram_addr
+                        .set_nametable_y(self.vram_addr.nametable_y() ^ 1);
+                } else if y == 31 {
+                    y = 0;
+                } else {
+                    y += 1;
+                }
+                self.vram_addr.set_coarse_y(y);
+            }
+        }
+    }
+
+    fn update_shift_registers(&mut self) {
+        if self.mask.show_background() {
+            self.pattern_table_shift_low <<= 1;
+            self.pattern_table_shift_high <<= 1;
+            self.palette_attrib_shift_low <<= 1;
+            self.palette_attrib_shift_high <<= 1;
+            self.tile_slot_shift <<= 1;
+        }
+    }
+
+    fn load_shift_registers(&mut self) {
+        self.pattern_table_shift_low =
+            (self.pattern_table_shift_low & 0xFF00) | self.next_tile_pattern_low as u16;
+        self.pattern_table_shift_high =
+            (self.pattern_table_shift_high & 0xFF00) | self.next_tile_pattern_high as u16;
+        self.palette_attrib_shift_low = (self.palette_attrib_shift_low & 0xFF00)
+            | if self.next_tile_attrib & 0b01 != 0 {
+                0xFF
+            } else {
+                0x00
+            };
+        self.palette_attrib_shift_high = (self.palette_attrib_shift_high & 0xFF00)
+            | if self.next_tile_attrib & 0b10 != 0 {
+                0xFF
+            } else {
+                0x00
+            };
+
+        // Alternate between two slots for the tile being loaded, tracking which slot each pixel's
+        // tile is in with a shift register just like the pattern and attribute data.
+        self.next_tile_slot ^= 1;
+        self.background_tiles[self.next_tile_slot] = BackgroundTile {
+            nametable_addr: self.next_tile_addr,
+            tile: self.next_tile_nametable,
+            pattern_addr: ((self.control.background_pattern() as u16) << 12)
+                | ((self.next_tile_nametable as u16) << 4),
+        };
+        self.tile_slot_shift =
+            (self.tile_slot_shift & 0xFF00) | if self.next_tile_slot != 0 { 0xFF } else { 0x00 };
+    }
+}
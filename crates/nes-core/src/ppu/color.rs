@@ -0,0 +1,56 @@
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    pub fn decode(index: u8) -> Self {
+        PALETTE[index as usize]
+    }
+
+    /// Applies the color emphasis bits of PPUMASK to the color decoded from `index`, which
+    /// darkens the channels that aren't emphasized. Colors $xE and $xF are black and unaffected.
+    #[cfg(any(feature = "memview", test))]
+    pub fn emphasize(self, index: u8, red: bool, green: bool, blue: bool) -> Self {
+        if !(red || green || blue) || index & 0x0F >= 0x0E {
+            return self;
+        }
+        let attenuate = |channel: u8, is_emphasized: bool| {
+            if is_emphasized {
+                channel
+            } else {
+                (channel as u16 * EMPHASIS_ATTENUATION / 256) as u8
+            }
+        };
+        Self {
+            r: attenuate(self.r, red),
+            g: attenuate(self.g, green),
+            b: attenuate(self.b, blue),
+        }
+    }
+}
+
+/// How much a channel is scaled by when another channel is emphasized, out of 256. Roughly
+/// matches the attenuation measured on an NTSC PPU.
+#[cfg(any(feature = "memview", test))]
+const EMPHASIS_ATTENUATION: u16 = 191;
+
+const PALETTE: [Color; 64] = {
+    let colors = include_bytes!("../../ntsc.pal");
+    let mut result = [Color::new(0, 0, 0); 64];
+    let mut i = 0;
+    while i < colors.len() / 3 {
+        let r = colors[i * 3];
+        let g = colors[i * 3 + 1];
+        let b = colors[i * 3 + 2];
+        result[i] = Color::new(r, g, b);
+        i += 1;
+    }
+    result
+};
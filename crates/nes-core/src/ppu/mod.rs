@@ -0,0 +1,1975 @@
+use std::{
+    cell::RefCell,
+    rc::{Rc, Weak},
+};
+
+mod background;
+mod color;
+mod registers;
+mod scanline;
+mod sprites;
+mod validation;
+mod vram;
+
+use crate::{
+    savestate::{PpuState, StateSerialize},
+    Bus, Cartridge, FrameTiming,
+};
+use background::BackgroundTile;
+use color::Color;
+use scanline::ScanlineRegisters;
+pub use scanline::{BackgroundPixel, ScanlinePixel, ScanlineRender, SpritePixel};
+pub use validation::{PpuWarning, PpuWarningKind};
+pub use vram::POWER_UP_PALETTE;
+
+/// The number of dots a write to PPUMASK takes to enable or disable rendering.
+const MASK_DELAY: u8 = 3;
+/// The number of dots PPU A12 has to stay low for before the MMC3 counts it rising, which is
+/// about 3 CPU cycles.
+const A12_FILTER_DOTS: u16 = 9;
+
+pub struct Ppu {
+    control: PpuControl,
+    mask: PpuMask,
+    /// A PPUMASK write that hasn't enabled or disabled rendering yet, and the dots left until it
+    /// does.
+    pending_mask: Option<(PpuMask, u8)>,
+    has_mask_delay: bool,
+    /// Whether writes to some registers are ignored, from power-on until the end of the first
+    /// vblank.
+    is_warming_up: bool,
+    /// Whether the cartridge's scanline counter is clocked by watching PPU A12 rise, rather than
+    /// once per scanline at a fixed dot.
+    has_a12_filter: bool,
+    /// The number of dots PPU A12 has been low for, while the A12 filter is enabled.
+    a12_low_dots: u16,
+    has_sprite_evaluation_timing: bool,
+    status: PpuStatus,
+
+    bus: Weak<RefCell<Bus>>,
+    cartridge: Rc<RefCell<Cartridge>>,
+    /// Sized to fit the largest pixel format. Only the leading portion used by the current format
+    /// is meaningful.
+    buffer: Box<[u8; 256 * 240 * 4]>,
+    pixel_format: PixelFormat,
+    #[cfg(feature = "memview")]
+    nametable_buffer: Box<[u8; 512 * 480 * 3]>,
+    #[cfg(feature = "memview")]
+    pattern_table_buffer: Box<[u8; 256 * 128 * 3]>,
+    #[cfg(feature = "memview")]
+    oam_buffer: Box<[u8; 64 * 64 * 3]>,
+    nametables: Box<[u8; 2048]>,
+    palette_ram: Box<[u8; 32]>,
+    /// Palette RAM with the mirrored entries resolved, so that rendering can index it directly.
+    /// Rebuilt whenever palette RAM changes.
+    palette_lookup: [u8; 32],
+    oam: Box<[u8; 256]>,
+    pub oam_addr: u8,
+    cycle: u16,
+    scanline: u16,
+    ppu_data_buffer: u8,
+    vram_addr: VramAddress,
+    temp_vram_addr: VramAddress,
+    fine_x_scroll: u8,
+    addr_latch: u8,
+
+    pattern_table_shift_low: u16,
+    pattern_table_shift_high: u16,
+    palette_attrib_shift_low: u16,
+    palette_attrib_shift_high: u16,
+
+    next_tile_nametable: u8,
+    next_tile_attrib: u8,
+    next_tile_pattern_low: u8,
+    next_tile_pattern_high: u8,
+
+    secondary_oam: [u8; 32],
+    secondary_oam_sprite_count: u8,
+    /// The sprites found on the next scanline so far, with their bytes as they were in OAM when
+    /// each was found.
+    sprite_candidates: [(u8, [u8; 4]); 64],
+    sprite_candidate_count: usize,
+    /// The next sprite to check and the dot it's checked on, while sprite evaluation is timed.
+    next_evaluated_sprite: usize,
+    next_evaluation_dot: u16,
+    sprite_pattern_shift_low: [u8; 8],
+    sprite_pattern_shift_high: [u8; 8],
+    sprite_attrib: [u8; 8],
+    sprite_x_pos: [u8; 8],
+    is_sprite_zero_active: bool,
+
+    /// The tiles currently in the background shift registers, indexed by the bits shifted through
+    /// `tile_slot_shift`.
+    background_tiles: [BackgroundTile; 2],
+    tile_slot_shift: u16,
+    next_tile_slot: usize,
+    next_tile_addr: u16,
+    secondary_oam_index: [u8; 8],
+    sprite_oam_index: [u8; 8],
+    sprite_pattern_addr: [u16; 8],
+    /// Where each pixel in the output buffer came from. Only tracked while pixel inspection is
+    /// enabled, as it slows down rendering.
+    pixel_sources: Option<Box<[PixelSource; 256 * 240]>>,
+    /// The scroll position and settings each visible scanline was last rendered with, for
+    /// [Ppu::render_scanline].
+    scanline_registers: Box<[ScanlineRegisters; 240]>,
+    /// Whether pixels are left out of the output buffer, for frameskip.
+    is_output_skipped: bool,
+    /// Whether only every other pixel is drawn, for slow devices.
+    is_half_horizontal_resolution: bool,
+
+    pub is_frame_ready: bool,
+    pub emit_nmi: bool,
+    /// The palette the pattern tables are drawn with in the memory viewer, where 0-3 are the
+    /// background palettes and 4-7 the sprite palettes.
+    #[cfg(feature = "memview")]
+    pattern_table_palette: u8,
+    /// The 8 KiB page of CHR the memory viewer shows in the pattern tables, or `None` for what's
+    /// currently banked in.
+    #[cfg(feature = "memview")]
+    pattern_table_chr_page: Option<usize>,
+    /// Whether the memory viewer applies the color emphasis bits of PPUMASK.
+    #[cfg(feature = "memview")]
+    memview_emphasis: bool,
+    is_odd_frame: bool,
+    timing: FrameTiming,
+    overclock_scanlines: u16,
+    /// Seeds the rotation of where sprite evaluation starts, if sprite flicker is enabled.
+    sprite_flicker_seed: Option<u64>,
+    extra_scanline: u16,
+    frame_count: u64,
+    /// The scanline and dot sprite 0 hit was set on during the current frame.
+    sprite_zero_hit_position: Option<(u16, u16)>,
+    is_validating: bool,
+    warnings: Vec<PpuWarning>,
+}
+
+impl Ppu {
+    pub fn new(cartridge: Rc<RefCell<Cartridge>>) -> Self {
+        let buffer = crate::new_boxed_array();
+        #[cfg(feature = "memview")]
+        let nametable_buffer = crate::new_boxed_array();
+        #[cfg(feature = "memview")]
+        let pattern_table_buffer = crate::new_boxed_array();
+        #[cfg(feature = "memview")]
+        let oam_buffer = crate::new_boxed_array();
+
+        let mut ppu = Self {
+            control: PpuControl::default(),
+            mask: PpuMask::default(),
+            pending_mask: None,
+            has_mask_delay: true,
+            is_warming_up: false,
+            has_a12_filter: false,
+            a12_low_dots: 0,
+            has_sprite_evaluation_timing: false,
+            status: PpuStatus::default(),
+
+            bus: Weak::new(),
+            cartridge,
+            buffer,
+            pixel_format: PixelFormat::default(),
+            #[cfg(feature = "memview")]
+            nametable_buffer,
+            #[cfg(feature = "memview")]
+            pattern_table_buffer,
+            #[cfg(feature = "memview")]
+            oam_buffer,
+            nametables: crate::new_boxed_array(),
+            palette_ram: Box::new(POWER_UP_PALETTE),
+            palette_lookup: [0; 32],
+            oam: crate::new_boxed_array(),
+            oam_addr: 0,
+            cycle: 0,
+            scanline: 0,
+            ppu_data_buffer: 0,
+            vram_addr: VramAddress::default(),
+            temp_vram_addr: VramAddress::default(),
+            fine_x_scroll: 0,
+            addr_latch: 0,
+
+            pattern_table_shift_low: 0,
+            pattern_table_shift_high: 0,
+            palette_attrib_shift_low: 0,
+            palette_attrib_shift_high: 0,
+
+            next_tile_nametable: 0,
+            next_tile_attrib: 0,
+            next_tile_pattern_low: 0,
+            next_tile_pattern_high: 0,
+
+            secondary_oam: [0; 32],
+            secondary_oam_sprite_count: 0,
+            sprite_candidates: [(0, [0; 4]); 64],
+            sprite_candidate_count: 0,
+            next_evaluated_sprite: 64,
+            next_evaluation_dot: 0,
+            sprite_pattern_shift_low: [0; 8],
+            sprite_pattern_shift_high: [0; 8],
+            sprite_attrib: [0; 8],
+            sprite_x_pos: [0; 8],
+            is_sprite_zero_active: false,
+
+            background_tiles: [BackgroundTile::default(); 2],
+            tile_slot_shift: 0,
+            next_tile_slot: 0,
+            next_tile_addr: 0,
+            secondary_oam_index: [0; 8],
+            sprite_oam_index: [0; 8],
+            sprite_pattern_addr: [0; 8],
+            pixel_sources: None,
+            scanline_registers: Box::new([ScanlineRegisters::default(); 240]),
+            is_output_skipped: false,
+            is_half_horizontal_resolution: false,
+
+            is_frame_ready: false,
+            emit_nmi: false,
+            #[cfg(feature = "memview")]
+            pattern_table_palette: 0,
+            #[cfg(feature = "memview")]
+            pattern_table_chr_page: None,
+            #[cfg(feature = "memview")]
+            memview_emphasis: true,
+            is_odd_frame: false,
+            timing: FrameTiming::default(),
+            overclock_scanlines: 0,
+            sprite_flicker_seed: None,
+            extra_scanline: 0,
+            frame_count: 0,
+            sprite_zero_hit_position: None,
+            is_validating: false,
+            warnings: Vec::new(),
+        };
+        ppu.update_palette_lookup();
+        ppu
+    }
+
+    pub fn reset(&mut self) {
+        self.control = PpuControl::default();
+        self.mask = PpuMask::default();
+        self.pending_mask = None;
+        self.status = PpuStatus::default();
+        self.sprite_zero_hit_position = None;
+
+        self.cycle = 0;
+        self.scanline = 0;
+        self.ppu_data_buffer = 0;
+        self.fine_x_scroll = 0;
+        self.addr_latch = 0;
+
+        self.pattern_table_shift_low = 0;
+        self.pattern_table_shift_high = 0;
+        self.palette_attrib_shift_low = 0;
+        self.palette_attrib_shift_high = 0;
+
+        self.next_tile_nametable = 0;
+        self.next_tile_attrib = 0;
+        self.next_tile_pattern_low = 0;
+        self.next_tile_pattern_high = 0;
+
+        self.is_frame_ready = false;
+        self.emit_nmi = false;
+        self.is_odd_frame = false;
+        self.extra_scanline = 0;
+    }
+
+    /// Returns the color drawn where neither layer has an opaque pixel. This is normally the
+    /// universal background color, but while rendering is disabled and the address register
+    /// points into palette RAM, it's whichever color is being pointed at. Demos use this to draw
+    /// more colors than the palettes hold by writing to palette RAM mid-scanline.
+    fn backdrop_color(&self) -> u8 {
+        let addr = self.vram_addr.0 & 0x3FFF;
+        let is_rendering_enabled = self.mask.show_background() || self.mask.show_sprites();
+        if !is_rendering_enabled && addr >= 0x3F00 {
+            self.apply_grayscale(self.ppu_read(addr))
+        } else {
+            self.sample_palette_ram(0, 0)
+        }
+    }
+
+    /// Returns the last value written to PPUMASK, including any change to rendering that hasn't
+    /// taken effect yet.
+    fn next_mask(&self) -> PpuMask {
+        self.pending_mask.map_or(self.mask, |(mask, _)| mask)
+    }
+
+    pub fn connect_bus(&mut self, bus: Weak<RefCell<Bus>>) {
+        self.bus = bus;
+    }
+
+    pub fn overclock_scanlines(&self) -> u16 {
+        self.overclock_scanlines
+    }
+
+    /// Sets the number of extra scanlines inserted at the end of vblank, giving the CPU more time
+    /// each frame without changing the timing of anything games can observe during vblank. This
+    /// reduces slowdown in games that lag, and setting it to 0 disables overclocking.
+    pub fn set_overclock_scanlines(&mut self, scanlines: u16) {
+        self.overclock_scanlines = scanlines;
+    }
+
+    pub fn has_mask_delay(&self) -> bool {
+        self.has_mask_delay
+    }
+
+    /// Sets whether PPUMASK writes take a few dots to enable or disable rendering, as on real
+    /// consoles, rather than applying immediately. Only games with precise raster effects notice.
+    pub fn set_mask_delay(&mut self, is_enabled: bool) {
+        self.has_mask_delay = is_enabled;
+    }
+
+    pub fn is_warming_up(&self) -> bool {
+        self.is_warming_up
+    }
+
+    /// Sets whether the PPU ignores writes to PPUCTRL, PPUMASK, PPUSCROLL, and PPUADDR from
+    /// power-on until the end of the first vblank, as real consoles do while the PPU warms up.
+    /// Games wait this out, but some test ROMs check for it. This only has an effect before the
+    /// first frame has finished.
+    pub fn set_warm_up(&mut self, is_enabled: bool) {
+        self.is_warming_up = is_enabled && self.frame_count == 0;
+    }
+
+    pub fn has_a12_filter(&self) -> bool {
+        self.has_a12_filter
+    }
+
+    /// Sets whether the cartridge's scanline counter is clocked when PPU address line A12 rises
+    /// after staying low for a few CPU cycles, as the MMC3 does, rather than once per scanline at
+    /// dot 260. This follows the pattern table each fetch uses, so games that put the background
+    /// and sprites in the same pattern table get no IRQs, as on hardware.
+    pub fn set_a12_filter(&mut self, is_enabled: bool) {
+        self.has_a12_filter = is_enabled;
+        self.a12_low_dots = 0;
+    }
+
+    pub fn has_sprite_evaluation_timing(&self) -> bool {
+        self.has_sprite_evaluation_timing
+    }
+
+    /// Sets whether sprites are found on the next scanline a few at a time between dots 65 and
+    /// 256, as on hardware, rather than all at once at dot 257. This only makes a difference to
+    /// games that write to OAM while rendering.
+    pub fn set_sprite_evaluation_timing(&mut self, is_enabled: bool) {
+        self.has_sprite_evaluation_timing = is_enabled;
+    }
+
+    pub fn sprite_flicker_seed(&self) -> Option<u64> {
+        self.sprite_flicker_seed
+    }
+
+    /// Enables or disables sprite flicker. On scanlines with more than 8 sprites, the PPU drops
+    /// every sprite after the 8th it finds in OAM, so the same sprites disappear every frame.
+    /// With flicker enabled, sprite evaluation starts from a different sprite each frame on those
+    /// scanlines, like games that cycle their sprites' priorities, so the dropped sprites flicker
+    /// instead. The starting sprite is derived from the seed and the frame count, so recordings
+    /// made with the same seed are reproducible.
+    pub fn set_sprite_flicker_seed(&mut self, seed: Option<u64>) {
+        self.sprite_flicker_seed = seed;
+    }
+
+    pub fn is_output_skipped(&self) -> bool {
+        self.is_output_skipped
+    }
+
+    /// Sets whether rendered pixels are written to the output buffer, which keeps the last frame
+    /// drawn while skipped. Everything games can observe, such as sprite 0 hits and NMIs, still
+    /// happens as normal, so skipping output doesn't affect emulation.
+    pub fn set_output_skipped(&mut self, is_skipped: bool) {
+        self.is_output_skipped = is_skipped;
+    }
+
+    pub fn is_half_horizontal_resolution(&self) -> bool {
+        self.is_half_horizontal_resolution
+    }
+
+    /// Sets whether only every other pixel of each scanline is worked out and drawn, with each
+    /// one repeated over the pixel to its right. This roughly halves the cost of drawing frames
+    /// on slow devices, at the cost of blurring thin details. Like [Ppu::set_output_skipped], it
+    /// doesn't affect emulation.
+    pub fn set_half_horizontal_resolution(&mut self, is_enabled: bool) {
+        self.is_half_horizontal_resolution = is_enabled;
+    }
+
+    /// Returns whether the PPU is in one of the extra scanlines added by overclocking. Other
+    /// components, such as the APU, shouldn't be clocked during these to keep their timing intact.
+    pub fn is_overclocking(&self) -> bool {
+        self.extra_scanline > 0
+    }
+
+    pub fn timing(&self) -> FrameTiming {
+        self.timing
+    }
+
+    /// Sets the frame layout to emulate. This should be done before running any frames, as
+    /// switching mid-frame may leave the PPU on a scanline past the end of the new frame.
+    pub fn set_timing(&mut self, timing: FrameTiming) {
+        self.timing = timing;
+    }
+
+    /// Returns the scanline currently being rendered, from 0 to the pre-render scanline (261 on
+    /// NTSC consoles).
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    /// Returns the dot within the current scanline that will be rendered next, from 0 to 340.
+    pub fn dot(&self) -> u16 {
+        self.cycle
+    }
+
+    /// Returns the scanline and dot that sprite 0 hit was set on during the current frame, for
+    /// tuning raster splits. This is `None` until it's set, and after loading a savestate.
+    pub fn sprite_zero_hit_position(&self) -> Option<(u16, u16)> {
+        self.sprite_zero_hit_position
+    }
+
+    /// Returns the number of frames rendered since power-on.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    pub fn is_validating(&self) -> bool {
+        self.is_validating
+    }
+
+    /// Enables or disables checking for PPU accesses at times that cause glitches on real
+    /// hardware. Problems found are collected as warnings, retrieved with [Ppu::drain_warnings].
+    pub fn set_validation(&mut self, is_enabled: bool) {
+        self.is_validating = is_enabled;
+    }
+
+    /// Takes the warnings collected since the last call.
+    pub fn drain_warnings(&mut self) -> Vec<PpuWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Checks that the PPU's counters and indices are within the ranges the hardware allows, for
+    /// tests that drive it with random register writes. Returns a description of the first one
+    /// that isn't.
+    pub fn validate_invariants(&self) -> Result<(), String> {
+        let check = |is_valid: bool, description: &str| {
+            if is_valid {
+                Ok(())
+            } else {
+                Err(description.to_string())
+            }
+        };
+        check(self.cycle <= 340, "dot is past the end of the scanline")?;
+        check(
+            self.scanline <= self.timing.pre_render_scanline(),
+            "scanline is past the end of the frame",
+        )?;
+        check(self.fine_x_scroll < 8, "fine x scroll is over 7")?;
+        check(self.addr_latch <= 1, "address latch is neither 0 nor 1")?;
+        check(
+            u16::from(self.vram_addr) < 0x8000 && u16::from(self.temp_vram_addr) < 0x8000,
+            "vram address is over 15 bits",
+        )?;
+        check(
+            self.palette_ram.iter().all(|&color| color < 0x40),
+            "palette ram holds a color over $3F",
+        )?;
+        check(
+            self.secondary_oam_sprite_count <= 8,
+            "more than 8 sprites are in secondary oam",
+        )?;
+        check(
+            self.next_tile_slot < self.background_tiles.len(),
+            "next background tile slot is out of range",
+        )?;
+        check(
+            self.sprite_oam_index.iter().all(|&index| index < 64),
+            "a sprite's oam index is over 63",
+        )
+    }
+
+    /// Records a warning at the current position, if validation is enabled.
+    pub(crate) fn warn(&mut self, kind: PpuWarningKind) {
+        if self.is_validating && self.warnings.len() < validation::MAX_WARNINGS {
+            self.warnings.push(PpuWarning {
+                kind,
+                frame: self.frame_count,
+                scanline: self.scanline,
+                dot: self.cycle,
+            });
+        }
+    }
+
+    /// Returns whether the PPU is currently fetching or drawing, during which the CPU shouldn't
+    /// access VRAM.
+    fn is_rendering(&self) -> bool {
+        (self.mask.show_background() || self.mask.show_sprites())
+            && (self.scanline <= 239 || self.scanline == self.timing.pre_render_scanline())
+    }
+
+    /// Clocks the cartridge's scanline counter when PPU A12 rises after staying low for
+    /// [A12_FILTER_DOTS], as the MMC3 does.
+    fn watch_a12(&mut self) {
+        if self.is_rendering() && self.is_a12_high() {
+            if self.a12_low_dots >= A12_FILTER_DOTS {
+                self.cartridge.borrow_mut().count_scanline();
+            }
+            self.a12_low_dots = 0;
+        } else {
+            self.a12_low_dots = self.a12_low_dots.saturating_add(1);
+        }
+    }
+
+    /// Returns whether the fetch at the current dot is from the pattern table at $1000. Each
+    /// fetch takes 8 dots, with the pattern bytes read over the last 4.
+    fn is_a12_high(&self) -> bool {
+        if self.cycle == 0 || (self.cycle - 1) % 8 < 4 {
+            return false;
+        }
+        match self.cycle {
+            1..=256 | 321..=336 => self.control.background_pattern() != 0,
+            257..=320 => {
+                if self.control.sprite_size() == 0 {
+                    return self.control.sprite_pattern() != 0;
+                }
+                // 8x16 sprites pick their table with the tile index, and empty slots fetch tile
+                // $FF.
+                let slot = (self.cycle as usize - 257) / 8;
+                let tile = if slot < self.secondary_oam_sprite_count as usize {
+                    self.secondary_oam[slot * 4 + 1]
+                } else {
+                    0xFF
+                };
+                tile & 0x01 != 0
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the current frame, laid out according to [Ppu::pixel_format].
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer[..256 * 240 * self.pixel_format.bytes_per_pixel()]
+    }
+
+    /// Returns a checksum of the current frame, for comparing frames between runs without
+    /// keeping them around. The checksum depends on [Ppu::pixel_format], so only frames in the
+    /// same format can be compared.
+    pub fn frame_crc32(&self) -> u32 {
+        crate::crc32(self.buffer())
+    }
+
+    pub fn buffer_raw(&self) -> *const u8 {
+        self.buffer.as_ptr()
+    }
+
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.pixel_format
+    }
+
+    /// Changes the layout of the output buffer. The current frame is converted to the new format.
+    pub fn set_pixel_format(&mut self, pixel_format: PixelFormat) {
+        const PIXELS: usize = 256 * 240;
+
+        match (self.pixel_format, pixel_format) {
+            (PixelFormat::Rgb24, PixelFormat::Rgba32) => {
+                // Iterate backwards so that pixels aren't overwritten before they're moved.
+                for i in (0..PIXELS).rev() {
+                    let [r, g, b] = [0, 1, 2].map(|channel| self.buffer[i * 3 + channel]);
+                    PixelFormat::Rgba32.write(&mut self.buffer[i * 4..i * 4 + 4], [r, g, b]);
+                }
+            }
+            (PixelFormat::Rgba32, PixelFormat::Rgb24) => {
+                for i in 0..PIXELS {
+                    self.buffer.copy_within(i * 4..i * 4 + 3, i * 3);
+                }
+            }
+            _ => (),
+        }
+        self.pixel_format = pixel_format;
+    }
+
+    /// Downscales the current frame into an RGB24 thumbnail for savestate previews.
+    pub fn thumbnail(&self) -> Vec<u8> {
+        use crate::savestate::{THUMBNAIL_HEIGHT, THUMBNAIL_SIZE, THUMBNAIL_WIDTH};
+
+        const SCALE_X: usize = 256 / THUMBNAIL_WIDTH;
+        const SCALE_Y: usize = 240 / THUMBNAIL_HEIGHT;
+        let bytes_per_pixel = self.pixel_format.bytes_per_pixel();
+
+        let mut thumbnail = Vec::with_capacity(THUMBNAIL_SIZE);
+        for y in 0..THUMBNAIL_HEIGHT {
+            for x in 0..THUMBNAIL_WIDTH {
+                // Average each block of pixels rather than sampling a single one to reduce
+                // aliasing.
+                let mut sum = [0usize; 3];
+                for block_y in 0..SCALE_Y {
+                    for block_x in 0..SCALE_X {
+                        let pixel = x * SCALE_X + block_x + (y * SCALE_Y + block_y) * 256;
+                        let index = pixel * bytes_per_pixel;
+                        for (channel, total) in sum.iter_mut().enumerate() {
+                            *total += self.buffer[index + channel] as usize;
+                        }
+                    }
+                }
+                thumbnail.extend(sum.map(|total| (total / (SCALE_X * SCALE_Y)) as u8));
+            }
+        }
+        thumbnail
+    }
+
+    pub fn is_pixel_inspection_enabled(&self) -> bool {
+        self.pixel_sources.is_some()
+    }
+
+    /// Enables or disables recording where each pixel comes from, for use with
+    /// [Ppu::inspect_pixel].
+    pub fn set_pixel_inspection(&mut self, is_enabled: bool) {
+        if is_enabled != self.pixel_sources.is_some() {
+            self.pixel_sources = is_enabled.then(crate::new_boxed_array);
+        }
+    }
+
+    /// Returns which tile, sprite, and palette produced the pixel at the given position the last
+    /// time it was drawn. Returns `None` if pixel inspection is disabled or the position is off
+    /// screen.
+    pub fn inspect_pixel(&self, x: u16, y: u16) -> Option<PixelSource> {
+        if x >= 256 || y >= 240 {
+            return None;
+        }
+        let sources = self.pixel_sources.as_ref()?;
+        Some(sources[(x + y * 256) as usize])
+    }
+
+    /// Returns the PPU's 2 KiB of internal nametable RAM, in physical order regardless of the
+    /// cartridge's mirroring.
+    pub fn nametable_ram(&self) -> &[u8; 2048] {
+        &self.nametables
+    }
+
+    /// Returns the 32 entries of palette RAM as read through $3F00-$3F1F, so the sprite palettes'
+    /// transparent colors show the background colors they mirror.
+    pub fn palette_ram(&self) -> [u8; 32] {
+        std::array::from_fn(|i| self.ppu_read(0x3F00 + i as u16))
+    }
+
+    pub fn oam(&self) -> &[u8; 256] {
+        &self.oam
+    }
+
+    #[cfg(feature = "memview")]
+    pub fn nametable_buffer(&self) -> &[u8] {
+        self.nametable_buffer.as_ref()
+    }
+
+    #[cfg(feature = "memview")]
+    pub fn pattern_table_buffer(&self) -> &[u8] {
+        self.pattern_table_buffer.as_ref()
+    }
+
+    #[cfg(feature = "memview")]
+    pub fn oam_buffer(&self) -> &[u8] {
+        self.oam_buffer.as_ref()
+    }
+
+    pub fn clock(&mut self) {
+        if let Some((mask, dots)) = &mut self.pending_mask {
+            if *dots == 0 {
+                self.mask = *mask;
+                self.pending_mask = None;
+            } else {
+                *dots -= 1;
+            }
+        }
+
+        let pre_render_scanline = self.timing.pre_render_scanline();
+        if self.scanline <= 239 || self.scanline == pre_render_scanline {
+            self.clock_sprite_shifters();
+            self.clock_background();
+            if self.cycle == 260
+                && (self.mask.show_background() || self.mask.show_sprites())
+                && !self.has_a12_filter
+            {
+                self.cartridge.borrow_mut().count_scanline();
+            }
+        }
+        if self.has_a12_filter {
+            self.watch_a12();
+        }
+        if self.scanline == 240 {
+            // Idle scanline; do nothing.
+        }
+        if self.cycle == 1 && self.scanline == self.timing.vblank_scanline {
+            self.status.set_vblank(true);
+            if self.control.nmi() {
+                self.emit_nmi = true;
+            }
+        }
+        if self.scanline == pre_render_scanline {
+            if self.cycle == 1 {
+                self.is_warming_up = false;
+                self.status.set_vblank(false);
+                self.status.set_sprite_zero_hit(false);
+                self.sprite_zero_hit_position = None;
+                self.is_frame_ready = true;
+                self.is_odd_frame = !self.is_odd_frame;
+                self.frame_count += 1;
+            }
+            if self.cycle >= 280 && self.cycle <= 304 {
+                self.update_y_scroll();
+            }
+            // The idle dot at the end of the pre-render scanline is skipped on odd frames, but only
+            // while rendering.
+            let is_rendering_enabled = self.mask.show_background() || self.mask.show_sprites();
+            if self.cycle == 339
+                && self.is_odd_frame
+                && is_rendering_enabled
+                && self.timing.skips_odd_frame_dot
+            {
+                self.cycle = 0;
+                self.scanline = 0;
+            }
+            if self.cycle == 340 {
+                self.cycle = 0;
+                self.scanline = 0;
+            }
+        }
+        if self.scanline <= 239 {
+            self.evaluate_sprites();
+        }
+        if self.cycle == 320 {
+            self.record_scanline_registers();
+        }
+
+        let (background_palette, background_pattern) = self.background_pixel();
+        let (sprite_pattern, sprite_palette, sprite_attrib, active_sprite) = self.sprite_pixel();
+
+        let background_pattern = if !self.mask.show_left_background_tiles() && self.cycle < 9 {
+            0
+        } else {
+            background_pattern
+        };
+        let sprite_pattern = if !self.mask.show_left_sprite_tiles() && self.cycle < 9 {
+            0
+        } else {
+            sprite_pattern
+        };
+
+        if background_pattern != 0
+            && sprite_pattern != 0
+            && self.is_sprite_zero_active
+            && active_sprite == 0
+        {
+            if !self.status.sprite_zero_hit() {
+                self.sprite_zero_hit_position = Some((self.scanline, self.cycle));
+            }
+            self.status.set_sprite_zero_hit(true);
+        }
+
+        let x = self.cycle.saturating_sub(1);
+        // At half horizontal resolution, odd pixels are filled in with the pixel to their left.
+        let is_repeated_pixel = self.is_half_horizontal_resolution && x % 2 == 1;
+        let is_pixel_drawn = !(self.is_output_skipped || is_repeated_pixel);
+        let is_sprite_in_front =
+            sprite_pattern != 0 && (background_pattern == 0 || sprite_attrib & (1 << 5) == 0);
+        let color_index = if !is_pixel_drawn && self.pixel_sources.is_none() {
+            // Nothing will look at the color.
+            0
+        } else if is_sprite_in_front {
+            self.sample_palette_ram(sprite_palette + 4, sprite_pattern)
+        } else if background_pattern != 0 {
+            self.sample_palette_ram(background_palette, background_pattern)
+        } else {
+            self.backdrop_color()
+        };
+
+        if self.pixel_sources.is_some() {
+            let source = if is_sprite_in_front {
+                PixelSource::Sprite {
+                    oam_index: self.sprite_oam_index[active_sprite],
+                    tile: self.oam[self.sprite_oam_index[active_sprite] as usize * 4 + 1],
+                    pattern_addr: self.sprite_pattern_addr[active_sprite],
+                    palette: sprite_palette + 4,
+                    color: color_index,
+                }
+            } else if background_pattern != 0 {
+                let tile = self.background_tile();
+                PixelSource::Background {
+                    nametable_addr: tile.nametable_addr,
+                    tile: tile.tile,
+                    pattern_addr: tile.pattern_addr,
+                    palette: background_palette,
+                    color: color_index,
+                }
+            } else {
+                PixelSource::Backdrop { color: color_index }
+            };
+            self.record_pixel_source(x, self.scanline, source);
+        }
+
+        if is_pixel_drawn {
+            let color = Color::decode(color_index);
+            self.draw_pixel(x, self.scanline, color);
+            if self.is_half_horizontal_resolution {
+                self.draw_pixel(x + 1, self.scanline, color);
+            }
+        }
+        if self.cycle == 340 {
+            self.cycle = 0;
+            if self.scanline == pre_render_scanline - 1
+                && self.extra_scanline < self.overclock_scanlines
+            {
+                // Repeat the last vblank scanline instead of moving on to the pre-render scanline.
+                self.extra_scanline += 1;
+            } else {
+                self.extra_scanline = 0;
+                self.scanline += 1;
+            }
+        }
+        self.cycle += 1;
+    }
+
+    /// Returns the palette the pattern tables are drawn with, where 0-3 are the background
+    /// palettes and 4-7 the sprite palettes.
+    #[cfg(feature = "memview")]
+    pub fn pattern_table_palette(&self) -> u8 {
+        self.pattern_table_palette
+    }
+
+    /// Selects the palette the pattern tables are drawn with. Only the low 3 bits are used.
+    #[cfg(feature = "memview")]
+    pub fn set_pattern_table_palette(&mut self, palette: u8) {
+        self.pattern_table_palette = palette & 0x07;
+    }
+
+    /// Returns the 8 KiB page of CHR the pattern tables are drawn from, or `None` if they show
+    /// what's currently banked in.
+    #[cfg(feature = "memview")]
+    pub fn pattern_table_chr_page(&self) -> Option<usize> {
+        self.pattern_table_chr_page
+    }
+
+    /// Selects an 8 KiB page of CHR to draw the pattern tables from, so that all of CHR can be
+    /// browsed, not just what's banked in. Banks that aren't currently banked in are dimmed.
+    /// Pages past the end of CHR wrap around.
+    #[cfg(feature = "memview")]
+    pub fn set_pattern_table_chr_page(&mut self, page: Option<usize>) {
+        self.pattern_table_chr_page = page;
+    }
+
+    #[cfg(feature = "memview")]
+    pub fn memview_emphasis(&self) -> bool {
+        self.memview_emphasis
+    }
+
+    /// Sets whether the memory viewer is drawn with the color emphasis currently set in PPUMASK,
+    /// matching how the tiles look on screen. Enabled by default.
+    #[cfg(feature = "memview")]
+    pub fn set_memview_emphasis(&mut self, is_enabled: bool) {
+        self.memview_emphasis = is_enabled;
+    }
+
+    #[cfg(feature = "memview")]
+    fn memview_color(&self, color_index: u8) -> Color {
+        let color = Color::decode(color_index);
+        if !self.memview_emphasis {
+            return color;
+        }
+        color.emphasize(
+            color_index,
+            self.mask.emphasize_red(),
+            self.mask.emphasize_green(),
+            self.mask.emphasize_blue(),
+        )
+    }
+
+    #[cfg(feature = "memview")]
+    pub fn draw_nametables(&mut self) {
+        let pages = self.nametable_pages();
+        for nametable_y in 0..=1 {
+            for nametable_x in 0..=1 {
+                // Dim nametables that mirror an earlier one, so they don't look like separate
+                // screens.
+                let nametable = nametable_y as usize * 2 + nametable_x as usize;
+                let shift = pages[..nametable].contains(&pages[nametable]) as u8 * 2;
+                for tile_y in 0..30 {
+                    for tile_x in 0..32 {
+                        let nametable = self.ppu_read(
+                            0x2000
+                                | (nametable_y << 11)
+                                | (nametable_x << 10)
+                                | (tile_y << 5)
+                                | tile_x,
+                        );
+                        let mut attrib = self.ppu_read(
+                            0x23C0
+                                | (nametable_y << 11)
+                                | (nametable_x << 10)
+                                | ((tile_y >> 2) << 3)
+                                | (tile_x >> 2),
+                        );
+                        if tile_y & 0x02 != 0 {
+                            attrib >>= 4
+                        }
+                        if tile_x & 0x02 != 0 {
+                            attrib >>= 2
+                        }
+                        let attrib = attrib & 0x03;
+                        let background_pattern = (self.control.background_pattern() as u16) << 12;
+                        let mut pattern_low = [0u8; 8];
+                        for i in 0..8 {
+                            let value =
+                                self.ppu_read(background_pattern + ((nametable as u16) << 4) + i);
+                            pattern_low[i as usize] = value;
+                        }
+                        let mut pattern_high = [0u8; 8];
+                        for i in 0..8 {
+                            let value = self
+                                .ppu_read(background_pattern + ((nametable as u16) << 4) + i + 8);
+                            pattern_high[i as usize] = value;
+                        }
+
+                        for (y, (low, high)) in
+                            pattern_low.into_iter().zip(pattern_high).enumerate()
+                        {
+                            for x in 0..8 {
+                                let low = (low & (0x80 >> x) > 0) as u8;
+                                let high = (high & (0x80 >> x) > 0) as u8;
+                                let index = (high << 1) | low;
+                                let color_index = if index != 0 {
+                                    self.sample_palette_ram(attrib, index)
+                                } else {
+                                    self.sample_palette_ram(0, 0)
+                                };
+                                let color = self.memview_color(color_index);
+
+                                let index = x
+                                    + tile_x as usize * 8
+                                    + nametable_x as usize * 256
+                                    + (y + tile_y as usize * 8 + nametable_y as usize * 240) * 512;
+                                self.nametable_buffer[index * 3] = color.r >> shift;
+                                self.nametable_buffer[index * 3 + 1] = color.g >> shift;
+                                self.nametable_buffer[index * 3 + 2] = color.b >> shift;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "memview")]
+    pub fn draw_pattern_tables(&mut self) {
+        // Copy out the CHR being shown, and which of its banks are banked in.
+        let (chr_page, is_banked): (Vec<u8>, [bool; 8]) = match self.pattern_table_chr_page {
+            Some(page) => {
+                let cartridge = self.cartridge.borrow();
+                let chr = cartridge.chr();
+                let banked = cartridge.banked_chr_banks();
+                let start = page * 0x2000;
+                let is_banked = std::array::from_fn(|window| {
+                    let addr = (start + window * crate::CHR_BANK_SIZE) % chr.len();
+                    banked.contains(&(addr / crate::CHR_BANK_SIZE))
+                });
+                let chr_page = (0..0x2000).map(|i| chr[(start + i) % chr.len()]).collect();
+                (chr_page, is_banked)
+            }
+            None => (
+                (0..0x2000).map(|addr| self.ppu_read(addr)).collect(),
+                [true; 8],
+            ),
+        };
+        let read = |addr: u16| chr_page[addr as usize];
+
+        for table_half in 0..=1 {
+            for tile_y in 0..16 {
+                for tile_x in 0..16 {
+                    let mut pattern_low = [0u8; 8];
+                    for i in 0..8 {
+                        let value = read((table_half << 12) | (tile_y << 8) | (tile_x << 4) | i);
+                        pattern_low[i as usize] = value;
+                    }
+                    let mut pattern_high = [0u8; 8];
+                    for i in 0..8 {
+                        let value =
+                            read((table_half << 12) | (tile_y << 8) | (tile_x << 4) | i | 8);
+                        pattern_high[i as usize] = value;
+                    }
+                    // Each 1 KiB bank is 4 rows of tiles.
+                    let is_dimmed = !is_banked[table_half as usize * 4 + tile_y as usize / 4];
+
+                    for (y, (low, high)) in pattern_low.into_iter().zip(pattern_high).enumerate() {
+                        for x in 0..8 {
+                            let low = (low & (0x80 >> x) > 0) as u8;
+                            let high = (high & (0x80 >> x) > 0) as u8;
+                            let index = (high << 1) | low;
+                            let color_index =
+                                self.sample_palette_ram(self.pattern_table_palette, index);
+                            let color = self.memview_color(color_index);
+                            let shift = is_dimmed as u8 * 2;
+
+                            let index = x
+                                + tile_x as usize * 8
+                                + table_half as usize * 128
+                                + (y + tile_y as usize * 8) * 256;
+                            self.pattern_table_buffer[index * 3] = color.r >> shift;
+                            self.pattern_table_buffer[index * 3 + 1] = color.g >> shift;
+                            self.pattern_table_buffer[index * 3 + 2] = color.b >> shift;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "memview")]
+    pub fn draw_oam(&mut self) {
+        for sprite in 0..64 {
+            let index = self.oam[sprite as usize * 4 + 1] as u16;
+            let attrib = self.oam[sprite as usize * 4 + 2];
+            let palette = attrib & 0x03;
+            let flip_horizontally = attrib & (1 << 6) != 0;
+            let flip_vertically = attrib & (1 << 7) != 0;
+
+            let mut pattern_low = [0u8; 8];
+            for i in 0..8 {
+                let value = self
+                    .ppu_read(((self.control.sprite_pattern() as u16) << 12) | (index << 4) | i);
+                pattern_low[i as usize] = if flip_horizontally {
+                    value.reverse_bits()
+                } else {
+                    value
+                };
+            }
+            let mut pattern_high = [0u8; 8];
+            for i in 0..8 {
+                let value = self.ppu_read(
+                    ((self.control.sprite_pattern() as u16) << 12) | (index << 4) | i | 8,
+                );
+                pattern_high[i as usize] = if flip_horizontally {
+                    value.reverse_bits()
+                } else {
+                    value
+                };
+            }
+
+            let sprite_x = sprite & 0x07;
+            let sprite_y = sprite >> 3;
+
+            for (y, (low, high)) in pattern_low.into_iter().zip(pattern_high).enumerate() {
+                let y = if flip_vertically { 7 - y } else { y };
+                for x in 0..8 {
+                    let low = (low & (0x80 >> x) > 0) as u8;
+                    let high = (high & (0x80 >> x) > 0) as u8;
+                    let index = (high << 1) | low;
+                    let color_index = self.sample_palette_ram(palette + 4, index);
+                    let color = self.memview_color(color_index);
+
+                    let index = x + sprite_x as usize * 8 + (y + sprite_y as usize * 8) * 64;
+                    self.oam_buffer[index * 3] = color.r;
+                    self.oam_buffer[index * 3 + 1] = color.g;
+                    self.oam_buffer[index * 3 + 2] = color.b;
+                }
+            }
+        }
+    }
+
+    fn draw_pixel(&mut self, x: u16, y: u16, color: Color) {
+        if x >= 256 || y >= 240 {
+            return;
+        }
+        let bytes_per_pixel = self.pixel_format.bytes_per_pixel();
+        let index = (x + y * 256) as usize * bytes_per_pixel;
+        self.pixel_format.write(
+            &mut self.buffer[index..index + bytes_per_pixel],
+            [color.r, color.g, color.b],
+        );
+    }
+
+    fn record_pixel_source(&mut self, x: u16, y: u16, source: PixelSource) {
+        if x >= 256 || y >= 240 {
+            return;
+        }
+        if let Some(sources) = self.pixel_sources.as_mut() {
+            sources[(x + y * 256) as usize] = source;
+        }
+    }
+}
+
+impl Ppu {
+    /// Returns the same state as [StateSerialize::save_state], without serializing it.
+    pub fn state(&self) -> PpuState {
+        PpuState {
+            nametables: self.nametables.clone(),
+            palette_ram: self.palette_ram.clone(),
+            oam: self.oam.clone(),
+            control: self.control.0,
+            mask: self.next_mask().0,
+            status: self.status.0,
+            oam_addr: self.oam_addr,
+            tile_x_offset: self.fine_x_scroll,
+            addr_latch: self.addr_latch,
+            vram_addr: self.vram_addr.0,
+            temp_vram_addr: self.temp_vram_addr.0,
+            data_buffer: self.ppu_data_buffer,
+            general_latch: 0,
+        }
+    }
+}
+
+/// The frame buffer and its resolution, the memory viewer's buffers and settings, pixel
+/// inspection, the registers kept for [Ppu::render_scanline], overclocking, sprite flicker, and
+/// timing validation are presentation and debugging state, and are kept as they are when a state
+/// is applied.
+impl StateSerialize for Ppu {
+    type State<'a> = PpuState;
+
+    fn apply_state(&mut self, state: PpuState) {
+        self.nametables = state.nametables;
+        // Palette RAM only holds 6 bits per entry, and colors are looked up by them, so drop any
+        // extra bits a corrupt savestate might have.
+        self.palette_ram = state.palette_ram;
+        self.palette_ram.iter_mut().for_each(|color| *color &= 0x3F);
+        self.update_palette_lookup();
+        self.oam = state.oam;
+
+        self.control.0 = state.control;
+        self.mask.0 = state.mask;
+        self.pending_mask = None;
+        self.status.0 = state.status;
+        self.sprite_zero_hit_position = None;
+        self.oam_addr = state.oam_addr;
+
+        self.fine_x_scroll = state.tile_x_offset & 0x07;
+        self.addr_latch = state.addr_latch;
+        self.vram_addr = VramAddress::from(state.vram_addr);
+        self.temp_vram_addr = VramAddress::from(state.temp_vram_addr);
+        self.ppu_data_buffer = state.data_buffer;
+    }
+
+    fn save_state_into(&self, buffer: &mut Vec<u8>) {
+        use crate::savestate::serialize_into;
+
+        serialize_into(buffer, &self.nametables, "NTAR");
+        serialize_into(buffer, &self.palette_ram, "PRAM");
+        serialize_into(buffer, &self.oam, "SPRA");
+        serialize_into(
+            buffer,
+            &[
+                self.control.0,
+                self.next_mask().0,
+                self.status.0,
+                self.oam_addr,
+            ],
+            "PPUR",
+        );
+        serialize_into(buffer, &self.fine_x_scroll, "XOFF");
+        serialize_into(buffer, &self.addr_latch, "VTGL");
+        serialize_into(buffer, &self.vram_addr.0, "RADD");
+        serialize_into(buffer, &self.temp_vram_addr.0, "TADD");
+        serialize_into(buffer, &self.ppu_data_buffer, "VBUF");
+        serialize_into(buffer, &0u8, "PGEN"); // Unused debug variable.
+    }
+}
+
+/// The layout of each pixel in the PPU's output buffer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// Red, green, and blue bytes.
+    #[default]
+    Rgb24,
+    /// Red, green, blue, and alpha bytes. Alpha is always fully opaque.
+    Rgba32,
+}
+
+impl PixelFormat {
+    pub const fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Rgb24 => 3,
+            Self::Rgba32 => 4,
+        }
+    }
+
+    /// Writes a color into a single pixel, which must be [PixelFormat::bytes_per_pixel] long.
+    pub fn write(self, pixel: &mut [u8], [r, g, b]: [u8; 3]) {
+        match self {
+            Self::Rgb24 => pixel.copy_from_slice(&[r, g, b]),
+            Self::Rgba32 => pixel.copy_from_slice(&[r, g, b, 0xFF]),
+        }
+    }
+}
+
+/// Where a pixel in the output buffer came from, as returned by [Ppu::inspect_pixel].
+///
+/// Each variant's `color` is the palette RAM value the pixel was drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelSource {
+    /// The universal background color, shown where neither layer has an opaque pixel.
+    Backdrop { color: u8 },
+    Background {
+        /// The address of the tile's nametable entry, from $2000 to $2FFF.
+        nametable_addr: u16,
+        tile: u8,
+        /// The address of the tile's first byte in the pattern tables.
+        pattern_addr: u16,
+        /// The background palette, from 0 to 3.
+        palette: u8,
+        color: u8,
+    },
+    Sprite {
+        /// The sprite's index in OAM, from 0 to 63.
+        oam_index: u8,
+        tile: u8,
+        /// The address of the tile's first byte in the pattern tables. For 8x16 sprites, this is
+        /// whichever of the two tiles the pixel is in.
+        pattern_addr: u16,
+        /// The sprite palette, from 4 to 7.
+        palette: u8,
+        color: u8,
+    },
+}
+
+impl Default for PixelSource {
+    fn default() -> Self {
+        Self::Backdrop { color: 0 }
+    }
+}
+
+#[bitfield_struct::bitfield(u16)]
+#[derive(PartialEq, Eq)]
+struct VramAddress {
+    #[bits(5)]
+    coarse_x: u16,
+    #[bits(5)]
+    coarse_y: u16,
+    #[bits(1)]
+    nametable_x: u16,
+    #[bits(1)]
+    nametable_y: u16,
+    #[bits(3)]
+    fine_y: u16,
+    #[bits(1)]
+    __: u16,
+}
+
+#[bitfield_struct::bitfield(u8)]
+#[derive(PartialEq, Eq)]
+struct PpuControl {
+    #[bits(2)]
+    nametable: u8,
+    #[bits(1)]
+    address_increment: u8,
+    #[bits(1)]
+    sprite_pattern: u8,
+    #[bits(1)]
+    background_pattern: u8,
+    #[bits(1)]
+    sprite_size: u8,
+    #[bits(1)]
+    ppu_master_slave: u8,
+    nmi: bool,
+}
+
+#[bitfield_struct::bitfield(u8)]
+#[derive(PartialEq, Eq)]
+struct PpuMask {
+    grayscale: bool,
+    show_left_background_tiles: bool,
+    show_left_sprite_tiles: bool,
+    show_background: bool,
+    show_sprites: bool,
+    emphasize_red: bool,
+    emphasize_green: bool,
+    emphasize_blue: bool,
+}
+
+#[bitfield_struct::bitfield(u8)]
+#[derive(PartialEq, Eq)]
+struct PpuStatus {
+    #[bits(5)]
+    open_bus: u8,
+    sprite_overflow: bool,
+    sprite_zero_hit: bool,
+    vblank: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::Mirroring;
+
+    #[test]
+    #[ignore = "needs test_ppu_read_buffer.nes in test_roms"]
+    fn grayscale_test_rom() {
+        // Among its other checks, reads palette RAM through PPUDATA with grayscale enabled.
+        let path = "./test_roms/test_ppu_read_buffer.nes";
+        let (code, text) = crate::test_hook::run_test_rom(path);
+        assert_eq!(code, 0, "{text}");
+    }
+
+    #[test]
+    fn grayscale_palette_reads() {
+        let ppu = setup();
+        let mut ppu = ppu.borrow_mut();
+
+        // Write a color with both hue and brightness bits to the first palette entry.
+        ppu.cpu_write(0x06, 0x3F);
+        ppu.cpu_write(0x06, 0x00);
+        ppu.cpu_write(0x07, 0x2C);
+
+        // Palette reads are unaffected while grayscale is disabled.
+        ppu.cpu_write(0x06, 0x3F);
+        ppu.cpu_write(0x06, 0x00);
+        assert_eq!(ppu.cpu_read(0x07), 0x2C);
+        assert_eq!(ppu.sample_palette_ram(0, 0), 0x2C);
+
+        // Enabling grayscale should mask out the hue bits on both the PPUDATA read path and the
+        // render path, while leaving the underlying palette RAM untouched.
+        ppu.cpu_write(0x01, 0x01);
+        ppu.cpu_write(0x06, 0x3F);
+        ppu.cpu_write(0x06, 0x00);
+        assert_eq!(ppu.cpu_read(0x07), 0x20);
+        assert_eq!(ppu.sample_palette_ram(0, 0), 0x20);
+        assert_eq!(ppu.palette_ram[0], 0x2C);
+    }
+
+    #[test]
+    fn ppudata_palette_reads() {
+        let ppu = setup();
+        let mut ppu = ppu.borrow_mut();
+
+        // Put different values in the palette and in the nametable byte underneath it.
+        ppu.cpu_write(0x06, 0x2F);
+        ppu.cpu_write(0x06, 0x00);
+        ppu.cpu_write(0x07, 0x55);
+        ppu.cpu_write(0x06, 0x3F);
+        ppu.cpu_write(0x06, 0x00);
+        ppu.cpu_write(0x07, 0x2C);
+
+        // Palette reads bypass the buffer, but still fill it from the nametable mirror.
+        ppu.cpu_write(0x06, 0x3F);
+        ppu.cpu_write(0x06, 0x00);
+        assert_eq!(ppu.cpu_read(0x07), 0x2C);
+        ppu.cpu_write(0x06, 0x20);
+        ppu.cpu_write(0x06, 0x00);
+        assert_eq!(ppu.cpu_read(0x07), 0x55);
+
+        // With rendering disabled, the address steps down a row at a time when asked to.
+        ppu.cpu_write(0x00, 0x04);
+        ppu.cpu_write(0x06, 0x3F);
+        ppu.cpu_write(0x06, 0x00);
+        ppu.cpu_read(0x07);
+        assert_eq!(ppu.vram_addr.0, 0x3F20);
+
+        // Writes past the end of the address space wrap back around to the pattern tables.
+        ppu.cpu_write(0x00, 0x00);
+        ppu.cpu_write(0x06, 0x3F);
+        ppu.cpu_write(0x06, 0xFF);
+        ppu.cpu_write(0x07, 0x01);
+        ppu.cpu_write(0x07, 0x42);
+        assert_eq!(ppu.ppu_read(0x0000), 0x42);
+    }
+
+    #[test]
+    fn ppudata_palette_mirror_reads() {
+        let ppu = setup();
+        let mut ppu = ppu.borrow_mut();
+
+        ppu.cpu_write(0x06, 0x2F);
+        ppu.cpu_write(0x06, 0xF5);
+        ppu.cpu_write(0x07, 0x66);
+        ppu.cpu_write(0x06, 0x3F);
+        ppu.cpu_write(0x06, 0x15);
+        ppu.cpu_write(0x07, 0x1A);
+
+        // The last mirror of palette RAM covers up the end of the last nametable, so reading it
+        // fills the buffer from there.
+        ppu.cpu_write(0x06, 0x3F);
+        ppu.cpu_write(0x06, 0xF5);
+        assert_eq!(ppu.cpu_read(0x07), 0x1A);
+        assert_eq!(ppu.ppu_data_buffer, 0x66);
+        assert_eq!(ppu.ppu_read(0x2FF5), 0x66);
+    }
+
+    #[test]
+    fn overclock_scanlines_extend_vblank() {
+        let ppu = setup();
+        let mut ppu = ppu.borrow_mut();
+
+        // Returns the number of dots until the next frame, and whether any were overclocked.
+        let frame_length = |ppu: &mut Ppu| {
+            let mut dots = 0;
+            let mut saw_overclock = false;
+            ppu.is_frame_ready = false;
+            while !ppu.is_frame_ready {
+                ppu.clock();
+                dots += 1;
+                if ppu.is_overclocking() {
+                    // Vblank should stay set throughout the extra scanlines.
+                    assert!(ppu.status.vblank());
+                    saw_overclock = true;
+                }
+            }
+            (dots, saw_overclock)
+        };
+
+        // Skip the partial first frame, then measure a pair of odd and even frames.
+        frame_length(&mut ppu);
+        let normal = frame_length(&mut ppu).0 + frame_length(&mut ppu).0;
+
+        ppu.set_overclock_scanlines(10);
+        let (odd, saw_overclock) = frame_length(&mut ppu);
+        let (even, _) = frame_length(&mut ppu);
+
+        // Each extra scanline should take as long as a regular scanline, which starts at dot 1.
+        assert!(saw_overclock);
+        assert_eq!(odd + even - normal, 2 * 10 * 340);
+    }
+
+    #[test]
+    fn vram_access_while_rendering() {
+        let ppu = setup();
+        let mut ppu = ppu.borrow_mut();
+        for (i, color) in [0x0F, 0x16, 0x2A, 0x12].into_iter().enumerate() {
+            ppu.cpu_write(0x06, 0x3F);
+            ppu.cpu_write(0x06, i as u8);
+            ppu.cpu_write(0x07, color);
+        }
+
+        // With rendering disabled, the backdrop shows the palette entry being pointed at.
+        let pixel = |ppu: &Ppu, x: usize| ppu.buffer()[(20 * 256 + x) * 3..][..3].to_vec();
+        let decode = |color: u8| {
+            let color = Color::decode(color);
+            vec![color.r, color.g, color.b]
+        };
+        while ppu.scanline != 20 || ppu.dot() != 1 {
+            ppu.clock();
+        }
+        for i in 0..4 {
+            ppu.cpu_write(0x06, 0x3F);
+            ppu.cpu_write(0x06, i);
+            for _ in 0..8 {
+                ppu.clock();
+            }
+        }
+        let colors: Vec<_> = (0..4).map(|i| pixel(&ppu, i * 8 + 4)).collect();
+        let expected: Vec<_> = [0x0F, 0x16, 0x2A, 0x12].map(decode).to_vec();
+        assert_eq!(colors, expected);
+
+        // While rendering, accesses bump the coarse X and Y scroll instead of the address.
+        ppu.mask = PpuMask::new().with_show_background(true);
+        ppu.vram_addr = VramAddress::default().with_coarse_x(3).with_fine_y(7);
+        ppu.cpu_write(0x07, 0x00);
+        assert_eq!(ppu.vram_addr.coarse_x(), 4);
+        assert_eq!(ppu.vram_addr.coarse_y(), 1);
+        assert_eq!(ppu.vram_addr.fine_y(), 0);
+    }
+
+    #[test]
+    fn garbled_address_frames() {
+        // Draws a checkerboard of tiles, writing to PPUDATA partway down the first frame, and
+        // returns the checksums of that frame and the one after it.
+        let render = |writes: usize| {
+            let ppu = setup();
+            let mut ppu = ppu.borrow_mut();
+            // Tile 1 is solid in color 1.
+            ppu.cpu_write(0x06, 0x00);
+            ppu.cpu_write(0x06, 0x10);
+            for _ in 0..8 {
+                ppu.cpu_write(0x07, 0xFF);
+            }
+            ppu.cpu_write(0x06, 0x20);
+            ppu.cpu_write(0x06, 0x00);
+            for i in 0..960 {
+                ppu.cpu_write(0x07, ((i / 32 + i % 32) % 2) as u8);
+            }
+            ppu.cpu_write(0x06, 0x3F);
+            ppu.cpu_write(0x06, 0x00);
+            ppu.cpu_write(0x07, 0x0F);
+            ppu.cpu_write(0x07, 0x30);
+            ppu.cpu_write(0x06, 0x00);
+            ppu.cpu_write(0x06, 0x00);
+            ppu.cpu_write(0x01, 0x0A);
+
+            let next_frame = |ppu: &mut Ppu| {
+                while !ppu.is_frame_ready {
+                    ppu.clock();
+                }
+                ppu.is_frame_ready = false;
+            };
+            next_frame(&mut ppu);
+            while ppu.scanline != 100 {
+                ppu.clock();
+            }
+            for _ in 0..writes {
+                ppu.cpu_write(0x07, 0x01);
+                for _ in 0..64 {
+                    ppu.clock();
+                }
+            }
+            next_frame(&mut ppu);
+            let garbled = crate::crc32(ppu.buffer());
+            next_frame(&mut ppu);
+            (garbled, crate::crc32(ppu.buffer()))
+        };
+
+        let (clean, _) = render(0);
+        let (garbled, corrupted) = render(3);
+        // The writes scroll the rest of the frame.
+        assert_ne!(garbled, clean);
+        // And land in the nametable wherever rendering had the address pointed, so the next
+        // frame still shows them.
+        assert_ne!(corrupted, clean);
+        assert_eq!(clean, 0x5D28235A);
+        assert_eq!(garbled, 0xB234547A);
+        assert_eq!(corrupted, 0x33E5B810);
+    }
+
+    #[test]
+    fn mask_delay() {
+        let ppu = setup();
+        let mut ppu = ppu.borrow_mut();
+
+        // Rendering turns on 3 dots after the write, while grayscale applies right away.
+        ppu.cpu_write(0x01, 0x19);
+        assert!(ppu.mask.grayscale());
+        for _ in 0..MASK_DELAY {
+            assert!(!ppu.mask.show_background());
+            ppu.clock();
+        }
+        ppu.clock();
+        assert!(ppu.mask.show_background() && ppu.mask.show_sprites());
+
+        // Savestates made in between keep the written value.
+        ppu.cpu_write(0x01, 0x00);
+        assert!(ppu.mask.show_background());
+        assert_eq!(ppu.next_mask().0, 0x00);
+    }
+
+    #[test]
+    fn sprite_flicker() {
+        let ppu = setup();
+        let mut ppu = ppu.borrow_mut();
+        // 10 sprites on scanline 20, and the rest off-screen.
+        ppu.oam.fill(0xFF);
+        for sprite in 0..10 {
+            ppu.oam[sprite * 4] = 16;
+        }
+        let evaluated_sprites = |ppu: &mut Ppu, frame| {
+            ppu.frame_count = frame;
+            ppu.scanline = 20;
+            ppu.cycle = 64;
+            ppu.evaluate_sprites();
+            ppu.cycle = 257;
+            ppu.evaluate_sprites();
+            ppu.secondary_oam_index
+        };
+
+        assert_eq!(evaluated_sprites(&mut ppu, 0), [0, 1, 2, 3, 4, 5, 6, 7]);
+        ppu.set_sprite_flicker_seed(Some(1));
+        let frames: Vec<_> = (0..8)
+            .map(|frame| evaluated_sprites(&mut ppu, frame))
+            .collect();
+        assert!(frames.iter().all(|sprites| sprites[0] == 0));
+        assert!(frames.iter().any(|sprites| sprites.contains(&9)));
+        // The same seed and frame always give the same sprites.
+        assert_eq!(evaluated_sprites(&mut ppu, 3), frames[3]);
+    }
+
+    #[test]
+    fn sprite_evaluation_timing() {
+        let ppu = setup();
+        let mut ppu = ppu.borrow_mut();
+        ppu.oam.fill(0xFF);
+        // Sprites 0 and 1 are on scanline 20, so sprite 40 is checked at dot
+        // 65 + 2 * 8 + 38 * 2 = 157.
+        ppu.oam[0] = 16;
+        ppu.oam[4] = 16;
+        let evaluated_sprites = |ppu: &mut Ppu, moved_at: u16| {
+            ppu.oam[40 * 4] = 0xFF;
+            ppu.scanline = 20;
+            for cycle in 64..=257 {
+                if cycle == moved_at {
+                    ppu.oam[40 * 4] = 16;
+                }
+                ppu.cycle = cycle;
+                ppu.evaluate_sprites();
+            }
+            ppu.secondary_oam_index[..ppu.secondary_oam_sprite_count as usize].to_vec()
+        };
+
+        assert_eq!(evaluated_sprites(&mut ppu, 200), [0, 1, 40]);
+        ppu.set_sprite_evaluation_timing(true);
+        assert_eq!(evaluated_sprites(&mut ppu, 157), [0, 1, 40]);
+        assert_eq!(evaluated_sprites(&mut ppu, 158), [0, 1]);
+    }
+
+    #[test]
+    fn a12_filter() {
+        // An MMC3 cartridge with CHR RAM that counts IRQs in $10, with the background and sprites
+        // both fetched from the pattern table at $1000 or just the sprites, depending on $11.
+        let program = crate::cpu::assemble(
+            0xE000,
+            "LDA #5
+            STA $C000
+            STA $C001
+            STA $E001
+            LDA $11
+            STA $2000
+            LDA #$18
+            STA $2001
+            CLI
+            loop: JMP loop
+            irq: INC $10
+            STA $E000
+            STA $E001
+            RTI",
+        )
+        .unwrap();
+        let mut rom = vec![
+            0x4E, 0x45, 0x53, 0x1A, 2, 0, 0x40, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let mut prg = vec![0; 32 * 1024];
+        prg[0x6000..0x6000 + program.len()].copy_from_slice(&program);
+        let irq = 0xE000 + program.len() as u16 - 9;
+        prg[0x7FFA..].copy_from_slice(&[0x00, 0xE0, 0x00, 0xE0, irq as u8, (irq >> 8) as u8]);
+        rom.extend(prg);
+        let irqs = |control: u8, has_a12_filter: bool| {
+            let system = crate::System::new(Cartridge::new(&rom).unwrap());
+            system.bus.borrow_mut().cpu_write(0x11, control);
+            system.ppu.borrow_mut().set_a12_filter(has_a12_filter);
+            for _ in 0..2 {
+                system.run_frame(Default::default(), |_, _| {});
+            }
+            let irqs = system.bus.borrow_mut().cpu_read(0x10);
+            irqs
+        };
+
+        // With only the sprites at $1000, A12 rises once per scanline, so both models agree.
+        assert!(irqs(0x08, false) > 0);
+        assert_eq!(irqs(0x08, true), irqs(0x08, false));
+        // With both at $1000, A12 never stays low for long enough while rendering.
+        assert!(irqs(0x18, false) > 0);
+        assert_eq!(irqs(0x18, true), 0);
+    }
+
+    #[test]
+    fn inspect_pixel() {
+        let ppu = setup();
+        let mut ppu = ppu.borrow_mut();
+        ppu.set_pixel_inspection(true);
+        // Clear palette RAM so that every pixel reports color $00.
+        ppu.set_power_up_palette([0; 32]);
+
+        // Fill the low bit plane of tile 1 so that every pixel in it is opaque.
+        ppu.cpu_write(0x06, 0x00);
+        ppu.cpu_write(0x06, 0x10);
+        for _ in 0..8 {
+            ppu.cpu_write(0x07, 0xFF);
+        }
+        // Place tile 1 at column 3, row 2 of the first nametable.
+        ppu.cpu_write(0x06, 0x20);
+        ppu.cpu_write(0x06, 0x43);
+        ppu.cpu_write(0x07, 0x01);
+        // Place sprite 5 using tile 1 and palette 5 at (48, 100).
+        ppu.cpu_write(0x03, 5 * 4);
+        for data in [100, 0x01, 0x01, 48] {
+            ppu.cpu_write(0x04, data);
+        }
+        // Reset the scroll and enable rendering, including the leftmost column.
+        ppu.cpu_write(0x00, 0x00);
+        ppu.cpu_write(0x05, 0x00);
+        ppu.cpu_write(0x05, 0x00);
+        ppu.cpu_write(0x01, 0x1E);
+
+        // Skip the partial first frame.
+        for _ in 0..2 {
+            ppu.is_frame_ready = false;
+            while !ppu.is_frame_ready {
+                ppu.clock();
+            }
+        }
+
+        assert_eq!(
+            ppu.inspect_pixel(28, 20),
+            Some(PixelSource::Background {
+                nametable_addr: 0x2043,
+                tile: 1,
+                pattern_addr: 0x0010,
+                palette: 0,
+                color: 0,
+            })
+        );
+        assert_eq!(
+            ppu.inspect_pixel(8, 20),
+            Some(PixelSource::Backdrop { color: 0 })
+        );
+        // Sprites are drawn a scanline below their Y position.
+        assert_eq!(
+            ppu.inspect_pixel(50, 104),
+            Some(PixelSource::Sprite {
+                oam_index: 5,
+                tile: 1,
+                pattern_addr: 0x0010,
+                palette: 5,
+                color: 0,
+            })
+        );
+        assert_eq!(ppu.inspect_pixel(256, 0), None);
+    }
+
+    #[test]
+    fn render_scanline() {
+        let ppu = setup();
+        let mut ppu = ppu.borrow_mut();
+        ppu.set_pixel_inspection(true);
+        // Give each palette entry a different color so they can be told apart.
+        ppu.set_power_up_palette(std::array::from_fn(|i| i as u8));
+
+        // Tile 1 has opaque pixels on its left half, and tile 2 on its right half.
+        ppu.cpu_write(0x06, 0x00);
+        ppu.cpu_write(0x06, 0x10);
+        for data in [0xF0; 8].into_iter().chain([0x00; 8]).chain([0x0F; 16]) {
+            ppu.cpu_write(0x07, data);
+        }
+        // Fill the first two rows of the first nametable with both tiles, and give them palette
+        // 2, wrapping into the second nametable.
+        ppu.cpu_write(0x06, 0x20);
+        ppu.cpu_write(0x06, 0x00);
+        for column in 0..64 {
+            ppu.cpu_write(0x07, column % 2 + 1);
+        }
+        ppu.cpu_write(0x06, 0x23);
+        ppu.cpu_write(0x06, 0xC0);
+        ppu.cpu_write(0x07, 0xAA);
+        // Place sprite 0 behind the background, and sprite 1 in front of it overlapping it. The
+        // rest are moved off screen.
+        ppu.cpu_write(0x03, 0x00);
+        for data in [4, 0x02, 0x21, 20, 4, 0x01, 0x43, 16]
+            .into_iter()
+            .chain([0xFF; 248])
+        {
+            ppu.cpu_write(0x04, data);
+        }
+        // Scroll 5 pixels to the right and enable rendering, hiding the leftmost sprite column.
+        ppu.cpu_write(0x00, 0x00);
+        ppu.cpu_write(0x05, 0x05);
+        ppu.cpu_write(0x05, 0x00);
+        ppu.cpu_write(0x01, 0x1A);
+
+        // Skip the partial first frame.
+        for _ in 0..2 {
+            ppu.is_frame_ready = false;
+            while !ppu.is_frame_ready {
+                ppu.clock();
+            }
+        }
+
+        // Every pixel should match what the PPU drew.
+        for scanline in [0, 5, 8, 100, 239] {
+            let render = ppu.render_scanline(scanline).unwrap();
+            for (x, pixel) in render.pixels.iter().enumerate() {
+                assert_eq!(
+                    Some(pixel.source),
+                    ppu.inspect_pixel(x as u16, scanline),
+                    "scanline {scanline}, x {x}"
+                );
+            }
+        }
+
+        let render = ppu.render_scanline(5).unwrap();
+        assert_eq!(render.sprites, [0, 1]);
+        let pixel = render.pixels[20];
+        assert_eq!(pixel.background.nametable_addr, 0x2003);
+        assert_eq!(pixel.background.attribute_addr, 0x23C0);
+        assert_eq!(pixel.background.palette, 2);
+        assert_eq!(pixel.sprite.map(|sprite| sprite.oam_index), Some(1));
+        assert!(!pixel.is_sprite_zero_hit);
+        assert!(ppu.render_scanline(240).is_none());
+    }
+
+    #[test]
+    fn validation_warnings() {
+        let ppu = setup();
+        let mut ppu = ppu.borrow_mut();
+        ppu.set_validation(true);
+
+        // Accesses while rendering is disabled are always safe.
+        ppu.cpu_write(0x07, 0x00);
+        assert!(ppu.drain_warnings().is_empty());
+
+        ppu.cpu_write(0x01, 0x18);
+        while ppu.scanline() != 100 {
+            ppu.clock();
+        }
+        ppu.cpu_write(0x06, 0x3F);
+        ppu.cpu_write(0x06, 0x01);
+        ppu.cpu_write(0x07, 0x00);
+        ppu.cpu_write(0x4014, 0x02);
+
+        let warnings = ppu.drain_warnings();
+        assert_eq!(
+            warnings
+                .iter()
+                .map(|warning| warning.kind)
+                .collect::<Vec<_>>(),
+            [
+                PpuWarningKind::PaletteWriteWhileRendering { addr: 0x3F01 },
+                PpuWarningKind::OamDmaWhileRendering
+            ]
+        );
+        assert_eq!(warnings[0].scanline, 100);
+        assert!(ppu.drain_warnings().is_empty());
+
+        // Nothing is reported during vblank.
+        while ppu.scanline() != 250 {
+            ppu.clock();
+        }
+        ppu.cpu_read(0x07);
+        assert!(ppu.drain_warnings().is_empty());
+    }
+
+    #[test]
+    fn power_up_palette() {
+        let ppu = setup();
+        let mut ppu = ppu.borrow_mut();
+        assert_eq!(ppu.palette_ram()[0x0F], 0x2C);
+        // The sprite palettes' transparent colors mirror the background's.
+        assert_eq!(ppu.palette_ram()[0x14], POWER_UP_PALETTE[0x04]);
+
+        ppu.set_power_up_palette([0xFF; 32]);
+        assert_eq!(ppu.palette_ram(), [0x3F; 32]);
+    }
+
+    #[test]
+    fn color_emphasis() {
+        let color = Color::decode(0x30);
+        assert_eq!(color.emphasize(0x30, false, false, false), color);
+
+        let emphasized = color.emphasize(0x30, true, false, false);
+        assert_eq!(emphasized.r, color.r);
+        assert!(emphasized.g < color.g && emphasized.b < color.b);
+        // Black is unaffected.
+        let black = Color::decode(0x0F);
+        assert_eq!(black.emphasize(0x0F, false, true, true), black);
+    }
+
+    #[test]
+    fn memory_snapshots() {
+        let ppu = setup();
+        let mut ppu = ppu.borrow_mut();
+
+        // With horizontal mirroring, $2800 is backed by the second half of nametable RAM.
+        for (addr, data) in [
+            (0x2005, 0x11),
+            (0x2805, 0x22),
+            (0x3F00, 0x0F),
+            (0x3F05, 0x30),
+        ] {
+            ppu.cpu_write(0x06, (addr >> 8) as u8);
+            ppu.cpu_write(0x06, addr as u8);
+            ppu.cpu_write(0x07, data);
+        }
+        ppu.cpu_write(0x03, 0x10);
+        ppu.cpu_write(0x04, 0x42);
+
+        assert_eq!(ppu.nametable_ram()[0x005], 0x11);
+        assert_eq!(ppu.nametable_ram()[0x405], 0x22);
+        let palette_ram = ppu.palette_ram();
+        assert_eq!(palette_ram[0x00], 0x0F);
+        assert_eq!(palette_ram[0x10], 0x0F);
+        assert_eq!(palette_ram[0x05], 0x30);
+        assert_eq!(ppu.oam()[0x10], 0x42);
+        assert_eq!(ppu.cartridge.borrow().banked_chr().len(), 0x2000);
+    }
+
+    #[test]
+    fn palette_lookup_mirrors() {
+        let ppu = setup();
+        let mut ppu = ppu.borrow_mut();
+
+        // $3F10 mirrors $3F00, so the sprite palette's transparent color follows the backdrop.
+        ppu.ppu_write(0x3F10, 0x21);
+        assert_eq!(ppu.sample_palette_ram(0, 0), 0x21);
+        assert_eq!(ppu.sample_palette_ram(4, 0), 0x21);
+
+        ppu.ppu_write(0x3F15, 0x16);
+        assert_eq!(ppu.sample_palette_ram(5, 1), 0x16);
+        assert_eq!(ppu.sample_palette_ram(1, 1), POWER_UP_PALETTE[0x05]);
+    }
+
+    #[test]
+    fn nametable_mirroring() {
+        let ppu = setup();
+        let mut ppu = ppu.borrow_mut();
+        assert_eq!(ppu.mirroring(), Mirroring::Horizontal);
+        assert_eq!(ppu.nametable_pages(), [0, 0, 1, 1]);
+
+        // Nametables backed by the same page read back each other's writes.
+        ppu.ppu_write(0x2C05, 0x42);
+        assert_eq!(ppu.ppu_read(0x2805), 0x42);
+        assert_eq!(ppu.ppu_read(0x2405), 0x00);
+        assert_eq!(ppu.nametable_ram()[0x405], 0x42);
+    }
+
+    #[test]
+    fn half_horizontal_resolution() {
+        let ppu = setup();
+        let mut ppu = ppu.borrow_mut();
+        ppu.set_pixel_format(PixelFormat::Rgb24);
+
+        // Give tile 1 opaque pixels in every even column, and place it in the top left corner.
+        ppu.cpu_write(0x06, 0x00);
+        ppu.cpu_write(0x06, 0x10);
+        for _ in 0..8 {
+            ppu.cpu_write(0x07, 0xAA);
+        }
+        ppu.cpu_write(0x06, 0x20);
+        ppu.cpu_write(0x06, 0x00);
+        ppu.cpu_write(0x07, 0x01);
+        ppu.cpu_write(0x06, 0x3F);
+        ppu.cpu_write(0x06, 0x00);
+        ppu.cpu_write(0x07, 0x0F);
+        ppu.cpu_write(0x07, 0x30);
+        ppu.cpu_write(0x00, 0x00);
+        ppu.cpu_write(0x05, 0x00);
+        ppu.cpu_write(0x05, 0x00);
+        ppu.cpu_write(0x01, 0x0A);
+
+        let first_row = |ppu: &mut Ppu| {
+            for _ in 0..2 {
+                ppu.is_frame_ready = false;
+                while !ppu.is_frame_ready {
+                    ppu.clock();
+                }
+            }
+            let [black, white] = [0x0F, 0x30].map(|index| Color::decode(index).r);
+            ppu.buffer()[..8 * 3]
+                .iter()
+                .step_by(3)
+                .map(|&r| match r {
+                    r if r == black => 0,
+                    r if r == white => 1,
+                    _ => panic!("unexpected color"),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(first_row(&mut ppu), [1, 0, 1, 0, 1, 0, 1, 0]);
+        // Each even pixel covers the odd pixel after it.
+        ppu.set_half_horizontal_resolution(true);
+        assert_eq!(first_row(&mut ppu), [1; 8]);
+    }
+
+    #[test]
+    fn pixel_format_conversion() {
+        let ppu = setup();
+        let mut ppu = ppu.borrow_mut();
+        ppu.draw_pixel(1, 0, Color { r: 1, g: 2, b: 3 });
+
+        ppu.set_pixel_format(PixelFormat::Rgba32);
+        assert_eq!(ppu.buffer()[4..8], [1, 2, 3, 0xFF]);
+        ppu.set_pixel_format(PixelFormat::Rgb24);
+        assert_eq!(ppu.buffer()[3..6], [1, 2, 3]);
+    }
+
+    fn setup() -> Rc<RefCell<Ppu>> {
+        // An empty cartridge with CHR RAM.
+        let cartridge = Cartridge::from_raw_prg(&[], 0x8000, [0; 3]).unwrap();
+        Rc::new(RefCell::new(Ppu::new(Rc::new(RefCell::new(cartridge)))))
+    }
+}
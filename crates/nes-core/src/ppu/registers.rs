@@ -0,0 +1,154 @@
+//! The PPU's memory-mapped registers, as seen by the CPU at $2000-$2007 and $4014.
+
+use super::{Ppu, PpuMask, PpuWarningKind, MASK_DELAY};
+
+impl Ppu {
+    /// Reads the PPU's various registers. Accessible from the CPU.
+    pub fn cpu_read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x00 => 0, // PPUCTRL; not readable.
+            0x01 => 0, // PPUMASK; not readable.
+            // PPUSTATUS.
+            0x02 => {
+                // Only the top 3 bits are meaningful. The other 5 contain stale PPU bus data.
+                let data = (self.status.0 & 0xE0) | (self.ppu_data_buffer & 0x1F);
+                self.status.set_vblank(false);
+                self.addr_latch = 0;
+
+                data
+            }
+            0x03 => 0,                                // OAMADDR; not readable.
+            0x04 => self.oam[self.oam_addr as usize], // OAMDATA.
+            0x05 => 0,                                // PPUSCROLL; not readable.
+            0x06 => 0,                                // PPUADDR; not readable.
+            // PPUDATA.
+            0x07 => {
+                if self.is_validating && self.is_rendering() {
+                    self.warn(PpuWarningKind::VramAccessWhileRendering {
+                        addr: self.vram_addr.0,
+                    });
+                }
+
+                // Data is delayed one read cycle. As such, the data returned is the data requested
+                // the previous read.
+                let addr = self.vram_addr.0 & 0x3FFF;
+                let data = if addr >= 0x3F00 {
+                    // The data delay applies to all memory locations except palette RAM, which is
+                    // returned directly. The buffer is still filled, but with the nametable byte
+                    // the palette covers up.
+                    self.ppu_data_buffer = self.ppu_read(addr - 0x1000);
+                    self.apply_grayscale(self.ppu_read(addr))
+                } else {
+                    let data = self.ppu_data_buffer;
+                    self.ppu_data_buffer = self.ppu_read(addr);
+                    data
+                };
+
+                self.increment_vram_addr();
+                data
+            }
+            0x4014 => 0, // OAMDMA; not readable.
+            _ => 0,
+        }
+    }
+
+    /// Writes to the PPU's various registers. Accessible from the CPU.
+    pub fn cpu_write(&mut self, addr: u16, data: u8) {
+        if self.is_warming_up && matches!(addr, 0x00 | 0x01 | 0x05 | 0x06) {
+            return;
+        }
+        match addr {
+            // PPUCTRL.
+            0x00 => {
+                self.control.0 = data;
+                self.temp_vram_addr.set_nametable_x(data as u16 & 0b01);
+                self.temp_vram_addr
+                    .set_nametable_y((data as u16 & 0b10) >> 1);
+            }
+            // PPUMASK. Enabling or disabling rendering takes a few dots to take effect, while the
+            // other bits apply right away.
+            0x01 => {
+                let mask = PpuMask::from(data);
+                if !self.has_mask_delay {
+                    self.mask = mask;
+                    self.pending_mask = None;
+                    return;
+                }
+                self.mask = mask
+                    .with_show_background(self.mask.show_background())
+                    .with_show_sprites(self.mask.show_sprites());
+                self.pending_mask = Some((mask, MASK_DELAY));
+            }
+            0x02 => (),                   // PPUSTATUS; not writable.
+            0x03 => self.oam_addr = data, // OAMADDR.
+            // OAMDATA.
+            0x04 => {
+                self.oam[self.oam_addr as usize] = data;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            }
+            // PPUSCROLL.
+            0x05 => {
+                if self.addr_latch == 0 {
+                    self.temp_vram_addr.set_coarse_x(data as u16 >> 3);
+                    self.fine_x_scroll = data & 0x07;
+                    self.addr_latch = 1;
+                } else {
+                    self.temp_vram_addr.set_coarse_y(data as u16 >> 3);
+                    self.temp_vram_addr.set_fine_y(data as u16 & 0x07);
+                    self.addr_latch = 0;
+                }
+            }
+            // PPUADDR:
+            0x06 => {
+                // The CPU requires 2 writes to set the PPU's address.
+                if self.addr_latch == 0 {
+                    self.temp_vram_addr.0 =
+                        (self.temp_vram_addr.0 & !0xFF00) | ((data as u16 & 0x3F) << 8);
+                    self.addr_latch = 1;
+                } else {
+                    self.temp_vram_addr.0 = (self.temp_vram_addr.0 & !0x00FF) | data as u16;
+                    self.vram_addr = self.temp_vram_addr;
+                    self.addr_latch = 0;
+                }
+            }
+            // PPUDATA.
+            0x07 => {
+                if self.is_validating && self.is_rendering() {
+                    let addr = self.vram_addr.0;
+                    self.warn(if addr >= 0x3F00 {
+                        PpuWarningKind::PaletteWriteWhileRendering { addr }
+                    } else {
+                        PpuWarningKind::VramAccessWhileRendering { addr }
+                    });
+                }
+                self.ppu_write(self.vram_addr.0 & 0x3FFF, data);
+                self.increment_vram_addr();
+            }
+            // OAMDMA. The transfer itself is run by the bus; the PPU only checks its timing.
+            0x4014 if self.is_validating && self.is_rendering() => {
+                self.warn(PpuWarningKind::OamDmaWhileRendering);
+            }
+            _ => (),
+        }
+    }
+
+    /// Advances the address after a PPUDATA access, horizontally or vertically depending on the
+    /// control register. The address register is 15 bits wide, so it wraps rather than overflows.
+    ///
+    /// While rendering, the access instead bumps both the coarse X and Y scroll at once, as the
+    /// address register is busy being used for rendering. This garbles the scroll and makes
+    /// further accesses land all over VRAM, which some demos use on purpose.
+    fn increment_vram_addr(&mut self) {
+        if self.is_rendering() {
+            self.increment_x_scroll();
+            self.increment_y_scroll();
+            return;
+        }
+        let increment = if self.control.address_increment() == 0 {
+            1
+        } else {
+            32
+        };
+        self.vram_addr.0 = self.vram_addr.0.wrapping_add(increment) & 0x7FFF;
+    }
+}
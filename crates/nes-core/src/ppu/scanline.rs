@@ -0,0 +1,257 @@
+//! Re-rendering a single scanline on demand, with the details of every pixel, for tracking down
+//! glitches confined to a scanline or two without stepping through the frame.
+
+use super::{PixelSource, Ppu, PpuControl, PpuMask, VramAddress};
+
+/// The registers that decide what a scanline shows, as they were when its rendering started.
+#[derive(Debug, Default, Clone, Copy)]
+pub(super) struct ScanlineRegisters {
+    vram_addr: VramAddress,
+    fine_x_scroll: u8,
+    control: PpuControl,
+    mask: PpuMask,
+}
+
+/// A scanline re-rendered by [Ppu::render_scanline].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanlineRender {
+    pub scanline: u16,
+    /// The OAM index of every sprite in range of the scanline, in OAM order. Only the first 8 are
+    /// drawn; the rest are dropped, as with sprite overflow.
+    pub sprites: Vec<u8>,
+    /// The scanline's 256 pixels, from left to right.
+    pub pixels: Vec<ScanlinePixel>,
+}
+
+/// Everything that went into a pixel of a [ScanlineRender].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanlinePixel {
+    pub background: BackgroundPixel,
+    /// The frontmost sprite with an opaque pixel here, whether or not it's drawn in front of the
+    /// background.
+    pub sprite: Option<SpritePixel>,
+    /// Which layer the pixel is drawn from, and its final palette RAM value.
+    pub source: PixelSource,
+    /// Whether sprite 0 and the background are both opaque here, which sets sprite 0 hit.
+    pub is_sprite_zero_hit: bool,
+}
+
+/// The background tile under a pixel of a [ScanlinePixel], which is looked up even when the
+/// background is hidden.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackgroundPixel {
+    /// The address of the tile's nametable entry, from $2000 to $2FFF.
+    pub nametable_addr: u16,
+    pub tile: u8,
+    /// The address of the attribute table byte holding the tile's palette.
+    pub attribute_addr: u16,
+    /// The background palette, from 0 to 3.
+    pub palette: u8,
+    /// The address of the tile's first byte in the pattern tables.
+    pub pattern_addr: u16,
+    /// The pixel's 2-bit value in the tile, or 0 if the background is hidden or clipped here.
+    pub pattern: u8,
+}
+
+/// The sprite drawn at a pixel of a [ScanlinePixel].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpritePixel {
+    /// The sprite's index in OAM, from 0 to 63.
+    pub oam_index: u8,
+    pub tile: u8,
+    /// The sprite's attribute byte, holding its palette, priority, and flips.
+    pub attributes: u8,
+    /// The address of the tile's first byte in the pattern tables. For 8x16 sprites, this is
+    /// whichever of the two tiles the pixel is in.
+    pub pattern_addr: u16,
+    /// The pixel's 2-bit value in the tile, which is never 0.
+    pub pattern: u8,
+}
+
+impl Ppu {
+    /// Saves the registers the next visible scanline will be rendered with. Called at the end of
+    /// each scanline's sprite fetches, after the scroll position for the next one has been set.
+    pub(super) fn record_scanline_registers(&mut self) {
+        let next_scanline = if self.scanline == self.timing.pre_render_scanline() {
+            0
+        } else if self.scanline < 239 {
+            self.scanline + 1
+        } else {
+            return;
+        };
+        self.scanline_registers[next_scanline as usize] = ScanlineRegisters {
+            vram_addr: self.vram_addr,
+            fine_x_scroll: self.fine_x_scroll,
+            control: self.control,
+            mask: self.mask,
+        };
+    }
+
+    /// Renders a visible scanline again without running the PPU, reporting the tile, palette, and
+    /// sprite behind each of its pixels. Returns `None` if the scanline isn't visible.
+    ///
+    /// The scroll position and PPUCTRL and PPUMASK settings are the ones the scanline was last
+    /// rendered with, but the pattern tables, nametables, palettes, and OAM are read as they are
+    /// now. Calling this right after [crate::Bus::run_frame] shows the frame that was just drawn,
+    /// unless the game changed them partway through it, such as by switching CHR banks. Sprites
+    /// are taken in OAM order, without sprite flicker.
+    pub fn render_scanline(&self, scanline: u16) -> Option<ScanlineRender> {
+        let registers = *self.scanline_registers.get(scanline as usize)?;
+        let (control, mask) = (registers.control, registers.mask);
+
+        // Sprites are found on the scanline before the one they're drawn on.
+        let evaluated_scanline = match scanline {
+            0 => self.timing.pre_render_scanline(),
+            _ => scanline - 1,
+        };
+        let sprite_height = (control.sprite_size() as u16 + 1) * 8;
+        let sprites: Vec<u8> = (0..64)
+            .filter(|&sprite| {
+                let y_pos = self.oam[sprite as usize * 4];
+                evaluated_scanline.wrapping_sub(y_pos as u16) < sprite_height
+            })
+            .collect();
+
+        let pixels = (0..256)
+            .map(|x| {
+                let background = self.scanline_background_pixel(registers, x);
+                let sprite = sprites
+                    .iter()
+                    .take(8)
+                    .find_map(|&sprite| {
+                        self.scanline_sprite_pixel(control, sprite, x, evaluated_scanline)
+                    })
+                    .filter(|_| mask.show_sprites() && (x >= 8 || mask.show_left_sprite_tiles()));
+
+                let is_sprite_in_front = sprite.is_some_and(|sprite| {
+                    background.pattern == 0 || sprite.attributes & (1 << 5) == 0
+                });
+                let source = match sprite {
+                    Some(sprite) if is_sprite_in_front => {
+                        let palette = (sprite.attributes & 0x03) + 4;
+                        PixelSource::Sprite {
+                            oam_index: sprite.oam_index,
+                            tile: sprite.tile,
+                            pattern_addr: sprite.pattern_addr,
+                            palette,
+                            color: self.sample_palette_ram(palette, sprite.pattern),
+                        }
+                    }
+                    _ if background.pattern != 0 => PixelSource::Background {
+                        nametable_addr: background.nametable_addr,
+                        tile: background.tile,
+                        pattern_addr: background.pattern_addr,
+                        palette: background.palette,
+                        color: self.sample_palette_ram(background.palette, background.pattern),
+                    },
+                    _ => PixelSource::Backdrop {
+                        color: self.sample_palette_ram(0, 0),
+                    },
+                };
+                // Like the PPU, sprite 0 stops being checked for hits 2 pixels from the right edge.
+                let is_sprite_zero_hit = background.pattern != 0
+                    && sprite.is_some_and(|sprite| sprite.oam_index == 0)
+                    && x < 254;
+
+                ScanlinePixel {
+                    background,
+                    sprite,
+                    source,
+                    is_sprite_zero_hit,
+                }
+            })
+            .collect();
+
+        Some(ScanlineRender {
+            scanline,
+            sprites,
+            pixels,
+        })
+    }
+
+    /// Looks up the background tile under pixel `x`, the same way the background pipeline fetches
+    /// it, starting from the scroll position the scanline was rendered with.
+    fn scanline_background_pixel(&self, registers: ScanlineRegisters, x: u16) -> BackgroundPixel {
+        let addr = registers.vram_addr;
+        let offset = registers.fine_x_scroll as u16 + x;
+        let column = addr.coarse_x() + offset / 8;
+        let nametable_x = addr.nametable_x() ^ (column / 32);
+        let coarse_x = column % 32;
+
+        let nametable_addr = 0x2000
+            | (addr.nametable_y() << 11)
+            | (nametable_x << 10)
+            | (addr.coarse_y() << 5)
+            | coarse_x;
+        let attribute_addr = 0x23C0
+            | (addr.nametable_y() << 11)
+            | (nametable_x << 10)
+            | ((addr.coarse_y() >> 2) << 3)
+            | (coarse_x >> 2);
+        let shift = ((addr.coarse_y() & 0x02) << 1) | (coarse_x & 0x02);
+        let palette = (self.ppu_read(attribute_addr) >> shift) & 0x03;
+
+        let tile = self.ppu_read(nametable_addr);
+        let pattern_addr =
+            ((registers.control.background_pattern() as u16) << 12) | ((tile as u16) << 4);
+        let row = pattern_addr + addr.fine_y();
+        let bit = 7 - (offset % 8);
+        let low = (self.ppu_read(row) >> bit) & 1;
+        let high = (self.ppu_read(row + 8) >> bit) & 1;
+        let is_shown = registers.mask.show_background()
+            && (x >= 8 || registers.mask.show_left_background_tiles());
+
+        BackgroundPixel {
+            nametable_addr,
+            tile,
+            attribute_addr,
+            palette,
+            pattern_addr,
+            pattern: if is_shown { (high << 1) | low } else { 0 },
+        }
+    }
+
+    /// Returns the sprite's pixel at `x` on the scanline after `evaluated_scanline`, if it covers
+    /// it and is opaque there.
+    fn scanline_sprite_pixel(
+        &self,
+        control: PpuControl,
+        sprite: u8,
+        x: u16,
+        evaluated_scanline: u16,
+    ) -> Option<SpritePixel> {
+        let [y_pos, tile, attributes, x_pos] = self.oam[sprite as usize * 4..][..4] else {
+            unreachable!()
+        };
+        let column = x.checked_sub(x_pos as u16).filter(|&column| column < 8)?;
+        let flip_horizontally = attributes & (1 << 6) != 0;
+        let flip_vertically = attributes & (1 << 7) != 0;
+        let line = evaluated_scanline.wrapping_sub(y_pos as u16) & 0x0F;
+
+        let pattern_addr = if control.sprite_size() == 0 {
+            ((control.sprite_pattern() as u16) << 12) | ((tile as u16) << 4)
+        } else if (line < 8) != flip_vertically {
+            ((tile as u16 & 1) << 12) | ((tile as u16 & 0xFE) << 4)
+        } else {
+            ((tile as u16 & 1) << 12) | (((tile as u16 & 0xFE) + 1) << 4)
+        };
+        let line = line & 0x07;
+        let line = if flip_vertically { 7 - line } else { line };
+        let bit = if flip_horizontally {
+            column
+        } else {
+            7 - column
+        };
+        let low = (self.ppu_read(pattern_addr | line) >> bit) & 1;
+        let high = (self.ppu_read(pattern_addr | 8 | line) >> bit) & 1;
+        let pattern = (high << 1) | low;
+
+        (pattern != 0).then_some(SpritePixel {
+            oam_index: sprite,
+            tile,
+            attributes,
+            pattern_addr,
+            pattern,
+        })
+    }
+}
@@ -0,0 +1,165 @@
+//! Sprite evaluation, pattern fetches, and the per-sprite shifters.
+
+use super::Ppu;
+
+impl Ppu {
+    /// Counts down the X positions of the sprites on the current scanline, shifting out the
+    /// pattern of any sprite that has been reached.
+    pub(super) fn clock_sprite_shifters(&mut self) {
+        if self.cycle >= 2 && self.cycle <= 257 && self.mask.show_sprites() {
+            for i in 0..8 {
+                if self.sprite_x_pos[i] != 0 {
+                    self.sprite_x_pos[i] -= 1;
+                } else {
+                    self.sprite_pattern_shift_low[i] <<= 1;
+                    self.sprite_pattern_shift_high[i] <<= 1;
+                }
+            }
+        }
+        if self.cycle == 255 {
+            self.is_sprite_zero_active = false;
+        }
+    }
+
+    /// Clears secondary OAM, finds the sprites on the next scanline, and fetches their patterns.
+    pub(super) fn evaluate_sprites(&mut self) {
+        if self.cycle == 64 {
+            self.secondary_oam = [0xFF; 32];
+            self.secondary_oam_sprite_count = 0;
+            self.sprite_candidate_count = 0;
+            self.next_evaluated_sprite = 0;
+            self.next_evaluation_dot = 65;
+        }
+        if self.has_sprite_evaluation_timing
+            && self.cycle <= 256
+            && self.cycle == self.next_evaluation_dot
+            && self.next_evaluated_sprite < 64
+        {
+            // Checking a sprite's Y position takes 2 dots, and copying the rest of one that's on
+            // the scanline takes 6 more, until secondary OAM is full.
+            let is_copied = self.sprite_candidate_count < 8;
+            let is_found = self.add_sprite_candidate(self.next_evaluated_sprite);
+            self.next_evaluated_sprite += 1;
+            self.next_evaluation_dot += if is_found && is_copied { 8 } else { 2 };
+        }
+        if self.cycle == 257 {
+            if !self.has_sprite_evaluation_timing {
+                for sprite in 0..64 {
+                    self.add_sprite_candidate(sprite);
+                }
+            }
+            let count = self.sprite_candidate_count;
+            let sprites = &mut self.sprite_candidates[..count];
+            if let Some(seed) = self.sprite_flicker_seed.filter(|_| count > 8) {
+                // Sprite 0 stays first, so that flicker can't take away a sprite 0 hit the game is
+                // waiting for.
+                let rotated = if sprites[0].0 == 0 {
+                    &mut sprites[1..]
+                } else {
+                    &mut sprites[..]
+                };
+                let rotation = flicker_rotation(seed, self.frame_count) % rotated.len() as u64;
+                rotated.rotate_left(rotation as usize);
+            }
+
+            for i in 0..count.min(8) {
+                let (sprite, bytes) = self.sprite_candidates[i];
+                if sprite == 0 {
+                    self.is_sprite_zero_active = true;
+                }
+                self.secondary_oam_index[i] = sprite;
+                self.secondary_oam[i * 4..i * 4 + 4].copy_from_slice(&bytes);
+            }
+            self.secondary_oam_sprite_count = count.min(8) as u8;
+        }
+        if self.cycle == 320 {
+            self.fetch_sprite_patterns();
+        }
+    }
+
+    /// Adds the sprite at the given index in OAM to the sprites found on the next scanline if it's
+    /// on the current one, returning whether it was.
+    fn add_sprite_candidate(&mut self, sprite: usize) -> bool {
+        if !self.is_on_scanline(sprite) {
+            return false;
+        }
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&self.oam[sprite * 4..sprite * 4 + 4]);
+        self.sprite_candidates[self.sprite_candidate_count] = (sprite as u8, bytes);
+        self.sprite_candidate_count += 1;
+        true
+    }
+
+    /// Returns whether the sprite at the given index in OAM is on the current scanline.
+    fn is_on_scanline(&self, sprite: usize) -> bool {
+        let y_pos = self.oam[sprite * 4];
+        self.scanline.wrapping_sub(y_pos as u16) < (self.control.sprite_size() as u16 + 1) * 8
+    }
+
+    /// Returns the pattern, palette, and attributes of the frontmost opaque sprite pixel at the
+    /// current dot, along with which of the 8 sprite slots it came from.
+    pub(super) fn sprite_pixel(&self) -> (u8, u8, u8, usize) {
+        for sprite in 0..8 {
+            if self.sprite_x_pos[sprite] != 0 {
+                continue;
+            }
+            let pattern_low = (self.sprite_pattern_shift_low[sprite] & 0x80 > 0) as u8;
+            let pattern_high = (self.sprite_pattern_shift_high[sprite] & 0x80 > 0) as u8;
+            let pattern = (pattern_high << 1) | pattern_low;
+            if pattern != 0 {
+                let attrib = self.sprite_attrib[sprite];
+                return (pattern, attrib & 0x03, attrib, sprite);
+            }
+        }
+        (0, 0, 0, 0)
+    }
+
+    fn fetch_sprite_patterns(&mut self) {
+        for i in 0..self.secondary_oam_sprite_count {
+            let i = i as usize;
+            self.sprite_x_pos[i] = self.secondary_oam[i * 4 + 3];
+            let y_pos = self.secondary_oam[i * 4];
+            let index = self.secondary_oam[i * 4 + 1];
+            let attrib = self.secondary_oam[i * 4 + 2];
+            let flip_horizontally = attrib & (1 << 6) != 0;
+            let flip_vertically = attrib & (1 << 7) != 0;
+            let line = (self.scanline.wrapping_sub(y_pos as u16)) & 0x0F;
+
+            let tile_addr = if self.control.sprite_size() == 0 {
+                ((self.control.sprite_pattern() as u16) << 12) | ((index as u16) << 4)
+            } else if (line < 8 && !flip_vertically) || (flip_vertically && line > 7) {
+                ((index as u16 & 1) << 12) | ((index as u16 & 0xFE) << 4)
+            } else {
+                ((index as u16 & 1) << 12) | (((index as u16 & 0xFE) + 1) << 4)
+            };
+            let line = line & 0x07;
+            let line = if flip_vertically { 7 - line } else { line };
+            let pattern_low = self.ppu_read(tile_addr | line);
+            let pattern_high = self.ppu_read(tile_addr | 8 | line);
+            let (pattern_low, pattern_high) = if flip_horizontally {
+                (pattern_low.reverse_bits(), pattern_high.reverse_bits())
+            } else {
+                (pattern_low, pattern_high)
+            };
+            self.sprite_pattern_shift_low[i] = pattern_low;
+            self.sprite_pattern_shift_high[i] = pattern_high;
+            self.sprite_attrib[i] = attrib;
+            self.sprite_oam_index[i] = self.secondary_oam_index[i];
+            self.sprite_pattern_addr[i] = tile_addr;
+        }
+        for i in self.secondary_oam_sprite_count..8 {
+            let i = i as usize;
+            self.sprite_pattern_shift_low[i] = 0;
+            self.sprite_pattern_shift_high[i] = 0;
+        }
+    }
+}
+
+/// Picks how far to rotate the order sprites are evaluated in for a frame with sprite flicker
+/// enabled, by mixing the seed and frame count with SplitMix64.
+fn flicker_rotation(seed: u64, frame: u64) -> u64 {
+    let mut x = seed.wrapping_add(frame.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
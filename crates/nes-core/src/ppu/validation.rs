@@ -0,0 +1,49 @@
+/// The most warnings kept between calls to [super::Ppu::drain_warnings]. Anything past this is
+/// dropped, as a game with a timing bug will usually repeat it every frame.
+pub(super) const MAX_WARNINGS: usize = 1024;
+
+/// A PPU access at a time when it's likely to cause glitches on real hardware, as reported while
+/// validation is enabled with [super::Ppu::set_validation].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PpuWarning {
+    pub kind: PpuWarningKind,
+    /// The number of frames the PPU had finished when the access happened.
+    pub frame: u64,
+    pub scanline: u16,
+    pub dot: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PpuWarningKind {
+    /// PPUDATA was read or written while rendering, which corrupts the scroll position instead of
+    /// accessing the intended address.
+    VramAccessWhileRendering { addr: u16 },
+    /// Palette RAM was written through PPUDATA while rendering. This is reported instead of
+    /// [PpuWarningKind::VramAccessWhileRendering] for addresses in palette RAM.
+    PaletteWriteWhileRendering { addr: u16 },
+    /// OAM DMA was started while rendering, which corrupts sprite evaluation.
+    OamDmaWhileRendering,
+    /// Vblank ended before the CPU returned from the NMI handler, so any PPU updates the handler
+    /// makes from then on happen during rendering.
+    NmiOverran,
+}
+
+impl std::fmt::Display for PpuWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "frame {}, scanline {}, dot {}: ",
+            self.frame, self.scanline, self.dot
+        )?;
+        match self.kind {
+            PpuWarningKind::VramAccessWhileRendering { addr } => {
+                write!(f, "PPUDATA accessed at ${addr:04X} while rendering")
+            }
+            PpuWarningKind::PaletteWriteWhileRendering { addr } => {
+                write!(f, "palette written at ${addr:04X} while rendering")
+            }
+            PpuWarningKind::OamDmaWhileRendering => write!(f, "OAM DMA started while rendering"),
+            PpuWarningKind::NmiOverran => write!(f, "NMI handler still running after vblank"),
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! The PPU's address space: pattern tables on the cartridge, nametables in internal RAM mirrored
+//! according to the cartridge, and palette RAM.
+
+use crate::mapper::Mirroring;
+
+use super::Ppu;
+
+/// The contents of palette RAM at power-on, as dumped from hardware by blargg's power_up_palette
+/// test. The real values vary between consoles, but some games and test ROMs expect these.
+pub const POWER_UP_PALETTE: [u8; 32] = [
+    0x09, 0x01, 0x00, 0x01, 0x00, 0x02, 0x02, 0x0D, 0x08, 0x10, 0x08, 0x24, 0x00, 0x00, 0x04, 0x2C,
+    0x09, 0x01, 0x34, 0x03, 0x00, 0x04, 0x00, 0x14, 0x08, 0x3A, 0x00, 0x02, 0x00, 0x20, 0x2C, 0x08,
+];
+
+impl Ppu {
+    pub fn ppu_read(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.cartridge.borrow().ppu_read(addr),
+            0x2000..=0x3EFF => self.nametables[self.nametable_index(addr)],
+            0x3F00..=0x3FFF => self.palette_ram[palette_index(addr)],
+            _ => 0,
+        }
+    }
+
+    pub fn ppu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.cartridge.borrow_mut().ppu_write(addr, data),
+            0x2000..=0x3EFF => {
+                let index = self.nametable_index(addr);
+                self.nametables[index] = data;
+            }
+            0x3F00..=0x3FFF => {
+                self.palette_ram[palette_index(addr)] = data & 0x3F;
+                self.update_palette_lookup();
+            }
+            _ => (),
+        }
+    }
+
+    /// Sets the contents of palette RAM at power-on, replacing [POWER_UP_PALETTE]. Palette RAM
+    /// isn't cleared by a reset, so this should be done before running any frames.
+    pub fn set_power_up_palette(&mut self, palette: [u8; 32]) {
+        *self.palette_ram = palette.map(|color| color & 0x3F);
+        self.update_palette_lookup();
+    }
+
+    /// Returns the color of a palette entry as the PPU would output it.
+    pub(super) fn sample_palette_ram(&self, palette: u8, index: u8) -> u8 {
+        self.apply_grayscale(self.palette_lookup[(((palette << 2) + index) & 0x1F) as usize])
+    }
+
+    pub(super) fn update_palette_lookup(&mut self) {
+        for (addr, color) in self.palette_lookup.iter_mut().enumerate() {
+            *color = self.palette_ram[palette_index(addr as u16)];
+        }
+    }
+
+    /// Strips the hue from a palette value if grayscale mode is enabled, leaving only the
+    /// brightness bits.
+    pub(super) fn apply_grayscale(&self, color_index: u8) -> u8 {
+        if self.mask.grayscale() {
+            color_index & 0x30
+        } else {
+            color_index
+        }
+    }
+
+    /// Maps an address in $2000-$3EFF to an index into nametable RAM.
+    /// Returns the cartridge's current nametable mirroring.
+    pub fn mirroring(&self) -> Mirroring {
+        self.cartridge.borrow().mirroring()
+    }
+
+    /// Returns which 1 KiB page of nametable RAM backs each of the 4 nametables, as set by the
+    /// cartridge's current mirroring.
+    pub fn nametable_pages(&self) -> [usize; 4] {
+        self.mirroring().nametable_pages()
+    }
+
+    fn nametable_index(&self, addr: u16) -> usize {
+        let page = self.nametable_pages()[(addr as usize >> 10) & 0x03];
+        page * 0x0400 + (addr as usize & 0x03FF)
+    }
+}
+
+/// Maps an address in $3F00-$3FFF to an index into palette RAM.
+fn palette_index(addr: u16) -> usize {
+    let addr = addr & 0x1F;
+
+    // Addresses 0x04, 0x08, 0x0C (transparent colors of background palettes) can contain data not
+    // normally used by the PPU for rendering, but 0x10, 0x14, 0x18, 0x1C (transparent colors of
+    // sprite palettes) are mirrors of 0x00, 0x04, 0x08, 0x0C, respectively.
+    let addr = match addr {
+        0x10 => 0x00,
+        0x14 => 0x04,
+        0x18 => 0x08,
+        0x1C => 0x0C,
+        _ => addr,
+    };
+    addr as usize
+}
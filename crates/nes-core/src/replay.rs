@@ -1,6 +1,6 @@
-use std::{iter::Peekable, str::FromStr};
+use std::{iter::Peekable, str::FromStr, time::Duration};
 
-use crate::Controller;
+use crate::{AccuracyProfile, Controller, FrameTiming, Region};
 
 #[allow(dead_code)]
 #[derive(Debug)]
@@ -26,7 +26,11 @@ where
     guid: String,
     rom_checksum: String,
     savestate: Option<String>,
+    /// An extension recording the [AccuracyProfile] the movie was made with.
+    accuracy_profile: Option<AccuracyProfile>,
     iter: Peekable<I>,
+    is_microphone_active: bool,
+    fourscore_controllers: (Controller, Controller),
 }
 
 impl<'a, I> Replay<'a, I>
@@ -76,6 +80,7 @@ where
                     "guid" => builder.set_guid(value.to_string()),
                     "romChecksum" => builder.set_rom_checksum(value.to_string()),
                     "savestate" => builder.set_savestate(value.to_string()),
+                    "accuracyProfile" => builder.set_accuracy_profile(parse(key, value)?),
                     _ => return Err(format!("unrecognized key `{key}`")),
                 };
             }
@@ -86,18 +91,9 @@ where
         if replay.version != 3 {
             return Err(format!("invalid version number `{}`", replay.version));
         }
-        if replay.pal_flag.unwrap_or_default() {
-            return Err("pal not supported".into());
-        }
         if replay.fds.unwrap_or_default() {
             return Err("fds not supported".into());
         }
-        if replay.fourscore {
-            return Err("fourscore not supported".into());
-        }
-        if replay.microphone.unwrap_or_default() {
-            return Err("microphone not supported".into());
-        }
         if replay.binary.unwrap_or_default() {
             return Err("binary input log not supported".into());
         }
@@ -107,6 +103,116 @@ where
 
         Ok(replay)
     }
+
+    /// Returns the region the replay was recorded on, which it must be played back on to stay in
+    /// sync.
+    pub fn region(&self) -> Region {
+        match self.pal_flag {
+            Some(true) => Region::Pal,
+            _ => Region::Ntsc,
+        }
+    }
+
+    /// Returns the accuracy profile the replay was recorded with, if it says. Movies from other
+    /// emulators don't, and are best played back with the default profile.
+    pub fn accuracy_profile(&self) -> Option<AccuracyProfile> {
+        self.accuracy_profile
+    }
+
+    /// Returns whether the Famicom microphone was active during the most recently returned frame.
+    pub fn is_microphone_active(&self) -> bool {
+        self.is_microphone_active
+    }
+
+    /// Returns whether the replay was recorded with a Four Score, which has 4 controllers.
+    pub fn is_fourscore(&self) -> bool {
+        self.fourscore
+    }
+
+    /// Returns controllers 3 and 4 for the most recently returned frame of a Four Score replay.
+    /// These are always empty in other replays.
+    pub fn fourscore_controllers(&self) -> (Controller, Controller) {
+        self.fourscore_controllers
+    }
+
+    /// Returns the name of the ROM the replay was recorded with, which may not match the file
+    /// name of the ROM it's played back with.
+    pub fn rom_filename(&self) -> &str {
+        &self.rom_filename
+    }
+
+    /// Returns the ROM's checksum as written in the header, usually base64-encoded MD5.
+    pub fn rom_checksum(&self) -> &str {
+        &self.rom_checksum
+    }
+
+    pub fn guid(&self) -> &str {
+        &self.guid
+    }
+
+    /// Returns the author's comment, such as `author Name`, if there is one.
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+
+    pub fn rerecord_count(&self) -> Option<u32> {
+        self.rerecord_count
+    }
+
+    /// Returns the number of frames in the input log, if the header says. FCEUX doesn't write
+    /// this, so most movies leave it out.
+    pub fn length(&self) -> Option<u32> {
+        self.length
+    }
+
+    /// Counts the frames left in the input log without consuming them.
+    pub fn count_remaining_frames(&self) -> u32
+    where
+        I: Clone,
+    {
+        self.iter
+            .clone()
+            .filter(|line| line.starts_with('|'))
+            .count() as u32
+    }
+
+    /// Collects the header's descriptive fields, for frontends to show.
+    pub fn metadata(&self) -> ReplayMetadata {
+        ReplayMetadata {
+            rom_filename: self.rom_filename.clone(),
+            rom_checksum: self.rom_checksum.clone(),
+            guid: self.guid.clone(),
+            comment: self.comment.clone(),
+            emu_version: self.emu_version,
+            rerecord_count: self.rerecord_count,
+            length: self.length,
+            region: self.region(),
+        }
+    }
+}
+
+/// The descriptive fields of a replay's header, as returned by [Replay::metadata].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ReplayMetadata {
+    pub rom_filename: String,
+    pub rom_checksum: String,
+    pub guid: String,
+    pub comment: Option<String>,
+    /// The version of the emulator that recorded the replay, such as 22020 for FCEUX 2.2.2.
+    pub emu_version: u32,
+    pub rerecord_count: Option<u32>,
+    /// The number of frames in the input log, if the header says.
+    pub length: Option<u32>,
+    pub region: Region,
+}
+
+impl ReplayMetadata {
+    /// Returns how long the replay takes to play on a real console, if its length is known.
+    pub fn duration(&self) -> Option<Duration> {
+        let frame_rate = FrameTiming::new(self.region).frame_rate();
+        self.length
+            .map(|length| Duration::from_secs_f64(length as f64 / frame_rate))
+    }
 }
 
 impl<'a, I> Iterator for Replay<'a, I>
@@ -121,6 +227,14 @@ where
             let (command, line) = line.split_once('|')?;
             let (controller_1, line) = line.split_once('|')?;
             let (controller_2, line) = line.split_once('|')?;
+            // Four Score movies have a column for each controller in place of the ports.
+            let (controller_3, controller_4, line) = if self.fourscore {
+                let (controller_3, line) = line.split_once('|')?;
+                let (controller_4, line) = line.split_once('|')?;
+                (controller_3, controller_4, line)
+            } else {
+                ("", "", line)
+            };
             let (port_2, _) = line.split_once('|')?;
 
             // Port 2 must be empty.
@@ -128,15 +242,52 @@ where
                 return None;
             }
 
+            // Microphone movies have an extra column after controller 2's buttons.
+            let (controller_2, microphone) = match controller_2.split_at_checked(8) {
+                Some((controller_2, microphone)) if self.microphone.unwrap_or_default() => {
+                    (controller_2, microphone)
+                }
+                _ => (controller_2, ""),
+            };
+            self.is_microphone_active = !matches!(microphone, "" | " " | ".");
+
             let command: InputCommand = command.parse::<u8>().ok()?.into();
             let controller_1 = parse_controller(controller_1);
             let controller_2 = parse_controller(controller_2);
+            self.fourscore_controllers = (
+                parse_controller(controller_3),
+                parse_controller(controller_4),
+            );
 
             Some((command, controller_1, controller_2))
         })?
     }
 }
 
+/// Formats a frame of input as a line of an FM2 input log.
+///
+/// Four Score movies have a column for each of the 4 controllers. Other movies have a column for
+/// each of the 2 ports, where `None` leaves an unused port's column empty. Movies with the
+/// `microphone 1` header pass whether the microphone was active, which is recorded at the end of
+/// controller 2's column.
+pub fn format_input_line(
+    command: InputCommand,
+    controllers: &[Option<Controller>],
+    microphone: Option<bool>,
+) -> String {
+    let mut line = format!("|{command}|");
+    for (index, controller) in controllers.iter().enumerate() {
+        if let Some(controller) = controller {
+            line += &controller.to_string();
+        }
+        if let (1, Some(microphone)) = (index, microphone) {
+            line.push(if microphone { 'M' } else { '.' });
+        }
+        line.push('|');
+    }
+    line + "|"
+}
+
 fn parse_controller(controller: &str) -> Controller {
     if controller.len() != 8 {
         return Controller::default();
@@ -180,6 +331,7 @@ struct ReplayBuilder {
     guid: Option<String>,
     rom_checksum: Option<String>,
     savestate: Option<String>,
+    accuracy_profile: Option<AccuracyProfile>,
 }
 
 impl ReplayBuilder {
@@ -259,6 +411,10 @@ impl ReplayBuilder {
         self.savestate = Some(savestate);
         self
     }
+    fn set_accuracy_profile(&mut self, accuracy_profile: AccuracyProfile) -> &mut Self {
+        self.accuracy_profile = Some(accuracy_profile);
+        self
+    }
 
     fn build<'a, I>(self, iter: Peekable<I>) -> Result<Replay<'a, I>, String>
     where
@@ -313,7 +469,10 @@ impl ReplayBuilder {
             guid,
             rom_checksum,
             savestate: self.savestate,
+            accuracy_profile: self.accuracy_profile,
             iter,
+            is_microphone_active: false,
+            fourscore_controllers: Default::default(),
         })
     }
 }
@@ -396,3 +555,140 @@ impl std::fmt::Display for InputCommand {
         write!(f, "{}", self.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fourscore() {
+        // A made-up 4-player movie in the layout FCEUX records them in.
+        let movie = "version 3
+emuVersion 22020
+rerecordCount 12
+fourscore 1
+port0 1
+port1 1
+port2 0
+romFilename Four Score Test
+guid 00000000-0000-0000-0000-000000000000
+romChecksum base64:AAAAAAAAAAAAAAAAAAAAAA==
+|0|........|........|........|........||
+|0|....T...|.......A|......B.|R......A||
+";
+        let mut replay = Replay::new(movie.lines()).unwrap();
+        assert!(replay.is_fourscore());
+        let metadata = replay.metadata();
+        assert_eq!(metadata.rom_filename, "Four Score Test");
+        assert_eq!(metadata.rerecord_count, Some(12));
+        assert_eq!(metadata.duration(), None);
+        replay.next().unwrap();
+        assert_eq!(replay.fourscore_controllers(), Default::default());
+
+        let (_, controller_1, controller_2) = replay.next().unwrap();
+        assert_eq!(controller_1, Controller::new().with_start(true));
+        assert_eq!(controller_2, Controller::new().with_a(true));
+        let (controller_3, controller_4) = replay.fourscore_controllers();
+        assert_eq!(controller_3, Controller::new().with_b(true));
+        assert_eq!(
+            controller_4,
+            Controller::new().with_right(true).with_a(true)
+        );
+
+        let line = format_input_line(
+            InputCommand::new(),
+            &[controller_1, controller_2, controller_3, controller_4].map(Some),
+            None,
+        );
+        assert_eq!(line, movie.lines().last().unwrap());
+        assert_eq!(
+            format_input_line(InputCommand::new(), &[Some(controller_1), None], None),
+            "|0|....T...|||"
+        );
+    }
+
+    /// Reads a 4-player movie published for FCEUX from `test_roms/movies/fourscore.fm2`,
+    /// checking that every frame of its input log is written back the way FCEUX wrote it.
+    #[test]
+    #[ignore = "needs a 4-player FCEUX movie in test_roms/movies/fourscore.fm2"]
+    fn published_fourscore_movie() {
+        let movie = std::fs::read_to_string("./test_roms/movies/fourscore.fm2").unwrap();
+        let mut replay = Replay::new(movie.lines()).unwrap();
+        assert!(replay.is_fourscore());
+        let input_lines = movie.lines().filter(|line| line.starts_with('|'));
+        for (frame, line) in input_lines.enumerate() {
+            let (command, controller_1, controller_2) = replay.next().unwrap();
+            let (controller_3, controller_4) = replay.fourscore_controllers();
+            let controllers = [controller_1, controller_2, controller_3, controller_4];
+            let formatted = format_input_line(command, &controllers.map(Some), None);
+            assert_eq!(formatted, line.trim_end(), "frame {frame}");
+        }
+        assert!(replay.next().is_none());
+    }
+
+    #[test]
+    fn accuracy_profile() {
+        let header = "version 3
+emuVersion 22020
+fourscore 0
+port0 1
+port1 1
+port2 0
+romFilename Accuracy Test
+guid 00000000-0000-0000-0000-000000000000
+romChecksum base64:AAAAAAAAAAAAAAAAAAAAAA==
+";
+        let movie = format!("{header}|0|........|........||\n");
+        assert_eq!(Replay::new(movie.lines()).unwrap().accuracy_profile(), None);
+        let movie = format!("{header}accuracyProfile accurate\n|0|........|........||\n");
+        assert_eq!(
+            Replay::new(movie.lines()).unwrap().accuracy_profile(),
+            Some(AccuracyProfile::Accurate)
+        );
+        let movie = format!("{header}accuracyProfile exact\n|0|........|........||\n");
+        assert!(Replay::new(movie.lines()).is_err());
+    }
+
+    #[test]
+    fn microphone() {
+        let frames = "|0|........|........M||
+|0|.......A|.......A.||
+|0|........|........ ||
+";
+        let header = "version 3
+emuVersion 22020
+fourscore 0
+port0 1
+port1 1
+port2 0
+romFilename Microphone Test
+guid 00000000-0000-0000-0000-000000000000
+romChecksum base64:AAAAAAAAAAAAAAAAAAAAAA==
+";
+        let movie = format!("{header}microphone 1\n{frames}");
+        let mut replay = Replay::new(movie.lines()).unwrap();
+        let (_, _, controller_2) = replay.next().unwrap();
+        assert_eq!(controller_2, Controller::new());
+        assert!(replay.is_microphone_active());
+        let (_, controller_1, controller_2) = replay.next().unwrap();
+        assert_eq!(controller_1, Controller::new().with_a(true));
+        assert_eq!(controller_2, Controller::new().with_a(true));
+        assert!(!replay.is_microphone_active());
+        replay.next().unwrap();
+        assert!(!replay.is_microphone_active());
+
+        let mut lines = frames.lines();
+        let idle = Some(Controller::new());
+        let formatted = format_input_line(InputCommand::new(), &[idle, idle], Some(true));
+        assert_eq!(formatted, lines.next().unwrap());
+        let pressed = Some(Controller::new().with_a(true));
+        let formatted = format_input_line(InputCommand::new(), &[pressed, pressed], Some(false));
+        assert_eq!(formatted, lines.next().unwrap());
+
+        // Without the header, the extra column isn't read as the microphone.
+        let movie = format!("{header}{frames}");
+        let mut replay = Replay::new(movie.lines()).unwrap();
+        replay.next().unwrap();
+        assert!(!replay.is_microphone_active());
+    }
+}
@@ -0,0 +1,559 @@
+use std::{cell::RefCell, collections::BTreeSet, rc::Rc};
+
+use crate::{
+    Apu, Bus, ControllerPort, Cpu, FourScore, InputCommand, PixelFormat, Ppu, Replay, StopCondition,
+};
+
+/// A frame captured for a screenshot command in a replay.
+#[derive(Debug, Clone)]
+pub struct CapturedFrame {
+    /// The index of the frame with the command, as returned by [ReplayPlayer::frame] before it
+    /// was played.
+    pub frame: u32,
+    pub pixel_format: PixelFormat,
+    /// The PPU's frame buffer once the frame was rendered.
+    pub buffer: Vec<u8>,
+}
+
+impl CapturedFrame {
+    /// Encodes the frame as a binary PPM image, which almost any image viewer or converter can
+    /// open.
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut ppm = b"P6\n256 240\n255\n".to_vec();
+        let bytes_per_pixel = self.pixel_format.bytes_per_pixel();
+        for pixel in self.buffer.chunks_exact(bytes_per_pixel) {
+            ppm.extend_from_slice(&pixel[..3]);
+        }
+        ppm
+    }
+}
+
+/// What a frame of a replay looked like and when it ended, as recorded by
+/// [ReplayPlayer::frame_references] and checked by [ReplayPlayer::verify].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameReference {
+    /// The checksum of the frame as it was rendered, as returned by [Ppu::frame_crc32].
+    pub crc32: u32,
+    /// The number of CPU cycles run since power-on once the frame was finished, as returned by
+    /// [Bus::cycle], or `None` to only check the checksum.
+    pub cycle: Option<u64>,
+}
+
+/// A frame that looked different from the reference, or ended on a different cycle, as returned
+/// by [ReplayPlayer::verify].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameMismatch {
+    /// The index of the frame, counting from 0 at power-on.
+    pub frame: u32,
+    pub expected: FrameReference,
+    /// The frame as it was played, with its cycle count always filled in.
+    pub actual: FrameReference,
+}
+
+/// Plays back a [Replay] one frame at a time, with controls for seeking, pausing at chosen
+/// frames, and extracting savestates from the middle of a movie.
+///
+/// Replays are recorded from power-on, so the player should be created right after the console
+/// is powered on, and should be the only source of input while it's in use. Seeking runs every
+/// frame along the way, so the console ends up in exactly the state it would be in after watching
+/// the replay up to that point. Four Score replays connect a [FourScore] to each controller port
+/// when their first frame is played.
+pub struct ReplayPlayer<'a, I>
+where
+    I: Iterator<Item = &'a str>,
+{
+    replay: Replay<'a, I>,
+    frame: u32,
+    breakpoints: BTreeSet<u32>,
+    is_finished: bool,
+    /// Whether the input log has run out since [ReplayPlayer::take_finished] last returned true.
+    is_finish_pending: bool,
+    /// The number of frames in the input log, if the header says or it's been counted.
+    length: Option<u32>,
+    /// The frame whose screenshot command is waiting for the frame to be rendered.
+    pending_screenshot: Option<u32>,
+    captured_frames: Vec<CapturedFrame>,
+    /// The Four Score halves connected to each port, once a Four Score replay starts playing.
+    four_scores: Option<[Rc<RefCell<FourScore>>; 2]>,
+}
+
+impl<'a, I> ReplayPlayer<'a, I>
+where
+    I: Iterator<Item = &'a str>,
+{
+    pub fn new(replay: Replay<'a, I>) -> Self {
+        Self {
+            length: replay.length(),
+            replay,
+            frame: 0,
+            breakpoints: BTreeSet::new(),
+            is_finished: false,
+            is_finish_pending: false,
+            pending_screenshot: None,
+            captured_frames: Vec::new(),
+            four_scores: None,
+        }
+    }
+
+    pub fn replay(&self) -> &Replay<'a, I> {
+        &self.replay
+    }
+
+    /// Returns the number of frames played so far, which is also the index of the next frame.
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// Returns whether the input log has run out. Playing more frames is allowed, but runs them
+    /// with no buttons held.
+    pub fn is_finished(&self) -> bool {
+        self.is_finished
+    }
+
+    /// Returns true once after the input log runs out, so that frontends can react to the end of
+    /// the movie a single time, such as by switching to live input.
+    pub fn take_finished(&mut self) -> bool {
+        std::mem::take(&mut self.is_finish_pending)
+    }
+
+    /// Returns the number of frames in the input log, if the header says or
+    /// [ReplayPlayer::count_length] has been called.
+    pub fn length(&self) -> Option<u32> {
+        self.length
+    }
+
+    /// Counts the frames in the input log, for movies whose header doesn't say, and returns the
+    /// count. This reads through the rest of the log, so it's best done once before playing.
+    pub fn count_length(&mut self) -> u32
+    where
+        I: Clone,
+    {
+        let length = match self.length {
+            Some(length) if self.is_finished => length,
+            _ => self.frame + self.replay.count_remaining_frames(),
+        };
+        self.length = Some(length);
+        length
+    }
+
+    /// Returns how far through the input log playback is, from 0 to 1, if its length is known.
+    pub fn progress(&self) -> Option<f64> {
+        self.length.map(|length| match length {
+            0 => 1.0,
+            _ => (self.frame as f64 / length as f64).min(1.0),
+        })
+    }
+
+    /// Adds a breakpoint before the given frame is played, which frontends should check for with
+    /// [ReplayPlayer::is_at_breakpoint] to pause playback there.
+    pub fn add_breakpoint(&mut self, frame: u32) {
+        self.breakpoints.insert(frame);
+    }
+
+    pub fn remove_breakpoint(&mut self, frame: u32) {
+        self.breakpoints.remove(&frame);
+    }
+
+    /// Returns whether there's a breakpoint before the next frame.
+    pub fn is_at_breakpoint(&self) -> bool {
+        self.breakpoints.contains(&self.frame)
+    }
+
+    /// Takes the frames captured for screenshot commands so far.
+    pub fn drain_captured_frames(&mut self) -> Vec<CapturedFrame> {
+        std::mem::take(&mut self.captured_frames)
+    }
+
+    /// Captures the current frame if the last frame played had a screenshot command. Frontends
+    /// that clock frames themselves should call this once the frame has been rendered; otherwise
+    /// it's captured at the start of the next [ReplayPlayer::apply_next_input].
+    pub fn finish_frame(&mut self, ppu: &Ppu) {
+        if let Some(frame) = self.pending_screenshot.take() {
+            self.captured_frames.push(CapturedFrame {
+                frame,
+                pixel_format: ppu.pixel_format(),
+                buffer: ppu.buffer().to_vec(),
+            });
+        }
+    }
+
+    /// Passes the input for the next frame to the bus and performs any reset it calls for,
+    /// returning the frame's command, or `None` if the replay has finished. This is for frontends
+    /// that clock frames themselves; [ReplayPlayer::play_frame] also runs the frame.
+    pub fn apply_next_input(
+        &mut self,
+        bus: Rc<RefCell<Bus>>,
+        cpu: Rc<RefCell<Cpu>>,
+        ppu: Rc<RefCell<Ppu>>,
+    ) -> Option<InputCommand> {
+        self.finish_frame(&ppu.borrow());
+        let frame = self.frame;
+        self.frame += 1;
+        let Some((command, controller_1, controller_2)) = self.replay.next() else {
+            if !self.is_finished {
+                self.is_finished = true;
+                self.is_finish_pending = true;
+                // The header's length can be wrong, so trust where the log actually ended.
+                self.length = Some(frame);
+            }
+            let mut bus = bus.borrow_mut();
+            bus.set_controller_state(Default::default(), Default::default());
+            bus.set_microphone(false);
+            for four_score in self.four_scores.iter().flatten() {
+                four_score
+                    .borrow_mut()
+                    .set_controllers(Default::default(), Default::default());
+            }
+            return None;
+        };
+
+        if command.screenshot() {
+            self.pending_screenshot = Some(frame);
+        }
+        if command.soft_reset() {
+            Bus::reset(cpu, ppu);
+        }
+        if self.replay.is_fourscore() {
+            let [port_1, port_2] = self.four_scores.get_or_insert_with(|| {
+                let mut bus = bus.borrow_mut();
+                [ControllerPort::One, ControllerPort::Two].map(|port| {
+                    let four_score = Rc::new(RefCell::new(FourScore::new(port)));
+                    bus.connect_device(port, Some(Box::new(four_score.clone())));
+                    four_score
+                })
+            });
+            let (controller_3, controller_4) = self.replay.fourscore_controllers();
+            port_1
+                .borrow_mut()
+                .set_controllers(controller_1, controller_3);
+            port_2
+                .borrow_mut()
+                .set_controllers(controller_2, controller_4);
+        }
+        let mut bus = bus.borrow_mut();
+        bus.set_controller_state(controller_1, controller_2);
+        bus.set_microphone(self.replay.is_microphone_active());
+        Some(command)
+    }
+
+    /// Plays the next frame, returning its command, or `None` if the replay has finished.
+    pub fn play_frame(
+        &mut self,
+        bus: Rc<RefCell<Bus>>,
+        cpu: Rc<RefCell<Cpu>>,
+        ppu: Rc<RefCell<Ppu>>,
+        apu: Rc<RefCell<Apu>>,
+    ) -> Option<InputCommand> {
+        let command = self.apply_next_input(bus.clone(), cpu.clone(), ppu.clone());
+        Bus::run_until(bus, cpu, ppu.clone(), apu, StopCondition::Frame);
+        self.finish_frame(&ppu.borrow());
+        command
+    }
+
+    /// Plays every frame up to the given one as fast as possible, ignoring breakpoints, so that
+    /// it's the next one to be played. Audio generated along the way is discarded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the frame has already been played, as replays can only be played
+    /// forwards from power-on, or if the replay ends before reaching it.
+    pub fn seek_to_frame(
+        &mut self,
+        frame: u32,
+        bus: Rc<RefCell<Bus>>,
+        cpu: Rc<RefCell<Cpu>>,
+        ppu: Rc<RefCell<Ppu>>,
+        apu: Rc<RefCell<Apu>>,
+    ) -> Result<(), String> {
+        if frame < self.frame {
+            return Err(format!(
+                "can't seek back to frame {frame} from frame {}",
+                self.frame
+            ));
+        }
+        while self.frame < frame {
+            let command = self.play_frame(bus.clone(), cpu.clone(), ppu.clone(), apu.clone());
+            if command.is_none() {
+                return Err(format!("the replay ends at frame {}", self.frame - 1));
+            }
+            apu.borrow_mut().drain_audio_buffer();
+        }
+        Ok(())
+    }
+
+    /// Seeks to the given frame and returns a savestate of the console just before it's played.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error under the same conditions as [ReplayPlayer::seek_to_frame].
+    pub fn savestate_at_frame(
+        &mut self,
+        frame: u32,
+        bus: Rc<RefCell<Bus>>,
+        cpu: Rc<RefCell<Cpu>>,
+        ppu: Rc<RefCell<Ppu>>,
+        apu: Rc<RefCell<Apu>>,
+    ) -> Result<Vec<u8>, String> {
+        self.seek_to_frame(frame, bus.clone(), cpu, ppu, apu)?;
+        let state = bus.borrow().save_state();
+        Ok(state)
+    }
+
+    /// Plays the rest of the replay as fast as possible, returning the checksum and cycle count of
+    /// each frame, for use as a reference for [ReplayPlayer::verify].
+    pub fn frame_references(
+        &mut self,
+        bus: Rc<RefCell<Bus>>,
+        cpu: Rc<RefCell<Cpu>>,
+        ppu: Rc<RefCell<Ppu>>,
+        apu: Rc<RefCell<Apu>>,
+    ) -> Vec<FrameReference> {
+        let mut references = Vec::new();
+        while self
+            .play_frame(bus.clone(), cpu.clone(), ppu.clone(), apu.clone())
+            .is_some()
+        {
+            references.push(FrameReference {
+                crc32: ppu.borrow().frame_crc32(),
+                cycle: Some(bus.borrow().cycle() as u64),
+            });
+            apu.borrow_mut().drain_audio_buffer();
+        }
+        references
+    }
+
+    /// Plays the replay from power-on, checking each frame against the reference, one per frame
+    /// with checksums in the same pixel format, such as ones recorded by
+    /// [ReplayPlayer::frame_references]. A frame matches if its checksum does and it ended on the
+    /// same cycle, when the reference gives one. Returns the first frame that doesn't match, or
+    /// `None` if every frame in the reference does.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any frames have already been played, or if the replay ends before the
+    /// reference does.
+    pub fn verify(
+        &mut self,
+        reference: &[FrameReference],
+        bus: Rc<RefCell<Bus>>,
+        cpu: Rc<RefCell<Cpu>>,
+        ppu: Rc<RefCell<Ppu>>,
+        apu: Rc<RefCell<Apu>>,
+    ) -> Result<Option<FrameMismatch>, String> {
+        if self.frame != 0 {
+            return Err("replays can only be verified from power-on".into());
+        }
+        for &expected in reference {
+            let frame = self.frame;
+            let command = self.play_frame(bus.clone(), cpu.clone(), ppu.clone(), apu.clone());
+            if command.is_none() {
+                return Err(format!(
+                    "the replay ends at frame {frame}, before the reference's {} frames",
+                    reference.len()
+                ));
+            }
+            apu.borrow_mut().drain_audio_buffer();
+            let actual = FrameReference {
+                crc32: ppu.borrow().frame_crc32(),
+                cycle: Some(bus.borrow().cycle() as u64),
+            };
+            if actual.crc32 != expected.crc32
+                || expected
+                    .cycle
+                    .is_some_and(|cycle| actual.cycle != Some(cycle))
+            {
+                return Ok(Some(FrameMismatch {
+                    frame,
+                    expected,
+                    actual,
+                }));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cpu::assemble, Cartridge};
+
+    const HEADER: &str = "version 3
+emuVersion 22020
+port0 1
+port1 1
+port2 0
+fourscore 0
+romFilename test
+guid 00000000-0000-0000-0000-000000000000
+romChecksum base64:AAAAAAAAAAAAAAAAAAAAAA==
+";
+
+    #[test]
+    fn seeking() {
+        // Copies the A button into $10 as fast as possible.
+        let program = assemble(
+            0x8000,
+            "loop: LDA #1
+            STA $4016
+            LDA #0
+            STA $4016
+            LDA $4016
+            AND #1
+            STA $10
+            JMP loop",
+        )
+        .unwrap();
+        let cartridge = Cartridge::from_raw_prg(&program, 0x8000, [0x8000; 3]).unwrap();
+        let crate::System {
+            bus, cpu, ppu, apu, ..
+        } = crate::System::new(cartridge);
+
+        let log = "|0|........|........||\n".repeat(2)
+            + "|128|........|........||\n"
+            + "|0|.......A|........||\n";
+        let movie = format!("{HEADER}{log}");
+        let mut player = ReplayPlayer::new(Replay::new(movie.lines()).unwrap());
+        player.add_breakpoint(3);
+        assert_eq!(player.length(), None);
+        assert_eq!(player.count_length(), 4);
+        assert_eq!(player.progress(), Some(0.0));
+
+        player
+            .seek_to_frame(2, bus.clone(), cpu.clone(), ppu.clone(), apu.clone())
+            .unwrap();
+        assert_eq!(player.frame(), 2);
+        assert!(!player.is_at_breakpoint());
+        assert!(player
+            .play_frame(bus.clone(), cpu.clone(), ppu.clone(), apu.clone())
+            .is_some());
+        assert!(player.is_at_breakpoint());
+        assert_eq!(bus.borrow_mut().cpu_read(0x10), 0);
+        let captured_frames = player.drain_captured_frames();
+        assert_eq!(captured_frames.len(), 1);
+        assert_eq!(captured_frames[0].frame, 2);
+        assert_eq!(captured_frames[0].to_ppm().len(), 15 + 256 * 240 * 3);
+
+        let state = player
+            .savestate_at_frame(4, bus.clone(), cpu.clone(), ppu.clone(), apu.clone())
+            .unwrap();
+        assert_eq!(bus.borrow_mut().cpu_read(0x10), 1);
+        assert!(!state.is_empty());
+
+        assert!(player
+            .seek_to_frame(1, bus.clone(), cpu.clone(), ppu.clone(), apu.clone())
+            .is_err());
+        assert!(player.seek_to_frame(10, bus, cpu, ppu, apu).is_err());
+        assert!(player.is_finished());
+        assert_eq!(player.length(), Some(4));
+        assert_eq!(player.progress(), Some(1.0));
+        assert!(player.take_finished());
+        assert!(!player.take_finished());
+    }
+
+    #[test]
+    fn verification() {
+        // Shows the A button as the backdrop color.
+        let program = assemble(
+            0x8000,
+            "loop: LDA #1
+            STA $4016
+            LDA #0
+            STA $4016
+            LDA $4016
+            AND #1
+            LDX #$3F
+            STX $2006
+            LDX #$00
+            STX $2006
+            STA $2007
+            JMP loop",
+        )
+        .unwrap();
+        let log = "|0|........|........||\n|0|.......A|........||\n".repeat(2);
+        let movie = format!("{HEADER}{log}");
+        let start = || {
+            let cartridge = Cartridge::from_raw_prg(&program, 0x8000, [0x8000; 3]).unwrap();
+            let system = crate::System::new(cartridge);
+            let player = ReplayPlayer::new(Replay::new(movie.lines()).unwrap());
+            (player, system.bus, system.cpu, system.ppu, system.apu)
+        };
+        let verify = |reference: &[FrameReference]| {
+            let (mut player, bus, cpu, ppu, apu) = start();
+            player.verify(reference, bus, cpu, ppu, apu)
+        };
+
+        let (mut player, bus, cpu, ppu, apu) = start();
+        let reference = player.frame_references(bus.clone(), cpu.clone(), ppu.clone(), apu.clone());
+        assert_eq!(reference.len(), 4);
+        assert_ne!(reference[0].crc32, reference[1].crc32);
+        assert_eq!(verify(&reference), Ok(None));
+        assert_eq!(verify(&reference[..2]), Ok(None));
+        // Only whole replays can be verified.
+        assert!(player.verify(&reference, bus, cpu, ppu, apu).is_err());
+
+        let mut wrong = reference.clone();
+        wrong[2].crc32 ^= 1;
+        assert_eq!(
+            verify(&wrong),
+            Ok(Some(FrameMismatch {
+                frame: 2,
+                expected: wrong[2],
+                actual: reference[2],
+            }))
+        );
+
+        // A frame that ends on a different cycle doesn't match, unless the reference leaves its
+        // cycle count out.
+        let mut wrong = reference.clone();
+        wrong[1].cycle = wrong[1].cycle.map(|cycle| cycle + 1);
+        assert_eq!(verify(&wrong).unwrap().unwrap().frame, 1);
+        wrong[1].cycle = None;
+        assert_eq!(verify(&wrong), Ok(None));
+
+        assert!(verify(&[reference.as_slice(), &reference[..1]].concat()).is_err());
+    }
+
+    /// Plays each movie in `test_roms/replays` on the ROM of the same name, checking every frame
+    /// against the `.crc` file next to it. Each line holds a frame's checksum in hex, recorded
+    /// with the frame buffer in RGB, optionally followed by its cycle count in decimal.
+    #[test]
+    #[ignore = "needs movies, ROMs and reference checksums in test_roms/replays"]
+    fn reference_replays() {
+        let mut checked = 0;
+        for entry in std::fs::read_dir("./test_roms/replays").unwrap() {
+            let movie_path = entry.unwrap().path();
+            if movie_path
+                .extension()
+                .is_none_or(|extension| extension != "fm2")
+            {
+                continue;
+            }
+            let rom = std::fs::read(movie_path.with_extension("nes")).unwrap();
+            let movie = std::fs::read_to_string(&movie_path).unwrap();
+            let reference: Vec<FrameReference> =
+                std::fs::read_to_string(movie_path.with_extension("crc"))
+                    .unwrap()
+                    .lines()
+                    .map(|line| {
+                        let mut fields = line.split_whitespace();
+                        FrameReference {
+                            crc32: u32::from_str_radix(fields.next().unwrap(), 16).unwrap(),
+                            cycle: fields.next().map(|cycle| cycle.parse().unwrap()),
+                        }
+                    })
+                    .collect();
+
+            let system = crate::System::new(Cartridge::new(&rom).unwrap());
+            let replay = Replay::new(movie.lines()).unwrap();
+            system.bus.borrow_mut().set_region(replay.region());
+            let mut player = ReplayPlayer::new(replay);
+            let mismatch = player
+                .verify(&reference, system.bus, system.cpu, system.ppu, system.apu)
+                .unwrap();
+            assert_eq!(mismatch, None, "{}", movie_path.display());
+            checked += 1;
+        }
+        assert!(checked > 0);
+    }
+}
@@ -0,0 +1,103 @@
+/// The number of output samples each step in the input is spread across by [Resampler::Sinc].
+const TAPS: usize = 32;
+/// The number of positions between two output samples that filter kernels are precomputed for.
+const PHASES: usize = 64;
+/// The filter's cutoff, as a fraction of the output sample rate. Slightly below the Nyquist
+/// frequency so the filter has room to roll off.
+const CUTOFF: f64 = 0.45;
+
+/// How the APU's output, which changes once per CPU cycle, is resampled to the audio sample rate.
+///
+/// This only affects how the output is filtered, not emulation, so it can be changed at any time
+/// without affecting determinism.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Resampler {
+    /// Takes the output at the cycle each sample falls on. This is the cheapest, but high notes
+    /// and noise alias audibly.
+    #[default]
+    Nearest,
+    /// Interpolates between the outputs of the cycles either side of each sample. Cheap enough
+    /// for low-power devices.
+    Linear,
+    /// Band-limits the output with a windowed sinc filter, which removes aliasing at the cost of
+    /// more work whenever the output changes, and a delay of 16 samples.
+    Sinc,
+}
+
+/// Band-limits a signal that changes at arbitrary points in time, by spreading each step in it
+/// over the surrounding output samples with a windowed sinc kernel.
+pub(crate) struct SincFilter {
+    /// Kernels for steps at each phase between two output samples, indexed by how far before the
+    /// next output sample the step happens. Each kernel sums to 1.
+    kernels: Vec<[f64; TAPS]>,
+    /// The changes to the output from steps so far, as a ring buffer starting at `position`, which
+    /// is the next output sample.
+    deltas: [f64; TAPS],
+    position: usize,
+    output: f64,
+    level: f32,
+}
+
+impl SincFilter {
+    pub fn new(level: f32) -> Self {
+        let kernels = (0..=PHASES)
+            .map(|phase| {
+                let offset = phase as f64 / PHASES as f64;
+                let mut kernel = [0.0; TAPS];
+                for (i, value) in kernel.iter_mut().enumerate() {
+                    let x = i as f64 - (TAPS / 2) as f64 + offset;
+                    *value = sinc(2.0 * CUTOFF * x) * blackman(x / (TAPS / 2) as f64);
+                }
+                let sum: f64 = kernel.iter().sum();
+                kernel.map(|value| value / sum)
+            })
+            .collect();
+
+        Self {
+            kernels,
+            deltas: [0.0; TAPS],
+            position: 0,
+            output: level as f64,
+            level,
+        }
+    }
+
+    /// Changes the input level, `time_to_next` output samples before the next output sample is
+    /// due, from 0 to 1.
+    pub fn set_level(&mut self, level: f32, time_to_next: f32) {
+        if level == self.level {
+            return;
+        }
+        let delta = (level - self.level) as f64;
+        self.level = level;
+
+        let phase = (time_to_next.clamp(0.0, 1.0) * PHASES as f32).round() as usize;
+        for (i, value) in self.kernels[phase].iter().enumerate() {
+            self.deltas[(self.position + i) % TAPS] += delta * value;
+        }
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        self.output += std::mem::take(&mut self.deltas[self.position]);
+        self.position = (self.position + 1) % TAPS;
+        self.output as f32
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        let x = x * std::f64::consts::PI;
+        x.sin() / x
+    }
+}
+
+/// The Blackman window, over -1 to 1.
+fn blackman(x: f64) -> f64 {
+    if x.abs() > 1.0 {
+        return 0.0;
+    }
+    let x = x * std::f64::consts::PI;
+    0.42 + 0.5 * x.cos() + 0.08 * (2.0 * x).cos()
+}
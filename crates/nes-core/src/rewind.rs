@@ -0,0 +1,119 @@
+use std::collections::VecDeque;
+
+use crate::{Bus, SavestateError};
+
+/// Keeps a savestate of each of the last few frames, for stepping the console back through them.
+///
+/// States are saved with [Bus::save_state_into], and the buffers of states that are dropped or
+/// rewound past are reused for later ones, so that taking a state every frame doesn't allocate
+/// once the buffer has filled up.
+pub struct RewindBuffer {
+    states: VecDeque<Vec<u8>>,
+    /// Buffers of states that have been loaded, kept for saving new states into.
+    spare: Vec<Vec<u8>>,
+    capacity: usize,
+}
+
+impl RewindBuffer {
+    /// Creates a buffer that keeps up to `capacity` states, dropping the oldest beyond that.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            states: VecDeque::with_capacity(capacity),
+            spare: Vec::new(),
+            capacity,
+        }
+    }
+
+    /// Returns the number of states that can be rewound to.
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    /// Saves the current state of the console, such as once per frame.
+    pub fn push(&mut self, bus: &Bus) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut state = if self.states.len() == self.capacity {
+            self.states.pop_front().unwrap_or_default()
+        } else {
+            self.spare.pop().unwrap_or_default()
+        };
+        bus.save_state_into(&mut state);
+        self.states.push_back(state);
+    }
+
+    /// Loads the most recent state and removes it, so that the next call goes back further.
+    /// Returns false if there are no states left.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the state can't be applied, which only happens if the cartridge has
+    /// been swapped since it was saved.
+    pub fn rewind(&mut self, bus: &mut Bus) -> Result<bool, SavestateError> {
+        let Some(state) = self.states.pop_back() else {
+            return Ok(false);
+        };
+        let result = bus.load_state(&state);
+        self.spare.push(state);
+        result.map(|()| true)
+    }
+
+    /// Drops every state, such as after loading a savestate or swapping the cartridge.
+    pub fn clear(&mut self) {
+        self.spare.extend(self.states.drain(..));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cpu::assemble, Cartridge};
+
+    #[test]
+    fn rewinding() {
+        // Counts frames in $10.
+        let program = assemble(
+            0x8000,
+            "LDA #$80
+            STA $2000
+            loop: JMP loop
+            nmi: INC $10
+            RTI",
+        )
+        .unwrap();
+        let nmi = 0x8000 + program.len() as u16 - 3;
+        let cartridge = Cartridge::from_raw_prg(&program, 0x8000, [nmi, 0x8000, 0]).unwrap();
+        let system = crate::System::new(cartridge);
+        let frames = || system.bus.borrow().peek(0x10);
+
+        let mut rewind = RewindBuffer::new(3);
+        for _ in 0..5 {
+            rewind.push(&system.bus.borrow());
+            system.run_frame(Default::default(), |_, _| {});
+        }
+        assert_eq!((rewind.len(), frames()), (3, 5));
+
+        let mut bus = system.bus.borrow_mut();
+        assert_eq!(rewind.rewind(&mut bus), Ok(true));
+        assert_eq!(bus.peek(0x10), 4);
+        assert_eq!(rewind.rewind(&mut bus), Ok(true));
+        assert_eq!(bus.peek(0x10), 3);
+        drop(bus);
+
+        // Loaded states' buffers are reused rather than allocating new ones.
+        let pointer = rewind.spare.last().unwrap().as_ptr();
+        rewind.push(&system.bus.borrow());
+        assert_eq!(rewind.states.back().unwrap().as_ptr(), pointer);
+
+        let mut bus = system.bus.borrow_mut();
+        assert_eq!(rewind.rewind(&mut bus), Ok(true));
+        assert_eq!(rewind.rewind(&mut bus), Ok(true));
+        assert_eq!(bus.peek(0x10), 2);
+        assert_eq!(rewind.rewind(&mut bus), Ok(false));
+    }
+}
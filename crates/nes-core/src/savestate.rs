@@ -0,0 +1,1987 @@
+//! Reading and writing FCEUX FCS savestates.
+//!
+//! Savestates are split into sections, each made up of named chunks. Only the chunks needed to
+//! restore this emulator's state are honored, and the rest are skipped:
+//!
+//! - CPU: `PC`, `A`, `P`, `X`, `Y`, `S`, `DB`, and `RAM`.
+//! - CPUC (cycle counters and jam state): `CYCL`, an extension holding the bus's cycle count.
+//!   FCEUX's own counters are ignored.
+//! - PPU: `NTAR`, `PRAM`, `SPRA`, `PPUR`, `XOFF`, `VTGL`, `RADD`, `TADD`, `VBUF`, and `PGEN`.
+//! - CTLR (controller state): ignored entirely.
+//! - SND: the pulse, triangle, and noise chunks. `FHCN`, `FCNT`, and the DMC chunks are ignored.
+//! - EXTRA: passed to the mapper, which reads its own chunks such as `CHRR`, `WRAM`, and its
+//!   registers. Chunks the mapper saves itself must match its own size, or the whole savestate is
+//!   rejected before anything is applied.
+//!
+//! Savestates written by this emulator also contain a thumbnail section, and a META section, both
+//! of which FCEUX skips. The META section holds a [SavestateMetadata]: the CRC32 of the ROM
+//! (`ROMC`), its mapper number (`MAPR`), the frame count (`FRAM`), and the emulator version
+//! (`VERS`), along with the time it was saved (`TIME`) and the ROM's name (`NAME`) if the frontend
+//! gave them, and the accuracy profile (`ACCU`), which is applied along with the savestate so that
+//! it plays out the same way it was recorded. The ROM's CRC32 is checked against the current
+//! cartridge before a savestate is applied, so that states from other games are turned away.
+
+// TODO: Remove
+#![allow(unused)]
+
+use std::{
+    borrow::Cow,
+    io::{Read, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use crate::{mapper::Mirroring, AccuracyProfile};
+
+/// The FCEUX version written to savestate headers, in FCEUX's numeric form, which is 2.6.6.
+pub const FCEUX_VERSION: u32 = 20606;
+/// Width of the framebuffer thumbnail stored in savestates.
+pub const THUMBNAIL_WIDTH: usize = 64;
+/// Height of the framebuffer thumbnail stored in savestates.
+pub const THUMBNAIL_HEIGHT: usize = 60;
+/// Size in bytes of an RGB24 savestate thumbnail.
+pub const THUMBNAIL_SIZE: usize = THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT * 3;
+/// The largest savestate that will be decompressed, in bytes. Real savestates are well under a
+/// megabyte, so this only stops a corrupt or crafted header from claiming gigabytes.
+pub const MAX_SAVESTATE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Why a savestate couldn't be loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SavestateError {
+    /// The savestate was made with a different ROM, identified by the CRC32 of its PRG and CHR
+    /// ROM. See [crate::Bus::set_savestate_rom_check].
+    WrongRom {
+        savestate_crc32: u32,
+        rom_crc32: u32,
+    },
+    /// The savestate is in a format from before FCEUX, identified by the fourth byte of its
+    /// header, which those wrote their version number in.
+    UnsupportedVersion(u8),
+    /// The savestate's header claims it decompresses to more than [MAX_SAVESTATE_SIZE], given as
+    /// the size of its body.
+    TooLarge(u32),
+    /// The savestate is malformed, or its mapper's state doesn't fit the current cartridge.
+    Corrupt(String),
+}
+
+impl std::fmt::Display for SavestateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::WrongRom {
+                savestate_crc32,
+                rom_crc32,
+            } => write!(
+                f,
+                "savestate is for a different ROM (CRC32 {savestate_crc32:08X}) than this one \
+                (CRC32 {rom_crc32:08X})"
+            ),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "savestate format version {version} isn't supported")
+            }
+            Self::TooLarge(size) => write!(
+                f,
+                "savestate claims to be {size} bytes, over the limit of {MAX_SAVESTATE_SIZE}"
+            ),
+            Self::Corrupt(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for SavestateError {}
+
+impl From<String> for SavestateError {
+    fn from(message: String) -> Self {
+        Self::Corrupt(message)
+    }
+}
+
+impl From<&str> for SavestateError {
+    fn from(message: &str) -> Self {
+        Self::Corrupt(message.to_string())
+    }
+}
+
+impl From<SavestateError> for String {
+    fn from(err: SavestateError) -> Self {
+        err.to_string()
+    }
+}
+
+pub struct Savestate<'a> {
+    pub(crate) header: Header,
+    pub(crate) cpu_state: CpuState,
+    pub(crate) cpuc_state: CpucState,
+    pub(crate) ppu_state: PpuState,
+    pub(crate) apu_state: ApuState,
+    pub(crate) mapper_state: MapperState<'a>,
+    pub(crate) metadata: SavestateMetadata,
+    pub(crate) thumbnail: Option<&'a [u8]>,
+    pub(crate) warnings: Vec<String>,
+}
+
+impl<'a> Savestate<'a> {
+    /// Parses an uncompressed FCEUX FCS savestate file.
+    ///
+    /// To parse a compressed savestate, see [Savestate::decompress].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file is malformed, compressed, or in a format from before FCEUX.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, SavestateError> {
+        if bytes.len() < 3 || &bytes[0..3] != b"FCS" {
+            return Err("not a savestate".into());
+        }
+        if bytes.len() < 16 {
+            return Err("header ended unexpectedly".into());
+        }
+
+        let (header, rest) = bytes.split_at(16);
+
+        let header = Header::new(header)?;
+
+        if header.compressed_size.is_some() {
+            return Err("savestate is compressed".into());
+        }
+
+        if rest.len() != header.file_size as usize {
+            return Err("file size doesn't match header".into());
+        }
+
+        if rest.len() < 5 {
+            return Err("section header ended unexpectedly".into());
+        }
+
+        let mut cpu_state = None;
+        let mut cpuc_state = None;
+        let mut ppu_state = None;
+        let mut apu_state = None;
+        let mut mapper_state = None;
+        let mut metadata = None;
+        let mut thumbnail = None;
+        let mut warnings = Vec::new();
+
+        for (section_kind, section) in sections(rest)? {
+            match section_kind {
+                SectionChunkKind::Cpu => cpu_state = Some(CpuState::new(section, &mut warnings)?),
+                SectionChunkKind::Cpuc => cpuc_state = Some(CpucState::new(section)?),
+                SectionChunkKind::Ppu => ppu_state = Some(PpuState::new(section, &mut warnings)?),
+                SectionChunkKind::Snd => apu_state = Some(ApuState::new(section, &mut warnings)?),
+                SectionChunkKind::Extra => mapper_state = Some(MapperState::new(section)?),
+                SectionChunkKind::Metadata => metadata = Some(SavestateMetadata::new(section)?),
+                // Thumbnails are purely cosmetic, so ignore any with an unexpected size.
+                SectionChunkKind::Thumbnail if section.len() == THUMBNAIL_SIZE => {
+                    thumbnail = Some(section)
+                }
+                _ => (), // TODO
+            };
+        }
+
+        Ok(Self {
+            header,
+            cpu_state: cpu_state.ok_or("missing cpu state")?,
+            cpuc_state: cpuc_state.unwrap_or_default(),
+            ppu_state: ppu_state.ok_or("missing ppu state")?,
+            apu_state: apu_state.ok_or("missing apu state")?,
+            mapper_state: mapper_state.ok_or("missing mapper state")?,
+            metadata: metadata.unwrap_or_default(),
+            thumbnail,
+            warnings,
+        })
+    }
+
+    /// Returns the sections of the savestate that weren't recognized and were skipped, as
+    /// warnings. Any from the mapper's state are only found once it's applied, and are reported
+    /// through [crate::Bus::drain_warnings] along with these.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Returns the RGB24 framebuffer thumbnail stored in the savestate, if any.
+    ///
+    /// The thumbnail is [THUMBNAIL_WIDTH] by [THUMBNAIL_HEIGHT] pixels. This can be used to
+    /// preview a savestate without applying it.
+    pub fn thumbnail(&self) -> Option<&'a [u8]> {
+        self.thumbnail
+    }
+
+    /// Returns what the savestate says about itself. Savestates from FCEUX leave everything out.
+    pub fn metadata(&self) -> &SavestateMetadata {
+        &self.metadata
+    }
+
+    /// Reads a savestate's metadata without parsing the rest of it, which is cheap enough to do
+    /// for every savestate in a save manager.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file is malformed.
+    pub fn read_metadata(bytes: &[u8]) -> Result<SavestateMetadata, SavestateError> {
+        let decompressed = Savestate::decompress(bytes)?;
+        if decompressed.len() < 16 {
+            return Err("header ended unexpectedly".into());
+        }
+        for (kind, section) in sections(&decompressed[16..])? {
+            if let SectionChunkKind::Metadata = kind {
+                return Ok(SavestateMetadata::new(section)?);
+            }
+        }
+        Ok(SavestateMetadata::default())
+    }
+
+    /// Decompresses a compressed FCEUX FCS savestate file.
+    ///
+    /// Use in conjunction with [Savestate::new] to parse the returned data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file is malformed or in a format from before FCEUX, if the
+    /// compressed data is corrupt or doesn't decompress to exactly the size given in the header,
+    /// or if that size is over [MAX_SAVESTATE_SIZE].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use nes_core::{Savestate, SavestateError};
+    ///
+    /// # fn main() -> Result<(), SavestateError> {
+    /// # let bytes = Vec::new();
+    /// let decompressed = Savestate::decompress(&bytes)?;
+    /// let savestate = Savestate::new(&decompressed)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn decompress(bytes: &'a [u8]) -> Result<Cow<'a, [u8]>, SavestateError> {
+        if bytes.len() < 3 || &bytes[0..3] != b"FCS" {
+            return Err("not a savestate".into());
+        }
+        if bytes.len() < 16 {
+            return Err("header ended unexpectedly".into());
+        }
+
+        let (header_bytes, rest) = bytes.split_at(16);
+
+        let header = Header::new(header_bytes)?;
+
+        match header.compressed_size {
+            Some(compressed_size) => {
+                if rest.len() != compressed_size as usize {
+                    return Err("compressed size doesn't match header".into());
+                }
+
+                // Checked so that a huge size can't wrap around on 32-bit targets.
+                let expected_output_size = (header.file_size as usize)
+                    .checked_add(header_bytes.len())
+                    .filter(|&size| size <= MAX_SAVESTATE_SIZE)
+                    .ok_or(SavestateError::TooLarge(header.file_size))?;
+
+                let mut decoder = ZlibDecoder::new(rest);
+                let mut output = vec![0u8; expected_output_size];
+
+                // Copy header into the output buffer while indicating that the data is
+                // uncompressed.
+                output[0..12].copy_from_slice(&header_bytes[0..12]);
+                output[12..16].fill(0xFF);
+
+                // Decompress data into the main body of the output buffer.
+                decoder
+                    .read_exact(&mut output[16..])
+                    .map_err(|err| match err.kind() {
+                        std::io::ErrorKind::UnexpectedEof => {
+                            "savestate decompressed to less data than its header claims".into()
+                        }
+                        _ => format!("failed to decompress savestate: {err}"),
+                    })?;
+                let mut excess = [0u8];
+                match decoder.read(&mut excess) {
+                    Ok(0) => (),
+                    Ok(_) => {
+                        return Err(
+                            "savestate decompressed to more data than its header claims".into()
+                        )
+                    }
+                    Err(err) => return Err(format!("failed to decompress savestate: {err}").into()),
+                }
+
+                Ok(Cow::Owned(output))
+            }
+            None => Ok(Cow::Borrowed(bytes)),
+        }
+    }
+
+    /// Saves the current system state to a new FCEUX FCS savestate file.
+    ///
+    /// This is an associated function to avoid having to copy data into the state structs, only to
+    /// then copy out of them immediately after. Use the save methods on the various system
+    /// components to obtain the necessary data.
+    ///
+    /// An empty metadata or thumbnail chunk is omitted entirely.
+    pub fn save(
+        cpu: &[u8],
+        cpuc: &[u8],
+        ppu: &[u8],
+        apu: &[u8],
+        mapper: &[u8],
+        metadata: &[u8],
+        thumbnail: &[u8],
+    ) -> Vec<u8> {
+        const TOTAL_HEADER_SIZE: usize = 7 * 5;
+
+        let mut input_buffer = Vec::with_capacity(
+            TOTAL_HEADER_SIZE
+                + cpu.len()
+                + cpuc.len()
+                + ppu.len()
+                + apu.len()
+                + mapper.len()
+                + metadata.len()
+                + thumbnail.len(),
+        );
+
+        input_buffer.push(SectionChunkKind::Cpu.into());
+        input_buffer.extend_from_slice(&(cpu.len() as u32).to_le_bytes());
+        input_buffer.extend_from_slice(cpu);
+
+        input_buffer.push(SectionChunkKind::Cpuc.into());
+        input_buffer.extend_from_slice(&(cpuc.len() as u32).to_le_bytes());
+        input_buffer.extend_from_slice(cpuc);
+
+        input_buffer.push(SectionChunkKind::Ppu.into());
+        input_buffer.extend_from_slice(&(ppu.len() as u32).to_le_bytes());
+        input_buffer.extend_from_slice(ppu);
+
+        input_buffer.push(SectionChunkKind::Snd.into());
+        input_buffer.extend_from_slice(&(apu.len() as u32).to_le_bytes());
+        input_buffer.extend_from_slice(apu);
+
+        input_buffer.push(SectionChunkKind::Extra.into());
+        input_buffer.extend_from_slice(&(mapper.len() as u32).to_le_bytes());
+        input_buffer.extend_from_slice(mapper);
+
+        // FCEUX skips over chunk kinds it doesn't recognize, so these stay compatible.
+        if !metadata.is_empty() {
+            input_buffer.push(SectionChunkKind::Metadata.into());
+            input_buffer.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+            input_buffer.extend_from_slice(metadata);
+        }
+        if !thumbnail.is_empty() {
+            input_buffer.push(SectionChunkKind::Thumbnail.into());
+            input_buffer.extend_from_slice(&(thumbnail.len() as u32).to_le_bytes());
+            input_buffer.extend_from_slice(thumbnail);
+        }
+
+        let uncompressed_length = input_buffer.len() as u32;
+
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(b"FCSX");
+        buffer.extend_from_slice(&uncompressed_length.to_le_bytes());
+        buffer.extend_from_slice(&FCEUX_VERSION.to_le_bytes());
+        buffer.extend_from_slice(&[0xFF; 4]);
+
+        let mut encoder = ZlibEncoder::new(buffer, Compression::best());
+        encoder.write_all(&input_buffer);
+
+        let mut output_buffer = encoder
+            .finish()
+            .expect("writing to a Vec should never fail");
+        let compressed_size = output_buffer.len() as u32 - 16;
+        output_buffer[12..16].copy_from_slice(&compressed_size.to_le_bytes());
+
+        output_buffer
+    }
+
+    /// Saves the system state into `buffer` as an uncompressed FCEUX FCS savestate, replacing its
+    /// contents. Each function appends the chunks of its section to the buffer, so that nothing is
+    /// allocated once the buffer has grown to fit a state. The metadata and thumbnail are left
+    /// out.
+    pub(crate) fn save_uncompressed_into(
+        buffer: &mut Vec<u8>,
+        cpu: impl FnOnce(&mut Vec<u8>),
+        cpuc: impl FnOnce(&mut Vec<u8>),
+        ppu: impl FnOnce(&mut Vec<u8>),
+        apu: impl FnOnce(&mut Vec<u8>),
+        mapper: impl FnOnce(&mut Vec<u8>),
+    ) {
+        fn write_section(
+            buffer: &mut Vec<u8>,
+            kind: SectionChunkKind,
+            write: impl FnOnce(&mut Vec<u8>),
+        ) {
+            buffer.push(kind.into());
+            let size_offset = buffer.len();
+            buffer.extend_from_slice(&[0; 4]);
+            write(buffer);
+            let size = (buffer.len() - size_offset - 4) as u32;
+            buffer[size_offset..size_offset + 4].copy_from_slice(&size.to_le_bytes());
+        }
+
+        buffer.clear();
+        buffer.extend_from_slice(b"FCSX");
+        buffer.extend_from_slice(&[0; 4]);
+        buffer.extend_from_slice(&FCEUX_VERSION.to_le_bytes());
+        buffer.extend_from_slice(&[0xFF; 4]);
+        write_section(buffer, SectionChunkKind::Cpu, cpu);
+        write_section(buffer, SectionChunkKind::Cpuc, cpuc);
+        write_section(buffer, SectionChunkKind::Ppu, ppu);
+        write_section(buffer, SectionChunkKind::Snd, apu);
+        write_section(buffer, SectionChunkKind::Extra, mapper);
+        let file_size = (buffer.len() - 16) as u32;
+        buffer[4..8].copy_from_slice(&file_size.to_le_bytes());
+    }
+}
+
+/// Splits the body of an uncompressed savestate into its sections.
+fn sections(mut bytes: &[u8]) -> Result<Vec<(SectionChunkKind, &[u8])>, String> {
+    let mut sections = Vec::new();
+    while !bytes.is_empty() {
+        if bytes.len() < 5 {
+            return Err("section header ended unexpectedly".into());
+        }
+        let (section_header, rest) = bytes.split_at(5);
+        let section_kind = SectionChunkKind::new(section_header[0]);
+        let section_size = u32::from_le_bytes(section_header[1..5].try_into().unwrap()) as usize;
+        if rest.len() < section_size {
+            return Err("section length doesn't match header".into());
+        }
+
+        let (section, rest) = rest.split_at(section_size);
+        bytes = rest;
+        sections.push((section_kind, section));
+    }
+    Ok(sections)
+}
+
+/// A chunk that differs between two savestates, as returned by [diff].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkDiff {
+    /// The section the chunk is in, such as `CPU` or `EXTRA`.
+    pub section: &'static str,
+    /// The chunk's description, such as `RAM` or `PC`. Unknown sections are compared as a whole,
+    /// with an empty description.
+    pub chunk: String,
+    pub change: ChunkChange,
+}
+
+/// How a chunk differs between two savestates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkChange {
+    /// The chunk is only in the first savestate.
+    Removed,
+    /// The chunk is only in the second savestate.
+    Added,
+    /// The chunk is a different size in each savestate.
+    Resized { old: usize, new: usize },
+    /// A chunk of at most 4 bytes, such as a register, has a different value. Values are read as
+    /// little-endian.
+    Value { old: u32, new: u32 },
+    /// A larger chunk, such as RAM, has different contents in the given byte ranges.
+    Bytes(Vec<Range<usize>>),
+}
+
+impl std::fmt::Display for ChunkDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.section)?;
+        if !self.chunk.is_empty() {
+            write!(f, "/{}", self.chunk)?;
+        }
+        match &self.change {
+            ChunkChange::Removed => write!(f, ": only in first"),
+            ChunkChange::Added => write!(f, ": only in second"),
+            ChunkChange::Resized { old, new } => write!(f, ": {old} bytes -> {new} bytes"),
+            ChunkChange::Value { old, new } => write!(f, ": ${old:02X} -> ${new:02X}"),
+            ChunkChange::Bytes(ranges) => {
+                let ranges: Vec<_> = ranges
+                    .iter()
+                    .map(|range| match range.len() {
+                        1 => format!("${:04X}", range.start),
+                        _ => format!("${:04X}-${:04X}", range.start, range.end - 1),
+                    })
+                    .collect();
+                write!(f, ": differs at {}", ranges.join(", "))
+            }
+        }
+    }
+}
+
+/// Compares two savestates chunk by chunk, returning the chunks that differ in the order they
+/// appear. Either savestate may be compressed.
+///
+/// This is useful for tracking down desyncs, and for checking that applying a savestate and
+/// saving it again round-trips losslessly. Thumbnails are purely cosmetic, so they're ignored.
+///
+/// # Errors
+///
+/// Returns an error if either savestate is malformed.
+pub fn diff(a: &[u8], b: &[u8]) -> Result<Vec<ChunkDiff>, String> {
+    let a = Savestate::decompress(a)?;
+    let b = Savestate::decompress(b)?;
+    let chunks_a = chunks(&a)?;
+    let chunks_b = chunks(&b)?;
+
+    let mut diffs = Vec::new();
+    for (section, chunk, data_a) in &chunks_a {
+        let data_b = chunks_b
+            .iter()
+            .find(|(other_section, other_chunk, _)| {
+                other_section == section && other_chunk == chunk
+            })
+            .map(|&(.., data)| data);
+        let change = match data_b {
+            None => ChunkChange::Removed,
+            Some(data_b) if data_a == &data_b => continue,
+            Some(data_b) if data_a.len() != data_b.len() => ChunkChange::Resized {
+                old: data_a.len(),
+                new: data_b.len(),
+            },
+            Some(data_b) if data_a.len() <= 4 => {
+                let value = |data: &[u8]| {
+                    let mut bytes = [0; 4];
+                    bytes[..data.len()].copy_from_slice(data);
+                    u32::from_le_bytes(bytes)
+                };
+                ChunkChange::Value {
+                    old: value(data_a),
+                    new: value(data_b),
+                }
+            }
+            Some(data_b) => ChunkChange::Bytes(differing_ranges(data_a, data_b)),
+        };
+        diffs.push(ChunkDiff {
+            section,
+            chunk: chunk.to_string(),
+            change,
+        });
+    }
+    for (section, chunk, _) in &chunks_b {
+        let is_in_a = chunks_a.iter().any(|(other_section, other_chunk, _)| {
+            other_section == section && other_chunk == chunk
+        });
+        if !is_in_a {
+            diffs.push(ChunkDiff {
+                section,
+                chunk: chunk.to_string(),
+                change: ChunkChange::Added,
+            });
+        }
+    }
+    Ok(diffs)
+}
+
+/// A chunk's section name, description, and data.
+type Chunk<'a> = (&'static str, &'a str, &'a [u8]);
+
+/// Lists every chunk in an uncompressed savestate along with the name of its section.
+fn chunks(bytes: &[u8]) -> Result<Vec<Chunk<'_>>, String> {
+    if bytes.len() < 16 {
+        return Err("header ended unexpectedly".into());
+    }
+    let mut chunks = Vec::new();
+    for (kind, section) in sections(&bytes[16..])? {
+        match kind {
+            SectionChunkKind::Thumbnail => (),
+            SectionChunkKind::Unknown => chunks.push((kind.name(), "", section)),
+            _ => {
+                for (description, data) in Subchunk::new(section)? {
+                    chunks.push((kind.name(), description, data));
+                }
+            }
+        }
+    }
+    Ok(chunks)
+}
+
+/// Returns the ranges of indices where two equally sized slices differ.
+fn differing_ranges(a: &[u8], b: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (index, _) in a.iter().zip(b).enumerate().filter(|(_, (a, b))| a != b) {
+        match ranges.last_mut() {
+            Some(range) if range.end == index => range.end += 1,
+            _ => ranges.push(index..index + 1),
+        }
+    }
+    ranges
+}
+
+#[derive(Debug)]
+pub struct Header {
+    old_version: u8,
+    file_size: u32,
+    version: u32,
+    compressed_size: Option<u32>,
+}
+
+impl Header {
+    pub fn new(bytes: &[u8]) -> Result<Self, SavestateError> {
+        let old_version = bytes[3];
+        // FCEUX writes `FCSX`, and the version number further on.
+        if old_version != b'X' && old_version != 0xFF {
+            return Err(SavestateError::UnsupportedVersion(old_version));
+        }
+        let file_size = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        let compressed_size = u32::from_le_bytes(bytes[12..16].try_into().unwrap());
+
+        let compressed_size = match compressed_size {
+            0x00000000 | 0xFFFFFFFF => None,
+            x => Some(x),
+        };
+
+        Ok(Header {
+            old_version,
+            file_size,
+            version,
+            compressed_size,
+        })
+    }
+}
+
+#[derive(Debug)]
+enum SectionChunkKind {
+    Cpu,
+    Cpuc,
+    Ppu,
+    Ctlr,
+    Snd,
+    Extra,
+    /// Extension chunk describing the savestate. Not part of the FCEUX format.
+    Metadata,
+    /// Extension chunk containing a framebuffer thumbnail. Not part of the FCEUX format.
+    Thumbnail,
+    Unknown,
+}
+
+impl SectionChunkKind {
+    pub fn new(byte: u8) -> Self {
+        match byte {
+            1 => Self::Cpu,
+            2 => Self::Cpuc,
+            3 => Self::Ppu,
+            4 => Self::Ctlr,
+            5 => Self::Snd,
+            16 => Self::Extra,
+            32 => Self::Thumbnail,
+            33 => Self::Metadata,
+            _ => Self::Unknown,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Cpu => "CPU",
+            Self::Cpuc => "CPUC",
+            Self::Ppu => "PPU",
+            Self::Ctlr => "CTLR",
+            Self::Snd => "SND",
+            Self::Extra => "EXTRA",
+            Self::Metadata => "META",
+            Self::Thumbnail => "THUMBNAIL",
+            Self::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+impl From<SectionChunkKind> for u8 {
+    fn from(value: SectionChunkKind) -> Self {
+        match value {
+            SectionChunkKind::Cpu => 1,
+            SectionChunkKind::Cpuc => 2,
+            SectionChunkKind::Ppu => 3,
+            SectionChunkKind::Ctlr => 4,
+            SectionChunkKind::Snd => 5,
+            SectionChunkKind::Extra => 16,
+            SectionChunkKind::Thumbnail => 32,
+            SectionChunkKind::Metadata => 33,
+            SectionChunkKind::Unknown => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CpuState {
+    pub(crate) accumulator: u8,
+    pub(crate) x_register: u8,
+    pub(crate) y_register: u8,
+    pub(crate) program_counter: u16,
+    pub(crate) stack_pointer: u8,
+    pub(crate) status: u8,
+    pub(crate) data_bus: u8,
+    pub(crate) ram: Box<[u8; 2048]>,
+}
+
+impl CpuState {
+    pub(crate) fn new(bytes: &[u8], warnings: &mut Vec<String>) -> Result<Self, String> {
+        let mut accumulator = 0;
+        let mut x_register = 0;
+        let mut y_register = 0;
+        let mut program_counter = 0;
+        let mut stack_pointer = 0;
+        let mut status = 0;
+        let mut data_bus = 0;
+        let mut ram = None;
+
+        let subchunk = Subchunk::new(bytes)?;
+        for (description, section) in subchunk {
+            match description {
+                "PC" => program_counter = deserialize(section)?,
+                "A" => accumulator = deserialize(section)?,
+                "P" => status = deserialize(section)?,
+                "X" => x_register = deserialize(section)?,
+                "Y" => y_register = deserialize(section)?,
+                "S" => stack_pointer = deserialize(section)?,
+                "DB" => data_bus = deserialize(section)?,
+                "RAM" => ram = Some(deserialize(section)?),
+                _ => warnings.push(unrecognized_section("cpu", description)),
+            }
+        }
+
+        Ok(Self {
+            accumulator,
+            x_register,
+            y_register,
+            program_counter,
+            stack_pointer,
+            status,
+            data_bus,
+            ram: ram.unwrap_or_else(crate::new_boxed_array),
+        })
+    }
+}
+
+/// The CPUC section, which FCEUX uses for its cycle counters.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct CpucState {
+    /// The number of CPU cycles the bus has run for. DMA reads and writes line up with the parity
+    /// of this count, so it's needed to resume DMA transfers the same way.
+    pub(crate) bus_cycle: Option<u64>,
+}
+
+impl CpucState {
+    fn new(bytes: &[u8]) -> Result<Self, String> {
+        let mut bus_cycle = None;
+
+        // FCEUX's own counters are relative to the current frame, not the whole run, so they're
+        // ignored.
+        let subchunk = Subchunk::new(bytes)?;
+        for (description, section) in subchunk {
+            if description == "CYCL" {
+                bus_cycle = Some(deserialize(section)?);
+            }
+        }
+
+        Ok(Self { bus_cycle })
+    }
+}
+
+/// What a savestate says about itself, for listing savestates in a save manager, read from the META
+/// section. Each field is left out of savestates that don't have it, such as ones from FCEUX.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SavestateMetadata {
+    /// When the savestate was made, in milliseconds since the Unix epoch.
+    pub timestamp: Option<u64>,
+    /// The name of the ROM the savestate was made with, such as its file name.
+    pub rom_name: Option<String>,
+    /// The CRC32 of the PRG and CHR ROM the savestate was made with.
+    pub rom_crc32: Option<u32>,
+    pub mapper_id: Option<u8>,
+    /// The number of frames rendered since power-on.
+    pub frame_count: Option<u64>,
+    /// The version of the emulator core that made the savestate.
+    pub emulator_version: Option<String>,
+    /// The accuracy profile the console was running with.
+    pub accuracy_profile: Option<AccuracyProfile>,
+}
+
+impl SavestateMetadata {
+    fn new(bytes: &[u8]) -> Result<Self, String> {
+        let mut metadata = Self::default();
+        let string = |section: &[u8]| {
+            String::from_utf8(section.to_vec()).map_err(|_| "invalid string in metadata")
+        };
+
+        for (description, section) in Subchunk::new(bytes)? {
+            match description {
+                "TIME" => metadata.timestamp = Some(deserialize(section)?),
+                "NAME" => metadata.rom_name = Some(string(section)?),
+                "ROMC" => metadata.rom_crc32 = Some(deserialize(section)?),
+                "MAPR" => metadata.mapper_id = Some(deserialize(section)?),
+                "FRAM" => metadata.frame_count = Some(deserialize(section)?),
+                "VERS" => metadata.emulator_version = Some(string(section)?),
+                // Profiles added by later versions are left out rather than turning the
+                // savestate away.
+                "ACCU" => metadata.accuracy_profile = string(section)?.parse().ok(),
+                _ => (),
+            }
+        }
+
+        Ok(metadata)
+    }
+
+    /// Serializes the metadata into the chunks of the META section, leaving out missing fields.
+    pub(crate) fn save(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+
+        if let Some(timestamp) = self.timestamp {
+            serialize_into(&mut buffer, &timestamp, "TIME");
+        }
+        if let Some(rom_name) = &self.rom_name {
+            serialize_into(&mut buffer, &rom_name.as_bytes(), "NAME");
+        }
+        if let Some(rom_crc32) = self.rom_crc32 {
+            serialize_into(&mut buffer, &rom_crc32, "ROMC");
+        }
+        if let Some(mapper_id) = self.mapper_id {
+            serialize_into(&mut buffer, &mapper_id, "MAPR");
+        }
+        if let Some(frame_count) = self.frame_count {
+            serialize_into(&mut buffer, &frame_count, "FRAM");
+        }
+        if let Some(version) = &self.emulator_version {
+            serialize_into(&mut buffer, &version.as_bytes(), "VERS");
+        }
+        if let Some(profile) = self.accuracy_profile {
+            serialize_into(&mut buffer, &profile.to_string().as_bytes(), "ACCU");
+        }
+
+        buffer
+    }
+}
+
+/// Reads the metadata of every `.fcs` savestate in a directory, newest first, for a save manager.
+/// Savestates without a timestamp are listed last, and files that can't be read are skipped.
+///
+/// # Errors
+///
+/// Returns an error if the directory can't be read.
+pub fn list(dir: impl AsRef<Path>) -> Result<Vec<(PathBuf, SavestateMetadata)>, String> {
+    let entries = std::fs::read_dir(dir).map_err(|err| err.to_string())?;
+    let mut savestates: Vec<_> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "fcs" {
+                return None;
+            }
+            let bytes = std::fs::read(&path).ok()?;
+            let metadata = Savestate::read_metadata(&bytes).ok()?;
+            Some((path, metadata))
+        })
+        .collect();
+    savestates.sort_by_key(|(_, metadata)| std::cmp::Reverse(metadata.timestamp));
+    Ok(savestates)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PpuState {
+    pub(crate) nametables: Box<[u8; 2048]>,
+    pub(crate) palette_ram: Box<[u8; 32]>,
+    pub(crate) oam: Box<[u8; 256]>,
+
+    pub(crate) control: u8,
+    pub(crate) mask: u8,
+    pub(crate) status: u8,
+    pub(crate) oam_addr: u8,
+
+    pub(crate) tile_x_offset: u8,
+    pub(crate) addr_latch: u8,
+    pub(crate) vram_addr: u16,
+    pub(crate) temp_vram_addr: u16,
+    pub(crate) data_buffer: u8,
+    pub(crate) general_latch: u8,
+}
+
+impl PpuState {
+    pub(crate) fn new(bytes: &[u8], warnings: &mut Vec<String>) -> Result<Self, String> {
+        let mut nametables = None;
+        let mut palette_ram = None;
+        let mut oam = None;
+
+        let mut control = 0;
+        let mut mask = 0;
+        let mut status = 0;
+        let mut oam_addr = 0;
+
+        let mut tile_x_offset = 0;
+        let mut addr_latch = 0;
+        let mut vram_addr = 0;
+        let mut temp_vram_addr = 0;
+        let mut data_buffer = 0;
+        let mut general_latch = 0;
+
+        let subchunk = Subchunk::new(bytes)?;
+        for (description, section) in subchunk {
+            match description {
+                "NTAR" => nametables = Some(deserialize(section)?),
+                "PRAM" => palette_ram = Some(deserialize(section)?),
+                "SPRA" => oam = Some(deserialize(section)?),
+                "PPUR" => [control, mask, status, oam_addr] = deserialize(section)?,
+                "XOFF" => tile_x_offset = deserialize(section)?,
+                "VTGL" => addr_latch = deserialize(section)?,
+                "RADD" => vram_addr = deserialize(section)?,
+                "TADD" => temp_vram_addr = deserialize(section)?,
+                "VBUF" => data_buffer = deserialize(section)?,
+                "PGEN" => general_latch = deserialize(section)?,
+                _ => warnings.push(unrecognized_section("ppu", description)),
+            }
+        }
+
+        Ok(Self {
+            nametables: nametables.unwrap_or_else(crate::new_boxed_array),
+            palette_ram: palette_ram.unwrap_or(Box::new(crate::ppu::POWER_UP_PALETTE)),
+            oam: oam.unwrap_or_else(crate::new_boxed_array),
+            control,
+            mask,
+            status,
+            oam_addr,
+            tile_x_offset,
+            addr_latch,
+            vram_addr,
+            temp_vram_addr,
+            data_buffer,
+            general_latch,
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ApuEnvelopeState {
+    pub(crate) divider_reload: u8,
+    pub(crate) divider: u8,
+    pub(crate) mode: u8,
+    pub(crate) decay_level: u8,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ApuSweepState {
+    pub(crate) is_enabled: bool,
+    pub(crate) target_period: u16,
+    pub(crate) divider: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ApuState {
+    /// All values from 0x4000-0x400F for channels 1-4, unused bytes included.
+    pub(crate) channel_data: [u8; 16],
+    pub(crate) channel_enables: u8,
+    pub(crate) frame_mode: u8,
+    pub(crate) noise_shift_register: u16,
+    pub(crate) triangle_linear_counter_reload_flag: bool,
+    pub(crate) triangle_linear_counter: u8,
+
+    pub(crate) pulse_1_envelope: ApuEnvelopeState,
+    pub(crate) pulse_2_envelope: ApuEnvelopeState,
+    pub(crate) noise_envelope: ApuEnvelopeState,
+
+    pub(crate) pulse_1_sweep: ApuSweepState,
+    pub(crate) pulse_2_sweep: ApuSweepState,
+
+    pub(crate) pulse_1_length_counter: u8,
+    pub(crate) pulse_2_length_counter: u8,
+    pub(crate) triangle_length_counter: u8,
+    pub(crate) noise_length_counter: u8,
+}
+
+impl ApuState {
+    pub fn new(bytes: &[u8], warnings: &mut Vec<String>) -> Result<Self, String> {
+        let mut channel_data = None;
+        let mut channel_enables = 0;
+        let mut frame_mode = 0;
+        let mut noise_shift_register = 1;
+        let mut triangle_linear_counter_reload_flag = false;
+        let mut triangle_linear_counter = 0;
+
+        let mut pulse_1_envelope = ApuEnvelopeState::default();
+        let mut pulse_2_envelope = ApuEnvelopeState::default();
+        let mut noise_envelope = ApuEnvelopeState::default();
+
+        let mut pulse_1_sweep = ApuSweepState::default();
+        let mut pulse_2_sweep = ApuSweepState::default();
+
+        let mut pulse_1_length_counter = 0;
+        let mut pulse_2_length_counter = 0;
+        let mut triangle_length_counter = 0;
+        let mut noise_length_counter = 0;
+
+        let subchunk = Subchunk::new(bytes)?;
+        for (description, section) in subchunk {
+            match description {
+                "FHCN" | "FCNT" => {} // Unsure what these counters are supposed to mean.
+                "PSG" => channel_data = Some(deserialize(section)?),
+                "ENCH" => channel_enables = deserialize(section)?,
+                "IQFM" => frame_mode = deserialize(section)?,
+                "NREG" => noise_shift_register = deserialize(section)?,
+                "TRIM" => triangle_linear_counter_reload_flag = deserialize(section)?,
+                "TRIC" => triangle_linear_counter = deserialize(section)?,
+
+                "E0SP" => pulse_1_envelope.divider_reload = deserialize(section)?,
+                "E1SP" => pulse_2_envelope.divider_reload = deserialize(section)?,
+                "E2SP" => noise_envelope.divider_reload = deserialize(section)?,
+
+                "E0MO" => pulse_1_envelope.mode = deserialize(section)?,
+                "E1MO" => pulse_2_envelope.mode = deserialize(section)?,
+                "E2MO" => noise_envelope.mode = deserialize(section)?,
+
+                "E0D1" => pulse_1_envelope.divider = deserialize(section)?,
+                "E1D1" => pulse_2_envelope.divider = deserialize(section)?,
+                "E2D1" => noise_envelope.divider = deserialize(section)?,
+
+                "E0DV" => pulse_1_envelope.decay_level = deserialize(section)?,
+                "E1DV" => pulse_2_envelope.decay_level = deserialize(section)?,
+                "E2DV" => noise_envelope.decay_level = deserialize(section)?,
+
+                // FCEUX treats these as u8 but stores them as i32 for some reason.
+                "LEN0" => pulse_1_length_counter = deserialize::<u32>(section)? as u8,
+                "LEN1" => pulse_2_length_counter = deserialize::<u32>(section)? as u8,
+                "LEN2" => triangle_length_counter = deserialize::<u32>(section)? as u8,
+                "LEN3" => noise_length_counter = deserialize::<u32>(section)? as u8,
+
+                "SWEE" => {
+                    [pulse_1_sweep.is_enabled, pulse_2_sweep.is_enabled] = deserialize(section)?
+                }
+
+                // FCEUX treats these as u16 but stores them as i32 for some reason.
+                "CRF1" => pulse_1_sweep.target_period = deserialize::<u32>(section)? as u16,
+                "CRF2" => pulse_2_sweep.target_period = deserialize::<u32>(section)? as u16,
+
+                "SWCT" => [pulse_1_sweep.divider, pulse_2_sweep.divider] = deserialize(section)?,
+                "SIRQ" | "5ACC" | "5BIT" | "5ADD" | "5SIZ" | "5SHF" | "5HVD" | "5HVS" | "5SZL"
+                | "5ADL" | "5FMT" | "RWDA" => {} // TODO: DMC channel.
+                _ => warnings.push(unrecognized_section("apu", description)),
+            }
+        }
+
+        Ok(Self {
+            channel_data: channel_data.unwrap_or_default(),
+            channel_enables,
+            frame_mode,
+            noise_shift_register,
+            triangle_linear_counter_reload_flag,
+            triangle_linear_counter,
+
+            pulse_1_envelope,
+            pulse_2_envelope,
+            noise_envelope,
+
+            pulse_1_sweep,
+            pulse_2_sweep,
+
+            pulse_1_length_counter,
+            pulse_2_length_counter,
+            triangle_length_counter,
+            noise_length_counter,
+        })
+    }
+}
+
+/// The state of every component of the system, as [crate::Bus::save_state] would save it, but
+/// kept parsed so that two systems can be compared or hashed directly, such as by
+/// [crate::find_divergence] and tests. Captured with [crate::Bus::system_state].
+///
+/// Unlike a savestate, this leaves out the metadata and thumbnail, which don't affect emulation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SystemState {
+    /// The CPU's registers and internal RAM.
+    pub cpu: CpuState,
+    /// The number of CPU cycles the bus has run for.
+    pub cycle: u64,
+    pub ppu: PpuState,
+    pub apu: ApuState,
+    pub cartridge: CartridgeState,
+}
+
+impl SystemState {
+    /// Returns the names of the components whose state differs from `other`'s: `CPU`, `RAM`,
+    /// `PPU`, `APU`, or `cartridge`. The cycle count isn't a component, so isn't listed.
+    pub fn diff(&self, other: &Self) -> Vec<&'static str> {
+        let (cpu, other_cpu) = (&self.cpu, &other.cpu);
+        let registers = |cpu: &CpuState| {
+            (
+                cpu.accumulator,
+                cpu.x_register,
+                cpu.y_register,
+                cpu.program_counter,
+                cpu.stack_pointer,
+                cpu.status,
+                cpu.data_bus,
+            )
+        };
+        [
+            ("CPU", registers(cpu) != registers(other_cpu)),
+            ("RAM", cpu.ram != other_cpu.ram),
+            ("PPU", self.ppu != other.ppu),
+            ("APU", self.apu != other.apu),
+            ("cartridge", self.cartridge != other.cartridge),
+        ]
+        .into_iter()
+        .filter(|&(_, differs)| differs)
+        .map(|(name, _)| name)
+        .collect()
+    }
+}
+
+/// The state of a cartridge's mapper, as its own savestate chunks hold it. ROM can't change, so
+/// only RAM and registers are kept.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CartridgeState {
+    /// CHR RAM, if the cartridge has it rather than CHR ROM.
+    pub(crate) chr_ram: Option<Vec<u8>>,
+    /// PRG RAM, or the mapper's own RAM, if the cartridge has any.
+    pub(crate) prg_ram: Option<Vec<u8>>,
+    pub(crate) registers: MapperRegisters,
+}
+
+/// The registers each mapper saves.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum MapperRegisters {
+    Mapper0,
+    Mapper1 {
+        control: u8,
+        chr_bank_0: u8,
+        chr_bank_1: u8,
+        prg_bank: u8,
+        shift: u8,
+        shift_count: u8,
+    },
+    Mapper2 {
+        prg_bank: u8,
+    },
+    Mapper4 {
+        bank_register: [u8; 8],
+        bank_select: u8,
+        mirroring: Mirroring,
+        prg_ram_protect: u8,
+        irq_reload: bool,
+        irq_counter: u8,
+        irq_latch: u8,
+        is_irq_enabled: bool,
+    },
+    Mapper228 {
+        addr_latch: u16,
+        data_latch: u8,
+    },
+}
+
+pub struct MapperState<'a> {
+    subchunk: Subchunk<'a>,
+}
+
+impl<'a> MapperState<'a> {
+    pub fn new(bytes: &'a [u8]) -> Result<Self, String> {
+        Ok(Self {
+            subchunk: Subchunk::new(bytes)?,
+        })
+    }
+
+    /// Iterates over the description and data of each chunk, without consuming the state.
+    pub fn chunks(&self) -> impl Iterator<Item = (&'a str, &'a [u8])> + '_ {
+        self.subchunk.sections.iter().copied()
+    }
+}
+
+impl<'a> IntoIterator for MapperState<'a> {
+    type Item = <Subchunk<'a> as IntoIterator>::Item;
+    type IntoIter = <Subchunk<'a> as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.subchunk.into_iter()
+    }
+}
+
+pub struct Subchunk<'a> {
+    sections: Vec<(&'a str, &'a [u8])>,
+}
+
+impl<'a> Subchunk<'a> {
+    pub fn new(mut bytes: &'a [u8]) -> Result<Self, String> {
+        let mut sections = Vec::new();
+
+        while !bytes.is_empty() {
+            if bytes.len() < 8 {
+                return Err("chunk header ended unexpectedly".into());
+            }
+
+            let (header, rest) = bytes.split_at(8);
+            let size = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+            if rest.len() < size {
+                return Err("chunk length doesn't match header".into());
+            }
+
+            let (section, rest) = rest.split_at(size);
+            bytes = rest;
+
+            let description = std::str::from_utf8(&header[0..4])
+                .map_err(|_| "invalid chunk description")?
+                .trim_end_matches('\0');
+            sections.push((description, section));
+        }
+
+        Ok(Self { sections })
+    }
+}
+
+impl<'a> IntoIterator for Subchunk<'a> {
+    type Item = (&'a str, &'a [u8]);
+    type IntoIter = <Vec<Self::Item> as IntoIterator>::IntoIter;
+
+    /// Iterates over tuples of a chunk's description and data.
+    fn into_iter(self) -> Self::IntoIter {
+        self.sections.into_iter()
+    }
+}
+
+/// Describes a section of a component's state that was skipped because it wasn't recognized, as
+/// with states from newer versions or other emulators.
+pub(crate) fn unrecognized_section(component: &str, description: &str) -> String {
+    format!("skipped unrecognized section `{description}` in {component} state")
+}
+
+pub fn deserialize<T: FromBytes>(bytes: &[u8]) -> Result<T, String> {
+    T::from_bytes(bytes).ok_or_else(|| "invalid section size".into())
+}
+
+pub fn serialize<T: ToBytes>(value: &T, description: &str) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    serialize_into(&mut buffer, value, description);
+    buffer
+}
+
+/// Appends a section to `buffer` like [serialize], without allocating once `buffer` has grown
+/// large enough, so that a reused buffer can hold a savestate every frame.
+pub fn serialize_into<T: ToBytes>(buffer: &mut Vec<u8>, value: &T, description: &str) {
+    let mut header = [0; 4];
+    for (byte, &description_byte) in header.iter_mut().zip(description.as_bytes()) {
+        *byte = description_byte;
+    }
+    buffer.extend_from_slice(&header);
+
+    // Write a placeholder length and fill it in once the data's size is known.
+    let length_index = buffer.len();
+    buffer.extend_from_slice(&[0; 4]);
+    value.write_bytes(buffer);
+    let data_length = (buffer.len() - length_index - 4) as u32;
+    buffer[length_index..length_index + 4].copy_from_slice(&data_length.to_le_bytes());
+}
+
+/// A component with state that's stored in savestates.
+///
+/// Only state that affects emulation belongs in a savestate. Presentation state, like frame
+/// buffers, the memory viewer, and audio settings, is left out of both saving and applying, so
+/// loading a state never changes how the frontend displays things, and saving at the same point in
+/// emulation produces the same bytes regardless of what the frontend has been doing. Each
+/// implementation notes which of its fields are left out.
+pub trait StateSerialize {
+    /// The component's state, as parsed from a savestate.
+    type State<'a>;
+
+    fn apply_state(&mut self, state: Self::State<'_>);
+    /// Serializes the component's state into the sections of its savestate chunk, appending them
+    /// to `buffer`.
+    fn save_state_into(&self, buffer: &mut Vec<u8>);
+
+    /// Serializes the component's state into a new buffer. See [StateSerialize::save_state_into].
+    fn save_state(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        self.save_state_into(&mut buffer);
+        buffer
+    }
+}
+
+pub trait FromBytes: Sized {
+    fn from_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+pub trait ToBytes {
+    /// Appends the value's bytes to `buffer`.
+    fn write_bytes(&self, buffer: &mut Vec<u8>);
+}
+
+impl FromBytes for u8 {
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(u8::from_le_bytes(bytes.try_into().ok()?))
+    }
+}
+
+impl FromBytes for u16 {
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(u16::from_le_bytes(bytes.try_into().ok()?))
+    }
+}
+
+impl FromBytes for u32 {
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(u32::from_le_bytes(bytes.try_into().ok()?))
+    }
+}
+
+impl FromBytes for u64 {
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(u64::from_le_bytes(bytes.try_into().ok()?))
+    }
+}
+
+impl FromBytes for bool {
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(u8::from_bytes(bytes)? != 0)
+    }
+}
+
+impl<const N: usize> FromBytes for Box<[u8; N]> {
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(Box::new(bytes.try_into().ok()?))
+    }
+}
+
+impl<const N: usize> FromBytes for [u8; N] {
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        bytes.try_into().ok()
+    }
+}
+
+impl<const N: usize> FromBytes for [bool; N] {
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        bytes
+            .iter()
+            .copied()
+            .map(|b| b != 0)
+            .collect::<Vec<_>>()
+            .try_into()
+            .ok()
+    }
+}
+
+impl FromBytes for Vec<u8> {
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(bytes.into())
+    }
+}
+
+impl ToBytes for u8 {
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl ToBytes for u16 {
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl ToBytes for u32 {
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl ToBytes for u64 {
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl ToBytes for bool {
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.push(*self as u8);
+    }
+}
+
+impl<const N: usize> ToBytes for Box<[u8; N]> {
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self[..]);
+    }
+}
+
+impl<const N: usize> ToBytes for [u8; N] {
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self[..]);
+    }
+}
+
+impl ToBytes for &[u8] {
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self[..]);
+    }
+}
+
+impl<const N: usize> ToBytes for [bool; N] {
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.extend(self.iter().map(|&b| b as u8));
+    }
+}
+
+impl ToBytes for Vec<u8> {
+    fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::{Apu, Bus, Cartridge, Cpu, PixelSource, Ppu, StopCondition};
+
+    /// A program in RAM that copies the first byte of the PRG bank at $8000 to $11 and the first
+    /// byte of PRG RAM to $12, while counting loop iterations in $10.
+    const PROGRAM: [u8; 15] = [
+        0xAD, 0x00, 0x80, // LDA $8000
+        0x85, 0x11, //       STA $11
+        0xAD, 0x00, 0x60, // LDA $6000
+        0x85, 0x12, //       STA $12
+        0xE6, 0x10, //       INC $10
+        0x4C, 0x00, 0x07, // JMP $0700
+    ];
+
+    #[test]
+    fn nrom() {
+        // Reads from $6000 are open bus, which holds the high byte of the address.
+        check_fixture(0, &[], 0, 0x60);
+    }
+
+    #[test]
+    fn mmc1() {
+        let mapper = [
+            serialize(&[0x55u8; 8 * 1024].as_slice(), "WRAM"),
+            // Switch 16k at $8000 (PRG mode 3) to bank 3, which is 8k bank 6.
+            serialize(&[0x0Cu8, 0, 0, 3], "DREG"),
+            serialize(&[0u8; 8], "LRST"),
+            serialize(&0u8, "BFFR"),
+            serialize(&0u8, "BFRS"),
+        ]
+        .concat();
+        check_fixture(1, &mapper, 6, 0x55);
+    }
+
+    #[test]
+    fn uxrom() {
+        let mapper = [serialize(&0u8, "BUSC"), serialize(&2u8, "LATC")].concat();
+        check_fixture(2, &mapper, 4, 0x60);
+    }
+
+    #[test]
+    fn mmc3() {
+        let mapper = [
+            // R6 selects the 8k bank at $8000.
+            serialize(&[0u8, 2, 4, 5, 6, 7, 5, 1], "REGS"),
+            serialize(&0u8, "CMD"),
+            serialize(&1u8, "A000"),
+            serialize(&0x80u8, "A001"),
+            serialize(&0u8, "IRQR"),
+            serialize(&0u8, "IRQC"),
+            serialize(&0u8, "IRQL"),
+            serialize(&0u8, "IRQA"),
+            serialize(&[0x44u8; 8 * 1024].as_slice(), "WRAM"),
+        ]
+        .concat();
+        check_fixture(4, &mapper, 5, 0x44);
+    }
+
+    /// Loads the savestate fixtures in `test_roms/fcs`, one for each supported mapper, checking
+    /// RAM and the frame after running from each against the checksums recorded with them. Unlike
+    /// the fixtures above, these are stored compressed on disk, so changes to how savestates are
+    /// read are checked against bytes that can't change along with them.
+    ///
+    /// For each mapper `N`, `mapper_N.nes` is the ROM, `mapper_N.fcs` is the savestate, and
+    /// `mapper_N.txt` holds the CRC32 of RAM and of the frame in RGB, in hex on separate lines,
+    /// after 10 frames. They were generated in FCEUX's layout with [fceux_state] and the mapper
+    /// chunks from the tests above, rather than saved from FCEUX itself.
+    #[test]
+    fn fceux_savestates() {
+        for mapper in crate::mapper::SUPPORTED_MAPPERS {
+            let path = |extension| format!("./test_roms/fcs/mapper_{mapper}.{extension}");
+            let rom = std::fs::read(path("nes")).unwrap();
+            let state = std::fs::read(path("fcs")).unwrap();
+            let expected: Vec<u32> = std::fs::read_to_string(path("txt"))
+                .unwrap()
+                .lines()
+                .map(|line| u32::from_str_radix(line.trim(), 16).unwrap())
+                .collect();
+
+            let system = crate::System::new(Cartridge::new(&rom).unwrap());
+            system.bus.borrow_mut().load_state(&state).unwrap();
+            for _ in 0..10 {
+                Bus::run_frame(
+                    system.bus.clone(),
+                    system.cpu.clone(),
+                    system.ppu.clone(),
+                    system.apu.clone(),
+                );
+            }
+            let actual = [
+                system.bus.borrow().ram_crc32(),
+                system.ppu.borrow().frame_crc32(),
+            ];
+            assert_eq!(actual.as_slice(), expected, "mapper {mapper}");
+        }
+    }
+
+    #[test]
+    fn thumbnail_round_trip() {
+        let system = crate::System::new(Cartridge::new(&rom(0)).unwrap());
+        system
+            .bus
+            .borrow_mut()
+            .load_state(&fceux_state(&[]))
+            .unwrap();
+        for _ in 0..2 {
+            let (bus, cpu, ppu, apu) = (&system.bus, &system.cpu, &system.ppu, &system.apu);
+            Bus::run_frame(bus.clone(), cpu.clone(), ppu.clone(), apu.clone());
+        }
+        let frame = system.ppu.borrow().thumbnail();
+        // The screen is filled with the second color of palette 0.
+        assert_eq!(frame.len(), THUMBNAIL_SIZE);
+        assert_ne!(frame[..3], [0, 0, 0]);
+        assert!(frame.chunks(3).all(|pixel| pixel == &frame[..3]));
+
+        let state = Savestate::decompress(&system.bus.borrow().save_state())
+            .unwrap()
+            .into_owned();
+        let savestate = Savestate::new(&state).unwrap();
+        assert_eq!(savestate.thumbnail(), Some(frame.as_slice()));
+
+        // A thumbnail of the wrong size is left out rather than misread.
+        let state = Savestate::save(
+            &system.cpu.borrow().save_state(),
+            &[],
+            &system.ppu.borrow().save_state(),
+            &system.apu.borrow().save_state(),
+            &system.cartridge.borrow().save_state(),
+            &[],
+            &frame[3..],
+        );
+        let state = Savestate::decompress(&state).unwrap();
+        assert_eq!(Savestate::new(&state).unwrap().thumbnail(), None);
+    }
+
+    #[test]
+    fn unrecognized_sections() {
+        let state = fceux_state(&serialize(&0u8, "ZZZZ"));
+        assert!(Savestate::new(&state).unwrap().warnings().is_empty());
+
+        let system = crate::System::new(Cartridge::new(&rom(0)).unwrap());
+        let mut bus = system.bus.borrow_mut();
+        bus.load_state(&state).unwrap();
+        assert_eq!(
+            bus.drain_warnings(),
+            ["skipped unrecognized section `ZZZZ` in mapper state"]
+        );
+        assert!(bus.drain_warnings().is_empty());
+    }
+
+    #[test]
+    fn diffing() {
+        let cartridge = Cartridge::from_raw_prg(&[], 0x8000, [0; 3]).unwrap();
+        let cartridge = Rc::new(RefCell::new(cartridge));
+        let cpu = Rc::new(RefCell::new(Cpu::new()));
+        let ppu = Rc::new(RefCell::new(Ppu::new(cartridge.clone())));
+        let apu = Rc::new(RefCell::new(Apu::new()));
+        let bus = Bus::new(cpu.clone(), crate::new_boxed_array(), ppu, apu, cartridge);
+        cpu.borrow_mut().reset();
+
+        let before = bus.borrow().save_state();
+        assert_eq!(diff(&before, &before), Ok(Vec::new()));
+
+        for addr in [0x0010, 0x0011, 0x0020] {
+            bus.borrow_mut().cpu_write(addr, 0xFF);
+        }
+        cpu.borrow_mut().set_program_counter(0x1234);
+        let after = bus.borrow().save_state();
+        let diffs = diff(&before, &after).unwrap();
+        assert_eq!(
+            diffs,
+            [
+                ChunkDiff {
+                    section: "CPU",
+                    chunk: "PC".into(),
+                    change: ChunkChange::Value {
+                        old: 0x0000,
+                        new: 0x1234
+                    },
+                },
+                ChunkDiff {
+                    section: "CPU",
+                    chunk: "RAM".into(),
+                    change: ChunkChange::Bytes(vec![0x10..0x12, 0x20..0x21]),
+                },
+            ]
+        );
+        assert_eq!(
+            diffs[1].to_string(),
+            "CPU/RAM: differs at $0010-$0011, $0020"
+        );
+
+        // Applying a savestate and saving it again is lossless.
+        bus.borrow_mut().load_state(&before).unwrap();
+        assert_eq!(diff(&before, &bus.borrow().save_state()), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let system = crate::System::new(Cartridge::new(&rom(0)).unwrap());
+        let mut state = fceux_state(&[]);
+        // FCE Ultra 0.98 wrote its version number in place of the `X`.
+        state[3] = 98;
+        assert_eq!(
+            system.bus.borrow_mut().load_state(&state),
+            Err(SavestateError::UnsupportedVersion(98))
+        );
+        state.truncate(20);
+        state[3] = b'X';
+        assert!(matches!(
+            system.bus.borrow_mut().load_state(&state),
+            Err(SavestateError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn decompression_limits() {
+        let state = fceux_state(&[]);
+        let compressed = compress(&state);
+        assert_eq!(
+            Savestate::decompress(&compressed).unwrap(),
+            state.as_slice()
+        );
+
+        let with_file_size = |file_size: u32| {
+            let mut compressed = compressed.clone();
+            compressed[4..8].copy_from_slice(&file_size.to_le_bytes());
+            compressed
+        };
+        let file_size = state.len() as u32 - 16;
+        assert_eq!(
+            Savestate::decompress(&with_file_size(u32::MAX)),
+            Err(SavestateError::TooLarge(u32::MAX))
+        );
+        let limit = MAX_SAVESTATE_SIZE as u32 - 16;
+        assert_eq!(
+            Savestate::decompress(&with_file_size(limit + 1)),
+            Err(SavestateError::TooLarge(limit + 1))
+        );
+        assert!(matches!(
+            Savestate::decompress(&with_file_size(limit)),
+            Err(SavestateError::Corrupt(_))
+        ));
+        assert!(Savestate::decompress(&with_file_size(file_size + 1)).is_err());
+        assert!(Savestate::decompress(&with_file_size(file_size - 1)).is_err());
+
+        // Cut off partway through the compressed data, with the header changed to match.
+        let mut truncated = compressed[..compressed.len() / 2].to_vec();
+        let compressed_size = truncated.len() as u32 - 16;
+        truncated[12..16].copy_from_slice(&compressed_size.to_le_bytes());
+        assert!(Savestate::decompress(&truncated).is_err());
+    }
+
+    #[test]
+    fn save_state_into_reuses_buffer() {
+        assert_eq!(
+            serialize(&0x1234u16, "XY"),
+            [b'X', b'Y', 0, 0, 2, 0, 0, 0, 0x34, 0x12]
+        );
+
+        let cartridge = Rc::new(RefCell::new(Cartridge::new(&rom(4)).unwrap()));
+        let apu = Apu::new();
+        let ppu = Ppu::new(cartridge.clone());
+
+        let mut buffer = Vec::new();
+        apu.save_state_into(&mut buffer);
+        ppu.save_state_into(&mut buffer);
+        cartridge.borrow().save_state_into(&mut buffer);
+        let expected = [
+            apu.save_state(),
+            ppu.save_state(),
+            cartridge.borrow().save_state(),
+        ]
+        .concat();
+        assert_eq!(buffer, expected);
+
+        // Once the buffer has grown to fit a state, saving into it again doesn't reallocate.
+        let (capacity, pointer) = (buffer.capacity(), buffer.as_ptr());
+        buffer.clear();
+        apu.save_state_into(&mut buffer);
+        ppu.save_state_into(&mut buffer);
+        cartridge.borrow().save_state_into(&mut buffer);
+        assert_eq!(buffer, expected);
+        assert_eq!((buffer.capacity(), buffer.as_ptr()), (capacity, pointer));
+    }
+
+    #[test]
+    fn metadata() {
+        let cartridge = Cartridge::from_raw_prg(&[], 0x8000, [0; 3]).unwrap();
+        let rom_crc32 = cartridge.rom_crc32();
+        let cartridge = Rc::new(RefCell::new(cartridge));
+        let cpu = Rc::new(RefCell::new(Cpu::new()));
+        let ppu = Rc::new(RefCell::new(Ppu::new(cartridge.clone())));
+        let apu = Rc::new(RefCell::new(Apu::new()));
+        let bus = Bus::new(cpu.clone(), crate::new_boxed_array(), ppu, apu, cartridge);
+        cpu.borrow_mut().reset();
+
+        let state = bus
+            .borrow()
+            .save_state_with_metadata(Some(1_700_000_000_000), Some("game.nes"));
+        let metadata = SavestateMetadata {
+            timestamp: Some(1_700_000_000_000),
+            rom_name: Some("game.nes".to_string()),
+            rom_crc32: Some(rom_crc32),
+            mapper_id: Some(0),
+            frame_count: Some(0),
+            emulator_version: Some(crate::capabilities::VERSION.to_string()),
+            accuracy_profile: Some(AccuracyProfile::Balanced),
+        };
+        assert_eq!(Savestate::read_metadata(&state), Ok(metadata.clone()));
+        let decompressed = Savestate::decompress(&state).unwrap();
+        assert_eq!(Savestate::new(&decompressed).unwrap().metadata(), &metadata);
+
+        // FCEUX doesn't write any metadata.
+        let state = fceux_state(&[]);
+        assert_eq!(
+            Savestate::read_metadata(&state),
+            Ok(SavestateMetadata::default())
+        );
+    }
+
+    #[test]
+    fn presentation_state() {
+        let cartridge = Cartridge::from_raw_prg(&[], 0x8000, [0; 3]).unwrap();
+        let cartridge = Rc::new(RefCell::new(cartridge));
+        let cpu = Rc::new(RefCell::new(Cpu::new()));
+        let ppu = Rc::new(RefCell::new(Ppu::new(cartridge.clone())));
+        let apu = Rc::new(RefCell::new(Apu::new()));
+        let bus = Bus::new(
+            cpu.clone(),
+            crate::new_boxed_array(),
+            ppu.clone(),
+            apu.clone(),
+            cartridge,
+        );
+        cpu.borrow_mut().reset();
+        let before = bus.borrow().save_state();
+
+        // None of these settings end up in the savestate...
+        ppu.borrow_mut().set_pixel_inspection(true);
+        ppu.borrow_mut().set_overclock_scanlines(10);
+        apu.borrow_mut().is_pulse_1_enabled = false;
+        apu.borrow_mut().gains.noise = 0.5;
+        assert_eq!(before, bus.borrow().save_state());
+
+        // ...or are touched when one is applied.
+        bus.borrow_mut().load_state(&before).unwrap();
+        assert!(ppu.borrow().is_pixel_inspection_enabled());
+        assert!(!apu.borrow().is_pulse_1_enabled);
+        assert_eq!(apu.borrow().gains.noise, 0.5);
+    }
+
+    /// Loads a savestate laid out like one written by FCEUX, both compressed and uncompressed,
+    /// and checks that running from it reads the expected PRG bank and PRG RAM, renders the
+    /// saved nametables, and behaves identically each time.
+    fn check_fixture(mapper_id: u8, mapper_chunks: &[u8], prg_bank: u8, prg_ram: u8) {
+        let rom = rom(mapper_id);
+        let state = fceux_state(mapper_chunks);
+
+        let (ram, frame_hash) = run_state(&rom, &state);
+        assert_eq!(ram[0x11], prg_bank);
+        assert_eq!(ram[0x12], prg_ram);
+        // RAM untouched by the program is restored as saved.
+        assert_eq!(ram[0x0300], 0xA5);
+        assert!(ram[0x10] > 0);
+
+        assert_eq!(run_state(&rom, &compress(&state)), (ram, frame_hash));
+    }
+
+    /// Runs 3 frames from a savestate, returning the contents of RAM and a checksum of the last
+    /// frame.
+    fn run_state(rom: &[u8], state: &[u8]) -> ([u8; 2048], u32) {
+        let cartridge = Rc::new(RefCell::new(Cartridge::new(rom).unwrap()));
+        let cpu = Rc::new(RefCell::new(Cpu::new()));
+        let ppu = Rc::new(RefCell::new(Ppu::new(cartridge.clone())));
+        ppu.borrow_mut().set_pixel_inspection(true);
+        let apu = Rc::new(RefCell::new(Apu::new()));
+        let bus = Bus::new(
+            cpu.clone(),
+            crate::new_boxed_array(),
+            ppu.clone(),
+            apu.clone(),
+            cartridge,
+        );
+        cpu.borrow_mut().reset();
+
+        bus.borrow_mut().load_state(state).unwrap();
+        for _ in 0..3 {
+            let condition = StopCondition::Frame;
+            assert!(Bus::run_until(
+                bus.clone(),
+                cpu.clone(),
+                ppu.clone(),
+                apu.clone(),
+                condition
+            ));
+        }
+
+        // Every tile in the saved nametables is opaque, using the second color of palette 0.
+        assert_eq!(
+            ppu.borrow().inspect_pixel(100, 100),
+            Some(PixelSource::Background {
+                nametable_addr: 0x2000 + 12 * 32 + 12,
+                tile: 1,
+                pattern_addr: 0x0010,
+                palette: 0,
+                color: 0x16,
+            })
+        );
+
+        let mut ram = [0; 2048];
+        for (addr, byte) in ram.iter_mut().enumerate() {
+            *byte = bus.borrow_mut().cpu_read(addr as u16);
+        }
+        let frame_crc32 = ppu.borrow().frame_crc32();
+
+        (ram, frame_crc32)
+    }
+
+    /// Builds a 128k ROM with CHR RAM for the given mapper, where the first byte of each 8k PRG
+    /// bank is the bank's index.
+    fn rom(mapper_id: u8) -> Vec<u8> {
+        let prg_size = if mapper_id == 0 { 2 } else { 8 };
+        let header = [
+            0x4E,
+            0x45,
+            0x53,
+            0x1A,
+            prg_size,
+            0,
+            mapper_id << 4,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ];
+
+        let mut rom = header.to_vec();
+        rom.resize(header.len() + prg_size as usize * 16 * 1024, 0);
+        for bank in 0..prg_size as usize * 2 {
+            rom[header.len() + bank * 8 * 1024] = bank as u8;
+        }
+        rom
+    }
+
+    /// Builds an uncompressed savestate with the sections and chunks FCEUX writes, including the
+    /// ones this emulator ignores.
+    fn fceux_state(mapper_chunks: &[u8]) -> Vec<u8> {
+        let mut ram = [0u8; 2048];
+        ram[0x0700..0x0700 + PROGRAM.len()].copy_from_slice(&PROGRAM);
+        ram[0x0300] = 0xA5;
+        let cpu = [
+            serialize(&0x0700u16, "PC"),
+            serialize(&0u8, "A"),
+            serialize(&0x24u8, "P"),
+            serialize(&0u8, "X"),
+            serialize(&0u8, "Y"),
+            serialize(&0xFDu8, "S"),
+            serialize(&0u8, "DB"),
+            serialize(&ram, "RAM"),
+        ]
+        .concat();
+        let cpuc = [
+            serialize(&0u8, "JAMM"),
+            serialize(&0u8, "IQLB"),
+            serialize(&0u32, "ICoa"),
+            serialize(&0u32, "ICou"),
+        ]
+        .concat();
+
+        // Fill both nametables with tile 1, using palette 0 throughout.
+        let mut nametables = [1u8; 2048];
+        nametables[0x03C0..0x0400].fill(0);
+        nametables[0x07C0..0x0800].fill(0);
+        let mut palette_ram = [0x0Fu8; 32];
+        palette_ram[1] = 0x16;
+        let ppu = [
+            serialize(&nametables, "NTAR"),
+            serialize(&palette_ram, "PRAM"),
+            // Hide all sprites below the screen.
+            serialize(&[0xFFu8; 256], "SPRA"),
+            // Show the background, including the leftmost column.
+            serialize(&[0x00u8, 0x0A, 0x00, 0x00], "PPUR"),
+            serialize(&0u8, "XOFF"),
+            serialize(&0u8, "VTGL"),
+            serialize(&0u16, "RADD"),
+            serialize(&0u16, "TADD"),
+            serialize(&0u8, "VBUF"),
+            serialize(&0u8, "PGEN"),
+        ]
+        .concat();
+        let ctlr = [serialize(&[0u8; 4], "JOYS"), serialize(&[0u8; 4], "LATC")].concat();
+
+        let mut snd = vec![
+            serialize(&0u32, "FHCN"),
+            serialize(&0u32, "FCNT"),
+            serialize(&[0u8; 16], "PSG"),
+            serialize(&0u8, "ENCH"),
+            serialize(&0u8, "IQFM"),
+            serialize(&1u16, "NREG"),
+            serialize(&0u8, "TRIM"),
+            serialize(&0u8, "TRIC"),
+        ];
+        for prefix in ["E0", "E1", "E2"] {
+            for suffix in ["SP", "MO", "D1", "DV"] {
+                snd.push(serialize(&0u8, &format!("{prefix}{suffix}")));
+            }
+        }
+        for description in ["LEN0", "LEN1", "LEN2", "LEN3", "CRF1", "CRF2"] {
+            snd.push(serialize(&0u32, description));
+        }
+        snd.push(serialize(&[0u8; 2], "SWEE"));
+        snd.push(serialize(&[0u8; 2], "SWCT"));
+        for description in ["SIRQ", "5BIT", "5SHF", "5HVD", "5HVS", "5FMT", "RWDA"] {
+            snd.push(serialize(&0u8, description));
+        }
+        for description in ["5ACC", "5ADD", "5SIZ", "5SZL", "5ADL"] {
+            snd.push(serialize(&0u32, description));
+        }
+        let snd = snd.concat();
+
+        // Tile 1 has every pixel set in its low bit plane.
+        let mut chr_ram = vec![0u8; 8 * 1024];
+        chr_ram[0x10..0x18].fill(0xFF);
+        let extra = [mapper_chunks, &serialize(&chr_ram, "CHRR")].concat();
+
+        let mut body = Vec::new();
+        for (kind, section) in [
+            (SectionChunkKind::Cpu, cpu),
+            (SectionChunkKind::Cpuc, cpuc),
+            (SectionChunkKind::Ppu, ppu),
+            (SectionChunkKind::Ctlr, ctlr),
+            (SectionChunkKind::Snd, snd),
+            (SectionChunkKind::Extra, extra),
+        ] {
+            body.push(kind.into());
+            body.extend_from_slice(&(section.len() as u32).to_le_bytes());
+            body.extend_from_slice(&section);
+        }
+
+        let mut state = b"FCSX".to_vec();
+        state.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        state.extend_from_slice(&20606u32.to_le_bytes());
+        state.extend_from_slice(&[0xFF; 4]);
+        state.extend_from_slice(&body);
+        state
+    }
+
+    /// Compresses an uncompressed savestate the way FCEUX does by default.
+    fn compress(state: &[u8]) -> Vec<u8> {
+        let (header, body) = state.split_at(16);
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body).unwrap();
+        let body = encoder.finish().unwrap();
+
+        let mut compressed = header.to_vec();
+        compressed[12..16].copy_from_slice(&(body.len() as u32).to_le_bytes());
+        compressed.extend_from_slice(&body);
+        compressed
+    }
+}
@@ -0,0 +1,224 @@
+//! Devices that can be plugged into the controller ports in place of a standard controller.
+//!
+//! Everything on the ports talks to the console through the same few signals: a strobe and two
+//! expansion outputs written through $4016, and a handful of data lines read one bit at a time
+//! through $4016 and $4017. [SerialDevice] exposes exactly that, so peripherals like multitaps
+//! and keyboards can be prototyped without touching the bus.
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::Controller;
+
+/// A device connected to one of the controller ports.
+///
+/// The Famicom's expansion port shares the same registers, so expansion port devices that are
+/// read through $4017, like the Family BASIC keyboard, are connected to [ControllerPort::Two].
+pub trait SerialDevice {
+    /// Receives a write to $4016. Bit 0 is the strobe, which standard controllers latch their
+    /// buttons on, and bits 1-2 are the expansion port outputs.
+    fn write(&mut self, data: u8);
+    /// Returns the data lines D0-D4 for a read of the port's register, then moves on to the next
+    /// bit. Only bits 0-4 are used; the rest are open bus.
+    fn read(&mut self) -> u8;
+}
+
+/// Lets a frontend keep a handle to a device after connecting it, to update its state.
+impl<T: SerialDevice> SerialDevice for Rc<RefCell<T>> {
+    fn write(&mut self, data: u8) {
+        self.borrow_mut().write(data);
+    }
+
+    fn read(&mut self) -> u8 {
+        self.borrow_mut().read()
+    }
+}
+
+/// One of the two controller ports, read through $4016 and $4017 respectively.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControllerPort {
+    One,
+    Two,
+}
+
+/// One half of a Four Score multitap, which reads two controllers through a single port.
+///
+/// Each read returns the 8 buttons of the first controller, then the second's, then an 8-bit
+/// signature identifying the port, and 1s after that.
+///
+/// See <https://www.nesdev.org/wiki/Four_player_adapters>.
+#[derive(Debug, Clone)]
+pub struct FourScore {
+    controllers: [Controller; 2],
+    signature: u8,
+    shift_register: u32,
+    strobe: bool,
+}
+
+impl FourScore {
+    /// Creates the half of the multitap connected to `port`, which reads controllers 1 and 3 on
+    /// port one, or 2 and 4 on port two.
+    pub fn new(port: ControllerPort) -> Self {
+        let signature = match port {
+            ControllerPort::One => 0x08,
+            ControllerPort::Two => 0x04,
+        };
+        Self {
+            controllers: [Controller::new(); 2],
+            signature,
+            shift_register: 0,
+            strobe: false,
+        }
+    }
+
+    pub fn set_controllers(&mut self, first: Controller, second: Controller) {
+        self.controllers = [first, second];
+    }
+
+    fn latch(&mut self) {
+        self.shift_register = self.controllers[0].0 as u32
+            | (self.controllers[1].0 as u32) << 8
+            | (self.signature as u32) << 16;
+    }
+}
+
+impl SerialDevice for FourScore {
+    fn write(&mut self, data: u8) {
+        self.strobe = data & 0x01 != 0;
+        if self.strobe {
+            self.latch();
+        }
+    }
+
+    fn read(&mut self) -> u8 {
+        if self.strobe {
+            self.latch();
+        }
+        let data = (self.shift_register & 0x01) as u8;
+        self.shift_register = self.shift_register >> 1 | 1 << 23;
+        data
+    }
+}
+
+/// A key on the Family BASIC keyboard, in the order of the keyboard's matrix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[rustfmt::skip]
+pub enum FamilyKey {
+    F8, Return, LeftBracket, RightBracket, Kana, RightShift, Yen, Stop,
+    F7, At, Colon, Semicolon, Underscore, Slash, Minus, Caret,
+    F6, O, L, K, Period, Comma, P, Num0,
+    F5, I, U, J, M, N, Num9, Num8,
+    F4, Y, G, H, B, V, Num7, Num6,
+    F3, T, R, D, F, C, Num5, Num4,
+    F2, W, S, A, X, Z, E, Num3,
+    F1, Escape, Q, Ctr, LeftShift, Graph, Num1, Num2,
+    ClrHome, Up, Right, Left, Down, Space, Delete, Insert,
+}
+
+/// The Family BASIC keyboard, which plugs into the Famicom's expansion port.
+///
+/// The keys are scanned as 9 rows of 2 columns, with 4 keys each. Writes to $4016 enable the
+/// keyboard, select the column, and reset to the first row, and moving from the second column
+/// back to the first advances to the next row. Reads from $4017 return the selected keys in bits
+/// 1-4, which are 0 while pressed.
+///
+/// See <https://www.nesdev.org/wiki/Family_BASIC_Keyboard>.
+#[derive(Debug, Default, Clone)]
+pub struct FamilyKeyboard {
+    /// A bit for each [FamilyKey] that's held down.
+    pressed_keys: u128,
+    row: u8,
+    column: u8,
+    is_enabled: bool,
+}
+
+impl FamilyKeyboard {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set_key(&mut self, key: FamilyKey, is_pressed: bool) {
+        let bit = 1 << key as u32;
+        if is_pressed {
+            self.pressed_keys |= bit;
+        } else {
+            self.pressed_keys &= !bit;
+        }
+    }
+
+    pub fn release_all(&mut self) {
+        self.pressed_keys = 0;
+    }
+}
+
+impl SerialDevice for FamilyKeyboard {
+    fn write(&mut self, data: u8) {
+        let previous_column = self.column;
+        self.column = (data >> 1) & 0x01;
+        self.is_enabled = data & 0x04 != 0;
+        if !self.is_enabled {
+            return;
+        }
+        if data & 0x01 != 0 {
+            self.row = 0;
+        } else if previous_column == 1 && self.column == 0 {
+            // A 10th row exists, but has no keys.
+            self.row = (self.row + 1) % 10;
+        }
+    }
+
+    fn read(&mut self) -> u8 {
+        if !self.is_enabled {
+            return 0;
+        }
+        let index = self.row as u32 * 8 + self.column as u32 * 4;
+        let keys = (self.pressed_keys >> index) as u8 & 0x0F;
+        (!keys & 0x0F) << 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn four_score() {
+        let mut four_score = FourScore::new(ControllerPort::Two);
+        four_score.set_controllers(
+            Controller::new().with_a(true),
+            Controller::new().with_b(true),
+        );
+        four_score.write(1);
+        four_score.write(0);
+
+        let bits: Vec<u8> = (0..26).map(|_| four_score.read()).collect();
+        assert_eq!(bits[0..8], [1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(bits[8..16], [0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(bits[16..24], [0, 0, 1, 0, 0, 0, 0, 0]);
+        assert_eq!(bits[24..], [1, 1]);
+    }
+
+    #[test]
+    fn family_keyboard() {
+        let mut keyboard = FamilyKeyboard::new();
+        keyboard.set_key(FamilyKey::Return, true);
+        keyboard.set_key(FamilyKey::Num3, true);
+        assert_eq!(keyboard.read(), 0);
+
+        // Reset to row 0 and select column 0.
+        keyboard.write(0x05);
+        assert_eq!(keyboard.read(), 0x1E & !0x04);
+        // Column 1 of row 0, then column 0 of row 1.
+        keyboard.write(0x06);
+        assert_eq!(keyboard.read(), 0x1E);
+        keyboard.write(0x04);
+        assert_eq!(keyboard.read(), 0x1E);
+
+        // Column 1 of row 6.
+        for _ in 1..6 {
+            keyboard.write(0x06);
+            keyboard.write(0x04);
+        }
+        keyboard.write(0x06);
+        assert_eq!(keyboard.read(), 0x1E & !0x10);
+    }
+}
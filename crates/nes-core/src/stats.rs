@@ -0,0 +1,225 @@
+use std::collections::VecDeque;
+
+use crate::{audio::SAMPLE_RATE, FrameTiming};
+
+/// How many recent frames statistics are averaged over.
+const WINDOW_FRAMES: usize = 60;
+/// How many frames behind [Frameskip] lets the host fall before it stops trying to catch up, so
+/// that a one-off stall, like a garbage collection pause, doesn't cause a long run of skipping.
+const MAX_LAG_FRAMES: f64 = 4.0;
+
+/// A summary of how well the emulator is keeping up, suitable for a performance HUD.
+///
+/// Times are in milliseconds so that they can be passed to and from JavaScript as is.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PerfStats {
+    /// The number of frames emulated per second of host time.
+    pub fps: f64,
+    /// How fast the emulator is running compared to a real console, as a percentage.
+    pub speed: f64,
+    /// The average host time taken to emulate a frame.
+    pub frame_time: f64,
+    /// The longest host time taken to emulate a frame.
+    pub max_frame_time: f64,
+    /// How long the most recently reported audio queue will take to play.
+    pub audio_latency: f64,
+    /// The number of frames for which the audio queue had run dry.
+    pub audio_underruns: u32,
+}
+
+impl std::fmt::Display for PerfStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:.1} FPS ({:.0}%) {:.1}ms A:{:.0}ms",
+            self.fps, self.speed, self.frame_time, self.audio_latency
+        )
+    }
+}
+
+/// Collects frame timings from a frontend's run loop and summarizes them as [PerfStats].
+///
+/// Timestamps are in milliseconds and can come from any monotonic clock, such as
+/// [std::time::Instant] on desktop or `performance.now()` in a browser.
+#[derive(Debug, Default)]
+pub struct PerfMonitor {
+    /// The start and end timestamps of recent frames, oldest first.
+    frames: VecDeque<(f64, f64)>,
+    queued_audio_samples: usize,
+    audio_underruns: u32,
+    /// The timing of the console being emulated, to compare the frame rate against.
+    timing: FrameTiming,
+}
+
+impl PerfMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the timing of the console being emulated, which the speed is measured against. This
+    /// should match the bus's timing.
+    pub fn set_timing(&mut self, timing: FrameTiming) {
+        self.timing = timing;
+    }
+
+    /// Records a frame that started emulating at `start` and finished at `end`, along with the
+    /// number of audio samples still queued for playback before the frame's samples were added.
+    pub fn record_frame(&mut self, start: f64, end: f64, queued_audio_samples: usize) {
+        if self.frames.len() == WINDOW_FRAMES {
+            self.frames.pop_front();
+        }
+        self.frames.push_back((start, end));
+
+        // An empty queue on the very first frame just means playback hasn't started yet.
+        if queued_audio_samples == 0 && self.frames.len() > 1 {
+            self.audio_underruns += 1;
+        }
+        self.queued_audio_samples = queued_audio_samples;
+    }
+
+    pub fn stats(&self) -> PerfStats {
+        let fps = match (self.frames.front(), self.frames.back()) {
+            (Some(&(first, _)), Some(&(last, _))) if last > first => {
+                (self.frames.len() - 1) as f64 * 1000.0 / (last - first)
+            }
+            _ => 0.0,
+        };
+        let frame_times = self.frames.iter().map(|(start, end)| end - start);
+        let frame_time = match self.frames.len() {
+            0 => 0.0,
+            len => frame_times.clone().sum::<f64>() / len as f64,
+        };
+
+        PerfStats {
+            fps,
+            speed: self.timing.speed_percentage(fps),
+            frame_time,
+            max_frame_time: frame_times.fold(0.0, f64::max),
+            audio_latency: self.queued_audio_samples as f64 * 1000.0 / SAMPLE_RATE as f64,
+            audio_underruns: self.audio_underruns,
+        }
+    }
+
+    /// Forgets all recorded frames, such as after pausing, so that the gap doesn't skew the stats.
+    pub fn reset(&mut self) {
+        *self = Self {
+            timing: self.timing,
+            ..Self::default()
+        };
+    }
+}
+
+/// Decides which frames to skip drawing when the host takes longer to emulate a frame than the
+/// console takes to run one, so that slow devices like phones stay at full speed, just with a
+/// choppier picture. Skipped frames are still emulated in full, only their pixels aren't drawn,
+/// with [crate::Ppu::set_output_skipped].
+///
+/// Frameskip is off until a maximum number of frames to skip in a row is set.
+#[derive(Debug, Default)]
+pub struct Frameskip {
+    max_skipped: u32,
+    /// How much longer the host has taken to emulate recent frames than they last, in ms.
+    lag: f64,
+    skipped: u32,
+    is_suspended: bool,
+    timing: FrameTiming,
+}
+
+impl Frameskip {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the timing of the console being emulated, which frame times are compared against.
+    /// This should match the bus's timing.
+    pub fn set_timing(&mut self, timing: FrameTiming) {
+        self.timing = timing;
+    }
+
+    pub fn max_skipped(&self) -> u32 {
+        self.max_skipped
+    }
+
+    /// Sets the most frames in a row that can be skipped, or 0 to disable frameskip.
+    pub fn set_max_skipped(&mut self, max_skipped: u32) {
+        self.max_skipped = max_skipped;
+        self.lag = 0.0;
+        self.skipped = 0;
+    }
+
+    /// Suspends frameskip while recording a replay, so that every frame the player made inputs
+    /// on is drawn.
+    pub fn set_suspended(&mut self, is_suspended: bool) {
+        self.is_suspended = is_suspended;
+        self.lag = 0.0;
+        self.skipped = 0;
+    }
+
+    /// Records a frame that started emulating at `start` and finished at `end`, in ms, and returns
+    /// whether the next frame should be skipped.
+    pub fn record_frame(&mut self, start: f64, end: f64) -> bool {
+        if self.max_skipped == 0 || self.is_suspended {
+            return false;
+        }
+        let frame_duration = 1000.0 / self.timing.frame_rate();
+        self.lag =
+            (self.lag + (end - start) - frame_duration).clamp(0.0, frame_duration * MAX_LAG_FRAMES);
+
+        // Still draw every so often, so the picture keeps moving however far behind the host is.
+        if self.lag > 0.0 && self.skipped < self.max_skipped {
+            self.skipped += 1;
+            true
+        } else {
+            self.skipped = 0;
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats() {
+        let mut monitor = PerfMonitor::new();
+        for frame in 0..10 {
+            let start = frame as f64 * 20.0;
+            let queued = if frame == 5 { 0 } else { 441 };
+            monitor.record_frame(start, start + 4.0 + frame as f64, queued);
+        }
+
+        let stats = monitor.stats();
+        assert_eq!(stats.fps, 50.0);
+        assert!((stats.speed - 83.2).abs() < 0.01);
+        assert_eq!(stats.frame_time, 8.5);
+        assert_eq!(stats.max_frame_time, 13.0);
+        assert_eq!(stats.audio_latency, 10.0);
+        assert_eq!(stats.audio_underruns, 1);
+    }
+
+    #[test]
+    fn frameskip() {
+        let mut frameskip = Frameskip::new();
+        assert!(!frameskip.record_frame(0.0, 40.0));
+
+        // Frames that take half again as long as they last are skipped every other frame, as
+        // skipped frames are quick enough to make up for it.
+        frameskip.set_max_skipped(2);
+        let skipped: Vec<bool> = (0..6)
+            .scan(false, |is_skipped, _| {
+                let time = if *is_skipped { 5.0 } else { 25.0 };
+                *is_skipped = frameskip.record_frame(0.0, time);
+                Some(*is_skipped)
+            })
+            .collect();
+        assert_eq!(skipped, [true, false, true, false, true, false]);
+
+        // However slow the host is, frames are still drawn in between skips.
+        let skipped: Vec<bool> = (0..6).map(|_| frameskip.record_frame(0.0, 100.0)).collect();
+        assert_eq!(skipped, [true, true, false, true, true, false]);
+
+        frameskip.set_suspended(true);
+        assert!(!frameskip.record_frame(0.0, 100.0));
+    }
+}
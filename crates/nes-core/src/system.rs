@@ -0,0 +1,157 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{AccuracyProfile, Apu, Bus, Cartridge, Controller, Cpu, Ppu};
+
+/// A powered-on console. This is the simplest way for a frontend to drive the core: create one
+/// around a cartridge, then call [System::run_frame] once per frame with the controller state and
+/// a callback that presents the frame and plays its audio.
+pub struct System {
+    pub bus: Rc<RefCell<Bus>>,
+    pub cpu: Rc<RefCell<Cpu>>,
+    pub ppu: Rc<RefCell<Ppu>>,
+    pub apu: Rc<RefCell<Apu>>,
+    pub cartridge: Rc<RefCell<Cartridge>>,
+}
+
+impl System {
+    /// Connects a console around the cartridge and powers it on, using the default accuracy
+    /// profile.
+    pub fn new(cartridge: Cartridge) -> Self {
+        Self::with_accuracy_profile(cartridge, AccuracyProfile::default())
+    }
+
+    /// Connects a console around the cartridge and powers it on with the given accuracy profile.
+    /// The profile is applied before power-on, so settings like the PPU's warm-up take effect,
+    /// which they wouldn't if it were set with [Bus::set_accuracy_profile] afterwards.
+    pub fn with_accuracy_profile(cartridge: Cartridge, profile: AccuracyProfile) -> Self {
+        let cartridge = Rc::new(RefCell::new(cartridge));
+        let cpu = Rc::new(RefCell::new(Cpu::new()));
+        let ppu = Rc::new(RefCell::new(Ppu::new(cartridge.clone())));
+        let apu = Rc::new(RefCell::new(Apu::new()));
+        let bus = Bus::new(
+            cpu.clone(),
+            crate::new_boxed_array(),
+            ppu.clone(),
+            apu.clone(),
+            cartridge.clone(),
+        );
+        bus.borrow_mut().set_accuracy_profile(profile);
+        cpu.borrow_mut().reset();
+
+        Self {
+            bus,
+            cpu,
+            ppu,
+            apu,
+            cartridge,
+        }
+    }
+
+    /// Runs the console up to the end of the next frame with the given controller state, then
+    /// calls `on_frame` with the finished frame, in the PPU's pixel format, and the audio samples
+    /// produced along the way.
+    pub fn run_frame(
+        &self,
+        (controller_1, controller_2): (Controller, Controller),
+        on_frame: impl FnOnce(&[u8], &[f32]),
+    ) {
+        self.bus
+            .borrow_mut()
+            .set_controller_state(controller_1, controller_2);
+        Bus::run_frame(
+            self.bus.clone(),
+            self.cpu.clone(),
+            self.ppu.clone(),
+            self.apu.clone(),
+        );
+        let samples = self.apu.borrow_mut().drain_audio_buffer();
+        on_frame(self.ppu.borrow().buffer(), &samples);
+    }
+
+    /// Presses the console's reset button, ramping the audio down rather than cutting it off.
+    pub fn reset(&self) {
+        Bus::reset(self.cpu.clone(), self.ppu.clone());
+        self.apu.borrow_mut().smooth_reset();
+    }
+
+    /// Returns the names of the components whose state differs from the other system's.
+    pub(crate) fn diff(&self, other: &Self) -> Vec<&'static str> {
+        let state = self.bus.borrow().system_state();
+        state.diff(&other.bus.borrow().system_state())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::assemble;
+
+    #[test]
+    fn frame_callback() {
+        let program = assemble(0x8000, "loop: inc $10\njmp loop").unwrap();
+        let cartridge = Cartridge::from_raw_prg(&program, 0x8000, [0x8000; 3]).unwrap();
+        let system = System::new(cartridge);
+        let mut calls = 0;
+        for _ in 0..2 {
+            system.run_frame((Controller::new(), Controller::new()), |frame, samples| {
+                assert_eq!(frame.len(), 256 * 240 * 3);
+                // A frame lasts about 1/60 of a second.
+                assert!((600..=900).contains(&samples.len()), "{}", samples.len());
+                calls += 1;
+            });
+        }
+        assert_eq!(calls, 2);
+        // The samples were handed to the callback rather than left to pile up.
+        assert!(system.apu.borrow_mut().drain_audio_buffer().is_empty());
+
+        system.reset();
+        assert_eq!(system.cpu.borrow().program_counter(), 0x8000);
+    }
+
+    #[test]
+    fn accuracy_profile() {
+        let cartridge = || Cartridge::from_raw_prg(&[0xEA], 0x8000, [0x8000; 3]).unwrap();
+        let system = System::new(cartridge());
+        assert_eq!(
+            system.bus.borrow().accuracy_profile(),
+            AccuracyProfile::default()
+        );
+
+        let system = System::with_accuracy_profile(cartridge(), AccuracyProfile::Accurate);
+        assert_eq!(
+            system.bus.borrow().accuracy_profile(),
+            AccuracyProfile::Accurate
+        );
+        assert!(system.ppu.borrow().is_warming_up());
+        system.run_frame((Controller::new(), Controller::new()), |_, _| {});
+        assert!(!system.ppu.borrow().is_warming_up());
+
+        let system = System::with_accuracy_profile(cartridge(), AccuracyProfile::Fast);
+        assert!(!system.bus.borrow().has_open_bus());
+        assert!(!system.ppu.borrow().is_warming_up());
+    }
+
+    #[test]
+    fn system_state() {
+        use std::hash::BuildHasher;
+
+        let system = || {
+            let cartridge = Cartridge::from_raw_prg(&[0xEA], 0x8000, [0x8000; 3]).unwrap();
+            System::new(cartridge)
+        };
+        let (a, b) = (system(), system());
+        let hasher = std::collections::hash_map::RandomState::new();
+        let state = a.bus.borrow().system_state();
+        assert_eq!(state, b.bus.borrow().system_state());
+        assert_eq!(
+            hasher.hash_one(&state),
+            hasher.hash_one(b.bus.borrow().system_state())
+        );
+
+        b.bus.borrow_mut().cpu_write(0x0010, 0x42);
+        b.bus.borrow_mut().cpu_write(0x2003, 0x08);
+        let other_state = b.bus.borrow().system_state();
+        assert_ne!(hasher.hash_one(&state), hasher.hash_one(&other_state));
+        assert_eq!(state.diff(&other_state), ["RAM", "PPU"]);
+    }
+}
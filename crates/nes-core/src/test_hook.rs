@@ -0,0 +1,159 @@
+/// The bytes test ROMs write after the status byte to show that it holds a valid result.
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+/// The status written while a test is still running.
+const STATUS_RUNNING: u8 = 0x80;
+/// The status written when a test needs the console to be reset before it can continue.
+const STATUS_RESET: u8 = 0x81;
+
+/// Watches for results reported by test ROMs through memory, following the convention used by
+/// blargg's test ROMs.
+///
+/// A test writes a status byte, followed by the signature `DE B0 61` and a null-terminated
+/// message. The status is $80 while running, $81 when the console needs to be reset, and the
+/// result code once finished, where 0 means the test passed. Writes are recorded by the hook as
+/// they happen, so results can be read even from cartridges without PRG RAM.
+///
+/// See <https://www.nesdev.org/wiki/Emulator_tests>.
+#[derive(Debug, Clone)]
+pub struct TestHook {
+    status_addr: u16,
+    text_addr: u16,
+    status: u8,
+    signature: [u8; 3],
+    text: Vec<u8>,
+}
+
+/// Something reported by a test ROM, collected with [crate::Bus::drain_test_events].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestEvent {
+    /// The test needs the console to be reset to continue, which should be done after at least
+    /// 100ms.
+    ResetRequested,
+    /// The test finished with a result code, which is 0 if it passed, and its message.
+    Finished { code: u8, text: String },
+}
+
+impl TestHook {
+    /// Creates a hook that watches the status byte at `status_addr`, with the signature in the
+    /// three bytes after it, and reads a message of up to `text_len` bytes from `text_addr`.
+    pub fn new(status_addr: u16, text_addr: u16, text_len: u16) -> Self {
+        Self {
+            status_addr,
+            text_addr,
+            status: 0,
+            signature: [0; 3],
+            text: vec![0; text_len as usize],
+        }
+    }
+
+    /// Records a write by the CPU, returning an event if it reported something.
+    pub fn observe_write(&mut self, addr: u16, data: u8) -> Option<TestEvent> {
+        let text_offset = addr.wrapping_sub(self.text_addr) as usize;
+        if let Some(byte) = self.text.get_mut(text_offset) {
+            *byte = data;
+        }
+        let signature_offset = addr.wrapping_sub(self.status_addr.wrapping_add(1)) as usize;
+        if let Some(byte) = self.signature.get_mut(signature_offset) {
+            *byte = data;
+        }
+        if addr != self.status_addr {
+            return None;
+        }
+
+        // Only report changes, since some tests write the same status more than once.
+        let previous = std::mem::replace(&mut self.status, data);
+        if self.signature != SIGNATURE || previous == data {
+            return None;
+        }
+        match data {
+            STATUS_RUNNING => None,
+            STATUS_RESET => Some(TestEvent::ResetRequested),
+            code => Some(TestEvent::Finished {
+                code,
+                text: self.text(),
+            }),
+        }
+    }
+
+    /// Returns the message written so far, up to its null terminator.
+    pub fn text(&self) -> String {
+        let len = self.text.iter().position(|&byte| byte == 0);
+        let text = &self.text[..len.unwrap_or(self.text.len())];
+        String::from_utf8_lossy(text).trim_end().to_string()
+    }
+}
+
+/// Runs a test ROM until it reports a result, resetting the console whenever it asks to, and
+/// returns the result code and message. Panics if the ROM can't be loaded or never finishes.
+#[cfg(test)]
+pub(crate) fn run_test_rom(path: &str) -> (u8, String) {
+    use crate::{Bus, StopCondition};
+
+    let rom = std::fs::read(path).unwrap_or_else(|err| panic!("failed to read {path}: {err}"));
+    let system = crate::System::new(crate::Cartridge::new(&rom).unwrap());
+    system
+        .bus
+        .borrow_mut()
+        .set_test_hook(Some(TestHook::default()));
+
+    // Each run stops after about 10 frames without a result, so this allows about a minute.
+    for _ in 0..360 {
+        Bus::run_until(
+            system.bus.clone(),
+            system.cpu.clone(),
+            system.ppu.clone(),
+            system.apu.clone(),
+            StopCondition::TestEvent,
+        );
+        for event in system.bus.borrow_mut().drain_test_events() {
+            match event {
+                TestEvent::ResetRequested => Bus::reset(system.cpu.clone(), system.ppu.clone()),
+                TestEvent::Finished { code, text } => return (code, text),
+            }
+        }
+    }
+    panic!("{path} didn't report a result");
+}
+
+impl Default for TestHook {
+    /// Watches the status at $6000 and the message at $6004, as blargg's test ROMs use.
+    fn default() -> Self {
+        Self::new(0x6000, 0x6004, 0x1000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_results() {
+        let mut hook = TestHook::default();
+        let mut write = |addr: u16, bytes: &[u8]| {
+            let mut event = None;
+            for (i, &byte) in bytes.iter().enumerate() {
+                event = hook.observe_write(addr + i as u16, byte);
+            }
+            event
+        };
+
+        // Nothing is reported until the signature is written.
+        assert_eq!(write(0x6000, &[0x81]), None);
+        assert_eq!(write(0x6001, &SIGNATURE), None);
+        assert_eq!(write(0x6000, &[STATUS_RUNNING]), None);
+        assert_eq!(
+            write(0x6000, &[STATUS_RESET]),
+            Some(TestEvent::ResetRequested)
+        );
+        assert_eq!(write(0x6000, &[STATUS_RESET]), None);
+
+        assert_eq!(write(0x6004, b"Failed #2\n\0"), None);
+        assert_eq!(
+            write(0x6000, &[0x02]),
+            Some(TestEvent::Finished {
+                code: 2,
+                text: "Failed #2".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use std::time::Duration;
+
+use crate::audio::SAMPLE_RATE;
+
+/// The video standard a console was built for, which determines its clock rates and frame length.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+}
+
+impl Region {
+    /// Guesses the region from the tags in a ROM's file name, following the GoodNES and No-Intro
+    /// naming conventions, such as `(E)`, `(Europe)`, or `(PAL)`.
+    pub fn from_filename(name: &str) -> Option<Self> {
+        const PAL_TAGS: [&str; 8] = [
+            "e",
+            "europe",
+            "pal",
+            "a",
+            "australia",
+            "g",
+            "germany",
+            "france",
+        ];
+        const NTSC_TAGS: [&str; 6] = ["u", "usa", "j", "japan", "ntsc", "ju"];
+
+        // Tags may list several regions, such as `(USA, Europe)`, in which case the first wins.
+        let tags = name
+            .split(['(', ')'])
+            .skip(1)
+            .step_by(2)
+            .flat_map(|tag| tag.split(','))
+            .map(|tag| tag.trim().to_ascii_lowercase());
+        for tag in tags {
+            if PAL_TAGS.contains(&tag.as_str()) {
+                return Some(Self::Pal);
+            }
+            if NTSC_TAGS.contains(&tag.as_str()) {
+                return Some(Self::Ntsc);
+            }
+        }
+        None
+    }
+}
+
+impl std::str::FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ntsc" => Ok(Self::Ntsc),
+            "pal" => Ok(Self::Pal),
+            _ => Err(format!("unknown region `{s}`")),
+        }
+    }
+}
+
+impl std::fmt::Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ntsc => write!(f, "NTSC"),
+            Self::Pal => write!(f, "PAL"),
+        }
+    }
+}
+
+/// The clock rates and frame layout of a console.
+///
+/// All other clocks are derived from the master clock by dividing it down, so cycle counts are
+/// exact integers when measured in master clocks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameTiming {
+    pub region: Region,
+    /// The frequency of the master clock, in Hz.
+    pub master_clock_rate: f64,
+    pub master_clocks_per_cpu_cycle: u32,
+    pub master_clocks_per_ppu_dot: u32,
+    pub scanlines_per_frame: u16,
+    pub dots_per_scanline: u16,
+    /// The scanline at the start of which the vblank flag is set and the NMI fires.
+    pub vblank_scanline: u16,
+    /// Whether the pre-render scanline is one dot shorter on odd frames while rendering is enabled.
+    pub skips_odd_frame_dot: bool,
+}
+
+impl FrameTiming {
+    pub const NTSC: Self = Self {
+        region: Region::Ntsc,
+        master_clock_rate: 236_250_000.0 / 11.0,
+        master_clocks_per_cpu_cycle: 12,
+        master_clocks_per_ppu_dot: 4,
+        scanlines_per_frame: 262,
+        dots_per_scanline: 341,
+        vblank_scanline: 241,
+        skips_odd_frame_dot: true,
+    };
+
+    pub const PAL: Self = Self {
+        region: Region::Pal,
+        master_clock_rate: 26_601_712.5,
+        master_clocks_per_cpu_cycle: 16,
+        master_clocks_per_ppu_dot: 5,
+        scanlines_per_frame: 312,
+        dots_per_scanline: 341,
+        vblank_scanline: 241,
+        skips_odd_frame_dot: false,
+    };
+
+    pub const fn new(region: Region) -> Self {
+        match region {
+            Region::Ntsc => Self::NTSC,
+            Region::Pal => Self::PAL,
+        }
+    }
+
+    /// The last scanline of the frame, during which the PPU prepares to render the next frame.
+    pub const fn pre_render_scanline(&self) -> u16 {
+        self.scanlines_per_frame - 1
+    }
+
+    /// The number of scanlines in vblank, not counting the pre-render scanline.
+    pub const fn vblank_scanlines(&self) -> u16 {
+        self.pre_render_scanline() - self.vblank_scanline
+    }
+
+    /// Returns the exact number of PPU dots in a frame.
+    pub const fn ppu_dots_per_frame(&self, is_odd_frame: bool, is_rendering: bool) -> u32 {
+        let dots = self.scanlines_per_frame as u32 * self.dots_per_scanline as u32;
+        if self.skips_odd_frame_dot && is_odd_frame && is_rendering {
+            dots - 1
+        } else {
+            dots
+        }
+    }
+
+    /// Returns the average number of PPU dots in a frame while rendering is enabled.
+    pub fn average_ppu_dots_per_frame(&self) -> f64 {
+        (self.ppu_dots_per_frame(false, true) + self.ppu_dots_per_frame(true, true)) as f64 / 2.0
+    }
+
+    /// Returns the average number of CPU cycles in a frame while rendering is enabled. This is
+    /// 29780.5 on NTSC consoles.
+    pub fn cpu_cycles_per_frame(&self) -> f64 {
+        self.average_ppu_dots_per_frame() * self.master_clocks_per_ppu_dot as f64
+            / self.master_clocks_per_cpu_cycle as f64
+    }
+
+    /// Returns the number of CPU cycles per second, roughly 1.79 MHz on NTSC consoles.
+    pub fn cpu_clock_rate(&self) -> f64 {
+        self.master_clock_rate / self.master_clocks_per_cpu_cycle as f64
+    }
+
+    /// Returns the number of PPU dots per second, roughly 5.37 MHz on NTSC consoles.
+    pub fn ppu_clock_rate(&self) -> f64 {
+        self.master_clock_rate / self.master_clocks_per_ppu_dot as f64
+    }
+
+    /// Returns how long the given number of CPU cycles takes on a real console, in microseconds.
+    pub fn cpu_cycles_to_micros(&self, cycles: u64) -> f64 {
+        cycles as f64 * 1_000_000.0 / self.cpu_clock_rate()
+    }
+
+    /// Returns how long the given number of CPU cycles takes on a real console.
+    pub fn cpu_cycles_to_duration(&self, cycles: u64) -> Duration {
+        Duration::from_secs_f64(cycles as f64 / self.cpu_clock_rate())
+    }
+
+    /// Returns the number of CPU cycles a real console runs in the given time, rounded down.
+    pub fn duration_to_cpu_cycles(&self, duration: Duration) -> u64 {
+        (duration.as_secs_f64() * self.cpu_clock_rate()) as u64
+    }
+
+    /// Returns how many frames the given number of CPU cycles spans, on average.
+    pub fn cpu_cycles_to_frames(&self, cycles: u64) -> f64 {
+        cycles as f64 / self.cpu_cycles_per_frame()
+    }
+
+    /// Returns how fast an emulator running the given number of frames per second is going
+    /// compared to a real console, as a percentage.
+    pub fn speed_percentage(&self, fps: f64) -> f64 {
+        fps * 100.0 / self.frame_rate()
+    }
+
+    /// Returns the number of frames per second, roughly 60.0988 on NTSC consoles.
+    pub fn frame_rate(&self) -> f64 {
+        self.cpu_clock_rate() / self.cpu_cycles_per_frame()
+    }
+
+    /// Returns the average number of audio samples the APU produces per frame, roughly 733.79 on
+    /// NTSC consoles.
+    pub fn audio_samples_per_frame(&self) -> f64 {
+        SAMPLE_RATE as f64 / self.frame_rate()
+    }
+
+    pub fn frame_duration(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.frame_rate())
+    }
+}
+
+impl Default for FrameTiming {
+    fn default() -> Self {
+        Self::NTSC
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycles_per_frame() {
+        assert_eq!(FrameTiming::NTSC.ppu_dots_per_frame(false, true), 89342);
+        assert_eq!(FrameTiming::NTSC.ppu_dots_per_frame(true, true), 89341);
+        assert_eq!(FrameTiming::NTSC.ppu_dots_per_frame(true, false), 89342);
+        assert_eq!(FrameTiming::NTSC.cpu_cycles_per_frame(), 29780.5);
+        assert_eq!(FrameTiming::NTSC.vblank_scanlines(), 20);
+        assert!((FrameTiming::NTSC.frame_rate() - 60.0988).abs() < 0.0001);
+        assert!((FrameTiming::NTSC.audio_samples_per_frame() - 733.79).abs() < 0.01);
+
+        assert_eq!(FrameTiming::PAL.ppu_dots_per_frame(true, true), 106392);
+        assert_eq!(FrameTiming::PAL.cpu_cycles_per_frame(), 33247.5);
+        assert_eq!(FrameTiming::PAL.vblank_scanlines(), 70);
+    }
+
+    #[test]
+    fn time_conversions() {
+        let timing = FrameTiming::NTSC;
+        assert!((timing.cpu_clock_rate() - 1_789_772.7).abs() < 0.1);
+        assert_eq!(timing.ppu_clock_rate(), timing.cpu_clock_rate() * 3.0);
+        assert!((timing.cpu_cycles_to_micros(179) - 100.01).abs() < 0.01);
+        assert_eq!(timing.cpu_cycles_to_frames(29780 * 2 + 1), 2.0);
+
+        let second = timing.cpu_clock_rate() as u64;
+        assert!((timing.cpu_cycles_to_duration(second).as_secs_f64() - 1.0).abs() < 1e-6);
+        assert_eq!(
+            timing.duration_to_cpu_cycles(Duration::from_secs(1)),
+            second
+        );
+        assert!((timing.speed_percentage(timing.frame_rate() / 2.0) - 50.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn region_from_filename() {
+        let region = Region::from_filename;
+        assert_eq!(region("Super Mario Bros. (E).nes"), Some(Region::Pal));
+        assert_eq!(region("Tetris (USA, Europe).nes"), Some(Region::Ntsc));
+        assert_eq!(region("Elite (Europe) (En,Fr,De).nes"), Some(Region::Pal));
+        assert_eq!(region("Homebrew (PAL) [!].nes"), Some(Region::Pal));
+        assert_eq!(region("Zelda (U) (PRG1).nes"), Some(Region::Ntsc));
+        assert_eq!(region("Micro Mages.nes"), None);
+        assert_eq!("pal".parse(), Ok(Region::Pal));
+    }
+}
@@ -0,0 +1,223 @@
+//! A minimal frontend where emulation runs on its own thread, paced by the console's own frame
+//! rate, while the main thread only handles the window, input, and presenting frames.
+//!
+//! The core is built on `Rc<RefCell<_>>`, so it isn't `Send`. Instead of sharing it, the
+//! emulation thread builds the whole console from the ROM's bytes and owns it outright. The
+//! threads only exchange plain data over channels:
+//!
+//! - Commands, such as controller input, go from the main thread to the emulation thread.
+//! - Finished frames go back through a queue holding at most 2 frames. If the renderer falls
+//!   behind, frames are dropped rather than slowing down emulation.
+//! - Audio goes straight to SDL's audio callback, which runs on SDL's own audio thread.
+//!
+//! Since nothing on the main thread blocks the emulation thread, a slow or vsynced renderer no
+//! longer changes how fast the game runs, unlike the desktop frontend's single loop.
+//!
+//! ```sh
+//! cargo run -p nes-sdl --release --example threaded -- /path/to/rom.nes
+//! ```
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    rc::Rc,
+    sync::mpsc::{self, Receiver, Sender, SyncSender, TryRecvError, TrySendError},
+    time::Instant,
+};
+
+use nes_core::{audio::SAMPLE_RATE, Apu, Bus, Cartridge, Controller, Cpu, PixelFormat, Ppu};
+use sdl2::{
+    audio::{AudioCallback, AudioSpecDesired},
+    event::Event,
+    keyboard::{Keycode, Scancode},
+    pixels::PixelFormatEnum,
+};
+
+/// How many frames the renderer can fall behind by before frames are dropped.
+const FRAME_QUEUE_SIZE: usize = 2;
+/// The most audio the callback holds on to, in samples. Anything past this is dropped, which
+/// keeps latency from building up if the host's audio clock runs slow.
+const MAX_QUEUED_SAMPLES: usize = 4096;
+/// How many frames behind emulation can fall before it gives up catching up.
+const MAX_LAG_FRAMES: u32 = 4;
+
+/// Messages from the main thread to the emulation thread.
+enum Command {
+    Input(Controller, Controller),
+    Quit,
+}
+
+pub fn main() {
+    let rom_path = std::env::args().nth(1).expect("no ROM path provided");
+    let rom = std::fs::read(&rom_path).expect("failed to read ROM");
+
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+    let audio_subsystem = sdl_context.audio().unwrap();
+    let window = video_subsystem
+        .window("NES Emulator (threaded)", 256 * 4, 240 * 4)
+        .position_centered()
+        .build()
+        .unwrap();
+    let mut canvas = window.into_canvas().present_vsync().build().unwrap();
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGB24, 256, 240)
+        .unwrap();
+
+    let (command_sender, command_receiver) = mpsc::channel();
+    let (frame_sender, frame_receiver) = mpsc::sync_channel(FRAME_QUEUE_SIZE);
+    let (audio_sender, audio_receiver) = mpsc::channel();
+
+    let desired_spec = AudioSpecDesired {
+        freq: Some(SAMPLE_RATE as i32),
+        channels: Some(1),
+        samples: None,
+    };
+    let audio_device = audio_subsystem
+        .open_playback(None, &desired_spec, |_| ChannelAudio {
+            receiver: audio_receiver,
+            samples: VecDeque::new(),
+        })
+        .unwrap();
+    audio_device.resume();
+
+    let emulation_thread = std::thread::spawn(move || {
+        run_emulation(&rom, command_receiver, frame_sender, audio_sender)
+    });
+
+    let mut event_pump = sdl_context.event_pump().unwrap();
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'running,
+                _ => (),
+            }
+        }
+
+        let (controller_1, controller_2) = get_controller_state(&event_pump);
+        if command_sender
+            .send(Command::Input(controller_1, controller_2))
+            .is_err()
+        {
+            // The emulation thread has stopped, so there's nothing left to show.
+            break;
+        }
+
+        // Only the newest frame matters; older ones would just be overwritten.
+        if let Some(frame) = frame_receiver.try_iter().last() {
+            texture.update(None, &frame, 256 * 3).unwrap();
+        }
+        canvas.copy(&texture, None, None).unwrap();
+        canvas.present();
+    }
+
+    // The thread may have already stopped, in which case this has nowhere to go.
+    let _ = command_sender.send(Command::Quit);
+    if let Err(err) = emulation_thread.join().unwrap() {
+        println!("warn: emulation stopped: {err}");
+    }
+}
+
+/// Builds the console and runs it at the console's own frame rate until told to quit or the
+/// main thread goes away.
+fn run_emulation(
+    rom: &[u8],
+    commands: Receiver<Command>,
+    frames: SyncSender<Vec<u8>>,
+    audio: Sender<Vec<f32>>,
+) -> Result<(), String> {
+    let cartridge = Rc::new(RefCell::new(Cartridge::new(rom)?));
+    let cpu = Rc::new(RefCell::new(Cpu::new()));
+    let ppu = Rc::new(RefCell::new(Ppu::new(cartridge.clone())));
+    ppu.borrow_mut().set_pixel_format(PixelFormat::Rgb24);
+    let apu = Rc::new(RefCell::new(Apu::new()));
+    let bus = Bus::new(
+        cpu.clone(),
+        nes_core::new_boxed_array(),
+        ppu.clone(),
+        apu.clone(),
+        cartridge.clone(),
+    );
+    let region = cartridge.borrow().detect_region(None);
+    bus.borrow_mut().set_region(region);
+    cpu.borrow_mut().reset();
+
+    let frame_duration = bus.borrow().timing().frame_duration();
+    let mut next_frame = Instant::now();
+    loop {
+        loop {
+            match commands.try_recv() {
+                Ok(Command::Input(controller_1, controller_2)) => {
+                    bus.borrow_mut()
+                        .set_controller_state(controller_1, controller_2);
+                }
+                Ok(Command::Quit) | Err(TryRecvError::Disconnected) => return Ok(()),
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+
+        Bus::run_frame(bus.clone(), cpu.clone(), ppu.clone(), apu.clone());
+        match frames.try_send(ppu.borrow().buffer().to_vec()) {
+            Ok(()) | Err(TrySendError::Full(_)) => (),
+            Err(TrySendError::Disconnected(_)) => return Ok(()),
+        }
+        if audio.send(apu.borrow_mut().drain_audio_buffer()).is_err() {
+            return Ok(());
+        }
+
+        // Pace frames against a fixed schedule rather than sleeping a frame's length, so that
+        // time spent emulating doesn't add up.
+        next_frame += frame_duration;
+        let now = Instant::now();
+        if next_frame > now {
+            std::thread::sleep(next_frame - now);
+        } else if now - next_frame > frame_duration * MAX_LAG_FRAMES {
+            next_frame = now;
+        }
+    }
+}
+
+/// Plays audio sent from the emulation thread, from SDL's audio thread.
+struct ChannelAudio {
+    receiver: Receiver<Vec<f32>>,
+    samples: VecDeque<f32>,
+}
+
+impl AudioCallback for ChannelAudio {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for samples in self.receiver.try_iter() {
+            self.samples.extend(samples);
+        }
+        let excess = self.samples.len().saturating_sub(MAX_QUEUED_SAMPLES);
+        self.samples.drain(..excess);
+
+        // Fill any gap with silence until more arrives.
+        for sample in out {
+            *sample = self.samples.pop_front().unwrap_or(0.0);
+        }
+    }
+}
+
+fn get_controller_state(event_pump: &sdl2::EventPump) -> (Controller, Controller) {
+    let keyboard_state = event_pump.keyboard_state();
+    let key = |key: Scancode| keyboard_state.is_scancode_pressed(key);
+
+    let controller_1 = Controller::new()
+        .with_a(key(Scancode::X))
+        .with_b(key(Scancode::Z))
+        .with_select(key(Scancode::RShift))
+        .with_start(key(Scancode::Return))
+        .with_up(key(Scancode::Up))
+        .with_down(key(Scancode::Down))
+        .with_left(key(Scancode::Left))
+        .with_right(key(Scancode::Right));
+
+    (controller_1, Controller::new())
+}
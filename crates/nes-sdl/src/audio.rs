@@ -0,0 +1,36 @@
+use nes_core::{audio::SAMPLE_RATE, AudioSink};
+use sdl2::{
+    audio::{AudioQueue, AudioSpecDesired},
+    AudioSubsystem,
+};
+
+/// Plays samples through an SDL audio queue.
+pub struct SdlAudioSink {
+    queue: AudioQueue<f32>,
+}
+
+impl SdlAudioSink {
+    pub fn new(audio_subsystem: &AudioSubsystem) -> Result<Self, String> {
+        let desired_spec = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE as i32),
+            channels: Some(1),
+            samples: None,
+        };
+        let queue = audio_subsystem.open_queue::<f32, _>(None, &desired_spec)?;
+        queue.resume();
+
+        Ok(Self { queue })
+    }
+}
+
+impl AudioSink for SdlAudioSink {
+    fn push_samples(&mut self, samples: &[f32]) {
+        if let Err(err) = self.queue.queue_audio(samples) {
+            println!("warn: failed to queue audio: {err}");
+        }
+    }
+
+    fn queued_samples(&self) -> usize {
+        self.queue.size() as usize / std::mem::size_of::<f32>()
+    }
+}
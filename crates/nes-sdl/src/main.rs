@@ -0,0 +1,1343 @@
+mod audio;
+
+use audio::SdlAudioSink;
+use nes_core::{
+    audio::WavRecorder, format_input_line, to_vgm, AccuracyProfile, Apu, AudioSink, AutoHold,
+    BufferWatermark, BufferWatermarks, Bus, Cartridge, Controller, ControllerPort, Cpu, DpadFilter,
+    DpadPolicy, FamilyKey, FamilyKeyboard, FastBoot, FastForwardAudio, FourScore, FrameBlend,
+    InputCommand, Osd, PerfMonitor, PixelFormat, Ppu, Region, Replay, ReplayPlayer, Resampler,
+    VideoFilter,
+};
+use sdl2::{
+    event::Event,
+    keyboard::{Keycode, Scancode},
+    pixels::PixelFormatEnum,
+    video::Window,
+};
+use std::{
+    cell::RefCell,
+    fmt::Display,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+const MAIN_SCALE: u32 = 4;
+/// How often, in frames, to refresh the snapshot written out if the emulator crashes.
+const RECOVERY_INTERVAL: u32 = 60;
+/// How often, in frames, to write battery-backed RAM out to disk if it has changed.
+const BATTERY_SAVE_INTERVAL: u32 = 300;
+/// How many frames on-screen messages stay visible for.
+const MESSAGE_FRAMES: u32 = 120;
+/// How often, in frames, to check whether the ROM has changed while watching it.
+const WATCH_INTERVAL: u32 = 30;
+/// How many extra scanlines to insert after vblank while overclocking is enabled.
+const OVERCLOCK_SCANLINES: u16 = 100;
+/// The most frames `--fast-boot` skips while waiting for the game to read the controllers.
+const FAST_BOOT_MAX_FRAMES: u32 = 600;
+/// How many frames to run per displayed frame while fast-forwarding.
+const FAST_FORWARD_SPEED: u32 = 4;
+/// How many samples of queued audio to wait for playback to drain down to, once it's filled up to
+/// the high watermark, before running emulation at full speed again.
+const AUDIO_LOW_WATERMARK: usize = 1024;
+const AUDIO_HIGH_WATERMARK: usize = 2048;
+
+#[cfg(feature = "memview")]
+const NAMETABLE_SCALE: u32 = 2;
+#[cfg(feature = "memview")]
+const PATTERN_SCALE: u32 = 3;
+#[cfg(feature = "memview")]
+const OAM_SCALE: u32 = 4;
+
+pub fn main() {
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+    let audio_subsystem = sdl_context.audio().unwrap();
+
+    let window = video_subsystem
+        .window("NES Emulator", 256 * MAIN_SCALE, 240 * MAIN_SCALE)
+        .position_centered()
+        .build()
+        .unwrap();
+
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let patch_path = take_value(&mut args, "--patch").map(|path| {
+        Some(path)
+            .filter(|path| !path.is_empty())
+            .error_message("No patch path provided", &window)
+    });
+    let watch_rom = take_flag(&mut args, "--watch");
+    let keep_ram = take_flag(&mut args, "--keep-ram");
+    let use_family_keyboard = take_flag(&mut args, "--keyboard");
+    let use_four_score = take_flag(&mut args, "--four-score");
+    let allow_any_savestate = take_flag(&mut args, "--any-savestate");
+    let fast_boot = take_flag(&mut args, "--fast-boot");
+    let log_apu = take_flag(&mut args, "--apu-log");
+    let record_wav = take_flag(&mut args, "--wav");
+    let region = take_value(&mut args, "--region").map(|region| {
+        region
+            .parse::<Region>()
+            .ok()
+            .error_message("No valid region provided (ntsc or pal)", &window)
+    });
+    let flicker_seed = take_value(&mut args, "--flicker-seed").map(|seed| {
+        seed.parse::<u64>()
+            .ok()
+            .error_message("No valid sprite flicker seed provided", &window)
+    });
+    let accuracy_profile = take_value(&mut args, "--accuracy").map(|profile| {
+        profile.parse::<AccuracyProfile>().ok().error_message(
+            "No valid accuracy profile provided (fast, balanced or accurate)",
+            &window,
+        )
+    });
+    let dpad_policy = take_value(&mut args, "--dpad-filter").map(|policy| {
+        policy.parse::<DpadPolicy>().ok().error_message(
+            "No valid d-pad policy provided (allow, neutral, last or first)",
+            &window,
+        )
+    });
+    let seek_frame = take_value(&mut args, "--seek").map(|frame| {
+        frame
+            .parse::<u32>()
+            .ok()
+            .error_message("No valid frame to seek to provided", &window)
+    });
+    let breakpoints = take_value(&mut args, "--pause-at").map(|frames| {
+        frames
+            .split(',')
+            .map(|frame| frame.parse::<u32>().ok())
+            .collect::<Option<Vec<_>>>()
+            .error_message("No valid frames to pause at provided", &window)
+    });
+    #[cfg(feature = "gdb")]
+    let gdb_port = take_value(&mut args, "--gdb").map(|port| {
+        port.parse::<u16>()
+            .ok()
+            .error_message("No valid GDB port provided", &window)
+    });
+    let mut args = args.into_iter();
+
+    let rom_path = args.next().error_message("No ROM path provided", &window);
+    let replay_data = args
+        .next()
+        .map(|path| std::fs::read(path).error_message("Failed to open replay file", &window))
+        .map(|data| String::from_utf8_lossy(&data).to_string())
+        .unwrap_or_default();
+
+    let mut replay = (!replay_data.is_empty()).then(|| {
+        let replay =
+            Replay::new(replay_data.lines()).error_message("Failed to parse replay", &window);
+        let mut player = ReplayPlayer::new(replay);
+        player.count_length();
+        for &frame in breakpoints.iter().flatten() {
+            player.add_breakpoint(frame);
+        }
+        player
+    });
+
+    #[cfg(feature = "memview")]
+    let nametable_window = video_subsystem
+        .window(
+            "Nametable Viewer",
+            512 * NAMETABLE_SCALE,
+            480 * NAMETABLE_SCALE,
+        )
+        .position(200, 200)
+        .build()
+        .unwrap();
+
+    #[cfg(feature = "memview")]
+    let pattern_window = video_subsystem
+        .window(
+            "Pattern Table Viewer",
+            256 * PATTERN_SCALE,
+            128 * PATTERN_SCALE,
+        )
+        .position(400, 400)
+        .build()
+        .unwrap();
+
+    #[cfg(feature = "memview")]
+    let oam_window = video_subsystem
+        .window("OAM Viewer", 64 * OAM_SCALE, 64 * OAM_SCALE)
+        .position(600, 600)
+        .build()
+        .unwrap();
+
+    let mut canvas = window.into_canvas().build().unwrap();
+    canvas
+        .set_scale(MAIN_SCALE as f32, MAIN_SCALE as f32)
+        .unwrap();
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGB24, 256, 240)
+        .unwrap();
+    // Every video filter upscales by the same amount, so they can share a texture.
+    let mut filtered_texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGB24, 512, 480)
+        .unwrap();
+
+    #[cfg(feature = "memview")]
+    let mut nametable_canvas = nametable_window.into_canvas().build().unwrap();
+    #[cfg(feature = "memview")]
+    nametable_canvas
+        .set_scale(NAMETABLE_SCALE as f32, NAMETABLE_SCALE as f32)
+        .unwrap();
+    #[cfg(feature = "memview")]
+    let nametable_texture_creator = nametable_canvas.texture_creator();
+    #[cfg(feature = "memview")]
+    let mut nametable_texture = nametable_texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGB24, 512, 480)
+        .unwrap();
+    #[cfg(feature = "memview")]
+    let mut last_mirroring = None;
+
+    #[cfg(feature = "memview")]
+    let mut pattern_canvas = pattern_window.into_canvas().build().unwrap();
+    #[cfg(feature = "memview")]
+    pattern_canvas
+        .set_scale(PATTERN_SCALE as f32, PATTERN_SCALE as f32)
+        .unwrap();
+    #[cfg(feature = "memview")]
+    let pattern_texture_creator = pattern_canvas.texture_creator();
+    #[cfg(feature = "memview")]
+    let mut pattern_texture = pattern_texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGB24, 256, 128)
+        .unwrap();
+
+    #[cfg(feature = "memview")]
+    let mut oam_canvas = oam_window.into_canvas().build().unwrap();
+    #[cfg(feature = "memview")]
+    oam_canvas
+        .set_scale(OAM_SCALE as f32, OAM_SCALE as f32)
+        .unwrap();
+    #[cfg(feature = "memview")]
+    let oam_texture_creator = oam_canvas.texture_creator();
+    #[cfg(feature = "memview")]
+    let mut oam_texture = oam_texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGB24, 64, 64)
+        .unwrap();
+
+    let mut audio_sink = SdlAudioSink::new(&audio_subsystem).unwrap();
+
+    let patch = patch_path
+        .map(|path| std::fs::read(path).error_message("Failed to read patch", canvas.window()));
+    let cartridge = load_cartridge(&rom_path, patch.as_deref())
+        .error_message("Failed to load ROM", canvas.window());
+    println!("rom info:\n{}", cartridge.rom_info());
+    let cartridge = Rc::new(RefCell::new(cartridge));
+    let cpu = Rc::new(RefCell::new(Cpu::new()));
+    let ppu = Rc::new(RefCell::new(Ppu::new(cartridge.clone())));
+    // Matches the format of the main texture.
+    ppu.borrow_mut().set_pixel_format(PixelFormat::Rgb24);
+    #[cfg(feature = "memview")]
+    ppu.borrow_mut().set_pixel_inspection(true);
+    ppu.borrow_mut().set_sprite_flicker_seed(flicker_seed);
+    let apu = Rc::new(RefCell::new(Apu::new()));
+    apu.borrow_mut().set_resampler(Resampler::Sinc);
+    let bus = Bus::new(
+        cpu.clone(),
+        nes_core::new_boxed_array(),
+        ppu.clone(),
+        apu.clone(),
+        cartridge.clone(),
+    );
+    // Replays have to run on the region they were recorded on to stay in sync.
+    let region = region
+        .or(replay.as_ref().map(|player| player.replay().region()))
+        .unwrap_or_else(|| cartridge.borrow().detect_region(Some(&rom_path)));
+    bus.borrow_mut().set_region(region);
+    println!("region: {region}");
+    // Likewise, the accuracy profile changes CPU and PPU timing in ways a replay can depend on.
+    let accuracy_profile = accuracy_profile
+        .or(replay
+            .as_ref()
+            .and_then(|player| player.replay().accuracy_profile()))
+        .unwrap_or_default();
+    bus.borrow_mut().set_accuracy_profile(accuracy_profile);
+    bus.borrow_mut()
+        .set_savestate_rom_check(!allow_any_savestate);
+    // The keyboard is read through the expansion port, in place of controller 2.
+    let family_keyboard = use_family_keyboard.then(|| {
+        let keyboard = Rc::new(RefCell::new(FamilyKeyboard::new()));
+        let device = Box::new(keyboard.clone());
+        bus.borrow_mut()
+            .connect_device(ControllerPort::Two, Some(device));
+        keyboard
+    });
+    // Replays connect their own Four Score if they were recorded with one.
+    let four_scores = (use_four_score && replay.is_none()).then(|| {
+        if family_keyboard.is_some() {
+            show_error(
+                "The Four Score can't be used with the Family BASIC keyboard",
+                canvas.window(),
+            );
+        }
+        [ControllerPort::One, ControllerPort::Two].map(|port| {
+            let four_score = Rc::new(RefCell::new(FourScore::new(port)));
+            bus.borrow_mut()
+                .connect_device(port, Some(Box::new(four_score.clone())));
+            four_score
+        })
+    });
+    cpu.borrow_mut().reset();
+
+    // Replays always start from power-on, so don't offer to resume when playing one back.
+    let autosave_path = Path::new(&rom_path).with_extension("autosave.fcs");
+    let battery_save_path = Path::new(&rom_path).with_extension("sav");
+    let mut osd = Osd::new();
+    if replay.is_none() {
+        load_battery_save(&cartridge, &battery_save_path);
+    }
+    let mut is_resumed = false;
+    if replay.is_none() && autosave_path.exists() && ask_resume(canvas.window()) {
+        let result = std::fs::read(&autosave_path)
+            .map_err(|err| err.to_string())
+            .and_then(|state| Ok(bus.borrow_mut().load_state(&state)?));
+        match result {
+            Ok(()) => {
+                is_resumed = true;
+                osd.show("Resumed last session", MESSAGE_FRAMES);
+            }
+            Err(err) => {
+                println!("warn: failed to resume last session: {err}");
+                osd.show("Failed to resume session", MESSAGE_FRAMES);
+            }
+        }
+    }
+    // Replays have to be played from power-on, and a resumed session is already past the intro.
+    if fast_boot && replay.is_none() && !is_resumed {
+        let boot = FastBoot::UntilInputRead {
+            max_frames: FAST_BOOT_MAX_FRAMES,
+        };
+        let frames = Bus::fast_boot(bus.clone(), cpu.clone(), ppu.clone(), apu.clone(), boot);
+        osd.show(format!("Skipped {frames} frames"), MESSAGE_FRAMES);
+    }
+    let mut apu_log = log_apu.then(|| {
+        let path = Path::new(&rom_path).with_extension("apulog");
+        let file =
+            std::fs::File::create(path).error_message("Failed to create APU log", canvas.window());
+        bus.borrow_mut().set_apu_logging(true);
+        BufWriter::new(file)
+    });
+    // Every write logged, kept to be exported as a VGM file on exit.
+    let mut apu_writes = Vec::new();
+    let mut wav_recorder = record_wav.then(WavRecorder::new);
+    if let Some(player) = &replay {
+        let description = describe_replay(&player.replay().metadata());
+        println!("replay: {description}");
+        osd.show(description, MESSAGE_FRAMES);
+    }
+    if let (Some(player), Some(frame)) = (replay.as_mut(), seek_frame) {
+        player
+            .seek_to_frame(frame, bus.clone(), cpu.clone(), ppu.clone(), apu.clone())
+            .error_message("Failed to seek replay", canvas.window());
+        osd.show(format!("Seeked to frame {frame}"), MESSAGE_FRAMES);
+    }
+    #[cfg(feature = "gdb")]
+    let mut gdb_server = gdb_port.map(|port| {
+        let server = nes_core::GdbServer::bind(
+            ("127.0.0.1", port),
+            bus.clone(),
+            cpu.clone(),
+            ppu.clone(),
+            apu.clone(),
+        )
+        .error_message("Failed to start GDB server", canvas.window());
+        println!("gdb: listening on port {port}");
+        server
+    });
+    let recovery_snapshot = install_recovery_hook(autosave_path.clone());
+    let frame_duration = ppu.borrow().timing().frame_duration();
+    let mut frames_since_snapshot = 0;
+    let mut frames_since_battery_save = 0;
+    let mut frames_since_watch_check = 0;
+    let mut rom_modified = watch_rom.then(|| modified_time(&rom_path)).flatten();
+
+    let mut event_pump = sdl_context.event_pump().unwrap();
+
+    let mut run_emulation = false;
+    let mut step_frame = false;
+
+    let clock_start = Instant::now();
+    let timestamp = || clock_start.elapsed().as_secs_f64() * 1000.0;
+    let mut perf_monitor = PerfMonitor::new();
+    perf_monitor.set_timing(bus.borrow().timing());
+    let mut show_perf = false;
+    let mut frame_blend: Option<FrameBlend> = None;
+    let mut video_filter = VideoFilter::None;
+    let mut filtered_frame = Vec::new();
+
+    let mut has_reported_halt = false;
+
+    let mut record_replay = false;
+    let mut replay_screenshot = false;
+    let mut replay_recording: Vec<(InputCommand, Controller, Controller, bool)> = Vec::new();
+    let mut audio_watermarks = BufferWatermarks::new(AUDIO_LOW_WATERMARK, AUDIO_HIGH_WATERMARK);
+    let mut auto_hold = [AutoHold::new(Controller::from(0xFF)); 2];
+    let mut dpad_filters = [DpadFilter::new(dpad_policy.unwrap_or_default()); 2];
+    // The replay frame playback last paused at, so resuming doesn't pause there again.
+    let mut last_breakpoint = None;
+
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            // Keys typed on the Family BASIC keyboard shouldn't also trigger shortcuts.
+            if family_keyboard.is_some()
+                && matches!(event, Event::KeyDown { keycode, .. } if keycode != Some(Keycode::Escape))
+            {
+                continue;
+            }
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(Keycode::I),
+                    ..
+                } => {
+                    while !cpu.borrow().is_instruction_finished {
+                        Bus::clock(bus.clone(), cpu.clone(), ppu.clone(), apu.clone());
+                    }
+                    cpu.borrow_mut().is_instruction_finished = false;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    ..
+                } => {
+                    run_emulation = !run_emulation;
+                    // Don't count time spent paused against the frame rate.
+                    perf_monitor.reset();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::T),
+                    ..
+                } => {
+                    let is_validating = !ppu.borrow().is_validating();
+                    ppu.borrow_mut().set_validation(is_validating);
+                    let state = if is_validating { "on" } else { "off" };
+                    osd.show(format!("PPU validation {state}"), MESSAGE_FRAMES);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F),
+                    ..
+                } => {
+                    show_perf = !show_perf;
+                    if !show_perf {
+                        osd.set_status(None);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::G),
+                    ..
+                } => {
+                    let mode = match apu.borrow().fast_forward_audio() {
+                        FastForwardAudio::Decimate => FastForwardAudio::Mute,
+                        FastForwardAudio::Mute => FastForwardAudio::Decimate,
+                    };
+                    apu.borrow_mut().set_fast_forward_audio(mode);
+                    let state = if mode == FastForwardAudio::Mute {
+                        "on"
+                    } else {
+                        "off"
+                    };
+                    osd.show(
+                        format!("Mute while fast-forwarding {state}"),
+                        MESSAGE_FRAMES,
+                    );
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Space),
+                    ..
+                } => step_frame = true,
+                Event::KeyDown {
+                    keycode: Some(Keycode::R),
+                    ..
+                } => {
+                    Bus::reset(cpu.clone(), ppu.clone());
+                    apu.borrow_mut().smooth_reset();
+                    osd.show("Reset", MESSAGE_FRAMES);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::J),
+                    ..
+                } => {
+                    let halted_at = cpu.borrow().halt().map(|halt| halt.program_counter);
+                    if let Some(addr) = halted_at {
+                        cpu.borrow_mut().set_program_counter(addr.wrapping_add(1));
+                        osd.show(format!("Skipped ${addr:04X}"), MESSAGE_FRAMES);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::H),
+                    ..
+                } => {
+                    frame_blend = match frame_blend {
+                        Some(_) => None,
+                        None => Some(FrameBlend::default()),
+                    };
+                    let state = if frame_blend.is_some() { "on" } else { "off" };
+                    osd.show(format!("Frame blending {state}"), MESSAGE_FRAMES);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::C),
+                    ..
+                } => {
+                    video_filter = video_filter.next();
+                    osd.show(format!("Video filter: {video_filter}"), MESSAGE_FRAMES);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::O),
+                    ..
+                } => {
+                    let scanlines = match ppu.borrow().overclock_scanlines() {
+                        0 => OVERCLOCK_SCANLINES,
+                        _ => 0,
+                    };
+                    ppu.borrow_mut().set_overclock_scanlines(scanlines);
+                    let state = if scanlines != 0 { "on" } else { "off" };
+                    osd.show(format!("Overclocking {state}"), MESSAGE_FRAMES);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::U),
+                    ..
+                } => {
+                    let seed = match ppu.borrow().sprite_flicker_seed() {
+                        Some(_) => None,
+                        None => Some(flicker_seed.unwrap_or_default()),
+                    };
+                    ppu.borrow_mut().set_sprite_flicker_seed(seed);
+                    let state = if seed.is_some() { "on" } else { "off" };
+                    osd.show(format!("Sprite flicker {state}"), MESSAGE_FRAMES);
+                }
+                #[cfg(feature = "memview")]
+                Event::KeyDown {
+                    keycode: Some(Keycode::E),
+                    ..
+                } => {
+                    let mut ppu = ppu.borrow_mut();
+                    let palette = ppu.pattern_table_palette();
+                    ppu.set_pattern_table_palette(palette.wrapping_add(1));
+                    ppu.draw_pattern_tables();
+                }
+                #[cfg(feature = "memview")]
+                Event::KeyDown {
+                    keycode: Some(Keycode::Q),
+                    ..
+                } => {
+                    let mut ppu = ppu.borrow_mut();
+                    let palette = ppu.pattern_table_palette();
+                    ppu.set_pattern_table_palette(palette.wrapping_sub(1));
+                    ppu.draw_pattern_tables();
+                }
+                #[cfg(feature = "memview")]
+                Event::KeyDown {
+                    keycode: Some(keycode @ (Keycode::LeftBracket | Keycode::RightBracket)),
+                    ..
+                } => {
+                    // Cycle through each 8 KiB page of CHR, then back to what's banked in.
+                    let pages = cartridge.borrow().chr().len().div_ceil(0x2000);
+                    let mut ppu = ppu.borrow_mut();
+                    let page = match (ppu.pattern_table_chr_page(), keycode) {
+                        (None, Keycode::RightBracket) => Some(0),
+                        (None, _) => Some(pages - 1),
+                        (Some(page), Keycode::RightBracket) => {
+                            Some(page + 1).filter(|&page| page < pages)
+                        }
+                        (Some(page), _) => page.checked_sub(1),
+                    };
+                    ppu.set_pattern_table_chr_page(page);
+                    ppu.draw_pattern_tables();
+                    match page {
+                        Some(page) => {
+                            osd.show(format!("CHR page {}/{pages}", page + 1), MESSAGE_FRAMES)
+                        }
+                        None => osd.show("CHR: banked in", MESSAGE_FRAMES),
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::V),
+                    ..
+                } => {
+                    if !record_replay {
+                        osd.show("Replay recording started", MESSAGE_FRAMES);
+                        record_replay = true;
+                    } else {
+                        // Determine whether controller 2 and the microphone were used.
+                        let microphone_active =
+                            replay_recording.iter().any(|&(.., microphone)| microphone);
+                        let controller_2_active = microphone_active
+                            || replay_recording
+                                .iter()
+                                .any(|&(_, _, controller, _)| controller != Controller::default());
+
+                        if accuracy_profile != AccuracyProfile::default() {
+                            println!("accuracyProfile {accuracy_profile}");
+                        }
+                        if four_scores.is_some() {
+                            println!("fourscore 1");
+                        }
+                        if microphone_active {
+                            println!("microphone 1");
+                        }
+                        for &(command, controller_1, controller_2, microphone) in &replay_recording
+                        {
+                            // Only emit controller 2 data if necessary. Players 3 and 4 aren't
+                            // mapped to the keyboard, so they never press anything.
+                            let controllers = if four_scores.is_some() {
+                                let idle = Controller::new();
+                                [controller_1, controller_2, idle, idle].map(Some).to_vec()
+                            } else {
+                                vec![
+                                    Some(controller_1),
+                                    controller_2_active.then_some(controller_2),
+                                ]
+                            };
+                            let microphone = microphone_active.then_some(microphone);
+                            println!("{}", format_input_line(command, &controllers, microphone));
+                        }
+                        osd.show("Replay recording finished", MESSAGE_FRAMES);
+                        record_replay = false;
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::B),
+                    ..
+                } => replay_screenshot = true,
+                Event::KeyDown {
+                    keycode: Some(Keycode::N),
+                    ..
+                } => {
+                    if let Some(player) = &replay {
+                        let frame = player.frame();
+                        let path = Path::new(&rom_path).with_extension(format!("frame{frame}.fcs"));
+                        let state = save_state(&bus.borrow(), &rom_path);
+                        match std::fs::write(&path, state) {
+                            Ok(()) => osd.show(format!("Saved frame {frame}"), MESSAGE_FRAMES),
+                            Err(err) => {
+                                println!("warn: failed to save state: {err}");
+                                osd.show("Failed to save state", MESSAGE_FRAMES);
+                            }
+                        }
+                    }
+                }
+                #[cfg(feature = "memview")]
+                Event::MouseButtonDown {
+                    window_id,
+                    mouse_btn: sdl2::mouse::MouseButton::Right,
+                    y,
+                    ..
+                } if window_id == canvas.window().id() => {
+                    let y = (y / MAIN_SCALE as i32) as u16;
+                    if let Some(render) = ppu.borrow().render_scanline(y) {
+                        print_scanline(&render);
+                        osd.show(format!("Printed scanline {y}"), MESSAGE_FRAMES);
+                    }
+                }
+                #[cfg(feature = "memview")]
+                Event::MouseButtonDown {
+                    window_id, x, y, ..
+                } if window_id == canvas.window().id() => {
+                    let x = (x / MAIN_SCALE as i32) as u16;
+                    let y = (y / MAIN_SCALE as i32) as u16;
+                    if let Some(source) = ppu.borrow().inspect_pixel(x, y) {
+                        osd.show(describe_pixel_source(source), MESSAGE_FRAMES);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Num1),
+                    ..
+                } => {
+                    let is_pulse_1_enabled = apu.borrow().is_pulse_1_enabled;
+                    apu.borrow_mut().is_pulse_1_enabled = !is_pulse_1_enabled;
+                    print_apu_channel_status(&apu);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Num2),
+                    ..
+                } => {
+                    let is_pulse_2_enabled = apu.borrow().is_pulse_2_enabled;
+                    apu.borrow_mut().is_pulse_2_enabled = !is_pulse_2_enabled;
+                    print_apu_channel_status(&apu);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Num3),
+                    ..
+                } => {
+                    let is_triangle_enabled = apu.borrow().is_triangle_enabled;
+                    apu.borrow_mut().is_triangle_enabled = !is_triangle_enabled;
+                    print_apu_channel_status(&apu);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Num4),
+                    ..
+                } => {
+                    let is_noise_enabled = apu.borrow().is_noise_enabled;
+                    apu.borrow_mut().is_noise_enabled = !is_noise_enabled;
+                    print_apu_channel_status(&apu);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Num5),
+                    ..
+                } => {
+                    let expansion_gain = apu.borrow().gains.expansion;
+                    apu.borrow_mut().gains.expansion =
+                        if expansion_gain == 0.0 { 1.0 } else { 0.0 };
+                    print_apu_channel_status(&apu);
+                }
+                _ => {}
+            }
+        }
+
+        let is_fast_forwarding = run_emulation
+            && event_pump
+                .keyboard_state()
+                .is_scancode_pressed(Scancode::Tab);
+        let speed = if is_fast_forwarding {
+            FAST_FORWARD_SPEED
+        } else {
+            1
+        };
+        apu.borrow_mut().set_speed(speed);
+        if let Some(keyboard) = &family_keyboard {
+            update_family_keyboard(&mut keyboard.borrow_mut(), &event_pump);
+        }
+        #[cfg(feature = "gdb")]
+        if let Some(gdb_server) = gdb_server.as_mut() {
+            gdb_server.poll();
+            for warning in gdb_server.drain_warnings() {
+                println!("warn: {warning}");
+            }
+        }
+        #[cfg(feature = "gdb")]
+        let is_debugger_stopped = gdb_server
+            .as_ref()
+            .is_some_and(|server| server.is_stopped());
+        #[cfg(not(feature = "gdb"))]
+        let is_debugger_stopped = false;
+        for _ in 0..speed {
+            if (run_emulation || step_frame) && !is_debugger_stopped {
+                if let Some(player) = &replay {
+                    let frame = player.frame();
+                    if run_emulation && player.is_at_breakpoint() && last_breakpoint != Some(frame)
+                    {
+                        last_breakpoint = Some(frame);
+                        run_emulation = false;
+                        let length = player.length().unwrap_or_default();
+                        osd.show(format!("Paused at frame {frame}/{length}"), MESSAGE_FRAMES);
+                        break;
+                    }
+                }
+                let frame_start = timestamp();
+                match replay {
+                    // Once the movie ends, hand control back to the player.
+                    Some(ref mut player) if !player.is_finished() => {
+                        player.apply_next_input(bus.clone(), cpu.clone(), ppu.clone());
+                        if player.take_finished() {
+                            osd.show("Replay finished", MESSAGE_FRAMES);
+                        }
+                    }
+                    _ => {
+                        let (controller_1, controller_2) = get_controller_state(&event_pump);
+                        let is_toggle_held =
+                            event_pump.keyboard_state().is_scancode_pressed(Scancode::Y);
+                        let controller_1 = auto_hold[0].apply(controller_1, is_toggle_held);
+                        let controller_2 = auto_hold[1].apply(controller_2, is_toggle_held);
+                        let controller_1 = dpad_filters[0].apply(controller_1);
+                        let controller_2 = dpad_filters[1].apply(controller_2);
+                        let microphone =
+                            event_pump.keyboard_state().is_scancode_pressed(Scancode::M);
+                        if record_replay && (run_emulation || step_frame) {
+                            let command = InputCommand::new().with_screenshot(replay_screenshot);
+                            replay_recording.push((
+                                command,
+                                controller_1,
+                                controller_2,
+                                microphone,
+                            ));
+                            replay_screenshot = false;
+                        }
+
+                        bus.borrow_mut()
+                            .set_controller_state(controller_1, controller_2);
+                        bus.borrow_mut().set_microphone(microphone);
+                        if let Some([port_1, port_2]) = &four_scores {
+                            port_1
+                                .borrow_mut()
+                                .set_controllers(controller_1, Controller::new());
+                            port_2
+                                .borrow_mut()
+                                .set_controllers(controller_2, Controller::new());
+                        }
+                    }
+                }
+
+                #[cfg(feature = "gdb")]
+                if let Some(gdb_server) = gdb_server.as_mut() {
+                    if !gdb_server.run_frame() {
+                        // Stopped partway through the frame, so finish it once the debugger
+                        // resumes.
+                        break;
+                    }
+                }
+                Bus::run_frame(bus.clone(), cpu.clone(), ppu.clone(), apu.clone());
+                for warning in ppu.borrow_mut().drain_warnings() {
+                    println!("warn: ppu: {warning}");
+                }
+                for warning in bus.borrow_mut().drain_warnings() {
+                    println!("warn: {warning}");
+                }
+                if let Some(file) = apu_log.as_mut() {
+                    let writes = bus.borrow_mut().drain_apu_log();
+                    if let Err(err) = writes
+                        .iter()
+                        .try_for_each(|write| writeln!(file, "{write}"))
+                    {
+                        println!("warn: failed to write APU log: {err}");
+                        bus.borrow_mut().set_apu_logging(false);
+                        apu_log = None;
+                    }
+                    apu_writes.extend(writes);
+                }
+                if let Some(player) = replay.as_mut() {
+                    player.finish_frame(&ppu.borrow());
+                    for capture in player.drain_captured_frames() {
+                        let path = Path::new(&rom_path)
+                            .with_extension(format!("frame{}.ppm", capture.frame));
+                        if let Err(err) = std::fs::write(&path, capture.to_ppm()) {
+                            println!("warn: failed to save screenshot: {err}");
+                        }
+                    }
+                }
+                step_frame = false;
+                frames_since_snapshot += 1;
+                if frames_since_snapshot >= RECOVERY_INTERVAL {
+                    *recovery_snapshot.lock().unwrap() = Some(bus.borrow().save_state());
+                    frames_since_snapshot = 0;
+                }
+                frames_since_battery_save += 1;
+                if frames_since_battery_save >= BATTERY_SAVE_INTERVAL && replay.is_none() {
+                    write_battery_save(&cartridge, &battery_save_path);
+                    frames_since_battery_save = 0;
+                }
+                frames_since_watch_check += 1;
+                if watch_rom && frames_since_watch_check >= WATCH_INTERVAL {
+                    frames_since_watch_check = 0;
+                    let modified = modified_time(&rom_path);
+                    if modified.is_some() && modified != rom_modified {
+                        rom_modified = modified;
+                        match load_cartridge(&rom_path, patch.as_deref()) {
+                            Ok(new_cartridge) => {
+                                if replay.is_none() {
+                                    write_battery_save(&cartridge, &battery_save_path);
+                                }
+                                Bus::swap_cartridge(bus.clone(), new_cartridge, keep_ram);
+                                if replay.is_none() {
+                                    load_battery_save(&cartridge, &battery_save_path);
+                                }
+                                if let Some(frame_blend) = frame_blend.as_mut() {
+                                    frame_blend.reset();
+                                }
+                                osd.show("ROM reloaded", MESSAGE_FRAMES);
+                            }
+                            Err(err) => {
+                                println!("warn: failed to reload ROM: {err}");
+                                osd.show("Failed to reload ROM", MESSAGE_FRAMES);
+                            }
+                        }
+                    }
+                }
+                perf_monitor.record_frame(frame_start, timestamp(), audio_sink.queued_samples());
+                let samples = apu.borrow_mut().drain_audio_buffer();
+                audio_sink.push_samples(&samples);
+                if let Some(wav_recorder) = wav_recorder.as_mut() {
+                    wav_recorder.push_samples(&samples);
+                }
+                if let Some(frame_blend) = frame_blend.as_mut() {
+                    frame_blend.push(ppu.borrow().buffer());
+                }
+                #[cfg(feature = "memview")]
+                {
+                    ppu.borrow_mut().draw_nametables();
+                    ppu.borrow_mut().draw_pattern_tables();
+                    ppu.borrow_mut().draw_oam();
+                }
+            }
+        }
+        match cpu.borrow().halt() {
+            Some(halt) if !has_reported_halt => {
+                let trace: Vec<_> = halt.trace.iter().map(|pc| format!("${pc:04X}")).collect();
+                println!("recent instructions: {}", trace.join(" "));
+                println!("registers: {}", cpu.borrow().snapshot());
+                osd.show(format!("CPU {halt}"), MESSAGE_FRAMES);
+                has_reported_halt = true;
+            }
+            Some(_) => (),
+            None => has_reported_halt = false,
+        }
+        audio_watermarks.update(audio_sink.queued_samples());
+        let is_audio_ahead = audio_watermarks.last() == Some(BufferWatermark::High);
+        if is_audio_ahead || !run_emulation || is_debugger_stopped {
+            std::thread::sleep(frame_duration);
+        }
+
+        if show_perf {
+            osd.set_status(Some(perf_monitor.stats().to_string()));
+        }
+        osd.tick();
+        if video_filter == VideoFilter::None {
+            texture
+                .with_lock(None, |buffer, _| {
+                    let ppu = ppu.borrow();
+                    let frame = frame_blend
+                        .as_ref()
+                        .and_then(FrameBlend::output)
+                        .unwrap_or(ppu.buffer());
+                    buffer.copy_from_slice(frame);
+                    osd.draw(buffer, PixelFormat::Rgb24);
+                })
+                .unwrap();
+            canvas.copy(&texture, None, None).unwrap();
+        } else {
+            // Draw messages before filtering, so they're upscaled along with everything else.
+            let mut frame = {
+                let ppu = ppu.borrow();
+                let frame = frame_blend
+                    .as_ref()
+                    .and_then(FrameBlend::output)
+                    .unwrap_or(ppu.buffer());
+                frame.to_vec()
+            };
+            osd.draw(&mut frame, PixelFormat::Rgb24);
+            video_filter.apply(&frame, PixelFormat::Rgb24, &mut filtered_frame);
+            filtered_texture
+                .with_lock(None, |buffer, _| buffer.copy_from_slice(&filtered_frame))
+                .unwrap();
+            canvas.copy(&filtered_texture, None, None).unwrap();
+        }
+
+        // Name the mirroring, since the viewer dims the nametables it makes into copies.
+        #[cfg(feature = "memview")]
+        {
+            let mirroring = ppu.borrow().mirroring();
+            if last_mirroring != Some(mirroring) {
+                last_mirroring = Some(mirroring);
+                let title = format!("Nametable Viewer ({mirroring} mirroring)");
+                nametable_canvas.window_mut().set_title(&title).unwrap();
+            }
+        }
+        #[cfg(feature = "memview")]
+        nametable_texture
+            .with_lock(None, |buffer, _| {
+                buffer.copy_from_slice(ppu.borrow().nametable_buffer());
+            })
+            .unwrap();
+        #[cfg(feature = "memview")]
+        nametable_canvas
+            .copy(&nametable_texture, None, None)
+            .unwrap();
+
+        #[cfg(feature = "memview")]
+        pattern_texture
+            .with_lock(None, |buffer, _| {
+                buffer.copy_from_slice(ppu.borrow().pattern_table_buffer());
+            })
+            .unwrap();
+        #[cfg(feature = "memview")]
+        pattern_canvas.copy(&pattern_texture, None, None).unwrap();
+
+        #[cfg(feature = "memview")]
+        oam_texture
+            .with_lock(None, |buffer, _| {
+                buffer.copy_from_slice(ppu.borrow().oam_buffer());
+            })
+            .unwrap();
+        #[cfg(feature = "memview")]
+        oam_canvas.copy(&oam_texture, None, None).unwrap();
+
+        canvas.present();
+        #[cfg(feature = "memview")]
+        {
+            nametable_canvas.present();
+            pattern_canvas.present();
+            oam_canvas.present();
+        }
+    }
+
+    if replay.is_none() {
+        write_battery_save(&cartridge, &battery_save_path);
+        match std::fs::write(&autosave_path, save_state(&bus.borrow(), &rom_path)) {
+            Ok(()) => println!("saved session to {}", autosave_path.display()),
+            Err(err) => println!("warn: failed to save session: {err}"),
+        }
+    }
+    if !apu_writes.is_empty() {
+        let path = Path::new(&rom_path).with_extension("vgm");
+        let vgm = to_vgm(&apu_writes, bus.borrow().timing());
+        match std::fs::write(&path, vgm) {
+            Ok(()) => println!("saved music to {}", path.display()),
+            Err(err) => println!("warn: failed to save music: {err}"),
+        }
+    }
+    if let Some(wav_recorder) = wav_recorder {
+        let path = Path::new(&rom_path).with_extension("wav");
+        let result = std::fs::File::create(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|file| wav_recorder.write(BufWriter::new(file)));
+        match result {
+            Ok(()) => println!("saved audio to {}", path.display()),
+            Err(err) => println!("warn: failed to save audio: {err}"),
+        }
+    }
+}
+
+/// Reads a ROM from disk, applying a patch to it if one is given.
+/// Removes a flag from the arguments, returning whether it was there.
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let index = args.iter().position(|arg| arg == flag);
+    index.map(|index| args.remove(index)).is_some()
+}
+
+/// Removes a flag and the value after it from the arguments, returning the value. A flag with
+/// nothing after it gives an empty value, so that it's reported as invalid rather than ignored.
+fn take_value(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == flag)?;
+    args.remove(index);
+    if index < args.len() {
+        Some(args.remove(index))
+    } else {
+        Some(String::new())
+    }
+}
+
+fn load_cartridge(path: &str, patch: Option<&[u8]>) -> Result<Cartridge, String> {
+    let rom = std::fs::read(path).map_err(|err| err.to_string())?;
+    match patch {
+        Some(patch) => Cartridge::with_patch(&rom, patch),
+        None => Cartridge::new(&rom),
+    }
+}
+
+/// Returns when a file was last modified, or `None` if that can't be determined.
+/// Saves a savestate labeled with the current time and the ROM's file name.
+fn save_state(bus: &Bus, rom_path: &str) -> Vec<u8> {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .map(|duration| duration.as_millis() as u64);
+    let rom_name = Path::new(rom_path)
+        .file_name()
+        .and_then(|name| name.to_str());
+    bus.save_state_with_metadata(timestamp, rom_name)
+}
+
+fn modified_time(path: &str) -> Option<std::time::SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+/// Restores battery-backed RAM from disk, if there's a save for it.
+fn load_battery_save(cartridge: &Rc<RefCell<Cartridge>>, path: &Path) {
+    if !path.exists() {
+        return;
+    }
+    let result = std::fs::read(path)
+        .map_err(|err| err.to_string())
+        .and_then(|data| cartridge.borrow_mut().load_battery_ram(&data));
+    if let Err(err) = result {
+        println!("warn: failed to load battery save: {err}");
+    }
+}
+
+/// Writes battery-backed RAM out to disk, but only if it has changed since it was last written.
+fn write_battery_save(cartridge: &Rc<RefCell<Cartridge>>, path: &Path) {
+    if !cartridge.borrow_mut().take_dirty() {
+        return;
+    }
+    let cartridge = cartridge.borrow();
+    let Some(data) = cartridge.battery_ram() else {
+        return;
+    };
+    if let Err(err) = std::fs::write(path, data) {
+        println!("warn: failed to write battery save: {err}");
+    }
+}
+
+/// Asks the user whether to resume from the previous session's autosave.
+fn ask_resume(window: &Window) -> bool {
+    use sdl2::messagebox::{ButtonData, ClickedButton, MessageBoxButtonFlag, MessageBoxFlag};
+
+    let buttons = [
+        ButtonData {
+            flags: MessageBoxButtonFlag::RETURNKEY_DEFAULT,
+            button_id: 1,
+            text: "Resume",
+        },
+        ButtonData {
+            flags: MessageBoxButtonFlag::ESCAPEKEY_DEFAULT,
+            button_id: 0,
+            text: "Start over",
+        },
+    ];
+    let clicked = sdl2::messagebox::show_message_box(
+        MessageBoxFlag::INFORMATION,
+        &buttons,
+        "Resume",
+        "Resume from where you left off last session?",
+        window,
+        None,
+    );
+
+    matches!(clicked, Ok(ClickedButton::CustomButton(button)) if button.button_id == 1)
+}
+
+/// Installs a panic hook that writes the most recent recovery snapshot to disk.
+///
+/// The system components can't be accessed from the hook itself, as they may be mid-borrow when
+/// the panic occurs. Instead, a snapshot should periodically be stored in the returned slot.
+fn install_recovery_hook(path: PathBuf) -> Arc<Mutex<Option<Vec<u8>>>> {
+    let snapshot: Arc<Mutex<Option<Vec<u8>>>> = Arc::default();
+    let hook_snapshot = snapshot.clone();
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if let Ok(snapshot) = hook_snapshot.lock() {
+            if let Some(state) = snapshot.as_ref() {
+                match std::fs::write(&path, state) {
+                    Ok(()) => eprintln!("saved recovery snapshot to {}", path.display()),
+                    Err(err) => eprintln!("failed to save recovery snapshot: {err}"),
+                }
+            }
+        }
+        default_hook(info);
+    }));
+
+    snapshot
+}
+
+fn get_controller_state(event_pump: &sdl2::EventPump) -> (Controller, Controller) {
+    let keyboard_state = event_pump.keyboard_state();
+    let key = |key: Scancode| keyboard_state.is_scancode_pressed(key);
+
+    let controller_1 = Controller::new()
+        .with_a(key(Scancode::X))
+        .with_b(key(Scancode::Z))
+        .with_select(key(Scancode::RShift))
+        .with_start(key(Scancode::Return))
+        .with_up(key(Scancode::Up))
+        .with_down(key(Scancode::Down))
+        .with_left(key(Scancode::Left))
+        .with_right(key(Scancode::Right));
+
+    let controller_2 = Controller::new()
+        .with_a(key(Scancode::L))
+        .with_b(key(Scancode::K))
+        .with_up(key(Scancode::W))
+        .with_down(key(Scancode::S))
+        .with_left(key(Scancode::A))
+        .with_right(key(Scancode::D));
+
+    (controller_1, controller_2)
+}
+
+fn update_family_keyboard(keyboard: &mut FamilyKeyboard, event_pump: &sdl2::EventPump) {
+    keyboard.release_all();
+    for scancode in event_pump.keyboard_state().pressed_scancodes() {
+        if let Some(key) = family_key(scancode) {
+            keyboard.set_key(key, true);
+        }
+    }
+}
+
+/// Maps a key on a US keyboard to the key in the same position on the Family BASIC keyboard.
+fn family_key(scancode: Scancode) -> Option<FamilyKey> {
+    let key = match scancode {
+        Scancode::A => FamilyKey::A,
+        Scancode::B => FamilyKey::B,
+        Scancode::C => FamilyKey::C,
+        Scancode::D => FamilyKey::D,
+        Scancode::E => FamilyKey::E,
+        Scancode::F => FamilyKey::F,
+        Scancode::G => FamilyKey::G,
+        Scancode::H => FamilyKey::H,
+        Scancode::I => FamilyKey::I,
+        Scancode::J => FamilyKey::J,
+        Scancode::K => FamilyKey::K,
+        Scancode::L => FamilyKey::L,
+        Scancode::M => FamilyKey::M,
+        Scancode::N => FamilyKey::N,
+        Scancode::O => FamilyKey::O,
+        Scancode::P => FamilyKey::P,
+        Scancode::Q => FamilyKey::Q,
+        Scancode::R => FamilyKey::R,
+        Scancode::S => FamilyKey::S,
+        Scancode::T => FamilyKey::T,
+        Scancode::U => FamilyKey::U,
+        Scancode::V => FamilyKey::V,
+        Scancode::W => FamilyKey::W,
+        Scancode::X => FamilyKey::X,
+        Scancode::Y => FamilyKey::Y,
+        Scancode::Z => FamilyKey::Z,
+        Scancode::Num0 => FamilyKey::Num0,
+        Scancode::Num1 => FamilyKey::Num1,
+        Scancode::Num2 => FamilyKey::Num2,
+        Scancode::Num3 => FamilyKey::Num3,
+        Scancode::Num4 => FamilyKey::Num4,
+        Scancode::Num5 => FamilyKey::Num5,
+        Scancode::Num6 => FamilyKey::Num6,
+        Scancode::Num7 => FamilyKey::Num7,
+        Scancode::Num8 => FamilyKey::Num8,
+        Scancode::Num9 => FamilyKey::Num9,
+        Scancode::F1 => FamilyKey::F1,
+        Scancode::F2 => FamilyKey::F2,
+        Scancode::F3 => FamilyKey::F3,
+        Scancode::F4 => FamilyKey::F4,
+        Scancode::F5 => FamilyKey::F5,
+        Scancode::F6 => FamilyKey::F6,
+        Scancode::F7 => FamilyKey::F7,
+        Scancode::F8 => FamilyKey::F8,
+        Scancode::Minus => FamilyKey::Minus,
+        Scancode::Equals => FamilyKey::Caret,
+        Scancode::International3 => FamilyKey::Yen,
+        Scancode::LeftBracket => FamilyKey::At,
+        Scancode::RightBracket => FamilyKey::LeftBracket,
+        Scancode::Backslash => FamilyKey::RightBracket,
+        Scancode::Semicolon => FamilyKey::Semicolon,
+        Scancode::Apostrophe => FamilyKey::Colon,
+        Scancode::Comma => FamilyKey::Comma,
+        Scancode::Period => FamilyKey::Period,
+        Scancode::Slash => FamilyKey::Slash,
+        Scancode::International1 => FamilyKey::Underscore,
+        Scancode::Grave => FamilyKey::Escape,
+        Scancode::Return => FamilyKey::Return,
+        Scancode::Space => FamilyKey::Space,
+        Scancode::LShift => FamilyKey::LeftShift,
+        Scancode::RShift => FamilyKey::RightShift,
+        Scancode::LCtrl => FamilyKey::Ctr,
+        Scancode::LAlt => FamilyKey::Graph,
+        Scancode::RAlt => FamilyKey::Kana,
+        Scancode::End => FamilyKey::Stop,
+        Scancode::Home => FamilyKey::ClrHome,
+        Scancode::Insert => FamilyKey::Insert,
+        Scancode::Backspace | Scancode::Delete => FamilyKey::Delete,
+        Scancode::Up => FamilyKey::Up,
+        Scancode::Down => FamilyKey::Down,
+        Scancode::Left => FamilyKey::Left,
+        Scancode::Right => FamilyKey::Right,
+        _ => return None,
+    };
+    Some(key)
+}
+
+fn describe_replay(metadata: &nes_core::ReplayMetadata) -> String {
+    let mut description = metadata.rom_filename.clone();
+    if let (Some(length), Some(duration)) = (metadata.length, metadata.duration()) {
+        let seconds = duration.as_secs();
+        description += &format!(", {length} frames ({}:{:02})", seconds / 60, seconds % 60);
+    }
+    if let Some(rerecord_count) = metadata.rerecord_count {
+        description += &format!(", {rerecord_count} rerecords");
+    }
+    if let Some(comment) = &metadata.comment {
+        description += &format!(", {comment}");
+    }
+    description
+}
+
+#[cfg(feature = "memview")]
+fn describe_pixel_source(source: nes_core::PixelSource) -> String {
+    use nes_core::PixelSource;
+
+    match source {
+        PixelSource::Backdrop { color } => format!("Backdrop C:{color:02X}"),
+        PixelSource::Background {
+            nametable_addr,
+            tile,
+            palette,
+            color,
+            ..
+        } => format!("BG ${nametable_addr:04X} T:{tile:02X} P{palette} C:{color:02X}"),
+        PixelSource::Sprite {
+            oam_index,
+            tile,
+            palette,
+            color,
+            ..
+        } => format!("Sprite {oam_index} T:{tile:02X} P{palette} C:{color:02X}"),
+    }
+}
+
+/// Prints every pixel of a re-rendered scanline, one per line, for tracking down glitches.
+#[cfg(feature = "memview")]
+fn print_scanline(render: &nes_core::ScanlineRender) {
+    println!("scanline {}: sprites {:?}", render.scanline, render.sprites);
+    for (x, pixel) in render.pixels.iter().enumerate() {
+        let background = pixel.background;
+        let sprite = pixel.sprite.map_or(String::new(), |sprite| {
+            format!(
+                " | sprite {} T:{:02X} A:{:02X} {}",
+                sprite.oam_index, sprite.tile, sprite.attributes, sprite.pattern
+            )
+        });
+        let hit = if pixel.is_sprite_zero_hit {
+            " | hit"
+        } else {
+            ""
+        };
+        println!(
+            "{x:3}: BG ${:04X} T:{:02X} ${:04X} P{} {}{sprite}{hit} -> {}",
+            background.nametable_addr,
+            background.tile,
+            background.attribute_addr,
+            background.palette,
+            background.pattern,
+            describe_pixel_source(pixel.source),
+        );
+    }
+}
+
+fn print_apu_channel_status(apu: &Rc<RefCell<Apu>>) {
+    let p1 = apu.borrow().is_pulse_1_enabled;
+    let p2 = apu.borrow().is_pulse_2_enabled;
+    let t = apu.borrow().is_triangle_enabled;
+    let n = apu.borrow().is_noise_enabled;
+    let e = apu.borrow().gains.expansion != 0.0;
+
+    println!("P1: {p1}, P2: {p2}, T: {t}, N: {n}, E: {e}");
+}
+
+trait ErrorMessage {
+    type Output;
+    fn error_message(self, message: &str, window: &Window) -> Self::Output;
+}
+
+impl<T, E> ErrorMessage for Result<T, E>
+where
+    E: Display,
+{
+    type Output = T;
+    fn error_message(self, message: &str, window: &Window) -> T {
+        self.unwrap_or_else(|err| show_error(&format!("{message}: {err}"), window))
+    }
+}
+
+impl<T> ErrorMessage for Option<T> {
+    type Output = T;
+    fn error_message(self, message: &str, window: &Window) -> T {
+        self.unwrap_or_else(|| show_error(message, window))
+    }
+}
+
+fn show_error(message: &str, window: &Window) -> ! {
+    use sdl2::messagebox::MessageBoxFlag;
+
+    sdl2::messagebox::show_simple_message_box(MessageBoxFlag::ERROR, "Error", message, window)
+        .unwrap();
+
+    panic!("{message}")
+}
@@ -0,0 +1,827 @@
+//! WebAssembly bindings for the emulator core, wrapping a whole console in a single [Nes] object
+//! for JavaScript frontends.
+//!
+//! Types that cross into JavaScript are mirrored in [types], so that the core doesn't depend on
+//! wasm-bindgen.
+//!
+//! Each [Nes] owns its own console, and neither crate keeps any global state besides the panic
+//! hook, so a page can run several at once, such as to compare them side by side.
+
+mod types;
+
+use std::{cell::RefCell, rc::Rc};
+
+use nes_core::{
+    AccuracyOption, Apu, Bus, Capabilities, Cartridge, Cpu, FastForwardAudio, FrameBlend,
+    Frameskip, Osd, PerfMonitor, Ppu, PpuWarning, Savestate, StopCondition, VERSION,
+};
+use wasm_bindgen::prelude::*;
+
+pub use types::{
+    AccuracyProfile, Controller, DmaStats, PerfStats, PixelFormat, Region, Resampler,
+    SavestateError, SavestateErrorKind, SavestateMetadata, VideoFilter,
+};
+
+#[wasm_bindgen(start)]
+fn start() {
+    console_error_panic_hook::set_once();
+}
+
+/// How much work goes into presentation, traded off against speed on weak devices. None of the
+/// levels affect emulation.
+#[wasm_bindgen]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Quality {
+    /// Full resolution, with the memory viewer's data available.
+    #[default]
+    High,
+    /// Full resolution, but the memory viewer's data isn't copied out, so the page can skip
+    /// drawing it.
+    Medium,
+    /// As with [Quality::Medium], and frames are also drawn at half horizontal resolution.
+    Low,
+}
+
+#[wasm_bindgen]
+pub struct Nes {
+    bus: Rc<RefCell<Bus>>,
+    cpu: Rc<RefCell<Cpu>>,
+    ppu: Rc<RefCell<Ppu>>,
+    apu: Rc<RefCell<Apu>>,
+    cartridge: Rc<RefCell<Cartridge>>,
+    perf: PerfMonitor,
+    frameskip: Frameskip,
+    video_filter: nes_core::VideoFilter,
+    frame_blend: Option<FrameBlend>,
+    osd: Osd,
+    /// The current frame with on-screen messages drawn over it, before filtering.
+    osd_image: Vec<u8>,
+    filtered_image: Vec<u8>,
+    quality: Quality,
+}
+
+#[wasm_bindgen]
+impl Nes {
+    pub fn new(rom: &[u8]) -> Result<Nes, String> {
+        let cartridge = Rc::new(RefCell::new(Cartridge::new(rom)?));
+        let cpu = Rc::new(RefCell::new(Cpu::new()));
+        let ppu = Rc::new(RefCell::new(Ppu::new(cartridge.clone())));
+        // Canvas image data is RGBA, so start with that.
+        ppu.borrow_mut()
+            .set_pixel_format(nes_core::PixelFormat::Rgba32);
+        let apu = Rc::new(RefCell::new(Apu::new()));
+        let bus = Bus::new(
+            cpu.clone(),
+            nes_core::new_boxed_array(),
+            ppu.clone(),
+            apu.clone(),
+            cartridge.clone(),
+        );
+        bus.borrow_mut()
+            .set_region(cartridge.borrow().detect_region(None));
+        cpu.borrow_mut().reset();
+
+        let mut perf = PerfMonitor::new();
+        perf.set_timing(bus.borrow().timing());
+        let mut frameskip = Frameskip::new();
+        frameskip.set_timing(bus.borrow().timing());
+
+        Ok(Self {
+            bus,
+            cpu,
+            ppu,
+            apu,
+            cartridge,
+            perf,
+            frameskip,
+            video_filter: nes_core::VideoFilter::None,
+            frame_blend: None,
+            osd: Osd::new(),
+            osd_image: Vec::new(),
+            filtered_image: Vec::new(),
+            quality: Quality::High,
+        })
+    }
+
+    /// Returns the version of the emulator core.
+    pub fn version() -> String {
+        VERSION.to_string()
+    }
+
+    /// Returns the iNES mapper numbers that cartridges can use.
+    pub fn supported_mappers() -> Vec<u8> {
+        Capabilities::current().mappers.to_vec()
+    }
+
+    /// Returns the names of the available accuracy options. See [AccuracyOption].
+    pub fn accuracy_options() -> Vec<String> {
+        let options = Capabilities::current().accuracy_options;
+        options.iter().map(AccuracyOption::to_string).collect()
+    }
+
+    /// Returns the names of the console regions that can be emulated. See [Region].
+    pub fn supported_regions() -> Vec<String> {
+        let regions = Capabilities::current().regions;
+        regions.iter().map(nes_core::Region::to_string).collect()
+    }
+
+    /// Returns the names of the accuracy profiles that can be chosen. See [AccuracyProfile].
+    pub fn accuracy_profiles() -> Vec<String> {
+        let profiles = Capabilities::current().accuracy_profiles;
+        profiles
+            .iter()
+            .map(nes_core::AccuracyProfile::to_string)
+            .collect()
+    }
+
+    /// Returns the name of the savestate format, as written by [Nes::save_state].
+    pub fn savestate_format() -> String {
+        Capabilities::current().savestate_format.to_string()
+    }
+
+    /// Returns the FCEUX version written to savestate headers.
+    pub fn savestate_version() -> u32 {
+        Capabilities::current().savestate_version
+    }
+
+    /// Returns the names of the optional features compiled into the core.
+    pub fn features() -> Vec<String> {
+        let features = Capabilities::current().features;
+        features.iter().map(|feature| feature.to_string()).collect()
+    }
+
+    /// Loads a ROM after applying an IPS or BPS patch to it.
+    pub fn new_patched(rom: &[u8], patch: &[u8]) -> Result<Nes, String> {
+        Self::new(&nes_core::patch::apply(rom, patch)?)
+    }
+
+    pub fn tick(&mut self) {
+        Bus::run_frame(
+            self.bus.clone(),
+            self.cpu.clone(),
+            self.ppu.clone(),
+            self.apu.clone(),
+        );
+        self.finish_frame();
+    }
+
+    /// Runs until exactly `samples` more audio samples have been added to the audio buffer,
+    /// returning the number of frames completed along the way.
+    ///
+    /// This lets a frontend drive emulation from its audio clock rather than from
+    /// `requestAnimationFrame`. Unlike [Nes::tick], this usually stops partway through a frame, so
+    /// the image buffer may already be partly drawn over by the next one.
+    pub fn run_for_samples(&mut self, samples: usize) -> u32 {
+        let target = self.apu.borrow().audio_buffer_length() + samples;
+        let mut frames = 0;
+        while self.apu.borrow().audio_buffer_length() < target {
+            self.clock();
+            let is_frame_ready = std::mem::take(&mut self.ppu.borrow_mut().is_frame_ready);
+            if is_frame_ready {
+                frames += 1;
+                self.finish_frame();
+            }
+        }
+        frames
+    }
+
+    /// Runs until the PPU reaches the given scanline and dot, returning whether it was reached.
+    pub fn run_to_dot(&self, scanline: u16, dot: u16) -> bool {
+        self.run_until(StopCondition::Dot { scanline, dot })
+    }
+
+    /// Runs until the CPU services the next NMI, returning whether one occurred.
+    pub fn run_to_nmi(&self) -> bool {
+        self.run_until(StopCondition::Nmi)
+    }
+
+    /// Runs until the CPU services the next IRQ, returning whether one occurred.
+    pub fn run_to_irq(&self) -> bool {
+        self.run_until(StopCondition::Irq)
+    }
+
+    /// Runs until sprite 0 hit is set, returning the scanline and dot it was set on, if it was.
+    pub fn run_to_sprite_zero_hit(&self) -> Option<Vec<u16>> {
+        self.run_until(StopCondition::SpriteZeroHit)
+            .then(|| self.ppu.borrow().sprite_zero_hit_position())
+            .flatten()
+            .map(|(scanline, dot)| vec![scanline, dot])
+    }
+
+    /// Sets how many times faster than real time the caller is running frames, so that audio is
+    /// compressed to match instead of piling up. 1 is normal speed.
+    pub fn set_speed(&self, speed: u32) {
+        self.apu.borrow_mut().set_speed(speed);
+    }
+
+    /// Sets whether audio is muted instead of time-compressed while running faster than real time.
+    pub fn set_fast_forward_muted(&self, is_muted: bool) {
+        let mode = if is_muted {
+            FastForwardAudio::Mute
+        } else {
+            FastForwardAudio::Decimate
+        };
+        self.apu.borrow_mut().set_fast_forward_audio(mode);
+    }
+
+    /// Sets how audio is resampled. [Resampler::Linear] is a good fit for low-power devices.
+    pub fn set_resampler(&self, resampler: Resampler) {
+        self.apu.borrow_mut().set_resampler(resampler.into());
+    }
+
+    /// Enables sprite flicker with the given seed, or disables it if there's none. See
+    /// [Ppu::set_sprite_flicker_seed].
+    pub fn set_sprite_flicker_seed(&self, seed: Option<u64>) {
+        self.ppu.borrow_mut().set_sprite_flicker_seed(seed);
+    }
+
+    /// Sets the number of extra scanlines to insert after vblank. 0 disables overclocking.
+    pub fn set_overclock_scanlines(&self, scanlines: u16) {
+        self.ppu.borrow_mut().set_overclock_scanlines(scanlines);
+    }
+
+    /// Loads a savestate, throwing a [SavestateError] and leaving the console untouched if it
+    /// can't be.
+    pub fn apply_state(&mut self, state: &[u8]) -> Result<(), SavestateError> {
+        self.bus.borrow_mut().load_state(state)?;
+        // Don't blend the restored frame with the one from before loading.
+        if let Some(frame_blend) = self.frame_blend.as_mut() {
+            frame_blend.reset();
+        }
+        Ok(())
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        self.bus.borrow().save_state()
+    }
+
+    /// Saves a savestate that records when it was saved, such as from `Date.now()`, and the name
+    /// of the ROM, for listing in a save manager.
+    pub fn save_state_with_metadata(&self, timestamp: f64, rom_name: Option<String>) -> Vec<u8> {
+        self.bus
+            .borrow()
+            .save_state_with_metadata(Some(timestamp as u64), rom_name.as_deref())
+    }
+
+    /// Reads what a savestate says about itself without applying it.
+    pub fn savestate_metadata(state: &[u8]) -> Result<SavestateMetadata, String> {
+        Ok(Savestate::read_metadata(state)?.into())
+    }
+
+    /// Sets whether savestates made with a different ROM are turned away. See
+    /// [Bus::set_savestate_rom_check].
+    pub fn set_savestate_rom_check(&self, is_enabled: bool) {
+        self.bus.borrow_mut().set_savestate_rom_check(is_enabled);
+    }
+
+    /// Returns the RGB24 thumbnail stored in a savestate without applying it, or an empty array if
+    /// the savestate doesn't contain one.
+    pub fn savestate_thumbnail(state: &[u8]) -> Result<Vec<u8>, String> {
+        let decompressed = Savestate::decompress(state)?;
+        let savestate = Savestate::new(&decompressed)?;
+
+        Ok(savestate.thumbnail().map(Vec::from).unwrap_or_default())
+    }
+
+    pub fn set_game_genie_codes(&self, codes: Vec<String>) -> Result<(), String> {
+        self.cartridge.borrow_mut().set_game_genie_codes(&codes)?;
+        Ok(())
+    }
+
+    pub fn image_buffer_raw(&self) -> *const u8 {
+        self.ppu.borrow().buffer_raw()
+    }
+
+    /// Returns the layout of the image buffers, which is [PixelFormat::Rgba32] unless changed.
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.ppu.borrow().pixel_format().into()
+    }
+
+    /// Sets the layout of the image buffers. Canvas image data is RGBA, but WebGL frontends can
+    /// upload RGB textures, which take a quarter less memory to copy each frame. The current
+    /// frame is converted, so it doesn't need to be drawn again.
+    pub fn set_pixel_format(&self, pixel_format: PixelFormat) {
+        self.ppu.borrow_mut().set_pixel_format(pixel_format.into());
+    }
+
+    pub fn set_video_filter(&mut self, filter: VideoFilter) {
+        self.video_filter = filter.into();
+    }
+
+    /// Returns how many times larger than 256x240 the filtered image is in each dimension.
+    pub fn video_filter_scale(&self) -> usize {
+        self.video_filter.scale()
+    }
+
+    /// Blends the current frame with the previous one if frame blending is on, draws on-screen
+    /// messages over it and runs the selected video filter over it, returning a pointer to the
+    /// upscaled image in the current [PixelFormat]. This is for drawing without WebGL;
+    /// [Nes::image_buffer_raw] is cheaper when none of these are needed.
+    pub fn filtered_image_buffer_raw(&mut self) -> *const u8 {
+        let ppu = self.ppu.borrow();
+        let pixel_format = ppu.pixel_format();
+        let frame = self
+            .frame_blend
+            .as_ref()
+            .and_then(FrameBlend::output)
+            // Switching pixel formats leaves the blended frame in the old one until the next frame.
+            .filter(|frame| frame.len() == ppu.buffer().len())
+            .unwrap_or(ppu.buffer());
+        self.osd_image.clear();
+        self.osd_image.extend_from_slice(frame);
+        drop(ppu);
+        // Messages are drawn before filtering, so they're upscaled along with everything else.
+        self.osd.draw(&mut self.osd_image, pixel_format);
+        self.video_filter
+            .apply(&self.osd_image, pixel_format, &mut self.filtered_image);
+        self.filtered_image.as_ptr()
+    }
+
+    /// Shows a message over the game for the given number of frames, in the image returned by
+    /// [Nes::filtered_image_buffer_raw]. Messages count down as frames are run.
+    pub fn show_message(&mut self, text: String, frames: u32) {
+        self.osd.show(text, frames);
+    }
+
+    /// Sets text that stays in the top left corner until cleared, such as an FPS counter.
+    pub fn set_status(&mut self, status: Option<String>) {
+        self.osd.set_status(status);
+    }
+
+    /// Blends each frame with the one before it in the image returned by
+    /// [Nes::filtered_image_buffer_raw], keeping `weight` of the previous frame, from 0 to 1, to
+    /// steady games that flicker sprites. 0, the default, turns blending off.
+    pub fn set_frame_blend(&mut self, weight: f32) {
+        if weight <= 0.0 {
+            self.frame_blend = None;
+        } else if let Some(frame_blend) = self.frame_blend.as_mut() {
+            frame_blend.set_weight(weight);
+        } else {
+            self.frame_blend = Some(FrameBlend::new(weight));
+        }
+    }
+
+    pub fn drain_audio_buffer(&mut self) {
+        self.apu.borrow_mut().drain_audio_buffer();
+    }
+
+    pub fn audio_buffer_raw(&mut self) -> *const f32 {
+        self.apu.borrow_mut().audio_buffer().as_ptr()
+    }
+
+    pub fn audio_buffer_length(&self) -> usize {
+        self.apu.borrow().audio_buffer_length()
+    }
+
+    pub fn quality(&self) -> Quality {
+        self.quality
+    }
+
+    /// Sets how much work goes into presentation. This can be changed at any time, such as when
+    /// [Nes::perf_stats] shows frames running long.
+    pub fn set_quality(&mut self, quality: Quality) {
+        self.quality = quality;
+        self.ppu
+            .borrow_mut()
+            .set_half_horizontal_resolution(quality == Quality::Low);
+    }
+
+    /// Returns whether the memory viewer's data is available at the current [Quality]. When it
+    /// isn't, [Nes::nametable_ram], [Nes::palette_ram], [Nes::oam], and [Nes::banked_chr] return
+    /// empty arrays.
+    pub fn is_memview_enabled(&self) -> bool {
+        self.quality == Quality::High
+    }
+
+    /// Returns a copy of the PPU's 2 KiB of internal nametable RAM.
+    pub fn nametable_ram(&self) -> Vec<u8> {
+        self.memview_data(|| self.ppu.borrow().nametable_ram().to_vec())
+    }
+
+    pub fn palette_ram(&self) -> Vec<u8> {
+        self.memview_data(|| self.ppu.borrow().palette_ram().to_vec())
+    }
+
+    pub fn oam(&self) -> Vec<u8> {
+        self.memview_data(|| self.ppu.borrow().oam().to_vec())
+    }
+
+    /// Returns a copy of the 8 KiB of CHR currently banked into the pattern tables.
+    pub fn banked_chr(&self) -> Vec<u8> {
+        self.memview_data(|| self.cartridge.borrow().banked_chr())
+    }
+
+    /// Returns a copy of battery-backed PRG RAM for saving, if the cartridge has a battery.
+    pub fn battery_ram(&self) -> Option<Vec<u8>> {
+        self.cartridge.borrow().battery_ram().map(<[u8]>::to_vec)
+    }
+
+    pub fn load_battery_ram(&self, data: &[u8]) -> Result<(), String> {
+        self.cartridge.borrow_mut().load_battery_ram(data)
+    }
+
+    /// Returns whether battery-backed PRG RAM has changed since the last call. Frontends should
+    /// poll this periodically and only write the save out when it returns true.
+    pub fn take_battery_dirty(&self) -> bool {
+        self.cartridge.borrow_mut().take_dirty()
+    }
+
+    pub fn set_controller_state(&self, controller_1: Controller, controller_2: Controller) {
+        self.bus
+            .borrow_mut()
+            .set_controller_state(controller_1.into(), controller_2.into());
+    }
+
+    /// Overrides the region detected from the ROM's header. This should be done before running any
+    /// frames.
+    pub fn set_region(&mut self, region: Region) {
+        self.bus.borrow_mut().set_region(region.into());
+        self.perf.set_timing(self.bus.borrow().timing());
+        self.frameskip.set_timing(self.bus.borrow().timing());
+    }
+
+    pub fn region(&self) -> Region {
+        self.ppu.borrow().timing().region.into()
+    }
+
+    /// Trades accuracy for speed, such as on low-power devices. Like the region, this should be set
+    /// before running any frames.
+    pub fn set_accuracy_profile(&self, profile: AccuracyProfile) {
+        self.bus.borrow_mut().set_accuracy_profile(profile.into());
+    }
+
+    /// Returns the number of frames per second the emulated console runs at.
+    pub fn frame_rate(&self) -> f64 {
+        self.ppu.borrow().timing().frame_rate()
+    }
+
+    /// Returns the CPU clock rate of the emulated console, in Hz.
+    pub fn cpu_clock_rate(&self) -> f64 {
+        self.ppu.borrow().timing().cpu_clock_rate()
+    }
+
+    /// Records how long the host took to emulate a frame, along with the number of audio samples
+    /// still queued before the frame's samples were added. Timestamps are in milliseconds, such as
+    /// from `performance.now()`.
+    ///
+    /// This also decides whether the next frame is skipped, if frameskip is enabled.
+    pub fn record_frame(&mut self, start: f64, end: f64, queued_audio_samples: usize) {
+        self.perf.record_frame(start, end, queued_audio_samples);
+        let is_skipped = self.frameskip.record_frame(start, end);
+        self.ppu.borrow_mut().set_output_skipped(is_skipped);
+    }
+
+    /// Skips drawing up to `max_skipped` frames in a row when frames take longer to emulate than
+    /// they last, keeping slow devices at full speed. 0, the default, disables frameskip. Skipped
+    /// frames leave the image buffer as it was, so the frontend can draw it as usual.
+    pub fn set_auto_frameskip(&mut self, max_skipped: u32) {
+        self.frameskip.set_max_skipped(max_skipped);
+        if max_skipped == 0 {
+            self.ppu.borrow_mut().set_output_skipped(false);
+        }
+    }
+
+    /// Tells the emulator whether the frontend is recording a replay, which turns frameskip off
+    /// until recording stops, so that every frame the player sees is drawn.
+    pub fn set_replay_recording(&mut self, is_recording: bool) {
+        self.frameskip.set_suspended(is_recording);
+        if is_recording {
+            self.ppu.borrow_mut().set_output_skipped(false);
+        }
+    }
+
+    pub fn perf_stats(&self) -> PerfStats {
+        self.perf.stats().into()
+    }
+
+    /// Returns the DMA activity during the last complete frame.
+    pub fn dma_stats(&self) -> DmaStats {
+        self.bus.borrow().dma_stats().into()
+    }
+
+    /// Takes the warnings about problems that were worked around since the last call, such as
+    /// junk in the ROM's header or savestate sections that were skipped. Worth checking after
+    /// loading a ROM or savestate.
+    pub fn drain_warnings(&self) -> Vec<String> {
+        self.bus.borrow_mut().drain_warnings()
+    }
+
+    /// Enables or disables checking for PPU accesses at times that glitch on real hardware.
+    pub fn set_ppu_validation(&self, is_enabled: bool) {
+        self.ppu.borrow_mut().set_validation(is_enabled);
+    }
+
+    /// Takes the PPU validation warnings found since the last call, formatted as text.
+    pub fn drain_ppu_warnings(&self) -> Vec<String> {
+        let warnings = self.ppu.borrow_mut().drain_warnings();
+        warnings.iter().map(PpuWarning::to_string).collect()
+    }
+
+    /// Describes why the CPU has halted, along with the addresses of the instructions leading up
+    /// to it, or returns `None` if it's running.
+    pub fn cpu_halt(&self) -> Option<String> {
+        let cpu = self.cpu.borrow();
+        let halt = cpu.halt()?;
+        let trace: Vec<_> = halt.trace.iter().map(|pc| format!("${pc:04X}")).collect();
+        Some(format!("{halt}\nrecent instructions: {}", trace.join(" ")))
+    }
+
+    /// Describes the CPU's registers, formatted like a nestest log line.
+    pub fn cpu_registers(&self) -> String {
+        self.cpu.borrow().snapshot().to_string()
+    }
+
+    /// Returns a checksum of the current frame, for checking that two runs haven't diverged.
+    pub fn frame_crc32(&self) -> u32 {
+        self.ppu.borrow().frame_crc32()
+    }
+
+    /// Returns a checksum of the console's internal RAM.
+    pub fn ram_crc32(&self) -> u32 {
+        self.bus.borrow().ram_crc32()
+    }
+
+    /// Jumps the CPU to the given address, recovering it if it has halted.
+    pub fn set_program_counter(&self, addr: u16) {
+        self.cpu.borrow_mut().set_program_counter(addr);
+    }
+
+    pub fn reset(&self) {
+        Bus::reset(self.cpu.clone(), self.ppu.clone());
+        self.apu.borrow_mut().smooth_reset();
+    }
+
+    pub fn set_microphone(&self, is_active: bool) {
+        self.bus.borrow_mut().set_microphone(is_active);
+    }
+
+    fn run_until(&self, condition: StopCondition) -> bool {
+        Bus::run_until(
+            self.bus.clone(),
+            self.cpu.clone(),
+            self.ppu.clone(),
+            self.apu.clone(),
+            condition,
+        )
+    }
+
+    /// Copies out data for the memory viewer, unless the current [Quality] skips it.
+    fn memview_data(&self, copy: impl FnOnce() -> Vec<u8>) -> Vec<u8> {
+        if self.is_memview_enabled() {
+            copy()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Updates presentation state that changes once per emulated frame.
+    fn finish_frame(&mut self) {
+        self.osd.tick();
+        if let Some(frame_blend) = self.frame_blend.as_mut() {
+            frame_blend.push(self.ppu.borrow().buffer());
+        }
+    }
+
+    fn clock(&self) {
+        Bus::clock(
+            self.bus.clone(),
+            self.cpu.clone(),
+            self.ppu.clone(),
+            self.apu.clone(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds an NROM ROM whose program writes `color` to the backdrop color, then loops.
+    fn rom(color: u8) -> Vec<u8> {
+        let program = [
+            0xA9, 0x3F, //       LDA #$3F
+            0x8D, 0x06, 0x20, // STA $2006
+            0xA9, 0x00, //       LDA #$00
+            0x8D, 0x06, 0x20, // STA $2006
+            0xA9, color, //      LDA #color
+            0x8D, 0x07, 0x20, // STA $2007
+            // Point the address away from palette RAM, as its color is shown instead of the
+            // backdrop while rendering is off.
+            0xA9, 0x00, //       LDA #$00
+            0x8D, 0x06, 0x20, // STA $2006
+            0x8D, 0x06, 0x20, // STA $2006
+            0xE6, 0x00, //       INC $00
+            0x4C, 0x17, 0x80, // JMP $8017
+        ];
+        let mut prg_rom = vec![0; 16 * 1024];
+        prg_rom[..program.len()].copy_from_slice(&program);
+        // The reset vector points to the start of the program at $8000.
+        prg_rom[0x3FFC..0x3FFE].copy_from_slice(&[0x00, 0x80]);
+
+        let mut rom = b"NES\x1a\x01\x01\x00\x00".to_vec();
+        rom.resize(16, 0);
+        rom.extend(prg_rom);
+        rom.resize(rom.len() + 8 * 1024, 0);
+        rom
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[cfg_attr(not(target_arch = "wasm32"), test)]
+    fn pixel_formats() {
+        let mut nes = Nes::new(&rom(0x16)).unwrap();
+        // The first frame starts before the backdrop color is set.
+        nes.tick();
+        nes.tick();
+        assert_eq!(nes.pixel_format(), PixelFormat::Rgba32);
+        let rgba = nes.ppu.borrow().buffer().to_vec();
+        assert_eq!(rgba.len(), 256 * 240 * 4);
+        assert!(rgba.chunks(4).all(|pixel| pixel[3] == 0xFF));
+
+        nes.set_pixel_format(PixelFormat::Rgb24);
+        let rgb = nes.ppu.borrow().buffer().to_vec();
+        assert_eq!(rgb.len(), 256 * 240 * 3);
+        assert!(rgb.chunks(3).eq(rgba.chunks(4).map(|pixel| &pixel[..3])));
+        // Newly drawn frames come out the same.
+        nes.tick();
+        assert!(nes.ppu.borrow().buffer() == rgb);
+
+        // Filters keep to the current format.
+        nes.set_video_filter(VideoFilter::Scanlines);
+        nes.filtered_image_buffer_raw();
+        assert_eq!(nes.filtered_image.len(), 512 * 480 * 3);
+        nes.set_pixel_format(PixelFormat::Rgba32);
+        nes.filtered_image_buffer_raw();
+        assert_eq!(nes.filtered_image.len(), 512 * 480 * 4);
+        assert_eq!(nes.filtered_image[..4], rgba[..4]);
+    }
+
+    // Runs natively with `cargo test` too, as nothing here needs JavaScript.
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[cfg_attr(not(target_arch = "wasm32"), test)]
+    fn multiple_instances() {
+        let run = |nes: &mut Nes| {
+            nes.tick();
+            nes.tick();
+            (nes.frame_crc32(), nes.ram_crc32(), nes.palette_ram()[0])
+        };
+        let alone = run(&mut Nes::new(&rom(0x16)).unwrap());
+
+        let mut first = Nes::new(&rom(0x16)).unwrap();
+        let mut second = Nes::new(&rom(0x2A)).unwrap();
+        first.tick();
+        second.tick();
+        second.tick();
+        second.tick();
+        first.tick();
+        // Running another console in between doesn't affect the first.
+        assert_eq!(
+            (
+                first.frame_crc32(),
+                first.ram_crc32(),
+                first.palette_ram()[0]
+            ),
+            alone
+        );
+        assert_eq!(second.palette_ram()[0], 0x2A);
+        assert_ne!(second.frame_crc32(), first.frame_crc32());
+
+        // Savestates stay with the ROM they were made for.
+        let err = second.apply_state(&first.save_state()).unwrap_err();
+        assert_eq!(err.kind, SavestateErrorKind::WrongRom);
+        drop(second);
+        assert_eq!(run(&mut first).2, 0x16);
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[cfg_attr(not(target_arch = "wasm32"), test)]
+    fn running_for_samples() {
+        let mut ticked = Nes::new(&rom(0x16)).unwrap();
+        ticked.tick();
+        let frame_samples = ticked.apu.borrow_mut().drain_audio_buffer();
+        let frame_length = frame_samples.len();
+
+        let mut nes = Nes::new(&rom(0x16)).unwrap();
+        assert_eq!(nes.run_for_samples(frame_length - 1), 0);
+        assert_eq!(nes.audio_buffer_length(), frame_length - 1);
+        // Across the end of the frame, stopping as soon as the last sample is produced.
+        assert_eq!(nes.run_for_samples(2), 1);
+        let samples = nes.apu.borrow_mut().drain_audio_buffer();
+        assert_eq!(samples.len(), frame_samples.len() + 1);
+        // Stopping partway doesn't change what's produced.
+        assert_eq!(samples[..frame_samples.len()], frame_samples);
+
+        // Several frames at once.
+        assert!((3..=4).contains(&nes.run_for_samples(4 * 735)));
+        assert_eq!(nes.audio_buffer_length(), 4 * 735);
+        assert_eq!(nes.run_for_samples(0), 0);
+        assert_eq!(nes.audio_buffer_length(), 4 * 735);
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[cfg_attr(not(target_arch = "wasm32"), test)]
+    fn on_screen_display() {
+        let mut nes = Nes::new(&rom(0x16)).unwrap();
+        nes.tick();
+        nes.tick();
+        let image = |nes: &mut Nes| {
+            let pointer = nes.filtered_image_buffer_raw();
+            assert_eq!(pointer, nes.filtered_image.as_ptr());
+            nes.filtered_image.clone()
+        };
+        let frame = nes.ppu.borrow().buffer().to_vec();
+        assert!(image(&mut nes) == frame);
+
+        nes.show_message("Saved".into(), 2);
+        nes.tick();
+        let with_message = image(&mut nes);
+        assert!(with_message != frame);
+        // Only the filtered image has the message.
+        assert!(nes.ppu.borrow().buffer() == frame);
+        // The message is upscaled with the frame.
+        nes.set_video_filter(VideoFilter::Scanlines);
+        assert_eq!(image(&mut nes).len(), frame.len() * 4);
+        nes.set_video_filter(VideoFilter::None);
+
+        nes.tick();
+        assert!(image(&mut nes) == frame);
+
+        nes.set_status(Some("60 FPS".into()));
+        assert!(image(&mut nes) != frame);
+        nes.set_status(None);
+        assert!(image(&mut nes) == frame);
+
+        // Running by audio samples counts frames too.
+        nes.show_message("Loaded".into(), 1);
+        assert!(image(&mut nes) != frame);
+        while nes.run_for_samples(100) == 0 {}
+        assert!(image(&mut nes) == frame);
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[cfg_attr(not(target_arch = "wasm32"), test)]
+    fn frame_blending() {
+        let mut nes = Nes::new(&rom(0x16)).unwrap();
+        nes.tick();
+        nes.tick();
+        let red = nes.ppu.borrow().buffer().to_vec();
+        let image = |nes: &mut Nes| {
+            nes.filtered_image_buffer_raw();
+            nes.filtered_image.clone()
+        };
+        let set_backdrop = |nes: &Nes, color: u8| {
+            let mut bus = nes.bus.borrow_mut();
+            for (addr, data) in [(0x2006, 0x3F), (0x2006, 0x00), (0x2007, color)] {
+                bus.cpu_write(addr, data);
+            }
+            bus.cpu_write(0x2006, 0x00);
+            bus.cpu_write(0x2006, 0x00);
+        };
+
+        // There's nothing to blend the first frame with.
+        nes.set_frame_blend(0.5);
+        nes.tick();
+        assert!(image(&mut nes) == red);
+        set_backdrop(&nes, 0x12);
+        nes.tick();
+        let blue = nes.ppu.borrow().buffer().to_vec();
+        assert!(blue != red);
+        let blended: Vec<u8> = red
+            .iter()
+            .zip(&blue)
+            .map(|(&red, &blue)| ((red as f32 + blue as f32) / 2.0).round() as u8)
+            .collect();
+        assert!(image(&mut nes) == blended);
+        // The unblended frame is still available.
+        assert!(nes.ppu.borrow().buffer() == blue);
+
+        // Loading a savestate starts over rather than blending with the frame before it.
+        let state = nes.save_state();
+        set_backdrop(&nes, 0x16);
+        nes.tick();
+        nes.apply_state(&state).unwrap();
+        nes.tick();
+        assert!(image(&mut nes) == blue);
+
+        nes.set_frame_blend(0.0);
+        set_backdrop(&nes, 0x16);
+        nes.tick();
+        assert!(image(&mut nes) == red);
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    #[cfg_attr(not(target_arch = "wasm32"), test)]
+    fn capabilities() {
+        let capabilities = Capabilities::current();
+        assert_eq!(Nes::version(), capabilities.version);
+        assert_eq!(Nes::supported_mappers(), capabilities.mappers);
+        assert_eq!(Nes::supported_regions(), ["NTSC", "PAL"]);
+        assert_eq!(Nes::accuracy_profiles(), ["fast", "balanced", "accurate"]);
+        assert_eq!(
+            Nes::accuracy_options().len(),
+            capabilities.accuracy_options.len()
+        );
+        assert_eq!(Nes::savestate_format(), capabilities.savestate_format);
+        assert_eq!(Nes::savestate_version(), capabilities.savestate_version);
+        assert_eq!(Nes::features(), capabilities.features);
+    }
+}
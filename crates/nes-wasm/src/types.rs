@@ -0,0 +1,172 @@
+//! Copies of the core's types that are passed to and from JavaScript, converted at the boundary.
+//! See the core's types for what each one means.
+
+use wasm_bindgen::prelude::*;
+
+/// Mirrors a fieldless enum from the core, with conversions both ways.
+macro_rules! mirror_enum {
+    ($name:ident { $($variant:ident),* $(,)? }) => {
+        #[wasm_bindgen]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum $name {
+            $($variant),*
+        }
+
+        impl From<$name> for nes_core::$name {
+            fn from(value: $name) -> Self {
+                match value {
+                    $($name::$variant => Self::$variant),*
+                }
+            }
+        }
+
+        impl From<nes_core::$name> for $name {
+            fn from(value: nes_core::$name) -> Self {
+                match value {
+                    $(nes_core::$name::$variant => Self::$variant),*
+                }
+            }
+        }
+    };
+}
+
+mirror_enum!(Region { Ntsc, Pal });
+mirror_enum!(Resampler {
+    Nearest,
+    Linear,
+    Sinc
+});
+mirror_enum!(AccuracyProfile {
+    Fast,
+    Balanced,
+    Accurate
+});
+mirror_enum!(PixelFormat { Rgb24, Rgba32 });
+mirror_enum!(VideoFilter {
+    None,
+    Scanlines,
+    Epx
+});
+
+#[wasm_bindgen]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Controller(u8);
+
+#[wasm_bindgen]
+impl Controller {
+    // Necessary because the From<u8> trait implementation is inaccessible from Wasm.
+    pub fn from_u8(value: u8) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Controller> for nes_core::Controller {
+    fn from(value: Controller) -> Self {
+        Self::from(value.0)
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PerfStats {
+    pub fps: f64,
+    pub speed: f64,
+    pub frame_time: f64,
+    pub max_frame_time: f64,
+    pub audio_latency: f64,
+    pub audio_underruns: u32,
+}
+
+impl From<nes_core::PerfStats> for PerfStats {
+    fn from(value: nes_core::PerfStats) -> Self {
+        Self {
+            fps: value.fps,
+            speed: value.speed,
+            frame_time: value.frame_time,
+            max_frame_time: value.max_frame_time,
+            audio_latency: value.audio_latency,
+            audio_underruns: value.audio_underruns,
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DmaStats {
+    pub stall_cycles: u32,
+    pub oam_transfers: u32,
+    pub bytes_read: u32,
+    pub last_read_addr: Option<u16>,
+}
+
+impl From<nes_core::DmaStats> for DmaStats {
+    fn from(value: nes_core::DmaStats) -> Self {
+        Self {
+            stall_cycles: value.stall_cycles,
+            oam_transfers: value.oam_transfers,
+            bytes_read: value.bytes_read,
+            last_read_addr: value.last_read_addr,
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SavestateErrorKind {
+    WrongRom,
+    UnsupportedVersion,
+    TooLarge,
+    Corrupt,
+}
+
+/// Thrown when a savestate can't be loaded, with the kind of problem for deciding how to react,
+/// and a message to show.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SavestateError {
+    pub kind: SavestateErrorKind,
+    pub message: String,
+}
+
+impl From<nes_core::SavestateError> for SavestateError {
+    fn from(value: nes_core::SavestateError) -> Self {
+        let kind = match value {
+            nes_core::SavestateError::WrongRom { .. } => SavestateErrorKind::WrongRom,
+            nes_core::SavestateError::UnsupportedVersion(_) => {
+                SavestateErrorKind::UnsupportedVersion
+            }
+            nes_core::SavestateError::TooLarge(_) => SavestateErrorKind::TooLarge,
+            nes_core::SavestateError::Corrupt(_) => SavestateErrorKind::Corrupt,
+        };
+        Self {
+            kind,
+            message: value.to_string(),
+        }
+    }
+}
+
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SavestateMetadata {
+    pub timestamp: Option<u64>,
+    pub rom_name: Option<String>,
+    pub rom_crc32: Option<u32>,
+    pub mapper_id: Option<u8>,
+    pub frame_count: Option<u64>,
+    pub emulator_version: Option<String>,
+    pub accuracy_profile: Option<AccuracyProfile>,
+}
+
+impl From<nes_core::SavestateMetadata> for SavestateMetadata {
+    fn from(value: nes_core::SavestateMetadata) -> Self {
+        Self {
+            timestamp: value.timestamp,
+            rom_name: value.rom_name,
+            rom_crc32: value.rom_crc32,
+            mapper_id: value.mapper_id,
+            frame_count: value.frame_count,
+            emulator_version: value.emulator_version,
+            accuracy_profile: value.accuracy_profile.map(Into::into),
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    FromSample, SampleFormat, SizedSample, StreamConfig, SupportedStreamConfig,
+};
+use nes_core::{
+    audio::{RateConverter, SAMPLE_RATE},
+    AudioSink,
+};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+/// Plays samples through the default output device of the system's default audio host.
+///
+/// The device is opened at [SAMPLE_RATE] in its own sample format if it supports that rate, and
+/// at its default rate otherwise, in which case samples are converted to that rate as they're
+/// pushed.
+pub struct CpalAudioSink {
+    queue: Arc<Mutex<VecDeque<f32>>>,
+    converter: Option<RateConverter>,
+    converted: Vec<f32>,
+    // Playback stops once the stream is dropped.
+    _stream: cpal::Stream,
+}
+
+impl CpalAudioSink {
+    pub fn new() -> Result<Self, String> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("no audio output device available")?;
+        let supported_config = choose_config(&device)?;
+        let config = supported_config.config();
+        let queue: Arc<Mutex<VecDeque<f32>>> = Arc::default();
+
+        let stream = match supported_config.sample_format() {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, queue.clone()),
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, queue.clone()),
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, queue.clone()),
+            SampleFormat::I32 => build_stream::<i32>(&device, &config, queue.clone()),
+            SampleFormat::U8 => build_stream::<u8>(&device, &config, queue.clone()),
+            format => Err(format!("unsupported sample format {format}")),
+        }?;
+        stream.play().map_err(|err| err.to_string())?;
+
+        let converter =
+            (config.sample_rate.0 != SAMPLE_RATE).then(|| RateConverter::new(config.sample_rate.0));
+        Ok(Self {
+            queue,
+            converter,
+            converted: Vec::new(),
+            _stream: stream,
+        })
+    }
+}
+
+impl AudioSink for CpalAudioSink {
+    fn push_samples(&mut self, samples: &[f32]) {
+        let samples = match &mut self.converter {
+            Some(converter) => {
+                self.converted.clear();
+                converter.convert(samples, &mut self.converted);
+                &self.converted
+            }
+            None => samples,
+        };
+        self.queue.lock().unwrap().extend(samples);
+    }
+
+    /// Returns the number of samples queued, counted at [SAMPLE_RATE] rather than the device's
+    /// rate.
+    fn queued_samples(&self) -> usize {
+        let queued = self.queue.lock().unwrap().len();
+        match &self.converter {
+            Some(converter) => {
+                (queued as u64 * SAMPLE_RATE as u64 / converter.output_rate() as u64) as usize
+            }
+            None => queued,
+        }
+    }
+}
+
+/// Picks the device's configuration that plays at [SAMPLE_RATE], preferring `f32` samples, or
+/// its default configuration if none does.
+fn choose_config(device: &cpal::Device) -> Result<SupportedStreamConfig, String> {
+    let rate = cpal::SampleRate(SAMPLE_RATE);
+    let matching = device
+        .supported_output_configs()
+        .map_err(|err| err.to_string())?
+        .filter(|range| (range.min_sample_rate()..=range.max_sample_rate()).contains(&rate))
+        .max_by_key(|range| range.sample_format() == SampleFormat::F32);
+    match matching {
+        Some(range) => Ok(range.with_sample_rate(rate)),
+        None => device
+            .default_output_config()
+            .map_err(|err| err.to_string()),
+    }
+}
+
+fn build_stream<T: SizedSample + FromSample<f32>>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    queue: Arc<Mutex<VecDeque<f32>>>,
+) -> Result<cpal::Stream, String> {
+    let channels = config.channels as usize;
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                let mut queue = queue.lock().unwrap();
+                // Samples are mono, so play each one on every channel.
+                for frame in data.chunks_mut(channels) {
+                    frame.fill(T::from_sample(queue.pop_front().unwrap_or_default()));
+                }
+            },
+            |err| println!("warn: audio stream error: {err}"),
+            None,
+        )
+        .map_err(|err| err.to_string())
+}
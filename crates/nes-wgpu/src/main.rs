@@ -0,0 +1,225 @@
+//! A minimal frontend built on winit and wgpu (through pixels), for systems without SDL2.
+
+mod audio;
+
+use audio::CpalAudioSink;
+use nes_core::{
+    AccuracyProfile, AudioSink, Cartridge, Controller, FrameBlend, Osd, PixelFormat, Resampler,
+    System, VideoFilter,
+};
+use pixels::{Pixels, SurfaceTexture};
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Instant,
+};
+use winit::{
+    dpi::LogicalSize,
+    event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
+    event_loop::EventLoop,
+    window::WindowBuilder,
+};
+
+const MAIN_SCALE: u32 = 4;
+/// How many frames on-screen messages stay visible for.
+const MESSAGE_FRAMES: u32 = 120;
+/// How often, in frames, to write battery-backed RAM out to disk if it has changed.
+const BATTERY_SAVE_INTERVAL: u32 = 300;
+
+pub fn main() {
+    let mut args = std::env::args().skip(1);
+    let rom_path = args.next().expect("no ROM path provided");
+    let accuracy_profile: AccuracyProfile = args
+        .next()
+        .map(|profile| profile.parse().expect("invalid accuracy profile"))
+        .unwrap_or_default();
+    let rom = std::fs::read(&rom_path).expect("failed to read ROM");
+    let mut cartridge = Cartridge::new(&rom).expect("failed to load ROM");
+    let battery_save_path = PathBuf::from(&rom_path).with_extension("sav");
+    if let Ok(data) = std::fs::read(&battery_save_path) {
+        if let Err(err) = cartridge.load_battery_ram(&data) {
+            println!("warn: failed to load battery save: {err}");
+        }
+    }
+    let region = cartridge.detect_region(Some(&rom_path));
+
+    let system = System::with_accuracy_profile(cartridge, accuracy_profile);
+    // The pixels surface is always RGBA.
+    system
+        .ppu
+        .borrow_mut()
+        .set_pixel_format(PixelFormat::Rgba32);
+    system.apu.borrow_mut().set_resampler(Resampler::Sinc);
+    system.bus.borrow_mut().set_region(region);
+    println!("region: {region}");
+
+    let mut audio_sink = CpalAudioSink::new()
+        .map_err(|err| println!("warn: failed to open audio device, running muted: {err}"))
+        .ok();
+
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title("NES Emulator")
+        .with_inner_size(LogicalSize::new(256 * MAIN_SCALE, 240 * MAIN_SCALE))
+        .with_min_inner_size(LogicalSize::new(256, 240))
+        .build(&event_loop)
+        .unwrap();
+
+    let size = window.inner_size();
+    let surface_texture = SurfaceTexture::new(size.width, size.height, &window);
+    let mut pixels = Pixels::new(256, 240, surface_texture).unwrap();
+
+    let frame_time = system.ppu.borrow().timing().frame_duration();
+    let mut next_frame = Instant::now();
+    let mut pressed_keys = HashSet::new();
+    let mut osd = Osd::new();
+    let mut frames_since_battery_save = 0;
+    let mut frame_blend: Option<FrameBlend> = None;
+    let mut video_filter = VideoFilter::None;
+    let mut osd_frame = Vec::new();
+    let mut filtered_frame = Vec::new();
+
+    event_loop.run(move |event, _, control_flow| match event {
+        Event::WindowEvent { event, .. } => match event {
+            WindowEvent::CloseRequested => control_flow.set_exit(),
+            WindowEvent::Resized(size) => {
+                if let Err(err) = pixels.resize_surface(size.width, size.height) {
+                    println!("warn: failed to resize surface: {err}");
+                }
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state,
+                        ..
+                    },
+                ..
+            } => match (key, state) {
+                (VirtualKeyCode::Escape, ElementState::Pressed) => control_flow.set_exit(),
+                (VirtualKeyCode::R, ElementState::Pressed) => {
+                    system.reset();
+                    osd.show("Reset", MESSAGE_FRAMES);
+                }
+                (VirtualKeyCode::C, ElementState::Pressed) => {
+                    video_filter = video_filter.next();
+                    let scale = video_filter.scale() as u32;
+                    if let Err(err) = pixels.resize_buffer(256 * scale, 240 * scale) {
+                        println!("warn: failed to resize buffer: {err}");
+                    }
+                    osd.show(format!("Video filter: {video_filter}"), MESSAGE_FRAMES);
+                }
+                (VirtualKeyCode::H, ElementState::Pressed) => {
+                    frame_blend = match frame_blend {
+                        Some(_) => None,
+                        None => Some(FrameBlend::default()),
+                    };
+                    let state = if frame_blend.is_some() { "on" } else { "off" };
+                    osd.show(format!("Frame blending {state}"), MESSAGE_FRAMES);
+                }
+                (key, ElementState::Pressed) => {
+                    pressed_keys.insert(key);
+                }
+                (key, ElementState::Released) => {
+                    pressed_keys.remove(&key);
+                }
+            },
+            _ => {}
+        },
+        Event::MainEventsCleared => {
+            if Instant::now() < next_frame {
+                control_flow.set_wait_until(next_frame);
+                return;
+            }
+            next_frame += frame_time;
+            // Don't try to catch up after falling far behind (e.g. while the window was dragged).
+            if next_frame < Instant::now() {
+                next_frame = Instant::now() + frame_time;
+            }
+
+            osd.tick();
+            system.run_frame(get_controller_state(&pressed_keys), |frame, samples| {
+                if let Some(audio_sink) = audio_sink.as_mut() {
+                    audio_sink.push_samples(samples);
+                }
+                let frame = match frame_blend.as_mut() {
+                    Some(frame_blend) => {
+                        frame_blend.push(frame);
+                        frame_blend.output().unwrap_or(frame)
+                    }
+                    None => frame,
+                };
+                if video_filter == VideoFilter::None {
+                    pixels.frame_mut().copy_from_slice(frame);
+                    osd.draw(pixels.frame_mut(), PixelFormat::Rgba32);
+                } else {
+                    // Draw messages before filtering, so they're upscaled along with everything
+                    // else.
+                    osd_frame.clear();
+                    osd_frame.extend_from_slice(frame);
+                    osd.draw(&mut osd_frame, PixelFormat::Rgba32);
+                    video_filter.apply(&osd_frame, PixelFormat::Rgba32, &mut filtered_frame);
+                    pixels.frame_mut().copy_from_slice(&filtered_frame);
+                }
+            });
+            window.request_redraw();
+
+            for warning in system.bus.borrow_mut().drain_warnings() {
+                println!("warn: {warning}");
+            }
+            frames_since_battery_save += 1;
+            if frames_since_battery_save >= BATTERY_SAVE_INTERVAL {
+                write_battery_save(&system.cartridge, &battery_save_path);
+                frames_since_battery_save = 0;
+            }
+        }
+        Event::RedrawRequested(_) => {
+            if let Err(err) = pixels.render() {
+                println!("warn: failed to render frame: {err}");
+                control_flow.set_exit();
+            }
+        }
+        Event::LoopDestroyed => write_battery_save(&system.cartridge, &battery_save_path),
+        _ => {}
+    });
+}
+
+/// Writes battery-backed RAM out to disk, but only if it has changed since it was last written.
+fn write_battery_save(cartridge: &Rc<RefCell<Cartridge>>, path: &Path) {
+    if !cartridge.borrow_mut().take_dirty() {
+        return;
+    }
+    let cartridge = cartridge.borrow();
+    let Some(data) = cartridge.battery_ram() else {
+        return;
+    };
+    if let Err(err) = std::fs::write(path, data) {
+        println!("warn: failed to write battery save: {err}");
+    }
+}
+
+fn get_controller_state(pressed_keys: &HashSet<VirtualKeyCode>) -> (Controller, Controller) {
+    let key = |key: VirtualKeyCode| pressed_keys.contains(&key);
+
+    let controller_1 = Controller::new()
+        .with_a(key(VirtualKeyCode::X))
+        .with_b(key(VirtualKeyCode::Z))
+        .with_select(key(VirtualKeyCode::RShift))
+        .with_start(key(VirtualKeyCode::Return))
+        .with_up(key(VirtualKeyCode::Up))
+        .with_down(key(VirtualKeyCode::Down))
+        .with_left(key(VirtualKeyCode::Left))
+        .with_right(key(VirtualKeyCode::Right));
+
+    let controller_2 = Controller::new()
+        .with_a(key(VirtualKeyCode::L))
+        .with_b(key(VirtualKeyCode::K))
+        .with_up(key(VirtualKeyCode::W))
+        .with_down(key(VirtualKeyCode::S))
+        .with_left(key(VirtualKeyCode::A))
+        .with_right(key(VirtualKeyCode::D));
+
+    (controller_1, controller_2)
+}
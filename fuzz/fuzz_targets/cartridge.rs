@@ -0,0 +1,22 @@
+//! Loads arbitrary ROMs and runs them for a few frames, which also throws arbitrary programs and
+//! mapper register writes at the CPU and mappers.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nes_core::{Bus, Cartridge, System};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(cartridge) = Cartridge::new(data) else {
+        return;
+    };
+    let system = System::new(cartridge);
+    for _ in 0..2 {
+        Bus::run_frame(
+            system.bus.clone(),
+            system.cpu.clone(),
+            system.ppu.clone(),
+            system.apu.clone(),
+        );
+    }
+});
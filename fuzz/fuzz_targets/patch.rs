@@ -0,0 +1,27 @@
+//! Applies arbitrary IPS and BPS patches to arbitrary ROMs. The first byte is the ROM's length,
+//! taken from the bytes after it, and the rest is the patch.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nes_core::{crc32, patch::apply};
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&rom_length, rest)) = data.split_first() else {
+        return;
+    };
+    let (rom, patch) = rest.split_at((rom_length as usize).min(rest.len()));
+    let mut patch = patch.to_vec();
+
+    // Fill in the source and patch checksums of BPS patches, so that their actions are reached
+    // rather than nearly every input being rejected as corrupt.
+    if patch.starts_with(b"BPS1") && patch.len() >= 16 {
+        let footer = patch.len() - 12;
+        patch[footer..footer + 4].copy_from_slice(&crc32(rom).to_le_bytes());
+        let checksum = crc32(&patch[..patch.len() - 4]);
+        let length = patch.len();
+        patch[length - 4..].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    let _ = apply(rom, &patch);
+});
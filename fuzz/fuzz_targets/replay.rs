@@ -0,0 +1,13 @@
+//! Parses arbitrary FM2 movies and reads every frame of input from them.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nes_core::Replay;
+
+fuzz_target!(|data: &[u8]| {
+    let text = String::from_utf8_lossy(data);
+    if let Ok(replay) = Replay::new(text.lines()) {
+        replay.for_each(drop);
+    }
+});
@@ -0,0 +1,22 @@
+//! Parses arbitrary savestates, and loads any that parse into a running console.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nes_core::{Bus, Cartridge, Savestate, System};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Savestate::read_metadata(data);
+
+    let cartridge = Cartridge::from_raw_prg(&[], 0x8000, [0x8000; 3]).unwrap();
+    let system = System::new(cartridge);
+    system.bus.borrow_mut().set_savestate_rom_check(false);
+    if system.bus.borrow_mut().load_state(data).is_ok() {
+        Bus::run_frame(
+            system.bus.clone(),
+            system.cpu.clone(),
+            system.ppu.clone(),
+            system.apu.clone(),
+        );
+    }
+});